@@ -1,6 +1,8 @@
 use rustc_version::{version_meta, Channel};
 
 fn main() {
+    // lib.rs gates the nightly intrinsics on this feature name
+    println!("cargo:rustc-check-cfg=cfg(feature, values(\"RUSTC_IS_NIGHTLY\"))");
     // Set cfg flags depending on release channel
     match version_meta().unwrap().channel {
         Channel::Stable => {
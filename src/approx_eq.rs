@@ -0,0 +1,180 @@
+//! Approximate comparison of reports with the `approx` crate.
+use approx::{AbsDiffEq, RelativeEq};
+use nalgebra::{DMatrix, RealField};
+
+use crate::lm::MinimizationReport;
+
+/// `None` only equals `None`, two values are compared with `eq`.
+fn option_eq<T>(a: &Option<T>, b: &Option<T>, eq: impl Fn(&T, &T) -> bool) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => eq(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+impl<F: RealField> MinimizationReport<F> {
+    /// Compare the floating-point fields with `eq` and all others exactly.
+    fn fields_eq(&self, other: &Self, eq: impl Fn(&F, &F) -> bool) -> bool {
+        let slice_eq =
+            |a: &[F], b: &[F]| a.len() == b.len() && a.iter().zip(b).all(|(a, b)| eq(a, b));
+        let matrix_eq = |a: &DMatrix<F>, b: &DMatrix<F>| {
+            a.shape() == b.shape() && slice_eq(a.as_slice(), b.as_slice())
+        };
+        self.termination == other.termination
+            && self.number_of_evaluations == other.number_of_evaluations
+            && self.number_of_residuals == other.number_of_residuals
+            && self.number_of_parameters == other.number_of_parameters
+            && self.terminated_at_iteration == other.terminated_at_iteration
+            && self.trust_region_reductions == other.trust_region_reductions
+            && self.incremental_factorizations == other.incremental_factorizations
+            && self.jacobian_is_numerical == other.jacobian_is_numerical
+            && self.qr_permutation == other.qr_permutation
+            && self.active_bounds == other.active_bounds
+            && eq(&self.objective_function, &other.objective_function)
+            && eq(&self.initial_objective, &other.initial_objective)
+            && eq(&self.final_step_norm, &other.final_step_norm)
+            && option_eq(&self.gradient_norm, &other.gradient_norm, &eq)
+            && option_eq(&self.lambda, &other.lambda, &eq)
+            && option_eq(&self.covariance, &other.covariance, matrix_eq)
+            && option_eq(&self.qr_r_factor, &other.qr_r_factor, matrix_eq)
+            && option_eq(&self.qr_qt_residuals, &other.qr_qt_residuals, |a, b| {
+                slice_eq(a.as_slice(), b.as_slice())
+            })
+            && option_eq(&self.leverages, &other.leverages, |a, b| {
+                slice_eq(a.as_slice(), b.as_slice())
+            })
+            && option_eq(&self.relative_step, &other.relative_step, |a, b| {
+                slice_eq(a.as_slice(), b.as_slice())
+            })
+            && option_eq(
+                &self.group_sums_of_squares,
+                &other.group_sums_of_squares,
+                |a, b| slice_eq(a, b),
+            )
+            && self.certificate.gradient_passed == other.certificate.gradient_passed
+            && self.certificate.step_passed == other.certificate.step_passed
+            && self.certificate.objective_passed == other.certificate.objective_passed
+            && eq(&self.certificate.gtol, &other.certificate.gtol)
+            && eq(&self.certificate.xtol, &other.certificate.xtol)
+            && eq(&self.certificate.ftol, &other.certificate.ftol)
+            && option_eq(
+                &self.certificate.gradient_norm,
+                &other.certificate.gradient_norm,
+                &eq,
+            )
+            && option_eq(
+                &self.certificate.step_norm,
+                &other.certificate.step_norm,
+                &eq,
+            )
+            && option_eq(
+                &self.certificate.objective_change,
+                &other.certificate.objective_change,
+                &eq,
+            )
+    }
+}
+
+/// The floating-point fields, like the objective function and the covariance,
+/// are compared with the tolerance. All other fields, like the termination
+/// reason and the number of evaluations, must be equal.
+impl<F: RealField> AbsDiffEq for MinimizationReport<F> {
+    type Epsilon = F;
+
+    fn default_epsilon() -> F {
+        F::default_epsilon()
+    }
+
+    fn abs_diff_eq(&self, other: &Self, epsilon: F) -> bool {
+        self.fields_eq(other, |a, b| a.abs_diff_eq(b, epsilon.clone()))
+    }
+}
+
+/// See [`AbsDiffEq`](#impl-AbsDiffEq-for-MinimizationReport<F>).
+impl<F: RealField> RelativeEq for MinimizationReport<F> {
+    fn default_max_relative() -> F {
+        F::default_max_relative()
+    }
+
+    fn relative_eq(&self, other: &Self, epsilon: F, max_relative: F) -> bool {
+        self.fields_eq(other, |a, b| {
+            a.relative_eq(b, epsilon.clone(), max_relative.clone())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::{assert_relative_eq, assert_relative_ne};
+    use nalgebra::{storage::Owned, Matrix4x2, Vector2, Vector4, U2, U4};
+
+    use crate::{LeastSquaresProblem, LevenbergMarquardt, MinimizationReport, TerminationReason};
+
+    /// Fit `$y = a t + b$` to four points shifted by `offset`.
+    struct Line {
+        offset: f64,
+        params: Vector2<f64>,
+    }
+
+    impl Line {
+        fn new(offset: f64) -> Self {
+            Self {
+                offset,
+                params: Vector2::zeros(),
+            }
+        }
+    }
+
+    impl LeastSquaresProblem<f64, U4, U2> for Line {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U4>;
+        type JacobianStorage = Owned<f64, U4, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector4<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            let t = Vector4::new(0., 1., 2., 3.);
+            Some(t.map(|t| a * t + b - self.offset) - Vector4::new(0.9, 3.1, 5.2, 6.8))
+        }
+
+        fn jacobian(&self) -> Option<Matrix4x2<f64>> {
+            Some(Matrix4x2::new(
+                0., 1., //
+                1., 1., //
+                2., 1., //
+                3., 1.,
+            ))
+        }
+    }
+
+    #[test]
+    fn equivalent_runs_compare_equal() {
+        let config = LevenbergMarquardt::new()
+            .with_covariance(true)
+            .with_qr_retention(true);
+        let (_, report) = config.minimize(Line::new(0.));
+        assert!(report.termination.was_successful());
+        assert!(report.covariance.is_some());
+        let (_, rerun) = config.minimize(Line::new(0.));
+        assert_relative_eq!(report, rerun, epsilon = 0.);
+
+        // a tiny shift of the data only changes the result in the last digits
+        let (_, shifted) = config.minimize(Line::new(1e-12));
+        assert_relative_ne!(report, shifted, epsilon = 0., max_relative = 0.);
+        assert_relative_eq!(report, shifted, epsilon = 1e-10);
+
+        let failed = MinimizationReport {
+            termination: TerminationReason::LostPatience,
+            ..rerun
+        };
+        assert_relative_ne!(report, failed, epsilon = 1.);
+    }
+}
@@ -0,0 +1,489 @@
+//! Trust-region subproblem for Jacobians with a block structure.
+//!
+//! Separable problems consist of independent sub-fits which share a few global
+//! parameters. The Jacobian then has a block-diagonal part and a few dense
+//! columns for the shared parameters. Instead of a dense QR decomposition of the
+//! full Jacobian, the normal equations are solved block by block and the shared
+//! parameters are handled with a Schur complement.
+use alloc::vec::Vec;
+use nalgebra::{
+    allocator::Allocator,
+    convert,
+    storage::{RawStorage, Storage},
+    Cholesky, DMatrix, DVector, DefaultAllocator, Dim, Dynamic, Matrix, OVector, RealField, Vector,
+    U1,
+};
+use num_traits::Float;
+
+use crate::trust_region::{LMParameter, Linearization};
+use crate::utils::{dwarf, enorm};
+
+/// Block structure of a Jacobian for separable problems.
+///
+/// The residuals are split into consecutive groups of rows, the blocks. Every
+/// block depends on its own local parameters and on parameters shared by all blocks.
+/// The parameters must be ordered such that the local parameters of the first
+/// block come first, followed by the local parameters of the second block and so
+/// forth. The shared parameters are last:
+/// ```math
+/// \mathbf{J} = \begin{pmatrix}
+///   \mathbf{A}_1 &        &              & \mathbf{B}_1 \\
+///                & \ddots &              & \vdots       \\
+///                &        & \mathbf{A}_k & \mathbf{B}_k
+/// \end{pmatrix}.
+/// ```
+///
+/// Entries of the Jacobian outside of the declared blocks are ignored, they
+/// are assumed to be zero.
+///
+/// Use it with [`LevenbergMarquardt::minimize_blocks`](struct.LevenbergMarquardt.html#method.minimize_blocks).
+///
+/// # Example
+///
+/// Two sub-fits with three residuals and two local parameters each, sharing one
+/// parameter:
+///
+/// ```
+/// # use levenberg_marquardt::BlockJacobian;
+/// let blocks = BlockJacobian::new(1).with_block(3, 2).with_block(3, 2);
+/// assert_eq!(blocks.nrows(), 6);
+/// assert_eq!(blocks.ncols(), 5);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockJacobian {
+    /// Number of residuals and number of local parameters for every block
+    blocks: Vec<(usize, usize)>,
+    shared: usize,
+}
+
+impl BlockJacobian {
+    /// Create a block structure with `shared` parameters common to all blocks.
+    pub fn new(shared: usize) -> Self {
+        Self {
+            blocks: Vec::new(),
+            shared,
+        }
+    }
+
+    /// Append a block with `residuals` rows depending on `params` local parameters.
+    #[must_use]
+    pub fn with_block(mut self, residuals: usize, params: usize) -> Self {
+        self.blocks.push((residuals, params));
+        self
+    }
+
+    /// The number of residuals `$m$` covered by the blocks.
+    pub fn nrows(&self) -> usize {
+        self.blocks.iter().map(|(rows, _)| rows).sum()
+    }
+
+    /// The number of parameters `$n$`, local and shared.
+    pub fn ncols(&self) -> usize {
+        self.blocks.iter().map(|(_, cols)| cols).sum::<usize>() + self.shared
+    }
+
+    /// Iterate over `(row offset, rows, column offset, columns)` of all blocks.
+    fn ranges(&self) -> impl Iterator<Item = (usize, usize, usize, usize)> + '_ {
+        let mut row = 0;
+        let mut col = 0;
+        self.blocks.iter().map(move |&(rows, cols)| {
+            let range = (row, rows, col, cols);
+            row += rows;
+            col += cols;
+            range
+        })
+    }
+}
+
+/// Factorization of `$\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D}$`
+/// using the block structure.
+struct BlockFactorization<F: RealField> {
+    /// Cholesky factors of `$\mathbf{A}_i^\top\mathbf{A}_i + \lambda\mathbf{D}_i\mathbf{D}_i$`
+    local: Vec<Cholesky<F, Dynamic>>,
+    /// The coupling matrices `$\mathbf{A}_i^\top \mathbf{B}_i$`
+    coupling: Vec<DMatrix<F>>,
+    /// Cholesky factor of the Schur complement for the shared parameters
+    schur: Option<Cholesky<F, Dynamic>>,
+}
+
+/// Block variant of [`LinearLeastSquaresDiagonalProblem`](../qr/struct.LinearLeastSquaresDiagonalProblem.html).
+pub(crate) struct BlockLeastSquaresDiagonalProblem<'a, F, N>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    structure: &'a BlockJacobian,
+    /// The Jacobian with all entries outside of the blocks set to zero.
+    jacobian: DMatrix<F>,
+    /// `$\mathbf{J}^\top\vec{r}$`
+    gradient: DVector<F>,
+    column_norms: OVector<F, N>,
+}
+
+impl<'a, F, N> BlockLeastSquaresDiagonalProblem<'a, F, N>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    pub fn new<M, JS, RS>(
+        structure: &'a BlockJacobian,
+        jacobian: Matrix<F, M, N, JS>,
+        residuals: &Vector<F, M, RS>,
+    ) -> Self
+    where
+        M: Dim,
+        JS: Storage<F, M, N>,
+        RS: Storage<F, M>,
+    {
+        let (m, n) = jacobian.shape();
+        assert_eq!(
+            structure.nrows(),
+            m,
+            "block structure does not match the number of residuals"
+        );
+        let n_local = n - structure.shared;
+        let mut dense = DMatrix::<F>::zeros(m, n);
+        for (row, rows, col, cols) in structure.ranges() {
+            for i in row..row + rows {
+                for j in (col..col + cols).chain(n_local..n) {
+                    dense[(i, j)] = jacobian[(i, j)];
+                }
+            }
+        }
+        let residuals = DVector::<F>::from_iterator(m, residuals.iter().copied());
+        let gradient = dense.tr_mul(&residuals);
+        let column_norms = OVector::<F, N>::from_iterator_generic(
+            jacobian.data.shape().1,
+            Dim::from_usize(1),
+            dense.column_iter().map(|c| enorm(&c)),
+        );
+        Self {
+            structure,
+            jacobian: dense,
+            gradient,
+            column_norms,
+        }
+    }
+
+    fn to_ovector(&self, x: DVector<F>) -> OVector<F, N> {
+        OVector::<F, N>::from_iterator_generic(
+            self.column_norms.data.shape().0,
+            Dim::from_usize(1),
+            x.iter().copied(),
+        )
+    }
+
+    /// Factorize `$\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D}$`.
+    ///
+    /// Returns `None` if the matrix is singular.
+    fn factorize(&self, diag: &OVector<F, N>, lambda: F) -> Option<BlockFactorization<F>> {
+        let shared = self.structure.shared;
+        let n_local = self.jacobian.ncols() - shared;
+        let mut local = Vec::with_capacity(self.structure.blocks.len());
+        let mut coupling = Vec::with_capacity(self.structure.blocks.len());
+        let mut schur = DMatrix::<F>::zeros(shared, shared);
+        for j in 0..shared {
+            schur[(j, j)] = lambda * Float::powi(diag[n_local + j], 2);
+        }
+        for (row, rows, col, cols) in self.structure.ranges() {
+            let a = self.jacobian.slice((row, col), (rows, cols));
+            let b = self.jacobian.slice((row, n_local), (rows, shared));
+            let mut u: DMatrix<F> = a.tr_mul(&a);
+            for j in 0..cols {
+                u[(j, j)] += lambda * Float::powi(diag[col + j], 2);
+            }
+            let u = u.cholesky()?;
+            let w: DMatrix<F> = a.tr_mul(&b);
+            let u_inv_w: DMatrix<F> = u.solve::<Dynamic, Dynamic, _>(&w);
+            schur += b.tr_mul(&b) - w.tr_mul(&u_inv_w);
+            local.push(u);
+            coupling.push(w);
+        }
+        let schur = if shared > 0 {
+            Some(schur.cholesky()?)
+        } else {
+            None
+        };
+        Some(BlockFactorization {
+            local,
+            coupling,
+            schur,
+        })
+    }
+
+    /// Solve `$(\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D})\vec{x} = \vec{b}$`
+    /// using a factorization from `factorize`.
+    fn solve(&self, factorization: &BlockFactorization<F>, rhs: &DVector<F>) -> DVector<F> {
+        let shared = self.structure.shared;
+        let n_local = self.jacobian.ncols() - shared;
+        let blocks = || {
+            self.structure.ranges().zip(
+                factorization
+                    .local
+                    .iter()
+                    .zip(factorization.coupling.iter()),
+            )
+        };
+        let mut x = rhs.clone();
+        if let Some(schur) = &factorization.schur {
+            let mut rhs_shared: DVector<F> = rhs.rows(n_local, shared).into_owned();
+            for ((_, _, col, cols), (u, w)) in blocks() {
+                let local: DVector<F> =
+                    u.solve::<Dynamic, U1, _>(&rhs.rows(col, cols).into_owned());
+                rhs_shared -= w.tr_mul(&local);
+            }
+            let x_shared: DVector<F> = schur.solve::<Dynamic, U1, _>(&rhs_shared);
+            x.rows_mut(n_local, shared).copy_from(&x_shared);
+        }
+        for ((_, _, col, cols), (u, w)) in blocks() {
+            let rhs_local: DVector<F> = rhs.rows(col, cols) - w * x.rows(n_local, shared);
+            let local: DVector<F> = u.solve::<Dynamic, U1, _>(&rhs_local);
+            x.rows_mut(col, cols).copy_from(&local);
+        }
+        x
+    }
+
+    /// Compute `$\|\mathbf{D}\vec{p}\|$` and the Newton correction for `$\lambda$`.
+    fn newton_correction(
+        &self,
+        factorization: &BlockFactorization<F>,
+        diag: &OVector<F, N>,
+        diag_p: &DVector<F>,
+        diag_p_norm: F,
+        fp: F,
+        delta: F,
+    ) -> F {
+        let v = DVector::<F>::from_iterator(
+            diag_p.nrows(),
+            diag_p
+                .iter()
+                .zip(diag.iter())
+                .map(|(dp, d)| *dp / diag_p_norm * *d),
+        );
+        // ||L^{-1} v||^2 = v^T (J^T J + lambda D D)^{-1} v
+        let norm_sq = v.dot(&self.solve(factorization, &v));
+        (fp / delta) / norm_sq
+    }
+}
+
+impl<'a, F, N> Linearization<F, N> for BlockLeastSquaresDiagonalProblem<'a, F, N>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn column_norms(&self) -> &OVector<F, N> {
+        &self.column_norms
+    }
+
+    fn max_a_t_b_scaled(&mut self, b_norm: F) -> Option<F> {
+        let mut max = F::zero();
+        for (g, scale) in self.gradient.iter().zip(self.column_norms.iter()) {
+            if scale.is_zero() {
+                continue;
+            }
+            let temp = Float::abs(*g / b_norm / *scale);
+            if temp.is_nan() {
+                return None;
+            }
+            max = Float::max(max, temp);
+        }
+        Some(max)
+    }
+
+    fn a_x_norm(&mut self, x: &OVector<F, N>) -> F {
+        let x = DVector::<F>::from_iterator(x.nrows(), x.iter().copied());
+        enorm(&(&self.jacobian * x))
+    }
+
+    fn a_t_b(&self) -> OVector<F, N> {
+        self.to_ovector(self.gradient.clone())
+    }
+
+    /// Same safeguarded Newton iteration as
+    /// [`determine_lambda_and_parameter_update`](../trust_region/fn.determine_lambda_and_parameter_update.html),
+    /// but all linear systems are solved using the block structure.
+    fn solve_trust_region(
+        &mut self,
+        diag: &OVector<F, N>,
+        delta: F,
+        initial_lambda: F,
+        max_iterations: usize,
+        _min_norm: bool,
+    ) -> LMParameter<F, N> {
+        const P1: f64 = 0.1;
+        let n = self.gradient.nrows();
+        let scale = |p: &DVector<F>| {
+            DVector::<F>::from_iterator(n, p.iter().zip(diag.iter()).map(|(p, d)| *p * *d))
+        };
+
+        // Gauss-Newton step, only available if J has full rank
+        let mut p = DVector::<F>::zeros(n);
+        let mut diag_p_norm = F::zero();
+        let mut fp = <F as Float>::infinity();
+        let mut lambda_lower = F::zero();
+        if let Some(factorization) = self.factorize(diag, F::zero()) {
+            p = self.solve(&factorization, &self.gradient);
+            let diag_p = scale(&p);
+            diag_p_norm = enorm(&diag_p);
+            fp = diag_p_norm - delta;
+            if fp <= delta * convert(P1) {
+                return LMParameter {
+                    step: self.to_ovector(p),
+                    lambda: F::zero(),
+                    dp_norm: diag_p_norm,
+                    lambda_iterations: 0,
+                };
+            }
+            lambda_lower =
+                self.newton_correction(&factorization, diag, &diag_p, diag_p_norm, fp, delta);
+        }
+
+        // Upper bound is given by ||(J * D^T)^T r|| / delta
+        let gnorm = enorm(&DVector::<F>::from_iterator(
+            n,
+            self.gradient.iter().zip(diag.iter()).map(|(g, d)| *g / *d),
+        ));
+        let mut lambda_upper = gnorm / delta;
+        if lambda_upper.is_zero() {
+            lambda_upper = dwarf::<F>() / Float::min(delta, convert(P1));
+        }
+
+        let mut lambda = Float::min(Float::max(initial_lambda, lambda_lower), lambda_upper);
+        if lambda.is_zero() && !diag_p_norm.is_zero() {
+            lambda = gnorm / diag_p_norm;
+        }
+
+        let mut lambda_iterations = 0;
+        for iteration in 1.. {
+            lambda_iterations = iteration;
+            if lambda.is_zero() {
+                lambda = Float::max(dwarf(), lambda_upper * convert(0.001));
+            }
+            let factorization = match self.factorize(diag, lambda) {
+                Some(factorization) => factorization,
+                None => break,
+            };
+            p = self.solve(&factorization, &self.gradient);
+            let diag_p = scale(&p);
+            diag_p_norm = enorm(&diag_p);
+            if iteration == max_iterations {
+                break;
+            }
+            let fp_old = fp;
+            fp = diag_p_norm - delta;
+            if Float::abs(fp) <= delta * convert(P1)
+                || (lambda_lower.is_zero() && fp <= fp_old && fp_old.is_negative())
+            {
+                break;
+            }
+
+            let newton_correction =
+                self.newton_correction(&factorization, diag, &diag_p, diag_p_norm, fp, delta);
+            if fp.is_positive() {
+                lambda_lower = Float::max(lambda_lower, lambda);
+            } else {
+                lambda_upper = Float::min(lambda_upper, lambda);
+            }
+            lambda = Float::max(lambda_lower, lambda + newton_correction);
+        }
+
+        LMParameter {
+            step: self.to_ovector(p),
+            lambda,
+            dp_norm: diag_p_norm,
+            lambda_iterations,
+        }
+    }
+
+    fn solve_damped(&mut self, diag: &OVector<F, N>, lambda: F) -> Option<LMParameter<F, N>> {
+        let factorization = self.factorize(diag, lambda)?;
+        let p = self.solve(&factorization, &self.gradient);
+        let dp_norm = enorm(&DVector::<F>::from_iterator(
+            p.nrows(),
+            p.iter().zip(diag.iter()).map(|(p, d)| *p * *d),
+        ));
+        Some(LMParameter {
+            step: self.to_ovector(p),
+            lambda,
+            dp_norm,
+            lambda_iterations: 1,
+        })
+    }
+
+    /// The Jacobian is not decomposed, there is no cheap estimate.
+    fn singular_value_estimates(&self, _diag: &OVector<F, N>) -> Option<(F, F)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qr::PivotedQR;
+    use approx::assert_relative_eq;
+    use nalgebra::{DMatrix, DVector, Dynamic};
+
+    /// Two blocks with three residuals and two local parameters, one shared parameter.
+    fn two_blocks() -> (BlockJacobian, DMatrix<f64>, DVector<f64>) {
+        let blocks = BlockJacobian::new(1).with_block(3, 2).with_block(3, 2);
+        #[rustfmt::skip]
+        let jacobian = DMatrix::from_row_slice(6, 5, &[
+            3., 1., 0., 0., 0.5,
+            -1., 2., 0., 0., 1.,
+            4., -2., 0., 0., -0.3,
+            0., 0., 7., 1., 2.,
+            0., 0., -3., 5., 0.1,
+            0., 0., 1., 1., -1.,
+        ]);
+        let residuals = DVector::from_column_slice(&[1., -2., 0.5, 3., 1., -0.7]);
+        (blocks, jacobian, residuals)
+    }
+
+    #[test]
+    fn test_block_solve_matches_dense() {
+        let (blocks, jacobian, residuals) = two_blocks();
+        let lls = BlockLeastSquaresDiagonalProblem::<f64, Dynamic>::new(
+            &blocks,
+            jacobian.clone(),
+            &residuals,
+        );
+        let diag = DVector::from_column_slice(&[1., 2., 0.5, 1.5, 3.]);
+        let lambda = 0.3;
+        let factorization = lls.factorize(&diag, lambda).unwrap();
+        let x = lls.solve(&factorization, &lls.gradient);
+
+        let d2 = DMatrix::from_diagonal(&diag.component_mul(&diag));
+        let dense = (jacobian.tr_mul(&jacobian) + d2 * lambda)
+            .lu()
+            .solve(&jacobian.tr_mul(&residuals))
+            .unwrap();
+        assert_relative_eq!(x, dense, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_block_trust_region_matches_dense() {
+        let (blocks, jacobian, residuals) = two_blocks();
+        let diag = DVector::from_column_slice(&[1., 2., 0.5, 1.5, 3.]);
+        for delta in [0.01, 0.1, 1.0, 100.0] {
+            let mut block = BlockLeastSquaresDiagonalProblem::<f64, Dynamic>::new(
+                &blocks,
+                jacobian.clone(),
+                &residuals,
+            );
+            let mut dense = PivotedQR::new(jacobian.clone())
+                .into_least_squares_diagonal_problem(residuals.clone());
+            let param_block = block.solve_trust_region(&diag, delta, 0., 10, false);
+            let param_dense = dense.solve_trust_region(&diag, delta, 0., 10, false);
+            assert_relative_eq!(param_block.step, param_dense.step, epsilon = 1e-10);
+            assert_relative_eq!(param_block.lambda, param_dense.lambda, epsilon = 1e-8);
+            assert_relative_eq!(
+                block.max_a_t_b_scaled(2.).unwrap(),
+                dense.max_a_t_b_scaled(2.).unwrap(),
+                epsilon = 1e-12
+            );
+        }
+    }
+}
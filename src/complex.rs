@@ -0,0 +1,284 @@
+//! Least squares problems with complex residuals and parameters.
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::Allocator, storage::Owned, Complex, Const, DMatrix, DVector, DefaultAllocator, Dim,
+    Dynamic, OMatrix, OVector, RealField,
+};
+
+use crate::LeastSquaresProblem;
+
+/// A least squares problem with complex residuals and parameters.
+///
+/// This is minimized with
+/// [`LevenbergMarquardt::minimize_complex`](struct.LevenbergMarquardt.html#method.minimize_complex).
+/// The residuals must be holomorphic in the parameters, then the Jacobian is
+/// the matrix of the complex derivatives `$\partial r_i/\partial z_j$`.
+///
+/// Internally, the problem is mapped to a real problem of double the size with
+/// the parameters `$(\operatorname{Re}\vec{z}, \operatorname{Im}\vec{z})$`,
+/// the residuals `$(\operatorname{Re}\vec{r}, \operatorname{Im}\vec{r})$`
+/// and the Jacobian
+/// ```math
+///   \begin{pmatrix}
+///     \operatorname{Re}\mathbf{J} & -\operatorname{Im}\mathbf{J} \\
+///     \operatorname{Im}\mathbf{J} & \operatorname{Re}\mathbf{J}
+///   \end{pmatrix}.
+/// ```
+/// The Euclidean norms of the real vectors are the complex norms, so the
+/// objective function is `$\frac{1}{2}\sum_i |r_i|^2$` and the convergence
+/// tests behave like for a real problem.
+pub trait ComplexLeastSquaresProblem<F, M, N>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    DefaultAllocator:
+        Allocator<Complex<F>, N> + Allocator<Complex<F>, M> + Allocator<Complex<F>, M, N>,
+{
+    /// Set the stored parameters `$\vec{z}$`.
+    fn set_params(&mut self, z: &OVector<Complex<F>, N>);
+
+    /// Get the current parameter vector `$\vec{z}$`.
+    fn params(&self) -> OVector<Complex<F>, N>;
+
+    /// Compute the residual vector.
+    fn residuals(&self) -> Option<OVector<Complex<F>, M>>;
+
+    /// Compute the Jacobian of the residual vector.
+    fn jacobian(&self) -> Option<OMatrix<Complex<F>, M, N>>;
+}
+
+/// The real problem of double the size for a complex problem.
+pub(crate) struct RealEmbedding<P, M, N> {
+    problem: P,
+    n: N,
+    marker: PhantomData<M>,
+}
+
+impl<P, M, N> RealEmbedding<P, M, N> {
+    pub(crate) fn new(problem: P, n: N) -> Self {
+        Self {
+            problem,
+            n,
+            marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+fn to_real<F, K>(z: &OVector<Complex<F>, K>) -> DVector<F>
+where
+    F: RealField + Copy,
+    K: Dim,
+    DefaultAllocator: Allocator<Complex<F>, K>,
+{
+    let k = z.nrows();
+    DVector::from_fn(2 * k, |i, _| if i < k { z[i].re } else { z[i - k].im })
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, Dynamic, Dynamic> for RealEmbedding<P, M, N>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: ComplexLeastSquaresProblem<F, M, N>,
+    DefaultAllocator:
+        Allocator<Complex<F>, N> + Allocator<Complex<F>, M> + Allocator<Complex<F>, M, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, Dynamic>;
+    type ParameterStorage = Owned<F, Dynamic>;
+
+    fn set_params(&mut self, x: &DVector<F>) {
+        let n = self.n.value();
+        let z = OVector::<Complex<F>, N>::from_fn_generic(self.n, Const::<1>, |j, _| {
+            Complex::new(x[j], x[n + j])
+        });
+        self.problem.set_params(&z);
+    }
+
+    fn params(&self) -> DVector<F> {
+        to_real(&self.problem.params())
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        Some(to_real(&self.problem.residuals()?))
+    }
+
+    fn jacobian(&self) -> Option<DMatrix<F>> {
+        let jacobian = self.problem.jacobian()?;
+        let (m, n) = jacobian.shape();
+        Some(DMatrix::from_fn(2 * m, 2 * n, |i, j| {
+            let entry = jacobian[(i % m, j % n)];
+            match (i < m, j < n) {
+                (true, true) | (false, false) => entry.re,
+                (true, false) => -entry.im,
+                (false, true) => entry.im,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{
+        storage::Owned, Complex, ComplexField, Const, DMatrix, DVector, Dynamic, OMatrix, OVector,
+        Vector2, Vector4, U2, U4,
+    };
+
+    use super::ComplexLeastSquaresProblem;
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    const I: Complex<f64> = Complex::new(0., 1.);
+
+    /// Fit `$z(t) = a e^{i\omega t}$` with complex amplitude `$a$` and frequency `$\omega$`.
+    struct Oscillation {
+        t: Vec<f64>,
+        z: Vec<Complex<f64>>,
+        params: Vector2<Complex<f64>>,
+    }
+
+    impl Oscillation {
+        fn new() -> Self {
+            let (a, omega) = (Complex::new(1.5, -0.5), 2.);
+            let t: Vec<f64> = (0..30).map(|k| 0.1 * k as f64).collect();
+            let z = t
+                .iter()
+                .enumerate()
+                .map(|(k, &t)| {
+                    let k = k as f64;
+                    let noise = Complex::new(f64::sin(7. * k), f64::cos(3. * k)) * 0.01;
+                    a * ComplexField::exp(I * omega * t) + noise
+                })
+                .collect();
+            Self {
+                t,
+                z,
+                params: Vector2::new(Complex::new(1., 0.), Complex::new(1.8, 0.)),
+            }
+        }
+    }
+
+    impl ComplexLeastSquaresProblem<f64, Dynamic, U2> for Oscillation {
+        fn set_params(&mut self, z: &Vector2<Complex<f64>>) {
+            self.params.copy_from(z);
+        }
+
+        fn params(&self) -> Vector2<Complex<f64>> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<OVector<Complex<f64>, Dynamic>> {
+            let [a, omega] = [self.params.x, self.params.y];
+            Some(OVector::<Complex<f64>, Dynamic>::from_iterator(
+                self.t.len(),
+                self.t
+                    .iter()
+                    .zip(self.z.iter())
+                    .map(|(&t, &z)| a * ComplexField::exp(I * omega * t) - z),
+            ))
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<Complex<f64>, Dynamic, U2>> {
+            let [a, omega] = [self.params.x, self.params.y];
+            let mut jacobian = OMatrix::<Complex<f64>, Dynamic, U2>::zeros(self.t.len());
+            for (i, &t) in self.t.iter().enumerate() {
+                let e = ComplexField::exp(I * omega * t);
+                jacobian[(i, 0)] = e;
+                jacobian[(i, 1)] = I * t * a * e;
+            }
+            Some(jacobian)
+        }
+    }
+
+    /// The same fit with real and imaginary parts split by hand.
+    struct SplitOscillation {
+        complex: Oscillation,
+    }
+
+    impl LeastSquaresProblem<f64, Dynamic, U4> for SplitOscillation {
+        type ParameterStorage = Owned<f64, U4>;
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, U4>;
+
+        fn set_params(&mut self, x: &Vector4<f64>) {
+            self.complex.params = Vector2::new(Complex::new(x[0], x[2]), Complex::new(x[1], x[3]));
+        }
+
+        fn params(&self) -> Vector4<f64> {
+            let [a, omega] = [self.complex.params.x, self.complex.params.y];
+            Vector4::new(a.re, omega.re, a.im, omega.im)
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            let r = ComplexLeastSquaresProblem::residuals(&self.complex)?;
+            let m = r.nrows();
+            Some(DVector::from_fn(2 * m, |i, _| {
+                if i < m {
+                    r[i].re
+                } else {
+                    r[i - m].im
+                }
+            }))
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U4>> {
+            let [a, omega] = [self.complex.params.x, self.complex.params.y];
+            let m = self.complex.t.len();
+            let mut jacobian = OMatrix::<f64, Dynamic, U4>::zeros(2 * m);
+            for (i, &t) in self.complex.t.iter().enumerate() {
+                let e = ComplexField::exp(I * omega * t);
+                let c = I * t * a * e;
+                for (j, d) in [e, c, I * e, I * c].iter().enumerate() {
+                    jacobian[(i, j)] = d.re;
+                    jacobian[(m + i, j)] = d.im;
+                }
+            }
+            Some(jacobian)
+        }
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let mut problem = super::RealEmbedding::new(Oscillation::new(), Const::<2>);
+        let numerical = crate::differentiate_numerically(&mut problem).unwrap();
+        let jacobian: DMatrix<f64> = problem.jacobian().unwrap();
+        assert_relative_eq!(jacobian, numerical, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn matches_manual_embedding() {
+        let lm = LevenbergMarquardt::new();
+        let (complex, report) = lm.minimize_complex(Oscillation::new());
+        assert!(report.termination.was_successful());
+        let (split, split_report) = lm.minimize(SplitOscillation {
+            complex: Oscillation::new(),
+        });
+        assert!(split_report.termination.was_successful());
+
+        assert_relative_eq!(complex.params.x.re, 1.5, epsilon = 1e-2);
+        assert_relative_eq!(complex.params.x.im, -0.5, epsilon = 1e-2);
+        assert_relative_eq!(complex.params.y.re, 2., epsilon = 1e-2);
+        assert_relative_eq!(complex.params.y.im, 0., epsilon = 1e-2);
+        assert_relative_eq!(
+            report.objective_function,
+            split_report.objective_function,
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(
+            Vector4::new(
+                complex.params.x.re,
+                complex.params.y.re,
+                complex.params.x.im,
+                complex.params.y.im
+            ),
+            split.params(),
+            epsilon = 1e-8
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Least squares problems stacked from several sub-problems.
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use nalgebra::{storage::Owned, DVector, Dim, Dynamic, OMatrix, RealField, Vector};
+
+use crate::LeastSquaresProblem;
+
+/// A problem whose residuals are the stacked residuals of several sub-problems.
+///
+/// All sub-problems share the same parameters `$\vec{x}$`. For the weights
+/// `$w_k \geq 0$` the objective function is
+/// ```math
+///   \frac{1}{2}\sum_k w_k \|\vec{r}_k(\vec{x})\|^2,
+/// ```
+/// the residuals and the Jacobian of sub-problem `$k$` are scaled by
+/// `$\sqrt{w_k}$` and stacked in the order the sub-problems were added.
+///
+/// The parameters are set on all sub-problems and read from the first one.
+/// To combine sub-problems of different types, wrap them in an enum which
+/// implements [`LeastSquaresProblem`](trait.LeastSquaresProblem.html).
+pub struct CompositeProblem<F, M, P> {
+    problems: Vec<(P, F)>,
+    marker: PhantomData<M>,
+}
+
+impl<F, M, P> CompositeProblem<F, M, P>
+where
+    F: RealField + Copy,
+{
+    /// Start with the sub-problem `problem` with the weight `weight`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the weight is negative or not finite.
+    pub fn new(problem: P, weight: F) -> Self {
+        Self {
+            problems: Vec::new(),
+            marker: PhantomData,
+        }
+        .with_problem(problem, weight)
+    }
+
+    /// Append the sub-problem `problem` with the weight `weight`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the weight is negative or not finite.
+    #[must_use]
+    pub fn with_problem(mut self, problem: P, weight: F) -> Self {
+        assert!(
+            weight >= F::zero() && weight.is_finite(),
+            "weight must be finite and >= 0"
+        );
+        self.problems.push((problem, weight.sqrt()));
+        self
+    }
+
+    /// The sub-problems in the order they were added.
+    pub fn problems(&self) -> impl Iterator<Item = &P> + '_ {
+        self.problems.iter().map(|(problem, _)| problem)
+    }
+
+    /// Extract the sub-problems.
+    pub fn into_inner(self) -> Vec<P> {
+        self.problems
+            .into_iter()
+            .map(|(problem, _)| problem)
+            .collect()
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, Dynamic, N> for CompositeProblem<F, M, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, N>;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        for (problem, _) in &mut self.problems {
+            problem.set_params(x);
+        }
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problems[0].0.params()
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        let mut stacked = Vec::new();
+        for (problem, scale) in &self.problems {
+            stacked.extend(problem.residuals()?.iter().map(|r| *r * *scale));
+        }
+        Some(DVector::from_vec(stacked))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, Dynamic, N>> {
+        let jacobians = self
+            .problems
+            .iter()
+            .map(|(problem, scale)| Some((problem.jacobian()?, *scale)))
+            .collect::<Option<Vec<_>>>()?;
+        let n = jacobians[0].0.shape_generic().1;
+        if jacobians
+            .iter()
+            .any(|(jacobian, _)| jacobian.ncols() != n.value())
+        {
+            return None;
+        }
+        let m = jacobians.iter().map(|(jacobian, _)| jacobian.nrows()).sum();
+        let mut stacked = OMatrix::<F, Dynamic, N>::zeros_generic(Dynamic::new(m), n);
+        let mut row = 0;
+        for (jacobian, scale) in &jacobians {
+            for j in 0..n.value() {
+                for i in 0..jacobian.nrows() {
+                    stacked[(row + i, j)] = jacobian[(i, j)] * *scale;
+                }
+            }
+            row += jacobian.nrows();
+        }
+        Some(stacked)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problems
+            .iter()
+            .any(|(problem, _)| problem.jacobian_is_numerical())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DVector, Dynamic, OMatrix, Vector2, U2};
+
+    use super::CompositeProblem;
+    use crate::{differentiate_numerically, LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Fit the line `$y = a + bt$` to samples, each row scaled by `scale`.
+    #[derive(Clone)]
+    struct Line {
+        t: DVector<f64>,
+        y: DVector<f64>,
+        scale: DVector<f64>,
+        params: Vector2<f64>,
+    }
+
+    impl Line {
+        fn new(t: &[f64], y: &[f64]) -> Self {
+            Self {
+                t: DVector::from_column_slice(t),
+                y: DVector::from_column_slice(y),
+                scale: DVector::from_element(t.len(), 1.),
+                params: Vector2::zeros(),
+            }
+        }
+    }
+
+    impl LeastSquaresProblem<f64, Dynamic, U2> for Line {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            let r = self.t.map(|t| a + b * t) - &self.y;
+            Some(r.component_mul(&self.scale))
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U2>> {
+            Some(OMatrix::<f64, Dynamic, U2>::from_fn(
+                self.t.len(),
+                |i, j| {
+                    if j == 0 {
+                        self.scale[i]
+                    } else {
+                        self.scale[i] * self.t[i]
+                    }
+                },
+            ))
+        }
+    }
+
+    fn sensors() -> (Line, Line) {
+        let first = Line::new(&[0., 1., 2., 3.], &[1.2, 2.9, 5.1, 7.2]);
+        let second = Line::new(&[0.5, 1.5, 2.5], &[1.8, 4.3, 5.6]);
+        (first, second)
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let (first, second) = sensors();
+        let mut problem = CompositeProblem::new(first, 1.).with_problem(second, 4.);
+        problem.set_params(&Vector2::new(0.5, 1.5));
+        let numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(problem.jacobian().unwrap(), numerical, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn matches_concatenated_problem() {
+        let (first, second) = sensors();
+        let (composite, report) = LevenbergMarquardt::new()
+            .minimize(CompositeProblem::new(first.clone(), 1.).with_problem(second.clone(), 4.));
+        assert!(report.termination.was_successful());
+
+        let t: Vec<f64> = first.t.iter().chain(second.t.iter()).copied().collect();
+        let y: Vec<f64> = first.y.iter().chain(second.y.iter()).copied().collect();
+        let mut concatenated = Line::new(&t, &y);
+        for i in first.t.len()..t.len() {
+            concatenated.scale[i] = 2.;
+        }
+        let (concatenated, expected) = LevenbergMarquardt::new().minimize(concatenated);
+        assert!(expected.termination.was_successful());
+        assert_relative_eq!(composite.params(), concatenated.params(), epsilon = 1e-12);
+        assert_relative_eq!(
+            report.objective_function,
+            expected.objective_function,
+            epsilon = 1e-12
+        );
+        for problem in composite.problems() {
+            assert_eq!(problem.params(), composite.params());
+        }
+    }
+}
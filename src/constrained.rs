@@ -0,0 +1,263 @@
+//! Nonlinear equality constraints with an augmented Lagrangian.
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::Allocator, storage::Owned, DVector, DefaultAllocator, Dim, Dynamic, OMatrix,
+    RealField, Vector,
+};
+use num_traits::Float;
+
+use crate::lm::MinimizationReport;
+use crate::LeastSquaresProblem;
+
+/// The result of
+/// [`LevenbergMarquardt::minimize_constrained`](struct.LevenbergMarquardt.html#method.minimize_constrained).
+#[derive(Debug)]
+pub struct ConstrainedReport<F: RealField> {
+    /// The report of the last minimization of the augmented problem.
+    ///
+    /// Its objective function includes the terms of the constraints, at the
+    /// solution they are close to `$\frac{1}{2\mu}\|\vec{\lambda}\|^2$`.
+    pub report: MinimizationReport<F>,
+    /// The Lagrange multipliers `$\vec{\lambda}$` of the constraints.
+    ///
+    /// At a solution `$\mathbf{J}^\top\vec{r} + \mathbf{C}^\top\vec{\lambda} = 0$`
+    /// holds approximately, with the Jacobian `$\mathbf{C}$` of the constraints.
+    pub multipliers: DVector<F>,
+    /// The penalty `$\mu$` of the last minimization.
+    pub penalty: F,
+    /// The largest violation `$\max_i |c_i(\vec{x})|$` at the solution.
+    ///
+    /// `NaN` if the constraints could not be computed.
+    pub constraint_violation: F,
+    /// The number of minimizations of the augmented problem.
+    pub outer_iterations: usize,
+}
+
+/// The residuals and constraints with the terms of the augmented Lagrangian.
+///
+/// For the penalty `$\mu$` and the multipliers `$\vec{\lambda}$` the residuals
+/// `$\sqrt{\mu}\,(\vec{c}(\vec{x}) + \vec{\lambda}/\mu)$` are appended to
+/// `$\vec{r}(\vec{x})$`. Up to a constant the objective function is
+/// ```math
+///   \frac{1}{2}\|\vec{r}(\vec{x})\|^2 + \vec{\lambda}^\top\vec{c}(\vec{x}) + \frac{\mu}{2}\|\vec{c}(\vec{x})\|^2.
+/// ```
+pub(crate) struct Augmented<P, C, M, K, F> {
+    problem: P,
+    constraints: C,
+    sqrt_penalty: F,
+    /// `$\vec{\lambda}/\mu$`, empty if the constraints could not be computed
+    shift: DVector<F>,
+    marker: PhantomData<(M, K)>,
+}
+
+impl<P, C, M, K, F> Augmented<P, C, M, K, F>
+where
+    F: RealField + Float,
+{
+    pub(crate) fn new(problem: P, constraints: C, penalty: F, multipliers: DVector<F>) -> Self {
+        Self {
+            problem,
+            constraints,
+            sqrt_penalty: Float::sqrt(penalty),
+            shift: multipliers / penalty,
+            marker: PhantomData,
+        }
+    }
+
+    /// Set the penalty `$\mu$` and the multipliers `$\vec{\lambda}$`.
+    pub(crate) fn update(&mut self, penalty: F, multipliers: &DVector<F>) {
+        self.sqrt_penalty = Float::sqrt(penalty);
+        self.shift = multipliers / penalty;
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+
+    /// The constraints `$\vec{c}(\vec{x})$` at the current parameters.
+    pub(crate) fn constraint_values<N>(&self) -> Option<Vector<F, K, C::ResidualStorage>>
+    where
+        N: Dim,
+        K: Dim,
+        C: LeastSquaresProblem<F, K, N>,
+    {
+        self.constraints.residuals()
+    }
+}
+
+impl<F, M, N, K, P, C> LeastSquaresProblem<F, Dynamic, N> for Augmented<P, C, M, K, F>
+where
+    F: RealField + Float,
+    M: Dim,
+    N: Dim,
+    K: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+    C: LeastSquaresProblem<F, K, N, ParameterStorage = Owned<F, N>>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, Dynamic, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, N>;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.set_params(x);
+        self.constraints.set_params(&x.clone_owned());
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        let residuals = self.problem.residuals()?;
+        let constraints = self.constraints.residuals()?;
+        if constraints.nrows() != self.shift.nrows() {
+            return None;
+        }
+        let stacked: Vec<F> = residuals
+            .iter()
+            .copied()
+            .chain(
+                constraints
+                    .iter()
+                    .zip(self.shift.iter())
+                    .map(|(c, shift)| self.sqrt_penalty * (*c + *shift)),
+            )
+            .collect();
+        Some(dvector(stacked))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, Dynamic, N>> {
+        let jacobian = self.problem.jacobian()?;
+        let constraints = self.constraints.jacobian()?;
+        let (rows, n) = jacobian.shape_generic();
+        if constraints.ncols() != n.value() {
+            return None;
+        }
+        let m = Dynamic::new(rows.value() + constraints.nrows());
+        let rows = rows.value();
+        Some(OMatrix::from_fn_generic(m, n, |i, j| {
+            if i < rows {
+                jacobian[(i, j)]
+            } else {
+                self.sqrt_penalty * constraints[(i - rows, j)]
+            }
+        }))
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical() || self.constraints.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals() + self.shift.nrows()
+    }
+}
+
+/// `DVector::from_vec` for contexts with other bounds on `DefaultAllocator`.
+pub(crate) fn dvector<F: RealField>(data: Vec<F>) -> DVector<F> {
+    DVector::from_vec(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DVector, Matrix1x2, Matrix2, Vector1, Vector2, U1, U2};
+
+    use super::Augmented;
+    use crate::{differentiate_numerically, LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Direct measurements `$x_1 = 2$` and `$x_2 = 1$`.
+    struct Measurements {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U2, U2> for Measurements {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            Some(self.params - Vector2::new(2., 1.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            Some(Matrix2::identity())
+        }
+    }
+
+    /// The unit circle `$x_1^2 + x_2^2 = 1$`.
+    struct UnitCircle {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U1, U2> for UnitCircle {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U1>;
+        type JacobianStorage = Owned<f64, U1, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector1<f64>> {
+            Some(Vector1::new(self.params.norm_squared() - 1.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix1x2<f64>> {
+            Some(2. * self.params.transpose())
+        }
+    }
+
+    fn problem() -> (Measurements, UnitCircle) {
+        let params = Vector2::new(0.5, 0.5);
+        (Measurements { params }, UnitCircle { params })
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let (measurements, circle) = problem();
+        let multipliers = DVector::from_element(1, 0.3);
+        let mut problem = Augmented::new(measurements, circle, 10., multipliers);
+        problem.set_params(&Vector2::new(0.7, -1.2));
+        let numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(problem.jacobian().unwrap(), numerical, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn solution_on_unit_circle() {
+        let (measurements, circle) = problem();
+        // ftol would only locate the point along the circle to its square root
+        let (fit, report) = LevenbergMarquardt::new()
+            .with_ftol(0.)
+            .with_xtol(1e-12)
+            .minimize_constrained(measurements, circle, 1e-10);
+        assert!(!report.report.termination.was_failure());
+        assert!(report.outer_iterations > 1);
+        assert!(report.constraint_violation <= 1e-10);
+        let x = fit.params();
+        assert_relative_eq!(x.norm(), 1., epsilon = 1e-10);
+        // the closest point on the circle
+        let target = Vector2::new(2., 1.);
+        assert_relative_eq!(x, target / target.norm(), epsilon = 1e-8);
+        // x - (2, 1) + 2 lambda x = 0
+        assert_relative_eq!(
+            report.multipliers[0],
+            (target.norm() - 1.) / 2.,
+            epsilon = 1e-6
+        );
+    }
+}
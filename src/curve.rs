@@ -0,0 +1,401 @@
+//! Fitting a model function to data points.
+use nalgebra::{
+    allocator::{Allocator, Reallocator},
+    storage::Owned,
+    DVector, DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Dynamic, OMatrix, OVector,
+    RealField,
+};
+use num_traits::Float;
+
+use crate::lm::{LevenbergMarquardt, MinimizationReport};
+use crate::utils::epsmch;
+use crate::LeastSquaresProblem;
+
+/// Fit the model `$f(\vec{x}, t)$` to the data points `$(t_i, y_i)$`.
+///
+/// The residuals are `$r_i = f(\vec{x}, t_i) - y_i$`. Without a derivative,
+/// the partial derivatives `$\partial f/\partial x_j$` are approximated by forward
+/// differences of the model with the steps of
+/// [`NumericalDifferentiation`](struct.NumericalDifferentiation.html). Pass the
+/// gradient of the model with respect to the parameters to
+/// [`with_derivative`](#method.with_derivative) for an exact Jacobian.
+///
+/// This is a [`LeastSquaresProblem`](trait.LeastSquaresProblem.html) with `$m$`
+/// the number of data points, so it can be weighted and minimized like any
+/// other problem. [`curve_fit`](fn.curve_fit.html) covers the common case.
+pub struct CurveFit<F, N, Mo, D = ()>
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    x: OVector<F, N>,
+    t: DVector<F>,
+    y: DVector<F>,
+    model: Mo,
+    derivative: D,
+}
+
+impl<F, N, Mo> CurveFit<F, N, Mo>
+where
+    F: RealField + Copy,
+    N: Dim,
+    Mo: Fn(&OVector<F, N>, F) -> F,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Fit `model` to `data`, starting at the parameters `x0`.
+    pub fn new(x0: OVector<F, N>, data: &[(F, F)], model: Mo) -> Self {
+        Self {
+            x: x0,
+            t: DVector::from_iterator(data.len(), data.iter().map(|(t, _)| *t)),
+            y: DVector::from_iterator(data.len(), data.iter().map(|(_, y)| *y)),
+            model,
+            derivative: (),
+        }
+    }
+
+    /// Use the gradient `$\nabla_{\vec{x}} f(\vec{x}, t)$` for the Jacobian.
+    ///
+    /// Row `$i$` of the Jacobian is `derivative(x, t_i)`, it must have one
+    /// entry per parameter.
+    pub fn with_derivative<D>(self, derivative: D) -> CurveFit<F, N, Mo, D>
+    where
+        D: Fn(&OVector<F, N>, F) -> OVector<F, N>,
+    {
+        CurveFit {
+            x: self.x,
+            t: self.t,
+            y: self.y,
+            model: self.model,
+            derivative,
+        }
+    }
+}
+
+impl<F, N, Mo, D> CurveFit<F, N, Mo, D>
+where
+    F: RealField + Copy,
+    N: Dim,
+    Mo: Fn(&OVector<F, N>, F) -> F,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The model `$t \mapsto f(\vec{x}, t)$` at the current parameters.
+    ///
+    /// Use it to evaluate the fit at new inputs, for example after minimizing
+    /// the problem with
+    /// [`LevenbergMarquardt::minimize`](struct.LevenbergMarquardt.html#method.minimize).
+    pub fn into_predictor(self) -> impl Fn(F) -> F {
+        let Self { x, model, .. } = self;
+        move |t| model(&x, t)
+    }
+}
+
+impl<F, N, Mo, D> CurveFit<F, N, Mo, D>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    Mo: Fn(&OVector<F, N>, F) -> F,
+    D: ModelDerivative<F, N>,
+    Dynamic: DimMin<N> + DimMax<N>,
+    DefaultAllocator: Allocator<F, N>
+        + Allocator<F, Dynamic, N>
+        + Reallocator<F, Dynamic, N, DimMaximum<Dynamic, N>, N>
+        + Allocator<usize, N>,
+{
+    /// Minimize with `config`.
+    ///
+    /// Returns the final parameters, the report and the fitted model
+    /// `$t \mapsto f(ec{x}, t)$`, see [`into_predictor`](#method.into_predictor).
+    pub fn fit(
+        self,
+        config: &LevenbergMarquardt<F>,
+    ) -> (OVector<F, N>, MinimizationReport<F>, impl Fn(F) -> F) {
+        let (problem, report) = config.minimize(self);
+        (problem.x.clone(), report, problem.into_predictor())
+    }
+
+    /// Minimize with the standard deviations `sigmas` of the `$y_i$`.
+    ///
+    /// See [`LeastSquaresProblem::with_sigmas`](trait.LeastSquaresProblem.html#method.with_sigmas).
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one standard deviation per data point, or one of
+    /// them is not positive or not finite.
+    pub fn fit_with_sigmas(
+        self,
+        sigmas: &[F],
+        config: &LevenbergMarquardt<F>,
+    ) -> (OVector<F, N>, MinimizationReport<F>, impl Fn(F) -> F) {
+        assert_eq!(
+            sigmas.len(),
+            self.t.nrows(),
+            "sigmas must have one entry per data point"
+        );
+        let mut standard_deviations = self.y.clone();
+        standard_deviations.copy_from_slice(sigmas);
+        let problem = self.with_sigmas(standard_deviations);
+        let (problem, report) = config.minimize(problem);
+        let problem = problem.into_inner();
+        (problem.x.clone(), report, problem.into_predictor())
+    }
+}
+
+/// The gradient of the model, `()` for forward differences.
+pub trait ModelDerivative<F, N>
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Whether the gradient is approximated.
+    const NUMERICAL: bool;
+
+    /// The gradient at `x` and `t`, `None` to approximate it.
+    fn gradient(&self, x: &OVector<F, N>, t: F) -> Option<OVector<F, N>>;
+}
+
+impl<F, N> ModelDerivative<F, N> for ()
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    const NUMERICAL: bool = true;
+
+    fn gradient(&self, _x: &OVector<F, N>, _t: F) -> Option<OVector<F, N>> {
+        None
+    }
+}
+
+impl<F, N, D> ModelDerivative<F, N> for D
+where
+    F: RealField,
+    N: Dim,
+    D: Fn(&OVector<F, N>, F) -> OVector<F, N>,
+    DefaultAllocator: Allocator<F, N>,
+{
+    const NUMERICAL: bool = false;
+
+    fn gradient(&self, x: &OVector<F, N>, t: F) -> Option<OVector<F, N>> {
+        Some(self(x, t))
+    }
+}
+
+impl<F, N, Mo, D> LeastSquaresProblem<F, Dynamic, N> for CurveFit<F, N, Mo, D>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    Mo: Fn(&OVector<F, N>, F) -> F,
+    D: ModelDerivative<F, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, Dynamic, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, N>;
+    type ParameterStorage = Owned<F, N>;
+
+    fn set_params(&mut self, x: &OVector<F, N>) {
+        self.x.copy_from(x);
+    }
+
+    fn params(&self) -> OVector<F, N> {
+        self.x.clone()
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        Some(self.t.zip_map(&self.y, |t, y| (self.model)(&self.x, t) - y))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, Dynamic, N>> {
+        let n = self.x.shape_generic().0;
+        let mut jacobian = OMatrix::<F, Dynamic, N>::zeros_generic(Dynamic::new(self.t.nrows()), n);
+        let eps = Float::sqrt(epsmch::<F>());
+        let mut x = self.x.clone();
+        for (i, t) in self.t.iter().enumerate() {
+            if let Some(gradient) = self.derivative.gradient(&self.x, *t) {
+                if gradient.nrows() != n.value() {
+                    return None;
+                }
+                jacobian.row_mut(i).tr_copy_from(&gradient);
+                continue;
+            }
+            let f = (self.model)(&self.x, *t);
+            for j in 0..n.value() {
+                let x_j = x[j];
+                let h = eps * Float::abs(x_j);
+                let h = if h.is_zero() { eps } else { h };
+                x[j] = x_j + h;
+                jacobian[(i, j)] = ((self.model)(&x, *t) - f) / h;
+                x[j] = x_j;
+            }
+        }
+        Some(jacobian)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        D::NUMERICAL
+    }
+}
+
+/// Fit the model `$f(\vec{x}, t)$` to the data points `$(t_i, y_i)$`.
+///
+/// This minimizes `$\frac{1}{2}\sum_i \bigl(f(\vec{x}, t_i) - y_i\bigr)^2$`
+/// starting at `x0`, with a forward-difference Jacobian. Use
+/// [`CurveFit`](struct.CurveFit.html) for an analytic derivative or the
+/// standard deviations of the data.
+///
+/// Returns the final parameters, the report and the fitted model
+/// `$t \mapsto f(\vec{x}, t)$` to evaluate at new inputs.
+///
+/// # Example
+///
+/// Fit a sinusoid `$a\sin(\omega t + \varphi)$`:
+///
+/// ```
+/// # use levenberg_marquardt::{curve_fit, LevenbergMarquardt};
+/// # use nalgebra::Vector3;
+/// # use approx::assert_relative_eq;
+/// let data: Vec<(f64, f64)> = (0..50)
+///     .map(|i| 0.1 * i as f64)
+///     .map(|t| (t, 1.5 * f64::sin(2. * t + 0.3)))
+///     .collect();
+///
+/// let (x, report, predict) = curve_fit(
+///     Vector3::new(1., 1.8, 0.),
+///     &data,
+///     |x: &Vector3<f64>, t| x[0] * f64::sin(x[1] * t + x[2]),
+///     &LevenbergMarquardt::new(),
+/// );
+/// assert!(report.termination.was_successful());
+/// assert_relative_eq!(x, Vector3::new(1.5, 2., 0.3), epsilon = 1e-6);
+/// assert_relative_eq!(predict(10.), 1.5 * f64::sin(20.3), epsilon = 1e-5);
+/// ```
+pub fn curve_fit<F, N, Mo>(
+    x0: OVector<F, N>,
+    data: &[(F, F)],
+    model: Mo,
+    config: &LevenbergMarquardt<F>,
+) -> (OVector<F, N>, MinimizationReport<F>, impl Fn(F) -> F)
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    Mo: Fn(&OVector<F, N>, F) -> F,
+    Dynamic: DimMin<N> + DimMax<N>,
+    DefaultAllocator: Allocator<F, N>
+        + Allocator<F, Dynamic, N>
+        + Reallocator<F, Dynamic, N, DimMaximum<Dynamic, N>, N>
+        + Allocator<usize, N>,
+{
+    CurveFit::new(x0, data, model).fit(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::Vector3;
+
+    use super::{curve_fit, CurveFit};
+    use crate::{differentiate_numerically, LeastSquaresProblem, LevenbergMarquardt};
+
+    fn sinusoid(x: &Vector3<f64>, t: f64) -> f64 {
+        x[0] * f64::sin(x[1] * t + x[2])
+    }
+
+    fn sinusoid_gradient(x: &Vector3<f64>, t: f64) -> Vector3<f64> {
+        let [a, w, phi] = [x[0], x[1], x[2]];
+        let [s, c] = [f64::sin(w * t + phi), f64::cos(w * t + phi)];
+        Vector3::new(s, a * t * c, a * c)
+    }
+
+    /// Samples of `$1.5\sin(2t + 0.3)$` with a small deterministic disturbance.
+    fn samples() -> Vec<(f64, f64)> {
+        (0..60)
+            .map(|i| 0.1 * i as f64)
+            .map(|t| {
+                let disturbance = 1e-3 * f64::cos(17. * t);
+                (t, 1.5 * f64::sin(2. * t + 0.3) + disturbance)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let data = samples();
+        let x = Vector3::new(1.2, 1.7, -0.4);
+        let mut analytic = CurveFit::new(x, &data, sinusoid).with_derivative(sinusoid_gradient);
+        let numerical = differentiate_numerically(&mut analytic).unwrap();
+        let jacobian = analytic.jacobian().unwrap();
+        assert_relative_eq!(jacobian, numerical, epsilon = 1e-6);
+        let forward = CurveFit::new(x, &data, sinusoid).jacobian().unwrap();
+        assert_relative_eq!(jacobian, forward, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn recovers_sinusoid() {
+        let data = samples();
+        let config = LevenbergMarquardt::new();
+        let x0 = Vector3::new(1., 1.8, 0.);
+        let (x, report, _) = CurveFit::new(x0, &data, sinusoid)
+            .with_derivative(sinusoid_gradient)
+            .fit(&config);
+        assert!(report.termination.was_successful());
+        assert!(!report.jacobian_is_numerical);
+        assert_relative_eq!(x, Vector3::new(1.5, 2., 0.3), epsilon = 1e-3);
+
+        let (numerical, report, _) = curve_fit(x0, &data, sinusoid, &config);
+        assert!(report.termination.was_successful());
+        assert!(report.jacobian_is_numerical);
+        assert_relative_eq!(numerical, x, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn sigmas_weigh_data_points() {
+        let mut data = samples();
+        data[10].1 += 5.;
+        let mut sigmas = [0.01; 60];
+        sigmas[10] = 1e4;
+        let config = LevenbergMarquardt::new();
+        let x0 = Vector3::new(1., 1.8, 0.);
+        let (x, report, _) = CurveFit::new(x0, &data, sinusoid)
+            .with_derivative(sinusoid_gradient)
+            .fit_with_sigmas(&sigmas, &config);
+        assert!(report.termination.was_successful());
+        // the outlier is ignored
+        assert_relative_eq!(x, Vector3::new(1.5, 2., 0.3), epsilon = 1e-3);
+        let (unweighted, _, _) = curve_fit(x0, &data, sinusoid, &config);
+        assert!((unweighted - x).norm() > 1e-2);
+    }
+
+    #[test]
+    fn predictor_matches_fitted_values() {
+        let data = samples();
+        let (x, report, predict) = curve_fit(
+            Vector3::new(1., 1.8, 0.),
+            &data,
+            sinusoid,
+            &LevenbergMarquardt::new(),
+        );
+        assert!(report.termination.was_successful());
+        let residuals = CurveFit::new(x, &data, sinusoid).residuals().unwrap();
+        for ((t, y), r) in data.iter().zip(residuals.iter()) {
+            assert_relative_eq!(predict(*t), y + r, epsilon = 1e-12);
+        }
+        // the predictor of a manual fit
+        let (problem, _) = LevenbergMarquardt::new().minimize(CurveFit::new(
+            Vector3::new(1., 1.8, 0.),
+            &data,
+            sinusoid,
+        ));
+        let manual = problem.into_predictor();
+        assert_eq!(manual(7.25), predict(7.25));
+    }
+
+    #[test]
+    #[should_panic(expected = "sigmas must have one entry per data point")]
+    fn sigmas_must_match_data() {
+        let data = samples();
+        let _ = CurveFit::new(Vector3::new(1., 1.8, 0.), &data, sinusoid)
+            .fit_with_sigmas(&[1.; 3], &LevenbergMarquardt::new());
+    }
+}
@@ -0,0 +1,243 @@
+//! Least squares problems which report diagnostics with the residuals.
+use core::cell::RefCell;
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, Matrix, OVector, RealField, Vector};
+
+use crate::LeastSquaresProblem;
+
+/// A least squares problem whose residual computation yields extra data.
+///
+/// This is minimized with
+/// [`LevenbergMarquardt::minimize_with_diagnostics`](struct.LevenbergMarquardt.html#method.minimize_with_diagnostics),
+/// which returns the diagnostics of the evaluation at the final parameters.
+/// Use it for intermediate quantities of the model, for example which branch
+/// of a measurement model was taken, so they do not have to be recomputed
+/// after the minimization.
+///
+/// All evaluations of the minimization call
+/// [`residuals_with_diagnostics`](#tymethod.residuals_with_diagnostics),
+/// [`LeastSquaresProblem::residuals`](trait.LeastSquaresProblem.html#tymethod.residuals)
+/// is not used.
+pub trait DiagnosticResiduals<F, M, N>: LeastSquaresProblem<F, M, N>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+{
+    /// The data computed along with the residuals.
+    type Diagnostics;
+
+    /// Compute the residual vector and the diagnostics at the current parameters.
+    #[allow(clippy::type_complexity)]
+    fn residuals_with_diagnostics(
+        &self,
+    ) -> Option<(Vector<F, M, Self::ResidualStorage>, Self::Diagnostics)>;
+}
+
+/// The diagnostics together with the parameters they were computed at.
+type Evaluated<F, N, P, D> = Option<(Vector<F, N, P>, D)>;
+
+/// The problem which keeps the diagnostics of the current point.
+///
+/// The minimization evaluates a trial point and either keeps it or resets the
+/// parameters to the previously accepted point. The diagnostics of the trial
+/// point replace the accepted ones when the parameters move on to another
+/// point.
+pub(crate) struct DiagnosticEvaluation<F, M, N, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: DiagnosticResiduals<F, M, N>,
+{
+    problem: P,
+    accepted: Evaluated<F, N, P::ParameterStorage, P::Diagnostics>,
+    trial: RefCell<Evaluated<F, N, P::ParameterStorage, P::Diagnostics>>,
+}
+
+impl<F, M, N, P> DiagnosticEvaluation<F, M, N, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: DiagnosticResiduals<F, M, N>,
+{
+    pub(crate) fn new(problem: P) -> Self {
+        Self {
+            problem,
+            accepted: None,
+            trial: RefCell::new(None),
+        }
+    }
+
+    /// Extract the problem and the diagnostics at its parameters.
+    ///
+    /// The residuals are only computed again if no evaluation happened at the
+    /// final parameters, for example because they were set by a failed
+    /// minimization.
+    pub(crate) fn into_inner(self) -> (P, Option<P::Diagnostics>) {
+        let x = self.problem.params();
+        let diagnostics = [self.trial.into_inner(), self.accepted]
+            .into_iter()
+            .flatten()
+            .find(|(at, _)| *at == x)
+            .map(|(_, diagnostics)| diagnostics);
+        let diagnostics = diagnostics.or_else(|| {
+            self.problem
+                .residuals_with_diagnostics()
+                .map(|(_, diagnostics)| diagnostics)
+        });
+        (self.problem, diagnostics)
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, M, N> for DiagnosticEvaluation<F, M, N, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: DiagnosticResiduals<F, M, N>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        let trial = self.trial.get_mut();
+        if trial.as_ref().is_some_and(|(at, _)| at != x) {
+            // Returning to the accepted point rejects the trial point, moving
+            // on to a new point accepts it.
+            if self.accepted.as_ref().is_none_or(|(at, _)| at != x) {
+                self.accepted = trial.take();
+            } else {
+                *trial = None;
+            }
+        }
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        let (residuals, diagnostics) = self.problem.residuals_with_diagnostics()?;
+        *self.trial.borrow_mut() = Some((self.problem.params(), diagnostics));
+        Some(residuals)
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        self.problem.jacobian()
+    }
+
+    fn jacobian_with_residuals(
+        &self,
+        residuals: &Vector<F, M, Self::ResidualStorage>,
+    ) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        self.problem.jacobian_with_residuals(residuals)
+    }
+
+    fn hessian_vector_product(&self, v: &OVector<F, N>) -> Option<OVector<F, N>>
+    where
+        DefaultAllocator: Allocator<F, N>,
+    {
+        self.problem.hessian_vector_product(v)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+    use nalgebra::{storage::Owned, Matrix2, Vector2, U2};
+
+    use super::DiagnosticResiduals;
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// The Rosenbrock function, every evaluation is numbered.
+    struct Rosenbrock {
+        params: Vector2<f64>,
+        evaluated: RefCell<Vec<Vector2<f64>>>,
+    }
+
+    impl Rosenbrock {
+        fn new(x: f64, y: f64) -> Self {
+            Self {
+                params: Vector2::new(x, y),
+                evaluated: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl LeastSquaresProblem<f64, U2, U2> for Rosenbrock {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            let [x, y] = [self.params.x, self.params.y];
+            Some(Vector2::new(10. * (y - x * x), 1. - x))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            Some(Matrix2::new(-20. * self.params.x, 10., -1., 0.))
+        }
+    }
+
+    impl DiagnosticResiduals<f64, U2, U2> for Rosenbrock {
+        type Diagnostics = usize;
+
+        fn residuals_with_diagnostics(&self) -> Option<(Vector2<f64>, usize)> {
+            let mut evaluated = self.evaluated.borrow_mut();
+            evaluated.push(self.params);
+            Some((self.residuals()?, evaluated.len()))
+        }
+    }
+
+    #[test]
+    fn diagnostics_of_final_point() {
+        for start in [(-1.2, 1.), (3., -4.), (-10., 20.)] {
+            let (problem, report, diagnostics) = LevenbergMarquardt::new()
+                .minimize_with_diagnostics(Rosenbrock::new(start.0, start.1));
+            assert!(report.termination.was_successful());
+            let evaluated = problem.evaluated.into_inner();
+            assert_eq!(evaluated.len(), report.number_of_evaluations);
+            let diagnostics = diagnostics.unwrap();
+            assert_eq!(evaluated[diagnostics - 1], problem.params);
+        }
+    }
+
+    #[test]
+    fn diagnostics_after_rejected_steps() {
+        let mut rejected = 0;
+        for max_evaluations in 2..30 {
+            let (problem, report, diagnostics) = LevenbergMarquardt::new()
+                .with_max_evaluations(max_evaluations)
+                .minimize_with_diagnostics(Rosenbrock::new(-10., 20.));
+            let evaluated = problem.evaluated.into_inner();
+            assert_eq!(evaluated.len(), report.number_of_evaluations);
+            let diagnostics = diagnostics.unwrap();
+            assert_eq!(evaluated[diagnostics - 1], problem.params);
+            if diagnostics < evaluated.len() {
+                rejected += 1;
+            }
+        }
+        // Some minimizations stopped after rejected trial points.
+        assert!(rejected > 0);
+    }
+}
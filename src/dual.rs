@@ -0,0 +1,264 @@
+//! Least squares problems with a Jacobian from dual numbers.
+use alloc::vec::Vec;
+use nalgebra::{
+    allocator::Allocator, storage::Owned, Const, DefaultAllocator, Dim, OMatrix, OVector, RealField,
+};
+use num_dual::{Dual, DualNum, DualNumFloat};
+
+use crate::LeastSquaresProblem;
+
+/// A least squares problem whose residuals are generic over dual numbers.
+///
+/// Wrap it in an [`AutomaticDifferentiation`](struct.AutomaticDifferentiation.html)
+/// to minimize it. The residuals are computed with `D = F` and the Jacobian
+/// with forward-mode automatic differentiation: for column `$j$` the residuals
+/// are evaluated once with the dual numbers `$x_k + \delta_{jk}\varepsilon$`,
+/// the derivative parts are `$\partial r_i/\partial x_j$`. In contrast to
+/// finite differences the Jacobian is exact up to rounding and there is no
+/// step size to choose.
+///
+/// The dual numbers are the ones of the [num-dual](https://crates.io/crates/num-dual)
+/// crate, version 0.6. Write the residuals with the operators and the methods of
+/// [`DualNum`](https://docs.rs/num-dual/0.6/num_dual/trait.DualNum.html),
+/// constants are converted with `D::from`.
+///
+/// ```
+/// # use levenberg_marquardt::{AutomaticDifferentiation, DualResiduals, LevenbergMarquardt};
+/// # use nalgebra::{Vector2, U2};
+/// # use num_dual::DualNum;
+/// struct Circle {
+///     params: Vector2<f64>,
+/// }
+///
+/// impl DualResiduals<f64, U2, U2> for Circle {
+///     fn set_params(&mut self, x: &Vector2<f64>) {
+///         self.params.copy_from(x);
+///     }
+///
+///     fn params(&self) -> Vector2<f64> {
+///         self.params
+///     }
+///
+///     fn residuals<D: DualNum<f64>>(&self, x: &[D]) -> Option<Vec<D>> {
+///         Some(vec![x[0] * x[0] + x[1] * x[1] - 2., x[0].exp() - 1.])
+///     }
+/// }
+///
+/// let problem = AutomaticDifferentiation::new(Circle {
+///     params: Vector2::new(0.5, 2.),
+/// });
+/// let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+/// assert!(report.termination.was_successful());
+/// assert!(problem.into_inner().params.x.abs() < 1e-10);
+/// ```
+pub trait DualResiduals<F, M, N>
+where
+    F: RealField + DualNumFloat + DualNum<F>,
+    M: Dim,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Set the stored parameters `$\vec{x}$`.
+    fn set_params(&mut self, x: &OVector<F, N>);
+
+    /// Get the current parameter vector `$\vec{x}$`.
+    fn params(&self) -> OVector<F, N>;
+
+    /// Compute the `$m$` residuals at the parameters `x`.
+    ///
+    /// This must not depend on the stored parameters, the Jacobian passes
+    /// the dual numbers in `x`. Returning the wrong number of residuals for
+    /// a fixed `$m$` counts as a failed computation.
+    fn residuals<D: DualNum<F>>(&self, x: &[D]) -> Option<Vec<D>>;
+}
+
+/// A problem whose Jacobian is computed with dual numbers.
+///
+/// See [`DualResiduals`](trait.DualResiduals.html). One Jacobian costs `$n$`
+/// evaluations of the residuals with dual numbers.
+pub struct AutomaticDifferentiation<P> {
+    problem: P,
+}
+
+impl<P> AutomaticDifferentiation<P> {
+    pub fn new(problem: P) -> Self {
+        Self { problem }
+    }
+
+    /// Extract the wrapped problem.
+    pub fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, M, N> for AutomaticDifferentiation<P>
+where
+    F: RealField + DualNumFloat + DualNum<F>,
+    M: Dim,
+    N: Dim,
+    P: DualResiduals<F, M, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, M> + Allocator<F, M, N>,
+{
+    type ResidualStorage = Owned<F, M>;
+    type JacobianStorage = Owned<F, M, N>;
+    type ParameterStorage = Owned<F, N>;
+
+    fn set_params(&mut self, x: &OVector<F, N>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> OVector<F, N> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<OVector<F, M>> {
+        let residuals = self.problem.residuals(self.problem.params().as_slice())?;
+        let m = residual_dim::<M>(residuals.len())?;
+        Some(OVector::from_vec_generic(m, Const::<1>, residuals))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, M, N>> {
+        let x = self.problem.params();
+        let mut dual: Vec<Dual<F, F>> = x.iter().map(|x_j| Dual::from_re(*x_j)).collect();
+        let mut jacobian: Option<OMatrix<F, M, N>> = None;
+        for j in 0..x.nrows() {
+            dual[j] = dual[j].derive();
+            let residuals = self.problem.residuals(&dual)?;
+            dual[j] = Dual::from_re(x[j]);
+            let m = residual_dim::<M>(residuals.len())?;
+            let jacobian =
+                jacobian.get_or_insert_with(|| OMatrix::zeros_generic(m, x.shape_generic().0));
+            if residuals.len() != jacobian.nrows() {
+                return None;
+            }
+            for (i, r) in residuals.iter().enumerate() {
+                jacobian[(i, j)] = r.eps[0];
+            }
+        }
+        jacobian
+    }
+}
+
+/// The dimension for `m` residuals, `None` if a fixed dimension does not match.
+fn residual_dim<M: Dim>(m: usize) -> Option<M> {
+    match M::try_to_usize() {
+        Some(fixed) if fixed != m => None,
+        _ => Some(M::from_usize(m)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DVector, Dynamic, OMatrix, Vector3, U3};
+    use num_dual::DualNum;
+
+    use super::{AutomaticDifferentiation, DualResiduals};
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Fit `$y = a e^{-kt} \sin(\omega t)$` to a few samples.
+    #[derive(Clone)]
+    struct Oscillation {
+        t: DVector<f64>,
+        y: DVector<f64>,
+        params: Vector3<f64>,
+    }
+
+    impl Oscillation {
+        fn new() -> Self {
+            let t = DVector::from_fn(20, |i, _| 0.25 * i as f64);
+            let y =
+                t.map(|t| 2. * f64::exp(-0.4 * t) * f64::sin(1.3 * t) + 0.01 * f64::cos(7. * t));
+            Self {
+                t,
+                y,
+                params: Vector3::new(1., 0.2, 1.2),
+            }
+        }
+    }
+
+    impl DualResiduals<f64, Dynamic, U3> for Oscillation {
+        fn set_params(&mut self, x: &Vector3<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector3<f64> {
+            self.params
+        }
+
+        fn residuals<D: DualNum<f64>>(&self, x: &[D]) -> Option<Vec<D>> {
+            let [a, k, w] = [x[0], x[1], x[2]];
+            let model = |t: f64| a * (-k * t).exp() * (w * t).sin();
+            Some(
+                self.t
+                    .iter()
+                    .zip(&self.y)
+                    .map(|(t, y)| model(*t) - *y)
+                    .collect(),
+            )
+        }
+    }
+
+    /// The same fit with a hand-coded Jacobian.
+    struct Analytic(Oscillation);
+
+    impl LeastSquaresProblem<f64, Dynamic, U3> for Analytic {
+        type ParameterStorage = Owned<f64, U3>;
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, U3>;
+
+        fn set_params(&mut self, x: &Vector3<f64>) {
+            self.0.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector3<f64> {
+            self.0.params
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            let residuals = self.0.residuals(self.0.params.as_slice())?;
+            Some(DVector::from_vec(residuals))
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U3>> {
+            let [a, k, w] = [self.0.params.x, self.0.params.y, self.0.params.z];
+            let mut jacobian = OMatrix::<f64, Dynamic, U3>::zeros(self.0.t.len());
+            for (i, &t) in self.0.t.iter().enumerate() {
+                let e = f64::exp(-k * t);
+                let [s, c] = [f64::sin(w * t), f64::cos(w * t)];
+                jacobian[(i, 0)] = e * s;
+                jacobian[(i, 1)] = -a * t * e * s;
+                jacobian[(i, 2)] = a * t * e * c;
+            }
+            Some(jacobian)
+        }
+    }
+
+    #[test]
+    fn jacobian_matches_analytic() {
+        let mut problem = Oscillation::new();
+        problem.params = Vector3::new(1.7, 0.5, 1.1);
+        let analytic = Analytic(problem.clone()).jacobian().unwrap();
+        let dual = AutomaticDifferentiation::new(problem).jacobian().unwrap();
+        assert_relative_eq!(
+            dual,
+            analytic,
+            epsilon = 0.,
+            max_relative = 4. * f64::EPSILON
+        );
+    }
+
+    #[test]
+    fn fit_matches_analytic() {
+        let (dual, report) =
+            LevenbergMarquardt::new().minimize(AutomaticDifferentiation::new(Oscillation::new()));
+        assert!(report.termination.was_successful());
+        assert!(!report.jacobian_is_numerical);
+        let (analytic, expected) = LevenbergMarquardt::new().minimize(Analytic(Oscillation::new()));
+        assert!(expected.termination.was_successful());
+        assert_eq!(report.number_of_evaluations, expected.number_of_evaluations);
+        assert_relative_eq!(dual.params(), analytic.params(), epsilon = 1e-12);
+        assert_relative_eq!(dual.params().y, 0.4, epsilon = 1e-2);
+    }
+}
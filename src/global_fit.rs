@@ -0,0 +1,299 @@
+//! Global fits of several datasets with shared and local parameters.
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use nalgebra::{storage::Owned, DMatrix, DVector, Dim, Dynamic, RealField};
+
+use crate::{BlockJacobian, LeastSquaresProblem};
+
+/// A global fit of several datasets which share some of their parameters.
+///
+/// Every dataset is a problem of its own whose parameters are its local
+/// parameters followed by the shared parameters. The combined parameters are
+/// the local parameters of all datasets in the order they were added, followed
+/// by the shared parameters once:
+/// ```math
+///   \vec{x} = (\vec{l}_1, \ldots, \vec{l}_k, \vec{s}).
+/// ```
+/// The residuals are stacked like for a
+/// [`CompositeProblem`](struct.CompositeProblem.html). The local parameters of
+/// a dataset only enter its own residuals, so the Jacobian has the arrowhead
+/// structure of a [`BlockJacobian`](struct.BlockJacobian.html).
+/// [`LevenbergMarquardt::minimize_global_fit`](struct.LevenbergMarquardt.html#method.minimize_global_fit)
+/// solves the linear systems block by block with a Schur complement for the
+/// shared parameters, the cost grows linearly with the number of datasets.
+///
+/// The shared parameters are set on all datasets and read from the first one.
+///
+/// # Example
+///
+/// ```
+/// # use levenberg_marquardt::{CurveFit, GlobalFitProblem, LevenbergMarquardt};
+/// # use nalgebra::Vector2;
+/// // y = a exp(-b t) with an amplitude per dataset and a shared rate
+/// let model = |x: &Vector2<f64>, t: f64| x[0] * f64::exp(-x[1] * t);
+/// let first = [(0., 2.), (1., 2. * f64::exp(-0.5)), (2., 2. * f64::exp(-1.))];
+/// let second = [(0., 5.), (1., 5. * f64::exp(-0.5)), (2., 5. * f64::exp(-1.))];
+/// let problem = GlobalFitProblem::new(1)
+///     .with_dataset(CurveFit::new(Vector2::new(1., 1.), &first, model))
+///     .with_dataset(CurveFit::new(Vector2::new(1., 1.), &second, model));
+/// let (problem, report) = LevenbergMarquardt::new().minimize_global_fit(problem);
+/// assert!(report.termination.was_successful());
+/// let x = problem.global_params();
+/// assert!((x[0] - 2.).abs() < 1e-8 && (x[1] - 5.).abs() < 1e-8);
+/// assert!((x[2] - 0.5).abs() < 1e-8);
+/// ```
+pub struct GlobalFitProblem<F, M, N, P> {
+    /// The datasets and the number of their local parameters
+    datasets: Vec<(P, usize)>,
+    shared: usize,
+    marker: PhantomData<(F, M, N)>,
+}
+
+impl<F, M, N, P> GlobalFitProblem<F, M, N, P> {
+    /// Create a global fit with `shared` parameters common to all datasets.
+    pub fn new(shared: usize) -> Self {
+        Self {
+            datasets: Vec::new(),
+            shared,
+            marker: PhantomData,
+        }
+    }
+
+    /// The datasets in the order they were added.
+    pub fn datasets(&self) -> impl Iterator<Item = &P> + '_ {
+        self.datasets.iter().map(|(dataset, _)| dataset)
+    }
+
+    /// Extract the datasets.
+    pub fn into_inner(self) -> Vec<P> {
+        self.datasets
+            .into_iter()
+            .map(|(dataset, _)| dataset)
+            .collect()
+    }
+}
+
+impl<F, M, N, P> GlobalFitProblem<F, M, N, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+{
+    /// Append a dataset.
+    ///
+    /// Its parameters are its local parameters followed by the shared ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the dataset has fewer parameters than there are shared ones.
+    #[must_use]
+    pub fn with_dataset(mut self, dataset: P) -> Self {
+        let n = dataset.params().nrows();
+        assert!(
+            n >= self.shared,
+            "dataset must have at least the shared parameters"
+        );
+        self.datasets.push((dataset, n - self.shared));
+        self
+    }
+
+    /// The combined parameters `$(\vec{l}_1, \ldots, \vec{l}_k, \vec{s})$`.
+    ///
+    /// These are the [`params`](trait.LeastSquaresProblem.html#tymethod.params)
+    /// of the global fit.
+    pub fn global_params(&self) -> DVector<F> {
+        let mut x = Vec::new();
+        for (dataset, local) in &self.datasets {
+            x.extend(dataset.params().iter().take(*local).copied());
+        }
+        if let Some((dataset, local)) = self.datasets.first() {
+            x.extend(dataset.params().iter().skip(*local).copied());
+        }
+        DVector::from_vec(x)
+    }
+
+    /// The block structure of the Jacobian.
+    ///
+    /// The residuals of every dataset are evaluated to get their number.
+    /// Returns `None` if they could not be computed.
+    pub fn block_structure(&self) -> Option<BlockJacobian> {
+        let mut blocks = BlockJacobian::new(self.shared);
+        for (dataset, local) in &self.datasets {
+            blocks = blocks.with_block(dataset.residuals()?.nrows(), *local);
+        }
+        Some(blocks)
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, Dynamic, Dynamic> for GlobalFitProblem<F, M, N, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, Dynamic>;
+    type ParameterStorage = Owned<F, Dynamic>;
+
+    fn set_params(&mut self, x: &DVector<F>) {
+        let shared = x.rows(x.nrows() - self.shared, self.shared);
+        let mut col = 0;
+        for (dataset, local) in &mut self.datasets {
+            let mut params = dataset.params();
+            params.rows_mut(0, *local).copy_from(&x.rows(col, *local));
+            params.rows_mut(*local, shared.nrows()).copy_from(&shared);
+            dataset.set_params(&params);
+            col += *local;
+        }
+    }
+
+    fn params(&self) -> DVector<F> {
+        self.global_params()
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        let mut stacked = Vec::new();
+        for (dataset, _) in &self.datasets {
+            stacked.extend(dataset.residuals()?.iter().copied());
+        }
+        Some(DVector::from_vec(stacked))
+    }
+
+    fn jacobian(&self) -> Option<DMatrix<F>> {
+        let jacobians = self
+            .datasets
+            .iter()
+            .map(|(dataset, local)| Some((dataset.jacobian()?, *local)))
+            .collect::<Option<Vec<_>>>()?;
+        if jacobians
+            .iter()
+            .any(|(jacobian, local)| jacobian.ncols() != local + self.shared)
+        {
+            return None;
+        }
+        let m = jacobians.iter().map(|(jacobian, _)| jacobian.nrows()).sum();
+        let n_local: usize = jacobians.iter().map(|(_, local)| local).sum();
+        let mut stacked = DMatrix::zeros(m, n_local + self.shared);
+        let mut row = 0;
+        let mut col = 0;
+        for (jacobian, local) in &jacobians {
+            for i in 0..jacobian.nrows() {
+                for j in 0..*local {
+                    stacked[(row + i, col + j)] = jacobian[(i, j)];
+                }
+                for j in 0..self.shared {
+                    stacked[(row + i, n_local + j)] = jacobian[(i, local + j)];
+                }
+            }
+            row += jacobian.nrows();
+            col += *local;
+        }
+        Some(stacked)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.datasets
+            .iter()
+            .any(|(dataset, _)| dataset.jacobian_is_numerical())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{DVector, Vector2, Vector3};
+
+    use super::GlobalFitProblem;
+    use crate::{differentiate_numerically, CurveFit, LeastSquaresProblem, LevenbergMarquardt};
+
+    type Model = fn(&Vector2<f64>, f64) -> f64;
+
+    /// `$y = a\exp(-bt)$` with the local amplitude `$a$` and the shared rate `$b$`.
+    fn decay(x: &Vector2<f64>, t: f64) -> f64 {
+        x[0] * f64::exp(-x[1] * t)
+    }
+
+    type Data = Vec<(f64, f64)>;
+
+    fn spectra() -> (Data, Data) {
+        let noise = [0.01, -0.02, 0.015, 0., -0.01, 0.02];
+        let sample = |a: f64, shift: f64| -> Data {
+            (0..6)
+                .map(|i| {
+                    let t = 0.4 * i as f64 + shift;
+                    (t, a * f64::exp(-0.7 * t) + noise[i])
+                })
+                .collect()
+        };
+        (sample(2., 0.), sample(3.5, 0.2))
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let (first, second) = spectra();
+        let model: Model = decay;
+        let mut problem = GlobalFitProblem::new(1)
+            .with_dataset(CurveFit::new(Vector2::new(1., 1.), &first, model))
+            .with_dataset(CurveFit::new(Vector2::new(1., 1.), &second, model));
+        problem.set_params(&DVector::from_column_slice(&[1.5, 3., 0.6]));
+        let numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(problem.jacobian().unwrap(), numerical, epsilon = 1e-6);
+        for dataset in problem.datasets() {
+            assert_eq!(dataset.params().y, 0.6);
+        }
+    }
+
+    #[test]
+    fn matches_dense_joint_fit() {
+        let (first, second) = spectra();
+        let model: Model = decay;
+        let problem = || {
+            GlobalFitProblem::new(1)
+                .with_dataset(CurveFit::new(Vector2::new(1., 1.), &first, model))
+                .with_dataset(CurveFit::new(Vector2::new(1., 1.), &second, model))
+        };
+        let (global, report) = LevenbergMarquardt::new().minimize_global_fit(problem());
+        assert!(report.termination.was_successful());
+        assert_eq!(report.number_of_parameters, 3);
+
+        // the naive fit with one model for both datasets and a dense Jacobian
+        let joint: Data = first
+            .iter()
+            .map(|&(t, y)| (t, y))
+            .chain(second.iter().map(|&(t, y)| (t + 100., y)))
+            .collect();
+        let joint_model = |x: &Vector3<f64>, t: f64| {
+            if t < 50. {
+                x[0] * f64::exp(-x[2] * t)
+            } else {
+                x[1] * f64::exp(-x[2] * (t - 100.))
+            }
+        };
+        let (dense, expected) = LevenbergMarquardt::new().minimize(CurveFit::new(
+            Vector3::new(1., 1., 1.),
+            &joint,
+            joint_model,
+        ));
+        assert!(expected.termination.was_successful());
+        assert_relative_eq!(
+            global.global_params(),
+            DVector::from_column_slice(dense.params().as_slice()),
+            epsilon = 1e-8
+        );
+        assert_relative_eq!(
+            report.objective_function,
+            expected.objective_function,
+            epsilon = 1e-12
+        );
+        // the same problem with the dense QR decomposition
+        let (_, dense_report) = LevenbergMarquardt::new().minimize(problem());
+        assert_relative_eq!(
+            report.objective_function,
+            dense_report.objective_function,
+            epsilon = 1e-12
+        );
+    }
+}
@@ -109,25 +109,97 @@
 //! in a unit test to verify that your Jacobian implementation matches the residuals.
 #![no_std]
 #![cfg_attr(feature = "RUSTC_IS_NIGHTLY", core_intrinsics)]
+// the tests keep the constants and bound checks of the MINPACK translations
+#![cfg_attr(
+    test,
+    allow(clippy::legacy_numeric_constants, clippy::manual_range_contains)
+)]
 
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+#[cfg(feature = "approx")]
+mod approx_eq;
+mod block;
+mod complex;
+mod composite;
+mod constrained;
+mod curve;
+mod diagnostics;
+#[cfg(feature = "num-dual")]
+mod dual;
+mod global_fit;
 mod lm;
+mod manifold;
+mod matrix_free;
+mod mixed;
+mod norm;
+mod numerical;
+mod observer;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod penalty;
 mod problem;
+mod profile;
 mod qr;
+mod refine;
+#[cfg(feature = "std")]
+mod shared;
+mod slice;
+mod stochastic;
+mod transform;
 mod trust_region;
 pub(crate) mod utils;
+mod weights;
 
-pub use lm::TerminationReason;
+pub use block::BlockJacobian;
+pub use complex::ComplexLeastSquaresProblem;
+pub use composite::CompositeProblem;
+pub use constrained::ConstrainedReport;
+pub use curve::{curve_fit, CurveFit};
+pub use diagnostics::DiagnosticResiduals;
+#[cfg(feature = "num-dual")]
+pub use dual::{AutomaticDifferentiation, DualResiduals};
+pub use global_fit::GlobalFitProblem;
+pub use lm::{
+    BoundSide, ConfigOverride, ConvergenceCertificate, CrossDirection, DiagUpdate, GtolReference,
+    Iterations, LineSearch, LmCheckpoint, TerminationReason, LM,
+};
+pub use manifold::ManifoldLeastSquaresProblem;
+pub use matrix_free::{JacobianOperator, MatrixFreeProblem};
+pub use mixed::MixedPrecision;
+pub use norm::{InfinityNorm, Norm};
+pub use numerical::{least_squares_numeric, NumericalDifferentiation, RelativeStep};
+pub use observer::{LMState, Observer, Trajectory};
+#[cfg(feature = "rayon")]
+pub use parallel::ParallelResiduals;
+pub use penalty::LinearPenalty;
 pub use problem::LeastSquaresProblem;
+pub use profile::profile;
+pub use refine::RefineReport;
+#[cfg(feature = "std")]
+pub use shared::SharedBest;
+pub use slice::{SliceLeastSquaresProblem, SliceParameters};
+pub use stochastic::StochasticProblem;
+pub use transform::ParamTransform;
+pub use trust_region::{
+    Dogleg, Levenberg, TrustRegionModel, TrustRegionStep, TrustRegionSubproblem,
+};
+pub use weights::Weighted;
 
-pub use utils::{differentiate_holomorphic_numerically, differentiate_numerically};
+pub use utils::{
+    differentiate_holomorphic_numerically, differentiate_numerically,
+    differentiate_numerically_one_sided, evaluate_jacobian, finite_difference_error,
+    objective_gradient,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature="minpack-compat")] {
         pub type LevenbergMarquardt = lm::LevenbergMarquardt<f64>;
         pub type MinimizationReport = lm::MinimizationReport<f64>;
+        pub type LmError = lm::LmError<f64>;
     } else {
-        pub use lm::{LevenbergMarquardt, MinimizationReport};
+        pub use lm::{LevenbergMarquardt, LmError, MinimizationReport};
     }
 }
@@ -1,13 +1,36 @@
-use crate::qr::{LinearLeastSquaresDiagonalProblem, PivotedQR};
-use crate::trust_region::{determine_lambda_and_parameter_update, LMParameter};
-use crate::utils::{enorm, epsmch};
+use crate::block::{BlockJacobian, BlockLeastSquaresDiagonalProblem};
+use crate::complex::{ComplexLeastSquaresProblem, RealEmbedding};
+use crate::constrained::{dvector, Augmented, ConstrainedReport};
+use crate::diagnostics::{DiagnosticEvaluation, DiagnosticResiduals};
+use crate::global_fit::GlobalFitProblem;
+use crate::manifold::{ManifoldLeastSquaresProblem, TangentSpace};
+use crate::matrix_free::{MatrixFree, MatrixFreeProblem};
+use crate::norm::Norm;
+use crate::observer::{LMState, Observer};
+#[cfg(feature = "rayon")]
+use crate::parallel::{ParallelEvaluation, ParallelResiduals};
+use crate::qr::{IncrementalQR, PivotedQR};
+use crate::refine::{Borrowed, RefineReport};
+use crate::stochastic::{Minibatch, StochasticProblem};
+use crate::trust_region::{LMParameter, Linearization, TrustRegionModel, TrustRegionSubproblem};
+use crate::utils::{enorm, epsmch, SplitMix64};
+use crate::weights::Scaled;
 use crate::LeastSquaresProblem;
+use alloc::{boxed::Box, collections::VecDeque, vec::Vec};
+use core::{fmt, ops::Range, time::Duration};
 use nalgebra::{
     allocator::{Allocator, Reallocator},
-    convert, DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Matrix, OVector, RealField, Vector,
+    convert,
+    storage::{Owned, Storage},
+    Complex, DMatrix, DVector, DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Dynamic, Matrix,
+    OVector, RealField, Vector,
 };
 use num_traits::Float;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 
+#[cfg(test)]
+mod test_blocks;
 #[cfg(test)]
 #[allow(
     clippy::float_cmp,
@@ -23,7 +46,7 @@ mod test_init_step;
 #[allow(clippy::float_cmp, clippy::clone_on_copy, clippy::redundant_clone)]
 mod test_update_diag;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 /// Reasons for terminating the minimization.
 pub enum TerminationReason {
     /// The residual or Jacobian computation was not successful, it returned `None`.
@@ -36,6 +59,11 @@ pub enum TerminationReason {
     ///
     /// This is the `gtol` termination criterion.
     Orthogonal,
+    /// All entries of the Jacobian are zero.
+    ///
+    /// The model is locally constant, so there is no direction to move in.
+    /// This often means that the Jacobian is computed incorrectly.
+    NoGradientInformation,
     /// The `ftol` or `xtol` criterion was fulfilled.
     Converged { ftol: bool, xtol: bool },
     /// The bound for `ftol`, `xtol` or `gtol` was set so low that the
@@ -44,11 +72,61 @@ pub enum TerminationReason {
     NoImprovementPossible(&'static str),
     /// Maximum number of function evaluations was hit.
     LostPatience,
+    /// The budget set with
+    /// [`with_max_evaluations`](struct.LevenbergMarquardt.html#method.with_max_evaluations)
+    /// was used up.
+    MaxEvaluations,
+    /// The time budget set with
+    /// [`with_evaluation_time_budget`](struct.LevenbergMarquardt.html#method.with_evaluation_time_budget)
+    /// was used up.
+    EvaluationTimeBudget,
+    /// The number of iterations set with
+    /// [`with_max_iterations`](struct.LevenbergMarquardt.html#method.with_max_iterations)
+    /// was reached.
+    MaxIterations,
+    /// A parameter crossed the value set with
+    /// [`with_parameter_target`](struct.LevenbergMarquardt.html#method.with_parameter_target).
+    ParameterTargetReached,
+    /// The objective function decreased too little over the window set with
+    /// [`with_stall_window`](struct.LevenbergMarquardt.html#method.with_stall_window).
+    NoImprovement,
+    /// The scaled gradient changed too little over the window set with
+    /// [`with_gradient_plateau`](struct.LevenbergMarquardt.html#method.with_gradient_plateau).
+    GradientPlateau,
+    /// The accepted parameters alternated between two points, see
+    /// [`with_cycle_detection`](struct.LevenbergMarquardt.html#method.with_cycle_detection).
+    Cycling,
+    /// The trial steps kept increasing the objective function, see
+    /// [`with_divergence_detection`](struct.LevenbergMarquardt.html#method.with_divergence_detection).
+    ///
+    /// This usually means that the Jacobian is computed incorrectly.
+    Diverged,
+    /// Rejected steps shrank the trust-region radius `$\Delta$` to
+    /// `$\max(\mathtt{xtol}, \varepsilon)\|\mathbf{D}\vec{x}\|$` although
+    /// the scaled gradient of the [`gtol`](struct.LevenbergMarquardt.html#method.with_gtol)
+    /// test is still larger than `$\varepsilon^{1/4}$`.
+    ///
+    /// The linear model predicts a reduction which the residuals do not
+    /// show even for the shortest steps. Without this test the minimization
+    /// would end with a `xtol` convergence at a point which is not a
+    /// minimizer. This usually means that the Jacobian is inaccurate or that
+    /// the residuals are noisy.
+    TrustRegionCollapsed,
+    /// The residuals at the initial guess contain `NaN` or `$\pm\infty$`.
+    ///
+    /// No step was tried, the initial parameters are outside of the domain
+    /// of the residuals. Choose a different initial guess.
+    InvalidInitialPoint,
     /// The number of parameters `$n$` is zero.
     NoParameters,
     /// The number of residuals `$m$` is zero.
     NoResiduals,
     /// The shape of the computed residuals or Jacobian is not correct.
+    ///
+    /// The number of residuals `$m$` is fixed by the first evaluation. Every
+    /// later evaluation must return `$m$` residuals and a Jacobian with `$m$`
+    /// rows, even for a dynamic `$m$`. The parameters are reset to the last
+    /// accepted point.
     WrongDimensions(&'static str),
 }
 
@@ -70,19 +148,241 @@ impl TerminationReason {
 
     /// A fundamental assumptions was not met.
     ///
-    /// For example if the number of residuals changed.
+    /// For example if the number of residuals changed or the initial guess
+    /// is invalid.
     pub fn was_usage_issue(&self) -> bool {
         matches!(
             self,
-            TerminationReason::NoParameters
+            TerminationReason::InvalidInitialPoint
+                | TerminationReason::NoParameters
                 | TerminationReason::NoResiduals
                 | TerminationReason::NoImprovementPossible(_)
                 | TerminationReason::WrongDimensions(_)
         )
     }
+
+    /// The minimization broke down.
+    ///
+    /// This is the case if the residuals or the Jacobian could not be computed,
+    /// contained `NaN` or `$\pm\infty$`, had the wrong dimensions or if the
+    /// minimization diverged or its trust region collapsed. Running out of evaluations or a too small
+    /// tolerance is not considered a failure.
+    pub fn was_failure(&self) -> bool {
+        matches!(
+            self,
+            TerminationReason::User(_)
+                | TerminationReason::Numerical(_)
+                | TerminationReason::InvalidInitialPoint
+                | TerminationReason::NoParameters
+                | TerminationReason::NoResiduals
+                | TerminationReason::WrongDimensions(_)
+                | TerminationReason::Diverged
+                | TerminationReason::TrustRegionCollapsed
+        )
+    }
+}
+
+/// Run `evaluate` and add the time it took to `elapsed`.
+///
+/// Without the `std` feature there is no clock and `elapsed` is unchanged.
+fn timed<R>(elapsed: &mut Duration, evaluate: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "std")]
+    {
+        let start = std::time::Instant::now();
+        let result = evaluate();
+        *elapsed += start.elapsed();
+        result
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = elapsed;
+        evaluate()
+    }
+}
+
+/// Strategy for updating the scaling diagonal `$\mathbf{D}$` after the first iteration.
+///
+/// The diagonal is always initialized from the column norms of the first Jacobian.
+/// This only has an effect if the variables are rescaled internally (see
+/// [`with_scale_diag`](struct.LevenbergMarquardt.html#method.with_scale_diag)).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiagUpdate {
+    /// Use the maximum of the previous entry and the current column norm.
+    ///
+    /// This is what MINPACK does, the entries can never decrease.
+    MinpackMonotone,
+    /// Recompute the entries from the current column norms in every iteration.
+    ///
+    /// The entries can shrink if the column norms of the Jacobian decrease.
+    Recompute,
+    /// Keep the entries computed in the first iteration.
+    Fixed,
+}
+
+/// The direction in which a parameter must cross its target.
+///
+/// See [`with_parameter_target`](struct.LevenbergMarquardt.html#method.with_parameter_target).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CrossDirection {
+    /// The parameter increases to the target or above.
+    Upward,
+    /// The parameter decreases to the target or below.
+    Downward,
+    /// The parameter reaches the target from either side.
+    Either,
+}
+
+impl CrossDirection {
+    /// Whether a parameter moving from `before` to `after` crosses `target`.
+    fn crossed<F: RealField>(self, before: F, after: F, target: F) -> bool {
+        let upward = before < target && after >= target;
+        let downward = before > target && after <= target;
+        match self {
+            CrossDirection::Upward => upward,
+            CrossDirection::Downward => downward,
+            CrossDirection::Either => upward || downward,
+        }
+    }
+}
+
+/// The line search along a rejected trust-region step.
+///
+/// See [`with_line_search`](struct.LevenbergMarquardt.html#method.with_line_search).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LineSearch<F> {
+    /// Only shrink the trust region, as in MINPACK.
+    None,
+    /// Backtrack until the Armijo condition holds.
+    ///
+    /// The step length `$\alpha$` starts at `rho` and is multiplied by `rho`
+    /// until
+    /// ```math
+    ///   f(\vec{x} - \alpha\vec{p}) \leq f(\vec{x}) - c\,\alpha\,\vec{p}^\top\mathbf{J}^\top\vec{r}.
+    /// ```
+    Backtracking { c: F, rho: F },
+}
+
+/// The norm the gradient is compared with in the `gtol` test.
+///
+/// See [`with_gtol_reference`](struct.LevenbergMarquardt.html#method.with_gtol_reference).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GtolReference<F> {
+    /// Divide by the norm of the current residuals `$\|\vec{r}\|$`.
+    ///
+    /// The test compares cosines, this is what MINPACK does.
+    ResidualNorm,
+    /// Divide by a fixed norm.
+    Fixed(F),
+}
+
+/// Settings which replace the ones of a configuration for a single call.
+///
+/// Every field which is `Some` is applied with the corresponding builder method
+/// of [`LevenbergMarquardt`](struct.LevenbergMarquardt.html), the other settings
+/// are kept. See
+/// [`minimize_with_override`](struct.LevenbergMarquardt.html#method.minimize_with_override).
+///
+/// ```
+/// # use levenberg_marquardt::{ConfigOverride, LevenbergMarquardt};
+/// let base = LevenbergMarquardt::new().with_patience(200);
+/// let tight = ConfigOverride {
+///     gtol: Some(0.),
+///     ..Default::default()
+/// };
+/// assert_eq!(base.with_override(&tight), base.with_gtol(0.));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ConfigOverride<F> {
+    /// See [`with_ftol`](struct.LevenbergMarquardt.html#method.with_ftol).
+    pub ftol: Option<F>,
+    /// See [`with_xtol`](struct.LevenbergMarquardt.html#method.with_xtol).
+    pub xtol: Option<F>,
+    /// See [`with_gtol`](struct.LevenbergMarquardt.html#method.with_gtol).
+    pub gtol: Option<F>,
+    /// See [`with_stepbound`](struct.LevenbergMarquardt.html#method.with_stepbound).
+    pub stepbound: Option<F>,
+    /// See [`with_patience`](struct.LevenbergMarquardt.html#method.with_patience).
+    pub patience: Option<usize>,
+    /// See [`with_max_evaluations`](struct.LevenbergMarquardt.html#method.with_max_evaluations).
+    pub max_evaluations: Option<usize>,
+    /// See [`with_max_iterations`](struct.LevenbergMarquardt.html#method.with_max_iterations).
+    pub max_iterations: Option<usize>,
+}
+
+/// The side of a bound a parameter is sitting at.
+///
+/// See [`MinimizationReport::active_bounds`](struct.MinimizationReport.html#structfield.active_bounds).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoundSide {
+    /// The parameter is at `$-\mathtt{limit}$`.
+    Lower,
+    /// The parameter is at `$\mathtt{limit}$`.
+    Upper,
+}
+
+/// The final values of the convergence tests together with their tolerances.
+///
+/// See [`MinimizationReport::certificate`](struct.MinimizationReport.html#structfield.certificate).
+/// A value is `None` if its test was never performed, a test with a tolerance
+/// of zero is disabled and never passes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConvergenceCertificate<F> {
+    /// The scaled gradient norm of the last `gtol` test, see
+    /// [`MinimizationReport::gradient_norm`](struct.MinimizationReport.html#structfield.gradient_norm).
+    pub gradient_norm: Option<F>,
+    /// See [`with_gtol`](struct.LevenbergMarquardt.html#method.with_gtol).
+    pub gtol: F,
+    /// Whether `$\mathtt{gradient\_norm} \leq \mathtt{gtol}$` held.
+    pub gradient_passed: bool,
+    /// The relative step `$\Delta / \|\mathbf{D}\vec{x}\|$` of the last `xtol` test.
+    ///
+    /// With [`with_xtol_componentwise`](struct.LevenbergMarquardt.html#method.with_xtol_componentwise),
+    /// [`with_typical_magnitudes`](struct.LevenbergMarquardt.html#method.with_typical_magnitudes)
+    /// or a custom step norm the test compares other quantities, but
+    /// `step_passed` is still its outcome.
+    pub step_norm: Option<F>,
+    /// See [`with_xtol`](struct.LevenbergMarquardt.html#method.with_xtol).
+    pub xtol: F,
+    /// Whether the `xtol` test passed.
+    pub step_passed: bool,
+    /// The relative reduction `$|f(\vec{x}) - f(\vec{x} - \vec{p})| / f(\vec{x})$`
+    /// of the objective function in the last `ftol` test.
+    ///
+    /// The test additionally requires the predicted reduction to be below
+    /// `ftol`, so this can be below `ftol` without the test passing.
+    pub objective_change: Option<F>,
+    /// See [`with_ftol`](struct.LevenbergMarquardt.html#method.with_ftol).
+    pub ftol: F,
+    /// Whether the `ftol` test passed.
+    pub objective_passed: bool,
+}
+
+impl<F: RealField + Copy> ConvergenceCertificate<F> {
+    fn untested(config: &LevenbergMarquardt<F>) -> Self {
+        Self {
+            gradient_norm: None,
+            gtol: config.gtol,
+            gradient_passed: false,
+            step_norm: None,
+            xtol: config.xtol,
+            step_passed: false,
+            objective_change: None,
+            ftol: config.ftol,
+            objective_passed: false,
+        }
+    }
+
+    /// Whether one of the tests passed.
+    ///
+    /// This is the case if the minimization terminated with
+    /// [`TerminationReason::Orthogonal`](enum.TerminationReason.html#variant.Orthogonal)
+    /// or [`TerminationReason::Converged`](enum.TerminationReason.html#variant.Converged).
+    pub fn passed(&self) -> bool {
+        self.gradient_passed || self.step_passed || self.objective_passed
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 /// Information about the minimization.
 ///
 /// Use this to inspect the minimization process. Most importantly
@@ -91,23 +391,563 @@ pub struct MinimizationReport<F: RealField> {
     pub termination: TerminationReason,
     /// Number of residuals which were computed.
     pub number_of_evaluations: usize,
-    /// Contains the value of `$f(\vec{x})$`.
+    /// The number `$m$` of residuals, zero if they could not be computed.
+    pub number_of_residuals: usize,
+    /// The number `$n$` of parameters.
+    pub number_of_parameters: usize,
+    /// Contains the value of `$f(\vec{x}) = \frac{1}{2}\|\vec{r}(\vec{x})\|^2$`.
+    ///
+    /// Note the factor `$\frac{1}{2}$`, other tools often report the plain
+    /// sum of squares `$\|\vec{r}\|^2$` instead. Compare their values with
+    /// [`sum_of_squares`](#method.sum_of_squares).
+    ///
+    /// The minimization itself only works with `$\|\vec{r}\|$`, which is
+    /// computed with the overflow-safe accumulation of MINPACK's `enorm`.
+    /// So this may be infinite for enormous residuals while the
+    /// minimization is not affected. With the `minpack-compat` feature the
+    /// norm uses the thresholds of MINPACK and agrees with it bit for bit.
+    /// Otherwise the thresholds are derived from the range of `F`, which can
+    /// change the last bits if components are tiny or huge.
     pub objective_function: F,
+    /// The objective function `$f(\vec{x}_0)$` at the initial parameters.
+    ///
+    /// `NaN` if the residuals could not be computed there.
+    pub initial_objective: F,
+    /// The scaled norm `$\|\mathbf{D}\vec{p}\|$` of the last accepted step.
+    ///
+    /// The `xtol` criterion compares the trust-region radius, which bounds
+    /// this norm, against `$\mathtt{xtol}\cdot\|\mathbf{D}\vec{x}\|$`.
+    /// Zero if no step was accepted.
+    pub final_step_norm: F,
+    /// The iteration in which the minimization terminated.
+    ///
+    /// Every iteration computes the Jacobian once, so this is the number of
+    /// Jacobian evaluations unless
+    /// [`with_constant_jacobian`](struct.LevenbergMarquardt.html#method.with_constant_jacobian)
+    /// is enabled or a constant Jacobian was detected with
+    /// [`with_detect_constant_jacobian`](struct.LevenbergMarquardt.html#method.with_detect_constant_jacobian). Zero if it terminated at the initial guess.
+    pub terminated_at_iteration: usize,
+    /// How often the trust-region radius `$\Delta$` was reduced because a
+    /// step did not decrease the objective function as predicted.
+    ///
+    /// Many reductions in the first iterations indicate that the initial
+    /// trust region is too large, see
+    /// [`with_stepbound`](struct.LevenbergMarquardt.html#method.with_stepbound).
+    pub trust_region_reductions: usize,
+    /// How often the QR factorization of the previous Jacobian was updated
+    /// row by row instead of factorizing the new one, see
+    /// [`LeastSquaresProblem::changed_jacobian_rows`](trait.LeastSquaresProblem.html#method.changed_jacobian_rows).
+    pub incremental_factorizations: usize,
+    /// Whether the Jacobian was approximated numerically.
+    ///
+    /// Take this into account when you derive uncertainties from the
+    /// Jacobian, they carry the error of the finite differences.
+    /// See [`LeastSquaresProblem::jacobian_is_numerical`](trait.LeastSquaresProblem.html#method.jacobian_is_numerical).
+    pub jacobian_is_numerical: bool,
+    /// The ratio which the `gtol` test compares against `gtol`.
+    ///
+    /// This is the largest cosine
+    /// ```math
+    ///   \max_j \frac{|\vec{j}_j^\top\vec{r}|}{\|\vec{j}_j\|\,\|\vec{r}\|}
+    /// ```
+    /// of the angle between a column `$\vec{j}_j$` of the Jacobian and the
+    /// residuals at the last Jacobian, the test fires once it is at most
+    /// `gtol`, see [`with_gtol`](struct.LevenbergMarquardt.html#method.with_gtol).
+    /// `None` if no Jacobian was computed.
+    pub gradient_norm: Option<F>,
+    /// The Levenberg-Marquardt parameter `$\lambda$` of the last trust-region step.
+    ///
+    /// `None` if no trust-region subproblem was solved.
+    pub lambda: Option<F>,
+    /// The relative changes `$|x_i - x_i'| / |x_i|$` of the parameters in
+    /// the last accepted step from `$\vec{x}'$` to the solution `$\vec{x}$`.
+    ///
+    /// Only reported if enabled with
+    /// [`with_qr_retention`](struct.LevenbergMarquardt.html#method.with_qr_retention).
+    /// Infinite for a parameter which moved to zero. `None` if no step was
+    /// accepted.
+    pub relative_step: Option<DVector<F>>,
+    /// The covariance matrix of the parameters at the solution.
+    ///
+    /// Only computed if enabled with
+    /// [`with_covariance`](struct.LevenbergMarquardt.html#method.with_covariance).
+    /// It is estimated as
+    /// ```math
+    ///   \mathbf{C} = s^2\bigl(\mathbf{J}^\top\mathbf{J}\bigr)^{-1}
+    ///   \quad\text{with}\quad s^2 = \frac{\|\vec{r}\|^2}{m - n}.
+    /// ```
+    /// The factor `$s^2$` is the [`residual_variance`](#method.residual_variance).
+    /// `None` if `$m \leq n$`, the Jacobian could not be computed or
+    /// `$\mathbf{J}^\top\mathbf{J}$` is singular.
+    pub covariance: Option<DMatrix<F>>,
+    /// The upper triangular factor `$\mathbf{R}$` of the pivoted QR
+    /// decomposition of the Jacobian at the solution.
+    ///
+    /// Only computed if enabled with
+    /// [`with_qr_retention`](struct.LevenbergMarquardt.html#method.with_qr_retention).
+    /// The columns are in pivoted order, `$\mathbf{J}\mathbf{P} = \mathbf{Q}\mathbf{R}$`.
+    /// Column `$j$` of `$\mathbf{R}$` belongs to parameter `qr_permutation[j]`,
+    /// so for example
+    /// ```math
+    ///   (\mathbf{J}^\top\mathbf{J})_{p_i p_j} = (\mathbf{R}^\top\mathbf{R})_{ij}.
+    /// ```
+    /// It has `$\min\{m, n\}$` rows. `None` if the Jacobian could not be computed.
+    pub qr_r_factor: Option<DMatrix<F>>,
+    /// The pivoting permutation `$\vec{p}$` which belongs to
+    /// [`qr_r_factor`](#structfield.qr_r_factor).
+    pub qr_permutation: Option<Vec<usize>>,
+    /// The first `$\min\{m, n\}$` entries of `$\mathbf{Q}^\top\vec{r}$` for
+    /// the residuals `$\vec{r}$` at the solution.
+    ///
+    /// Computed together with [`qr_r_factor`](#structfield.qr_r_factor).
+    pub qr_qt_residuals: Option<DVector<F>>,
+    /// The leverages `$h_i$` of the residuals at the solution, the diagonal of
+    /// the hat matrix `$\mathbf{J}(\mathbf{J}^\top\mathbf{J})^{-1}\mathbf{J}^\top$`.
+    ///
+    /// A leverage close to one means that the fit follows this residual
+    /// closely, whatever its measurement. Computed together with
+    /// [`qr_r_factor`](#structfield.qr_r_factor) from the rows `$\vec{j}_i$` of
+    /// the Jacobian as `$h_i = \|\mathbf{R}^{-\top}\mathbf{P}^\top\vec{j}_i\|^2$`.
+    /// There is one entry per residual except for the trailing
+    /// [penalty residuals](trait.LeastSquaresProblem.html#method.number_of_penalty_residuals),
+    /// which are part of `$\mathbf{J}$` in the hat matrix. `None` if `$m < n$`
+    /// or `$\mathbf{R}$` is singular.
+    pub leverages: Option<DVector<F>>,
+    /// The parameters which are at the bound of
+    /// [`with_param_magnitude_limit`](struct.LevenbergMarquardt.html#method.with_param_magnitude_limit)
+    /// at the solution, as their index and the side of the bound.
+    ///
+    /// These are the parameters the data would like to push further. A
+    /// parameter counts as active if `$|x_i| \geq (1 - \sqrt{\varepsilon})\,\mathtt{limit}$`.
+    /// Empty if there is no limit.
+    pub active_bounds: Vec<(usize, BoundSide)>,
+    /// The sums of squares `$\sum_{i \in G} r_i^2$` of the residuals in the
+    /// groups `$G$` set with
+    /// [`LM::with_residual_groups`](struct.LM.html#method.with_residual_groups),
+    /// at the solution.
+    ///
+    /// The residuals are evaluated again if they are not at hand when the
+    /// minimization terminates, this is not counted as an evaluation. `None`
+    /// if no groups were set or if the residuals could not be computed.
+    pub group_sums_of_squares: Option<Vec<F>>,
+    /// The final values of the `ftol`, `xtol` and `gtol` tests, which
+    /// [`TerminationReason::Converged`](enum.TerminationReason.html#variant.Converged)
+    /// only reports as flags, with the tolerances they were compared against.
+    pub certificate: ConvergenceCertificate<F>,
+}
+
+impl<F: RealField + Copy> MinimizationReport<F> {
+    /// The ratio `$f(\vec{x}_0) / f(\vec{x})$` of the initial and the final
+    /// objective function.
+    ///
+    /// This is at least one unless the minimization failed at the initial
+    /// guess, it is infinite if the residuals are zero at the solution.
+    pub fn improvement_factor(&self) -> F {
+        self.initial_objective / self.objective_function
+    }
+
+    /// Compute the variance of a prediction at the solution.
+    ///
+    /// Pass the gradient `$\vec{g}$` of the prediction with respect to the
+    /// parameters, for a model prediction this is the corresponding row of the
+    /// Jacobian. The variance is propagated to first order as
+    /// `$\vec{g}^\top\mathbf{C}\vec{g}$` with the
+    /// [`covariance`](#structfield.covariance) `$\mathbf{C}$`.
+    ///
+    /// Returns `None` if no covariance is available.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `jacobian_row` is not the number of parameters.
+    pub fn prediction_variance<N>(&self, jacobian_row: &OVector<F, N>) -> Option<F>
+    where
+        N: Dim,
+        DefaultAllocator: Allocator<F, N>,
+    {
+        let covariance = self.covariance.as_ref()?;
+        assert_eq!(
+            jacobian_row.nrows(),
+            covariance.nrows(),
+            "jacobian_row must have one entry per parameter"
+        );
+        let g = DVector::from_iterator(jacobian_row.nrows(), jacobian_row.iter().copied());
+        Some(g.dot(&(covariance * &g)))
+    }
+
+    /// The [`covariance`](#structfield.covariance) for a known variance of the
+    /// residuals,
+    /// ```math
+    ///   \sigma^2\bigl(\mathbf{J}^\top\mathbf{J}\bigr)^{-1}
+    /// ```
+    /// with `$\sigma^2$` = `sigma_sq`.
+    ///
+    /// Use this if the noise level is known beforehand, for example from a
+    /// calibration, instead of the estimate
+    /// [`residual_variance`](#method.residual_variance) from the fit.
+    /// Returns `None` if no covariance is available or the residuals are zero
+    /// at the solution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\sigma^2 < 0$`.
+    pub fn covariance_with_variance(&self, sigma_sq: F) -> Option<DMatrix<F>> {
+        assert!(!sigma_sq.is_negative(), "sigma_sq must be >= 0");
+        let covariance = self.covariance.as_ref()?;
+        let estimated = self.residual_variance().filter(|v| !v.is_zero())?;
+        Some(covariance * (sigma_sq / estimated))
+    }
+
+    /// The gradient `$\mathbf{J}^\top\vec{r}$` of the objective function at the
+    /// solution, one entry per parameter.
+    ///
+    /// At a minimum all entries should be close to zero. A large entry means that
+    /// the parameter is still pulling, either because the minimization did not
+    /// converge or because the parameter is held by a constraint.
+    ///
+    /// It is computed from the retained QR decomposition as
+    /// `$\mathbf{P}\mathbf{R}^\top\mathbf{Q}^\top\vec{r}$`, so it is `None`
+    /// unless [`with_qr_retention`](struct.LevenbergMarquardt.html#method.with_qr_retention)
+    /// was enabled and the Jacobian and the residuals could be computed.
+    pub fn gradient_components(&self) -> Option<DVector<F>> {
+        let r = self.qr_r_factor.as_ref()?;
+        let permutation = self.qr_permutation.as_ref()?;
+        let qt_r = self.qr_qt_residuals.as_ref()?;
+        let mut gradient = DVector::zeros(r.ncols());
+        for (j, column) in r.column_iter().enumerate() {
+            gradient[permutation[j]] = column.dot(qt_r);
+        }
+        Some(gradient)
+    }
+
+    /// A convergence score for every parameter, small for parameters which
+    /// settled.
+    ///
+    /// The score of parameter `$i$` is the larger of two measures: its
+    /// [`relative_step`](#structfield.relative_step) and its share
+    /// `$|g_i| / \|\vec{g}\|$` of the [`gradient_components`](#method.gradient_components)
+    /// times the [`gradient_norm`](#structfield.gradient_norm) of the `gtol`
+    /// test. A fit can terminate successfully while one parameter is still
+    /// drifting, for example along a flat direction of the objective
+    /// function, this shows up as one score far above the others.
+    ///
+    /// The step measure is zero if no step was accepted. `None` unless
+    /// [`with_qr_retention`](struct.LevenbergMarquardt.html#method.with_qr_retention)
+    /// was enabled and the Jacobian and the residuals could be computed.
+    pub fn parameter_convergence(&self) -> Option<DVector<F>> {
+        let gradient = self.gradient_components()?;
+        let gradient_norm = self.gradient_norm?;
+        let total = gradient.norm();
+        let mut score = gradient.map(|g| {
+            if total.is_zero() {
+                F::zero()
+            } else {
+                g.abs() / total * gradient_norm
+            }
+        });
+        if let Some(relative_step) = &self.relative_step {
+            for (score, step) in score.iter_mut().zip(relative_step.iter()) {
+                *score = score.max(*step);
+            }
+        }
+        Some(score)
+    }
+
+    /// An estimate of the smallest eigenvalue of `$\mathbf{J}^\top\mathbf{J}$`
+    /// at the solution.
+    ///
+    /// This is the curvature of the Gauss-Newton model along its flattest
+    /// direction. A value close to zero, compared to the other curvatures,
+    /// means that the data barely constrains some combination of the
+    /// parameters and that their estimates along it are unreliable.
+    ///
+    /// It is computed from the retained QR decomposition
+    /// `$\mathbf{J}^\top\mathbf{J} = \mathbf{P}\mathbf{R}^\top\mathbf{R}\mathbf{P}^\top$`
+    /// with a few steps of inverse iteration, started from the smallest
+    /// diagonal entry `$r_{nn}^2$` of the pivoted factor. The estimate is never
+    /// below the exact eigenvalue. Zero if `$\mathbf{R}$` is singular. `None`
+    /// unless [`with_qr_retention`](struct.LevenbergMarquardt.html#method.with_qr_retention)
+    /// was enabled and the Jacobian could be computed.
+    pub fn min_curvature(&self) -> Option<F> {
+        const ITERATIONS: usize = 8;
+        let r = self.qr_r_factor.as_ref()?;
+        let n = r.ncols();
+        if n == 0 {
+            return None;
+        }
+        if r.nrows() < n || r.diagonal().iter().any(|r| r.is_zero()) {
+            return Some(F::zero());
+        }
+        let diagonal = r.diagonal().map(|r| r * r).min();
+        let mut v = DVector::from_element(n, F::one() / convert::<_, F>(n as f64).sqrt());
+        for _ in 0..ITERATIONS {
+            // v <- (R^T R)^{-1} v, normalized
+            let z = r
+                .tr_solve_upper_triangular(&v)
+                .and_then(|w| r.solve_upper_triangular(&w));
+            match z.map(|z| (z.norm(), z)) {
+                Some((norm, z)) if norm.is_finite() && !norm.is_zero() => v = z / norm,
+                _ => return Some(F::zero()),
+            }
+        }
+        // the Rayleigh quotient of the approximate eigenvector
+        Some((r * v).norm_squared().min(diagonal))
+    }
+
+    /// The contribution `$\frac{1}{2}\sum_{i \in G} r_i^2$` of every residual
+    /// group `$G$` to the objective function at the solution.
+    ///
+    /// If the groups cover every residual once, the contributions add up to
+    /// the [`objective_function`](#structfield.objective_function). A group
+    /// whose contribution is large compared to its number of residuals points
+    /// to a mismatch between the model and that part of the data, for example
+    /// a miscalibrated sensor. See
+    /// [`group_sums_of_squares`](#structfield.group_sums_of_squares).
+    pub fn group_objectives(&self) -> Option<Vec<F>> {
+        let half: F = convert(0.5);
+        let sums = self.group_sums_of_squares.as_ref()?;
+        Some(sums.iter().map(|sum| *sum * half).collect())
+    }
+
+    /// The sum of squares `$\sum_i r_i^2 = \|\vec{r}\|^2 = 2 f(\vec{x})$` at the solution.
+    ///
+    /// This is the value to compare with tools which minimize `$\|\vec{r}\|^2$`
+    /// without the factor `$\frac{1}{2}$` of the
+    /// [`objective_function`](#structfield.objective_function). All statistics
+    /// of the report, like the [`residual_variance`](#method.residual_variance)
+    /// and the information criteria, are computed from it.
+    pub fn sum_of_squares(&self) -> F {
+        self.objective_function * convert(2.)
+    }
+
+    /// The unbiased estimate of the variance of the residuals,
+    /// ```math
+    ///   s^2 = \frac{\|\vec{r}\|^2}{m - n} = \frac{2 f(\vec{x})}{m - n}.
+    /// ```
+    ///
+    /// This is the factor of the [`covariance`](#structfield.covariance).
+    /// Returns `None` if `$m \leq n$`.
+    pub fn residual_variance(&self) -> Option<F> {
+        if self.number_of_residuals <= self.number_of_parameters {
+            return None;
+        }
+        let dof = self.number_of_residuals - self.number_of_parameters;
+        Some(self.sum_of_squares() / convert(dof as f64))
+    }
+
+    /// The effective degrees of freedom `$m - \sum_i h_i$` of the data.
+    ///
+    /// The sum runs over the [`leverages`](#structfield.leverages), so `$m$`
+    /// does not include the penalty residuals. Without penalties the leverages
+    /// add up to `$n$` and this is `$m - n$`. A penalty, for example a
+    /// regularization with
+    /// [`with_linear_penalty`](trait.LeastSquaresProblem.html#method.with_linear_penalty),
+    /// restricts the parameters and takes away part of the leverage of the data,
+    /// so the effective degrees of freedom grow. Use them instead of `$m - n$`
+    /// for the reduced `$\chi^2$` of a regularized fit.
+    ///
+    /// Returns `None` if no leverages were computed.
+    pub fn effective_dof(&self) -> Option<F> {
+        let leverages = self.leverages.as_ref()?;
+        Some(convert::<_, F>(leverages.nrows() as f64) - leverages.sum())
+    }
+
+    /// The Akaike information criterion for residuals with Gaussian errors,
+    /// ```math
+    ///   \mathrm{AIC} = m \ln\frac{\|\vec{r}\|^2}{m} + 2n.
+    /// ```
+    ///
+    /// This is `$-2\ln L + 2n$` for the maximum likelihood `$L$` of independent
+    /// errors with an unknown common variance, up to the constant
+    /// `$m(1 + \ln 2\pi)$`. The constant is the same for all models fitted to
+    /// the same `$m$` residuals, so only differences of the criterion are
+    /// meaningful: the model with the smallest value is preferred. The
+    /// variance is not counted as a parameter. `$-\infty$` if the residuals
+    /// are zero.
+    pub fn aic(&self) -> F {
+        let m = self.number_of_residuals as f64;
+        self.log_likelihood_term(m) + convert(2. * self.number_of_parameters as f64)
+    }
+
+    /// The Bayesian information criterion for residuals with Gaussian errors,
+    /// ```math
+    ///   \mathrm{BIC} = N \ln\frac{\|\vec{r}\|^2}{N} + n \ln N
+    /// ```
+    /// for `$N$` = `n_observations`.
+    ///
+    /// This uses the same convention as [`aic`](#method.aic). Usually `$N$` is
+    /// the number of residuals `$m$`, pass a different number if several
+    /// residuals belong to one observation. The penalty for the parameters grows
+    /// with `$N$`, so for `$N \geq 8$` this prefers simpler models than the AIC.
+    pub fn bic(&self, n_observations: usize) -> F {
+        let observations = n_observations as f64;
+        self.log_likelihood_term(observations)
+            + convert::<_, F>(self.number_of_parameters as f64 * observations.ln())
+    }
+
+    /// `$N \ln(\|\vec{r}\|^2 / N)$`
+    fn log_likelihood_term(&self, observations: f64) -> F {
+        let observations: F = convert(observations);
+        observations * (self.sum_of_squares() / observations).ln()
+    }
+
+    /// Terminate with `WrongDimensions` because the problem changed its
+    /// dimensions after the minimization, unless it already failed.
+    fn wrong_dimensions_after_fit(&mut self, what: &'static str) {
+        if !self.termination.was_failure() {
+            self.termination = TerminationReason::WrongDimensions(what);
+        }
+    }
+}
+
+impl<F: RealField + Float> MinimizationReport<F> {
+    /// The report before the residuals are evaluated for the first time.
+    fn unevaluated(
+        config: &LevenbergMarquardt<F>,
+        number_of_parameters: usize,
+        jacobian_is_numerical: bool,
+    ) -> Self {
+        Self {
+            termination: TerminationReason::ResidualsZero,
+            number_of_evaluations: 0,
+            number_of_residuals: 0,
+            number_of_parameters,
+            objective_function: <F as Float>::nan(),
+            initial_objective: <F as Float>::nan(),
+            final_step_norm: F::zero(),
+            terminated_at_iteration: 0,
+            trust_region_reductions: 0,
+            incremental_factorizations: 0,
+            jacobian_is_numerical,
+            gradient_norm: None,
+            lambda: None,
+            relative_step: None,
+            covariance: None,
+            qr_r_factor: None,
+            qr_permutation: None,
+            qr_qt_residuals: None,
+            leverages: None,
+            active_bounds: Vec::new(),
+            group_sums_of_squares: None,
+            certificate: ConvergenceCertificate::untested(config),
+        }
+    }
+}
+
+/// A multi-line summary of the minimization.
+///
+/// Fields which were not recorded, like the gradient norm if no Jacobian was
+/// computed, are omitted. The reduced `$\chi^2$` is the
+/// [`residual_variance`](struct.MinimizationReport.html#method.residual_variance),
+/// it is printed if there are more residuals than parameters. With
+/// [`with_qr_retention`](struct.LevenbergMarquardt.html#method.with_qr_retention)
+/// the ratio of the largest to the smallest `$|r_{jj}|$` of the
+/// [`qr_r_factor`](struct.MinimizationReport.html#structfield.qr_r_factor)
+/// is printed as an estimate of the condition number of the Jacobian.
+impl<F: RealField + Float> fmt::Display for MinimizationReport<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "termination:        {:?}", self.termination)?;
+        writeln!(f, "objective function: {}", self.objective_function)?;
+        writeln!(f, "iterations:         {}", self.terminated_at_iteration)?;
+        write!(f, "evaluations:        {}", self.number_of_evaluations)?;
+        if self.jacobian_is_numerical {
+            write!(f, " (numerical Jacobian)")?;
+        }
+        if let Some(gradient_norm) = &self.gradient_norm {
+            write!(f, "\ngradient norm:      {}", gradient_norm)?;
+        }
+        if let Some(lambda) = &self.lambda {
+            write!(f, "\nlambda:             {}", lambda)?;
+        }
+        if let Some(variance) = self.residual_variance() {
+            write!(f, "\nreduced chi^2:      {}", variance)?;
+        }
+        if let Some(r) = &self.qr_r_factor {
+            let diagonal = r.diagonal().map(Float::abs);
+            if !diagonal.is_empty() {
+                let condition = diagonal.max() / diagonal.min();
+                write!(f, "\ncondition number:   {}", condition)?;
+            }
+        }
+        write!(f, "\nfinal step norm:    {}", self.final_step_norm)
+    }
+}
+
+/// A failed minimization, returned by
+/// [`try_minimize`](struct.LevenbergMarquardt.html#method.try_minimize).
+///
+/// The [`termination`](struct.MinimizationReport.html#structfield.termination)
+/// of the report is a failure, see
+/// [`TerminationReason::was_failure`](enum.TerminationReason.html#method.was_failure).
+#[derive(Clone, Debug)]
+pub struct LmError<F: RealField> {
+    pub report: Box<MinimizationReport<F>>,
+}
+
+impl<F: RealField> fmt::Display for LmError<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "minimization failed: {:?}", self.report.termination)
+    }
 }
 
+impl<F: RealField> core::error::Error for LmError<F> {}
+
 /// Levenberg-Marquardt optimization algorithm.
 ///
 /// See the [module documentation](index.html) for a usage example.
 ///
 /// The runtime and termination behavior can be controlled by various hyperparameters.
+/// The configuration is `Copy`, so a base configuration can be reused and
+/// varied with the builder methods. Options which borrow state, like an
+/// [`Observer`](trait.Observer.html), are not part of it. They are set on the
+/// solver returned by [`solver`](#method.solver).
+///
+/// If the problem has fixed dimensions, everything lives on the stack and the
+/// minimization does not allocate. Only the options which produce or keep
+/// data of dynamic size allocate: the covariance and the QR retention in the
+/// report, the weighted `ftol` test, the stall window and the cycle detection.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct LevenbergMarquardt<F> {
     ftol: F,
     xtol: F,
+    xtol_componentwise: Option<F>,
     gtol: F,
     stepbound: F,
+    stepbound_abs: F,
+    acceptance_ratio: F,
+    nonmonotone: Option<usize>,
+    initial_delta: Option<F>,
+    param_magnitude_limit: Option<F>,
     patience: usize,
+    max_evaluations: Option<usize>,
+    evaluation_time_budget: Option<Duration>,
+    max_iterations: Option<usize>,
+    parameter_target: Option<(usize, F, CrossDirection)>,
+    max_lambda_iterations: usize,
+    damping_schedule: Option<(usize, F)>,
+    conditioning_guard: Option<F>,
+    recovery_attempts: Option<usize>,
+    stall_window: Option<(usize, F)>,
+    diag_reset_on_stall: bool,
+    patience_reset: bool,
+    gradient_plateau: Option<(usize, F)>,
+    cycle_detection: Option<(usize, F)>,
+    saddle_escape: Option<(u64, F)>,
+    divergence_detection: Option<(usize, F)>,
     scale_diag: bool,
+    autoscale: bool,
+    normalize_initial_objective: bool,
+    gradient_fallback: bool,
+    line_search: LineSearch<F>,
+    hessian_correction: bool,
+    weighted_ftol: bool,
+    covariance: bool,
+    qr_retention: bool,
+    min_norm: bool,
+    pivoting: bool,
+    constant_jacobian: bool,
+    detect_constant_jacobian: bool,
+    diag_update: DiagUpdate,
+    gtol_reference: GtolReference<F>,
 }
 
 impl<F: RealField + Float> Default for LevenbergMarquardt<F> {
@@ -123,20 +963,90 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
             Self {
                 ftol: user_tol,
                 xtol: user_tol,
+                xtol_componentwise: None,
                 gtol: F::zero(),
                 stepbound: convert(100.0),
+                stepbound_abs: F::zero(),
+                acceptance_ratio: convert(1.0e-4),
+                nonmonotone: None,
+                initial_delta: None,
+                param_magnitude_limit: None,
                 patience: 100,
+                max_evaluations: None,
+                evaluation_time_budget: None,
+                max_iterations: None,
+                parameter_target: None,
+                max_lambda_iterations: 10,
+                damping_schedule: None,
+                conditioning_guard: None,
+                recovery_attempts: None,
+                stall_window: None,
+                diag_reset_on_stall: false,
+                patience_reset: false,
+                gradient_plateau: None,
+                cycle_detection: None,
+                saddle_escape: None,
+                divergence_detection: None,
                 scale_diag: true,
+                autoscale: false,
+                normalize_initial_objective: false,
+                gradient_fallback: false,
+                line_search: LineSearch::None,
+                hessian_correction: false,
+                weighted_ftol: false,
+                covariance: false,
+                qr_retention: false,
+                min_norm: false,
+                pivoting: true,
+                constant_jacobian: false,
+                detect_constant_jacobian: false,
+                diag_update: DiagUpdate::MinpackMonotone,
+                gtol_reference: GtolReference::ResidualNorm,
             }
         } else {
             let user_tol = F::default_epsilon() * convert(30.0);
             Self {
                 ftol: user_tol,
                 xtol: user_tol,
+                xtol_componentwise: None,
                 gtol: user_tol,
                 stepbound: convert(100.0),
+                stepbound_abs: F::zero(),
+                acceptance_ratio: convert(1.0e-4),
+                nonmonotone: None,
+                initial_delta: None,
+                param_magnitude_limit: None,
                 patience: 100,
+                max_evaluations: None,
+                evaluation_time_budget: None,
+                max_iterations: None,
+                parameter_target: None,
+                max_lambda_iterations: 10,
+                damping_schedule: None,
+                conditioning_guard: None,
+                recovery_attempts: None,
+                stall_window: None,
+                diag_reset_on_stall: false,
+                patience_reset: false,
+                gradient_plateau: None,
+                cycle_detection: None,
+                saddle_escape: None,
+                divergence_detection: None,
                 scale_diag: true,
+                autoscale: false,
+                normalize_initial_objective: false,
+                gradient_fallback: false,
+                line_search: LineSearch::None,
+                hessian_correction: false,
+                weighted_ftol: false,
+                covariance: false,
+                qr_retention: false,
+                min_norm: false,
+                pivoting: true,
+                constant_jacobian: false,
+                detect_constant_jacobian: false,
+                diag_update: DiagUpdate::MinpackMonotone,
+                gtol_reference: GtolReference::ResidualNorm,
             }
         }
     }
@@ -145,6 +1055,14 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
     ///
     /// Termination occurs when both the actual and
     /// predicted relative reductions for `$f$` are at most `ftol`.
+    /// Set it to zero to disable the test.
+    ///
+    /// If the number of residuals `$m$` grows, for example by fitting the same
+    /// model to more samples, `$f$` and its reductions grow alike. So the
+    /// relative reductions and with them `ftol` do not depend on `$m$`. The
+    /// same holds for `xtol`, `gtol` and the threshold of
+    /// [`with_stall_window`](#method.with_stall_window), there is no absolute
+    /// tolerance on `$f$` which needs to be rescaled.
     ///
     /// # Panics
     ///
@@ -159,6 +1077,7 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
     ///
     /// Termination occurs when the relative error between
     /// two consecutive iterates is at most `xtol`.
+    /// Set it to zero to disable the test.
     ///
     /// # Panics
     ///
@@ -169,6 +1088,33 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
         Self { xtol, ..self }
     }
 
+    /// Require a small relative change of every parameter instead of the `xtol` test.
+    ///
+    /// Termination occurs when the last step `$\vec{p}$` fulfills
+    /// ```math
+    ///   \frac{|p_i|}{|x_i| + \varepsilon} \leq \mathtt{xtol}
+    ///   \quad\text{for all }i=1,\ldots,n
+    /// ```
+    /// for the parameters `$\vec{x}$` after the step and the machine epsilon
+    /// `$\varepsilon$`. The global test of [`with_xtol`](#method.with_xtol) is
+    /// dominated by the parameters of large magnitude. With this test a small
+    /// parameter which still moves keeps the minimization going until it
+    /// converged as well. In contrast to
+    /// [`LM::with_typical_magnitudes`](struct.LM.html#method.with_typical_magnitudes)
+    /// the magnitudes do not have to be known in advance. This replaces both.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{xtol} \leq 0$`.
+    #[must_use]
+    pub fn with_xtol_componentwise(self, xtol: F) -> Self {
+        assert!(xtol > F::zero(), "xtol must be > 0");
+        Self {
+            xtol_componentwise: Some(xtol),
+            ..self
+        }
+    }
+
     /// Set orthogonality desired between the residual vector and its derivative.
     ///
     /// Termination occurs when the cosine of the angle
@@ -186,6 +1132,13 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
     /// The angle check is scale invariant, whereas checking that
     /// `$\nabla f(\vec{x})\approx \vec{0}$` is not.
     ///
+    /// With a [parameter magnitude limit](#method.with_param_magnitude_limit)
+    /// the test uses the projected gradient: a parameter at the limit whose
+    /// descent direction points out of the limit is left out, because the
+    /// gradient is not zero at a solution on the boundary.
+    ///
+    /// Set it to zero to disable the test.
+    ///
     /// # Panics
     ///
     /// Panics if `$\mathtt{gtol} < 0$`.
@@ -195,9 +1148,42 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
         Self { gtol, ..self }
     }
 
+    /// Set the norm the gradient is divided by in the `gtol` test.
+    ///
+    /// By default the test in [`with_gtol`](#method.with_gtol) divides by the
+    /// norm of the current residuals. If this norm changes by orders of
+    /// magnitude during the minimization, the test is too eager while the
+    /// residuals are large and too lazy once they are small. With
+    /// [`GtolReference::Fixed(s)`](enum.GtolReference.html#variant.Fixed) the
+    /// algorithm terminates if
+    /// ```math
+    ///   \frac{|(\mathbf{J}^\top \vec{r})_i|}{\|\mathbf{J}\vec{e}_i\|\,s} \leq \texttt{gtol}
+    ///   \quad\text{for all }i=1,\ldots,n,
+    /// ```
+    /// for example with `$s$` the expected norm of the measurement noise.
+    /// [`MinimizationReport::gradient_norm`](struct.MinimizationReport.html#structfield.gradient_norm)
+    /// is scaled the same way.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a fixed norm is not positive or not finite.
+    #[must_use]
+    pub fn with_gtol_reference(self, gtol_reference: GtolReference<F>) -> Self {
+        if let GtolReference::Fixed(norm) = gtol_reference {
+            assert!(
+                norm > F::zero() && norm.is_finite(),
+                "reference norm must be > 0 and finite"
+            );
+        }
+        Self {
+            gtol_reference,
+            ..self
+        }
+    }
+
     /// Shortcut to set `tol` as in MINPACK `LMDER1`.
     ///
-    /// Sets `ftol = xtol = tol` and `gtol = 0`.
+    /// Sets `ftol = xtol = tol` and `gtol = 0`, which disables the `gtol` test.
     ///
     /// # Panics
     ///
@@ -213,11 +1199,30 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
         }
     }
 
+    /// Set `ftol`, `xtol` and `gtol` as tight as the floating-point type allows.
+    ///
+    /// The tolerances are set to `$30\varepsilon$` for the machine epsilon
+    /// `$\varepsilon$` of `F`, so the same configuration works for `f32` and
+    /// `f64`. These are the defaults unless the MINPACK compatibility is
+    /// enabled. Tighter tolerances only lead to
+    /// [`TerminationReason::NoImprovementPossible`](enum.TerminationReason.html#variant.NoImprovementPossible).
+    #[must_use]
+    pub fn with_machine_precision_tolerances(self) -> Self {
+        let tol = F::default_epsilon() * convert(30.0);
+        Self {
+            ftol: tol,
+            xtol: tol,
+            gtol: tol,
+            ..self
+        }
+    }
+
     /// Set factor for the initial step bound.
     ///
-    /// This bound is set to `$\mathtt{stepbound}\cdot\|\mathbf{D}\vec{x}\|$`
-    /// if nonzero, or else to `stepbound` itself. In most cases `stepbound` should lie
-    /// in the interval `$[0.1,100]$`.
+    /// This bound is set to `$\mathtt{stepbound}\cdot\|\mathbf{D}\vec{x}\|$`,
+    /// but at least to [`stepbound_abs`](#method.with_stepbound_abs). If both
+    /// are zero, it is set to `stepbound` itself. In most cases `stepbound`
+    /// should lie in the interval `$[0.1,100]$`.
     ///
     /// # Panics
     ///
@@ -228,138 +1233,2603 @@ impl<F: RealField + Float> LevenbergMarquardt<F> {
         Self { stepbound, ..self }
     }
 
-    /// Set factor for the maximal number of function evaluations.
+    /// Set an absolute lower bound for the initial step bound.
     ///
-    /// The maximal number of function evaluations is set to
-    /// `$\texttt{patience}\cdot(n + 1)$`.
+    /// The initial trust-region radius is
+    /// `$\max(\mathtt{stepbound}\cdot\|\mathbf{D}\vec{x}\|, \mathtt{stepbound\_abs})$`.
+    /// The relative bound alone becomes vanishingly small if the initial guess
+    /// is very close to, but not at, the origin. The default is zero.
     ///
     /// # Panics
     ///
-    /// Panics if `$\mathtt{patience} \leq 0$`.
+    /// Panics if `$\mathtt{stepbound\_abs} < 0$`.
     #[must_use]
-    pub fn with_patience(self, patience: usize) -> Self {
-        assert!(patience > 0, "patience must be > 0");
-        Self { patience, ..self }
+    pub fn with_stepbound_abs(self, stepbound_abs: F) -> Self {
+        assert!(stepbound_abs >= F::zero(), "stepbound_abs must be >= 0");
+        Self {
+            stepbound_abs,
+            ..self
+        }
     }
 
-    /// Enable or disable whether the variables will be rescaled internally.
+    /// Set the smallest gain ratio for which a trust-region step is accepted.
+    ///
+    /// The gain ratio is the actual relative reduction of the objective
+    /// function divided by the one predicted by the linear model. Steps with
+    /// a smaller ratio are rejected and the trust region shrinks. The default
+    /// is `$10^{-4}$`, as in MINPACK. Lower it on noisy objective functions to
+    /// accept marginal improvements, raise it to take only steps which are
+    /// described well by the model.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{acceptance\_ratio} < 0$` or `$\mathtt{acceptance\_ratio} \geq 1$`.
     #[must_use]
-    pub fn with_scale_diag(self, scale_diag: bool) -> Self {
-        Self { scale_diag, ..self }
+    pub fn with_acceptance_ratio(self, acceptance_ratio: F) -> Self {
+        assert!(
+            !acceptance_ratio.is_negative() && acceptance_ratio < F::one(),
+            "acceptance_ratio must be >= 0 and < 1"
+        );
+        Self {
+            acceptance_ratio,
+            ..self
+        }
     }
 
-    /// Try to solve the given least squares problem.
+    /// Accept steps by comparing with the worst of the last `window` iterations.
     ///
-    /// The paramters of the problem which are set when this function is called
-    /// are used as the initial guess for `$\vec{x}$`.
-    pub fn minimize<N, M, O>(&self, target: O) -> (O, MinimizationReport<F>)
-    where
-        N: Dim,
-        M: DimMin<N> + DimMax<N>,
-        O: LeastSquaresProblem<F, M, N>,
-        DefaultAllocator:
+    /// Let `$f_{\text{ref}}$` be the largest objective function of the
+    /// last `window` accepted points, including the current one. A step is
+    /// accepted if the gain ratio with the actual reduction measured from
+    /// `$f_{\text{ref}}$` instead of the current `$f(\vec{x})$` reaches the
+    /// [`acceptance_ratio`](#method.with_acceptance_ratio), and this ratio also
+    /// drives the update of the trust region. This is the non-monotone
+    /// criterion of Grippo et al., adapted to trust regions by Toint. Steps may
+    /// increase the objective function a little, which lets the minimization
+    /// follow curved valleys and noisy objective functions with longer steps.
+    /// With `window = 1` this is the usual monotone acceptance, which is the
+    /// default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{window} \leq 0$`.
+    #[must_use]
+    pub fn with_nonmonotone(self, window: usize) -> Self {
+        assert!(window > 0, "window must be > 0");
+        Self {
+            nonmonotone: Some(window),
+            ..self
+        }
+    }
+
+    /// Set the initial trust-region radius `$\Delta$` directly.
+    ///
+    /// This overrides the bound computed from
+    /// [`stepbound`](#method.with_stepbound) for the first iteration,
+    /// independent of the initial guess `$\vec{x}$`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{initial\_delta} \leq 0$`.
+    #[must_use]
+    pub fn with_initial_delta(self, initial_delta: F) -> Self {
+        assert!(initial_delta.is_positive(), "initial_delta must be > 0");
+        Self {
+            initial_delta: Some(initial_delta),
+            ..self
+        }
+    }
+
+    /// Set factor for the maximal number of function evaluations.
+    ///
+    /// The maximal number of function evaluations is set to
+    /// `$\texttt{patience}\cdot(n + 1)$`.
+    ///
+    /// This is the `maxfev` budget of MINPACK. By default it is cumulative:
+    /// all evaluations count, also the ones of accepted steps, and the counter
+    /// is never reset. A run of rejected steps followed by progress therefore
+    /// still uses up the budget. Enable
+    /// [`with_patience_reset`](#method.with_patience_reset) to count only the
+    /// evaluations since the last accepted step instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{patience} \leq 0$`.
+    #[must_use]
+    pub fn with_patience(self, patience: usize) -> Self {
+        assert!(patience > 0, "patience must be > 0");
+        Self { patience, ..self }
+    }
+
+    /// Start the budget of [`patience`](#method.with_patience) over after every accepted step.
+    ///
+    /// Then the minimization terminates with
+    /// [`TerminationReason::LostPatience`](enum.TerminationReason.html#variant.LostPatience)
+    /// only after `$\texttt{patience}\cdot(n + 1)$` evaluations without an
+    /// accepted step, so long fits can recover from a run of rejected steps.
+    /// Use [`with_max_evaluations`](#method.with_max_evaluations) to limit the
+    /// total number of evaluations. Disabled by default, like in MINPACK.
+    #[must_use]
+    pub fn with_patience_reset(self, patience_reset: bool) -> Self {
+        Self {
+            patience_reset,
+            ..self
+        }
+    }
+
+    /// Set the maximal number of residual and Jacobian evaluations.
+    ///
+    /// In contrast to [`patience`](#method.with_patience) this counts every call to
+    /// [`residuals`](trait.LeastSquaresProblem.html#tymethod.residuals) and
+    /// [`jacobian`](trait.LeastSquaresProblem.html#tymethod.jacobian), including
+    /// the evaluation at the initial guess. The minimization terminates with
+    /// [`TerminationReason::MaxEvaluations`](enum.TerminationReason.html#variant.MaxEvaluations)
+    /// once the budget is used up. There is no such limit by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{max\_evaluations} \leq 0$`.
+    #[must_use]
+    pub fn with_max_evaluations(self, max_evaluations: usize) -> Self {
+        assert!(max_evaluations > 0, "max_evaluations must be > 0");
+        Self {
+            max_evaluations: Some(max_evaluations),
+            ..self
+        }
+    }
+
+    /// Set the maximal time spent in residual and Jacobian evaluations.
+    ///
+    /// The time of every call to
+    /// [`residuals`](trait.LeastSquaresProblem.html#tymethod.residuals) and
+    /// [`jacobian`](trait.LeastSquaresProblem.html#tymethod.jacobian) is added
+    /// up, the time the solver spends on its own linear algebra does not count.
+    /// Once the sum reaches `budget` the minimization terminates with
+    /// [`TerminationReason::EvaluationTimeBudget`](enum.TerminationReason.html#variant.EvaluationTimeBudget)
+    /// before the next evaluation, so the last evaluation may overrun it. The
+    /// time is measured for one call, a solver created from a checkpoint starts
+    /// with the full budget. There is no such limit by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is zero.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_evaluation_time_budget(self, budget: Duration) -> Self {
+        assert!(!budget.is_zero(), "evaluation time budget must be > 0");
+        Self {
+            evaluation_time_budget: Some(budget),
+            ..self
+        }
+    }
+
+    /// Terminate once the parameter `index` crosses `value`.
+    ///
+    /// After every iteration the parameter before and after it is compared
+    /// with `value`. If it moved to `value` or beyond in the given direction,
+    /// the minimization terminates with
+    /// [`TerminationReason::ParameterTargetReached`](enum.TerminationReason.html#variant.ParameterTargetReached)
+    /// at the accepted point past the target. A parameter which starts at the
+    /// target does not count as crossing it. This is useful for continuation
+    /// methods which track a parameter towards a value. Like
+    /// [`with_max_iterations`](#method.with_max_iterations) this only applies
+    /// to one call: a solver created from a checkpoint which terminated here
+    /// continues. There is no such test by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` is not finite. The minimization panics if `index` is
+    /// not the index of a parameter.
+    #[must_use]
+    pub fn with_parameter_target(self, index: usize, value: F, direction: CrossDirection) -> Self {
+        assert!(value.is_finite(), "value must be finite");
+        Self {
+            parameter_target: Some((index, value, direction)),
+            ..self
+        }
+    }
+
+    /// Set the maximal number of iterations of one call.
+    ///
+    /// Every iteration computes the Jacobian and ends with an accepted step,
+    /// see [`terminated_at_iteration`](struct.MinimizationReport.html#structfield.terminated_at_iteration).
+    /// Once the iterations are used up, the minimization terminates with
+    /// [`TerminationReason::MaxIterations`](enum.TerminationReason.html#variant.MaxIterations)
+    /// at the last accepted point. Unlike the other limits this only counts the
+    /// iterations since the solver was created: a solver created with
+    /// [`solver_from_checkpoint`](#method.solver_from_checkpoint) from a
+    /// checkpoint which terminated with `MaxIterations` continues seamlessly.
+    /// With `with_max_iterations(1)` this gives one step per call, for example
+    /// to amortize a fit over the frames of a real-time application.
+    /// There is no such limit by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{max\_iterations} \leq 0$`.
+    #[must_use]
+    pub fn with_max_iterations(self, max_iterations: usize) -> Self {
+        assert!(max_iterations > 0, "max_iterations must be > 0");
+        Self {
+            max_iterations: Some(max_iterations),
+            ..self
+        }
+    }
+
+    /// Set a bound for the magnitude of every parameter.
+    ///
+    /// If a step would move a parameter `$x_i$` to `$|x_i| > \mathtt{limit}$`, the
+    /// step is shortened in the same direction until all parameters are within
+    /// the limit. This is a cheap safety rail for models which break down for
+    /// huge parameters, it is not a replacement for proper bounds: the steps are
+    /// only shortened, parameters which start outside of the limit stay untouched
+    /// and the limit is not taken into account in the subproblem.
+    /// There is no limit by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{limit} \leq 0$` or the limit is not finite.
+    #[must_use]
+    pub fn with_param_magnitude_limit(self, limit: F) -> Self {
+        assert!(
+            limit.is_positive() && limit.is_finite(),
+            "limit must be > 0 and finite"
+        );
+        Self {
+            param_magnitude_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Set how often a step is shrunk to recover from non-finite residuals.
+    ///
+    /// A trial step whose residuals contain `NaN` or `$\pm\infty$` is
+    /// rejected and the trust region shrinks, like for any step which does not
+    /// decrease the objective function. After `recovery_attempts` such steps in
+    /// a row, the next one terminates the minimization with
+    /// [`TerminationReason::Numerical`](enum.TerminationReason.html#variant.Numerical).
+    /// A non-finite Jacobian always terminates it, the point was accepted already.
+    /// There is no limit by default.
+    #[must_use]
+    pub fn with_recovery_attempts(self, recovery_attempts: usize) -> Self {
+        Self {
+            recovery_attempts: Some(recovery_attempts),
+            ..self
+        }
+    }
+
+    /// Terminate if the objective function stalls over several iterations.
+    ///
+    /// Let `$f_k$` be the objective function after iteration `$k$`. The
+    /// minimization terminates with
+    /// [`TerminationReason::NoImprovement`](enum.TerminationReason.html#variant.NoImprovement)
+    /// as soon as
+    /// ```math
+    ///   \frac{f_{k - \mathtt{window}} - f_k}{f_{k - \mathtt{window}}} < \mathtt{threshold}.
+    /// ```
+    /// The objective function never increases, so this is the relative reduction
+    /// of the best value over the last `window` iterations. In contrast to the
+    /// `ftol` test it is not fooled by a single iteration with a larger
+    /// reduction, for example on a noisy objective function.
+    /// There is no such test by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{window} \leq 0$` or `$\mathtt{threshold} < 0$`.
+    #[must_use]
+    pub fn with_stall_window(self, window: usize, threshold: F) -> Self {
+        assert!(window > 0, "window must be > 0");
+        assert!(!threshold.is_negative(), "threshold must be >= 0");
+        Self {
+            stall_window: Some((window, threshold)),
+            ..self
+        }
+    }
+
+    /// Reset the scaling diagonal `$\mathbf{D}$` once the stall test fires.
+    ///
+    /// With the MINPACK update `$\mathbf{D}$` never shrinks, so large column
+    /// norms of early Jacobians fix a scaling which may no longer suit the
+    /// Jacobian. If enabled, the
+    /// [stall test](#method.with_stall_window) does not terminate right away.
+    /// Instead `$\mathbf{D}$` is initialized again from the column norms of
+    /// the next Jacobian, like in the first iteration, together with the
+    /// trust-region radius `$\Delta$`. The minimization only terminates with
+    /// [`TerminationReason::NoImprovement`](enum.TerminationReason.html#variant.NoImprovement)
+    /// if it stalls again before a window with sufficient reduction. Without
+    /// a stall window this has no effect. Disabled by default.
+    #[must_use]
+    pub fn with_diag_reset_on_stall(self, diag_reset_on_stall: bool) -> Self {
+        Self {
+            diag_reset_on_stall,
+            ..self
+        }
+    }
+
+    /// Terminate if the scaled gradient plateaus over several iterations.
+    ///
+    /// Let `$g_k$` be the norm of the scaled gradient of the `gtol` test,
+    /// see [`MinimizationReport::gradient_norm`](struct.MinimizationReport.html#structfield.gradient_norm),
+    /// at the Jacobian of iteration `$k$`. The minimization terminates with
+    /// [`TerminationReason::GradientPlateau`](enum.TerminationReason.html#variant.GradientPlateau)
+    /// as soon as
+    /// ```math
+    ///   \frac{|g_{k - \mathtt{window}} - g_k|}{g_{k - \mathtt{window}}} < \mathtt{rel\_threshold}.
+    /// ```
+    /// This complements [`with_stall_window`](#method.with_stall_window): the
+    /// objective function may still decrease slowly while the gradient no
+    /// longer approaches zero, so further progress is mostly numerical noise.
+    /// There is no such test by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{window} \leq 0$` or `$\mathtt{rel\_threshold} < 0$`.
+    #[must_use]
+    pub fn with_gradient_plateau(self, window: usize, rel_threshold: F) -> Self {
+        assert!(window > 0, "window must be > 0");
+        assert!(!rel_threshold.is_negative(), "rel_threshold must be >= 0");
+        Self {
+            gradient_plateau: Some((window, rel_threshold)),
+            ..self
+        }
+    }
+
+    /// Terminate if the accepted parameters alternate between two points.
+    ///
+    /// Let `$\vec{x}_k$` be the parameters after iteration `$k$`. An iteration
+    /// returns to the point before the last one if
+    /// ```math
+    ///   \|\mathbf{D}(\vec{x}_k - \vec{x}_{k-2})\| \leq \mathtt{tol}\cdot\|\mathbf{D}\vec{x}_k\|.
+    /// ```
+    /// The minimization terminates with
+    /// [`TerminationReason::Cycling`](enum.TerminationReason.html#variant.Cycling)
+    /// once this happened in `repetitions` consecutive iterations. On degenerate
+    /// problems the steps can bounce back and forth while the objective function
+    /// barely changes, neither `ftol` nor `xtol` reliably catch this.
+    /// There is no such test by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{repetitions} \leq 0$` or `$\mathtt{tol} < 0$`.
+    #[must_use]
+    pub fn with_cycle_detection(self, repetitions: usize, tol: F) -> Self {
+        assert!(repetitions > 0, "repetitions must be > 0");
+        assert!(!tol.is_negative(), "tol must be >= 0");
+        Self {
+            cycle_detection: Some((repetitions, tol)),
+            ..self
+        }
+    }
+
+    /// Try to escape from stationary points with large residuals.
+    ///
+    /// At a saddle point or a maximum of the objective function, for example
+    /// at the center of a symmetric problem, the gradient vanishes and the
+    /// minimization would terminate with
+    /// [`TerminationReason::Orthogonal`](enum.TerminationReason.html#variant.Orthogonal)
+    /// or [`TerminationReason::NoGradientInformation`](enum.TerminationReason.html#variant.NoGradientInformation).
+    /// With this enabled, up to ten random perturbations
+    /// `$x_i + \mathtt{magnitude}\cdot u_i$` with `$u_i$` uniform in `$[-1, 1)$`
+    /// are tried first. The first one which decreases the objective function
+    /// is accepted and the minimization continues from there, at a minimum
+    /// none of them does and the minimization terminates as before. Every
+    /// perturbation counts as an evaluation.
+    ///
+    /// The perturbations are drawn from a pseudo-random generator initialized
+    /// with `seed`, so a minimization is reproducible. Choose `magnitude`
+    /// small compared to the typical magnitudes of the parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{magnitude} \leq 0$` or the magnitude is not finite.
+    #[must_use]
+    pub fn with_saddle_escape(self, seed: u64, magnitude: F) -> Self {
+        assert!(
+            magnitude > F::zero() && magnitude.is_finite(),
+            "magnitude must be > 0 and finite"
+        );
+        Self {
+            saddle_escape: Some((seed, magnitude)),
+            ..self
+        }
+    }
+
+    /// Terminate if the trial steps do not decrease the objective function.
+    ///
+    /// With a correct Jacobian a short enough step always decreases the
+    /// objective function. With a wrong one the trial steps point uphill, the
+    /// trust region shrinks until the `xtol` test passes and the minimization
+    /// seemingly converges at the initial guess. With this enabled it terminates
+    /// with [`TerminationReason::Diverged`](enum.TerminationReason.html#variant.Diverged):
+    ///
+    /// * after `window` consecutive trial steps whose objective function is not
+    ///   smaller than the current one, accepted or not, or
+    /// * as soon as a trial step has an objective function larger than
+    ///   `growth` times the initial one.
+    ///
+    /// The parameters are reset to the last accepted point. Choose `window`
+    /// larger than the few rejected steps a correct Jacobian needs after an
+    /// overly optimistic trust region, every rejection shrinks the trust region
+    /// by a factor of at least two. There is no such test by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{window} \leq 0$` or `$\mathtt{growth} \leq 1$`.
+    #[must_use]
+    pub fn with_divergence_detection(self, window: usize, growth: F) -> Self {
+        assert!(window > 0, "window must be > 0");
+        assert!(growth > F::one(), "growth must be > 1");
+        Self {
+            divergence_detection: Some((window, growth)),
+            ..self
+        }
+    }
+
+    /// Set the maximal number of iterations in the search for the
+    /// Levenberg-Marquardt parameter `$\lambda$`.
+    ///
+    /// Every iteration solves a linear least squares problem. If the search
+    /// has not converged after `max_lambda_iterations`, the step for the last
+    /// `$\lambda$` is used. The default is 10, as in MINPACK.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{max\_lambda\_iterations} \leq 0$`.
+    #[must_use]
+    pub fn with_max_lambda_iterations(self, max_lambda_iterations: usize) -> Self {
+        assert!(
+            max_lambda_iterations > 0,
+            "max_lambda_iterations must be > 0"
+        );
+        Self {
+            max_lambda_iterations,
+            ..self
+        }
+    }
+
+    /// Keep the Levenberg-Marquardt parameter `$\lambda$` above a floor for the
+    /// first iterations.
+    ///
+    /// In the first `initial_iterations` iterations every step solves
+    /// ```math
+    ///   (\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}^2)\vec{p} = \mathbf{J}^\top\vec{r}
+    /// ```
+    /// with `$\lambda \geq \mathtt{lambda\_floor}$`, even if the trust region
+    /// would allow a longer step. With `$\mathbf{D}$` holding the column norms
+    /// of the Jacobian, a floor of one halves the Gauss-Newton step along a
+    /// single column, larger floors turn the steps towards the scaled gradient.
+    /// From a poor initial guess the short, conservative steps keep the
+    /// minimization from jumping into another basin on the first linearization.
+    /// After `initial_iterations` the floor is removed and the trust region alone
+    /// controls `$\lambda$`, so the minimization ends with full Gauss-Newton
+    /// steps. There is no floor by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{initial\_iterations} \leq 0$` or
+    /// `$\mathtt{lambda\_floor} \leq 0$`.
+    #[must_use]
+    pub fn with_damping_schedule(self, initial_iterations: usize, lambda_floor: F) -> Self {
+        assert!(initial_iterations > 0, "initial_iterations must be > 0");
+        assert!(lambda_floor > F::zero(), "lambda_floor must be > 0");
+        Self {
+            damping_schedule: Some((initial_iterations, lambda_floor)),
+            ..self
+        }
+    }
+
+    /// Raise `$\lambda$` in iterations with an ill-conditioned Jacobian.
+    ///
+    /// The damped system of a step has the condition number
+    /// ```math
+    ///   \kappa_\lambda = \sqrt{\frac{\sigma_{\max}^2 + \lambda}{\sigma_{\min}^2 + \lambda}},
+    /// ```
+    /// where `$\sigma_{\max}, \sigma_{\min}$` are the extreme singular values of
+    /// `$\mathbf{J}\mathbf{D}^{-1}$`. They are estimated from the diagonal of the
+    /// pivoted QR decomposition of the Jacobian. If the estimate of
+    /// `$\kappa_\lambda$` for the `$\lambda$` of the trust-region step exceeds
+    /// `threshold`, the step is computed with the smallest `$\lambda$` for
+    /// which it does not, see
+    /// [`with_damping_schedule`](#method.with_damping_schedule) for how such a
+    /// floor is applied. This keeps a Jacobian which is nearly rank-deficient
+    /// in a single iteration from sending the parameters far along the poorly
+    /// determined direction. There is no guard by default.
+    ///
+    /// The estimate is a lower bound of the condition number, usually within a
+    /// small factor. Without [pivoting](#method.with_pivoting) it is worse.
+    /// There is no estimate for a
+    /// [`BlockJacobian`](struct.BlockJacobian.html), the guard has no effect
+    /// there.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{threshold} \leq 1$`.
+    #[must_use]
+    pub fn with_conditioning_guard(self, threshold: F) -> Self {
+        assert!(threshold > F::one(), "threshold must be > 1");
+        Self {
+            conditioning_guard: Some(threshold),
+            ..self
+        }
+    }
+
+    /// Enable or disable whether the variables will be rescaled internally.
+    ///
+    /// The trust region is always measured in the norm `$\|\mathbf{D}\vec{p}\|$`,
+    /// the initial radius is `$\mathtt{stepbound}\cdot\|\mathbf{D}\vec{x}\|$`
+    /// and the `xtol` test uses the same norm. If enabled, the diagonal
+    /// `$\mathbf{D}$` holds the column norms of the Jacobian. If disabled,
+    /// `$\mathbf{D}$` is the identity and the trust region is a Euclidean ball,
+    /// so the steps of parameters with very different magnitudes are bounded by
+    /// the same radius. Consider [`with_autoscale`](#method.with_autoscale)
+    /// for badly scaled problems then.
+    #[must_use]
+    pub fn with_scale_diag(self, scale_diag: bool) -> Self {
+        Self { scale_diag, ..self }
+    }
+
+    /// Enable or disable prescaling of the parameters.
+    ///
+    /// If enabled, the optimization runs over `$\tilde{\vec{x}}$` with
+    /// `$\vec{x} = \mathbf{S}\tilde{\vec{x}}$`, where the diagonal matrix
+    /// `$\mathbf{S}$` contains the inverse column norms of the initial Jacobian.
+    /// Columns with zero norm are not scaled. This is transparent to the problem,
+    /// it always sees and returns `$\vec{x}$`. The Jacobian is evaluated one more
+    /// time at the initial guess to compute `$\mathbf{S}$`.
+    ///
+    /// This is most useful without [`scale_diag`](#method.with_scale_diag),
+    /// which already compensates for diagonal scaling of the parameters.
+    #[must_use]
+    pub fn with_autoscale(self, autoscale: bool) -> Self {
+        Self { autoscale, ..self }
+    }
+
+    /// Enable or disable the normalization of the initial objective function.
+    ///
+    /// If enabled, all residuals are multiplied by the constant
+    /// `$1/\sqrt{f(\vec{x}_0)}$`, so the minimization runs on a problem whose
+    /// objective function is one at the initial guess. The minimizer is the
+    /// same and the steps only differ by rounding, but tolerances on the
+    /// objective function mean the same for problems of any scale. The
+    /// objective functions of the report are scaled back. The residuals are
+    /// evaluated one more time at the initial guess. If they cannot be
+    /// computed there or vanish, the problem is not normalized.
+    ///
+    /// Like [`with_autoscale`](#method.with_autoscale) this only applies to
+    /// [`minimize`](#method.minimize) and the methods based on it, not to
+    /// [`solver`](#method.solver).
+    #[must_use]
+    pub fn with_normalize_initial_objective(self, normalize_initial_objective: bool) -> Self {
+        Self {
+            normalize_initial_objective,
+            ..self
+        }
+    }
+
+    /// Enable or disable the steepest-descent fallback.
+    ///
+    /// If enabled, a scaled steepest-descent step
+    /// `$-\alpha\mathbf{D}^{-2}\mathbf{J}^\top\vec{r}$` is tried after
+    /// `$\max(1, \mathtt{patience}/2)$` consecutive rejected trust-region steps.
+    /// The step length `$\alpha$` starts at the minimizer of the linear
+    /// model along this direction and is halved until the objective decreases
+    /// sufficiently. If this fails, the trust-region iteration continues.
+    #[must_use]
+    pub fn with_gradient_fallback(self, gradient_fallback: bool) -> Self {
+        Self {
+            gradient_fallback,
+            ..self
+        }
+    }
+
+    /// Set the line search along rejected trust-region steps.
+    ///
+    /// With [`LineSearch::Backtracking`](enum.LineSearch.html#variant.Backtracking)
+    /// a rejected Levenberg-Marquardt step `$\vec{p}$` is not thrown away:
+    /// after the trust region shrank, the shorter steps `$\alpha\vec{p}$` along
+    /// the same direction are tried until one decreases the objective function
+    /// sufficiently. This saves the solutions of the subproblem for the smaller
+    /// trust regions if the direction is good but the model overestimates how
+    /// far it holds. The default is [`LineSearch::None`](enum.LineSearch.html#variant.None).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$c \notin (0, 1)$` or `$\mathtt{rho} \notin (0, 1)$`.
+    #[must_use]
+    pub fn with_line_search(self, line_search: LineSearch<F>) -> Self {
+        if let LineSearch::Backtracking { c, rho } = line_search {
+            assert!(c.is_positive() && c < F::one(), "c must be in (0, 1)");
+            assert!(rho.is_positive() && rho < F::one(), "rho must be in (0, 1)");
+        }
+        Self {
+            line_search,
+            ..self
+        }
+    }
+
+    /// Enable or disable the correction of the steps with the true Hessian.
+    ///
+    /// The Levenberg-Marquardt step minimizes the Gauss-Newton model, which
+    /// misses the second-order terms `$\sum_i r_i\nabla^2 r_i$` of the Hessian.
+    /// If the residuals at the solution are large, this makes the convergence
+    /// only linear. If enabled and the problem implements
+    /// [`hessian_vector_product`](trait.LeastSquaresProblem.html#method.hessian_vector_product),
+    /// every step is refined with a few conjugate gradient iterations on
+    /// ```math
+    ///   (\nabla^2 f + \lambda\mathbf{D}^2)\vec{p} = \mathbf{J}^\top\vec{r},
+    /// ```
+    /// starting at the Levenberg-Marquardt step. The iteration stops at the
+    /// trust-region boundary or on negative curvature, and the step is then
+    /// judged with the quadratic model of the true Hessian. If the refined step
+    /// does not decrease this model, the Levenberg-Marquardt step is used.
+    /// Every conjugate gradient iteration costs one Hessian-vector product.
+    #[must_use]
+    pub fn with_hessian_correction(self, hessian_correction: bool) -> Self {
+        Self {
+            hessian_correction,
+            ..self
+        }
+    }
+
+    /// Enable or disable the weighted `ftol` test.
+    ///
+    /// By default the `ftol` test uses the relative reduction of `$\|\vec{r}\|$`,
+    /// which is dominated by the residuals of the largest magnitude. If enabled,
+    /// the test uses `$\|\mathbf{W}\vec{r}\|$` instead, where `$\mathbf{W}$`
+    /// is diagonal with the inverse row norms of the first Jacobian. This is the
+    /// counterpart of the column scaling `$\mathbf{D}$` used for `xtol`, every
+    /// residual is measured relative to its sensitivity.
+    ///
+    /// This needs a copy of the Jacobian in every iteration.
+    #[must_use]
+    pub fn with_weighted_ftol(self, weighted_ftol: bool) -> Self {
+        Self {
+            weighted_ftol,
+            ..self
+        }
+    }
+
+    /// Enable or disable the computation of the parameter covariance.
+    ///
+    /// If enabled, the Jacobian is evaluated once more at the solution to compute the
+    /// [`covariance`](struct.MinimizationReport.html#structfield.covariance).
+    /// This evaluation is not counted in the report.
+    #[must_use]
+    pub fn with_covariance(self, covariance: bool) -> Self {
+        Self { covariance, ..self }
+    }
+
+    /// Enable or disable retaining the QR decomposition of the Jacobian at the solution.
+    ///
+    /// If enabled, the Jacobian is evaluated once more at the solution and the
+    /// [`qr_r_factor`](struct.MinimizationReport.html#structfield.qr_r_factor)
+    /// of its pivoted QR decomposition is stored in the report. This evaluation
+    /// is not counted in the report.
+    #[must_use]
+    pub fn with_qr_retention(self, qr_retention: bool) -> Self {
+        Self {
+            qr_retention,
+            ..self
+        }
+    }
+
+    /// Enable or disable minimum-norm steps for rank-deficient Jacobians.
+    ///
+    /// If the Jacobian does not have full column rank, in particular for
+    /// under-determined problems with `$m < n$`, the Gauss-Newton step is not
+    /// unique. By default a basic solution is used as in MINPACK, which sets the
+    /// components of the dependent columns to zero. If enabled, the step with
+    /// the smallest `$\|\mathbf{D}\vec{p}\|$` is used instead.
+    ///
+    /// All other steps are then orthogonal to the null space of the Jacobian
+    /// in the norm of `$\mathbf{D}$`. For a linear problem, the solution is the
+    /// least squares solution closest to the initial parameters, if
+    /// [`scale_diag`](#method.with_scale_diag) is disabled in the Euclidean
+    /// norm. If the residuals vanish at the initial parameters, the
+    /// minimization stops there right away.
+    #[must_use]
+    pub fn with_min_norm(self, min_norm: bool) -> Self {
+        Self { min_norm, ..self }
+    }
+
+    /// Enable or disable the column pivoting of the QR decomposition.
+    ///
+    /// The trust-region steps are computed from a QR decomposition of the
+    /// Jacobian. By default it uses column pivoting as in MINPACK, so
+    /// `$\mathbf{R}$` reveals the rank of the Jacobian. Without pivoting the
+    /// search for the pivot column and the update of the partial column norms
+    /// are skipped in every iteration. The Householder reflections dominate
+    /// the cost, so this only pays off for about a hundred parameters and more,
+    /// where the recomputations of partial column norms add up. For tens of
+    /// parameters the run time is the same.
+    ///
+    /// Disable pivoting only if the Jacobian has full column rank. The steps
+    /// are then the same up to rounding, for a well-conditioned Jacobian the
+    /// difference is of the order of the machine epsilon.
+    ///
+    /// The [`qr_r_factor`](struct.MinimizationReport.html#structfield.qr_r_factor)
+    /// and the covariance of the report are always computed with pivoting.
+    #[must_use]
+    pub fn with_pivoting(self, pivoting: bool) -> Self {
+        Self { pivoting, ..self }
+    }
+
+    /// Evaluate the Jacobian only once and reuse it in all iterations.
+    ///
+    /// Enable this if the residuals are affine in the parameters, then the
+    /// Jacobian does not change. The Jacobian at the initial parameters is
+    /// cached and only the residuals are evaluated after that. A linear
+    /// problem is then usually solved with a single accepted step, the next
+    /// iteration only confirms the convergence with the cached Jacobian.
+    ///
+    /// For a nonlinear problem the steps are computed with an outdated
+    /// Jacobian, the minimization converges slowly or not at all.
+    #[must_use]
+    pub fn with_constant_jacobian(self, constant_jacobian: bool) -> Self {
+        Self {
+            constant_jacobian,
+            ..self
+        }
+    }
+
+    /// Detect a constant Jacobian and reuse it like
+    /// [`with_constant_jacobian`](#method.with_constant_jacobian).
+    ///
+    /// The Jacobians at the first two points are compared. If they agree to
+    /// a relative tolerance of `$\sqrt{\varepsilon}$` in the Frobenius norm,
+    /// the residuals are taken to be affine in the parameters and the second
+    /// Jacobian is reused in all following iterations. Use this for a mix of
+    /// problems where it is not known in advance which ones are linear.
+    /// A numerical Jacobian of a linear problem usually differs by more than
+    /// the tolerance, then nothing changes.
+    ///
+    /// A nonlinear problem can have the same Jacobian at two points. Then the
+    /// reused Jacobian is outdated once the minimization moves on, and the
+    /// first rejected step turns the reuse off for the rest of the
+    /// minimization: the Jacobian is computed at the current parameters again
+    /// and in every following iteration.
+    ///
+    /// This has no effect if the constant Jacobian is enabled already.
+    #[must_use]
+    pub fn with_detect_constant_jacobian(self, detect_constant_jacobian: bool) -> Self {
+        Self {
+            detect_constant_jacobian,
+            ..self
+        }
+    }
+
+    /// Set how the scaling diagonal is updated after the first iteration.
+    ///
+    /// The default is [`DiagUpdate::MinpackMonotone`](enum.DiagUpdate.html#variant.MinpackMonotone).
+    #[must_use]
+    pub fn with_diag_update(self, diag_update: DiagUpdate) -> Self {
+        Self {
+            diag_update,
+            ..self
+        }
+    }
+
+    /// Apply the settings of `config` which are `Some`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a setting is invalid, like the builder method it replaces.
+    #[must_use]
+    pub fn with_override(self, config: &ConfigOverride<F>) -> Self {
+        let mut merged = self;
+        if let Some(ftol) = config.ftol {
+            merged = merged.with_ftol(ftol);
+        }
+        if let Some(xtol) = config.xtol {
+            merged = merged.with_xtol(xtol);
+        }
+        if let Some(gtol) = config.gtol {
+            merged = merged.with_gtol(gtol);
+        }
+        if let Some(stepbound) = config.stepbound {
+            merged = merged.with_stepbound(stepbound);
+        }
+        if let Some(patience) = config.patience {
+            merged = merged.with_patience(patience);
+        }
+        if let Some(max_evaluations) = config.max_evaluations {
+            merged = merged.with_max_evaluations(max_evaluations);
+        }
+        if let Some(max_iterations) = config.max_iterations {
+            merged = merged.with_max_iterations(max_iterations);
+        }
+        merged
+    }
+
+    /// Try to solve the given least squares problem.
+    ///
+    /// The paramters of the problem which are set when this function is called
+    /// are used as the initial guess for `$\vec{x}$`.
+    pub fn minimize<N, M, O>(&self, target: O) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        self.minimize_in(target, &mut None)
+    }
+
+    /// Like [`minimize`](#method.minimize), but failures are returned as an error.
+    ///
+    /// The minimization is `Ok` unless
+    /// [`TerminationReason::was_failure`](enum.TerminationReason.html#method.was_failure)
+    /// holds. In particular running out of evaluations or patience is `Ok`,
+    /// check the report if you need convergence. On failure the problem is
+    /// dropped.
+    pub fn try_minimize<N, M, O>(&self, target: O) -> Result<(O, MinimizationReport<F>), LmError<F>>
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let (target, report) = self.minimize(target);
+        if report.termination.was_failure() {
+            Err(LmError {
+                report: Box::new(report),
+            })
+        } else {
+            Ok((target, report))
+        }
+    }
+
+    /// Like [`minimize`](#method.minimize), with some settings replaced for this call.
+    ///
+    /// This minimizes with [`with_override(config)`](#method.with_override),
+    /// so a shared base configuration can be tuned per problem without
+    /// rebuilding it.
+    pub fn minimize_with_override<N, M, O>(
+        &self,
+        target: O,
+        config: &ConfigOverride<F>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        self.with_override(config).minimize(target)
+    }
+
+    /// Run at most `max_iterations` iterations on a problem in place.
+    ///
+    /// This is meant for tight loops which polish a solution repeatedly. The
+    /// parameters of `target` are the initial guess and are updated to the
+    /// result. Only the objective function before and after and the length of
+    /// the parameter change are reported, the statistics of the
+    /// [`MinimizationReport`](struct.MinimizationReport.html) are not computed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{max\_iterations} \leq 0$`.
+    pub fn refine<N, M, O>(&self, target: &mut O, max_iterations: usize) -> RefineReport<F>
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let before = target.params();
+        let (_, report) = self.with_max_iterations(max_iterations).minimize_scaled(
+            Borrowed(&mut *target),
+            &mut None,
+            |jacobian, residuals, factor| Ok(factor.factorize(jacobian, residuals, self.pivoting)),
+        );
+        let mut step = target.params();
+        step -= &before;
+        RefineReport {
+            termination: report.termination,
+            initial_objective: report.initial_objective,
+            objective_function: report.objective_function,
+            step_norm: enorm(&step),
+        }
+    }
+
+    /// Solve many independent least squares problems.
+    ///
+    /// Every problem is minimized like with [`minimize`](#method.minimize)
+    /// using this configuration. The results are returned in the same order.
+    /// The buffers of the solver are reused from one problem to the next if
+    /// they have the same number of parameters, so a batch of small problems
+    /// of the same shape allocates them only once.
+    pub fn minimize_batch<N, M, O, I>(&self, problems: I) -> Vec<(O, MinimizationReport<F>)>
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        I: IntoIterator<Item = O>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let problems = problems.into_iter();
+        let mut results = Vec::with_capacity(problems.size_hint().0);
+        let mut workspace = None;
+        results.extend(problems.map(|problem| self.minimize_in(problem, &mut workspace)));
+        results
+    }
+
+    /// Solve many independent least squares problems on the rayon thread pool.
+    ///
+    /// This works like [`minimize_batch`](#method.minimize_batch) but the
+    /// problems are distributed over the threads, every thread reuses its own
+    /// buffers. The results are returned in the same order as the problems.
+    /// Only available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn minimize_batch_parallel<N, M, O, I>(
+        &self,
+        problems: I,
+    ) -> Vec<(O, MinimizationReport<F>)>
+    where
+        F: Send + Sync,
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N> + Send,
+        I: IntoParallelIterator<Item = O>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        problems
+            .into_par_iter()
+            .map_init(
+                || None,
+                |workspace, problem| self.minimize_in(problem, workspace),
+            )
+            .collect()
+    }
+
+    /// Try to solve the given least squares problem with complex residuals.
+    ///
+    /// The problem is minimized as a real problem of double the size, see
+    /// [`ComplexLeastSquaresProblem`](trait.ComplexLeastSquaresProblem.html).
+    /// The parameters of the problem which are set when this function is called
+    /// are used as the initial guess.
+    pub fn minimize_complex<N, M, O>(&self, target: O) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: Dim,
+        O: ComplexLeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<Complex<F>, N> + Allocator<Complex<F>, M> + Allocator<Complex<F>, M, N>,
+    {
+        let n = target.params().shape_generic().0;
+        let (target, report) = self.minimize(RealEmbedding::new(target, n));
+        (target.into_inner(), report)
+    }
+
+    /// Try to solve the given least squares problem and keep the diagnostics.
+    ///
+    /// This works like [`minimize`](#method.minimize) but the residuals are
+    /// computed with
+    /// [`DiagnosticResiduals::residuals_with_diagnostics`](trait.DiagnosticResiduals.html#tymethod.residuals_with_diagnostics).
+    /// Additionally the diagnostics of the evaluation at the final parameters
+    /// are returned, they are `None` if the residuals could not be computed
+    /// there.
+    #[allow(clippy::type_complexity)]
+    pub fn minimize_with_diagnostics<N, M, O>(
+        &self,
+        target: O,
+    ) -> (O, MinimizationReport<F>, Option<O::Diagnostics>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: DiagnosticResiduals<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let (target, report) = self.minimize(DiagnosticEvaluation::new(target));
+        let (target, diagnostics) = target.into_inner();
+        (target, report, diagnostics)
+    }
+
+    /// Try to solve the given least squares problem with residuals computed in parallel.
+    ///
+    /// This works like [`minimize`](#method.minimize) but the residuals are
+    /// distributed over the rayon thread pool, see
+    /// [`ParallelResiduals`](trait.ParallelResiduals.html).
+    /// Only available with the `rayon` feature.
+    #[cfg(feature = "rayon")]
+    pub fn minimize_parallel<N, M, O>(&self, target: O) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: ParallelResiduals<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let (target, report) = self.minimize(ParallelEvaluation::new(target));
+        (target.into_inner(), report)
+    }
+
+    /// Try to solve the given least squares problem with parameters on a manifold.
+    ///
+    /// The steps are computed in the tangent space at the current parameters
+    /// and applied with the retraction, see
+    /// [`ManifoldLeastSquaresProblem`](trait.ManifoldLeastSquaresProblem.html).
+    /// The parameters of the problem which are set when this function is called
+    /// are used as the initial guess and must be on the manifold.
+    /// If the tangent dimension `$k$` is dynamic, the Jacobian is computed once
+    /// more at the initial guess to determine it.
+    pub fn minimize_manifold<N, M, K, O>(&self, target: O) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<K> + DimMax<K>,
+        K: Dim,
+        O: ManifoldLeastSquaresProblem<F, M, N, K>,
+        DefaultAllocator: Allocator<F, N>
+            + Allocator<F, K>
+            + Allocator<F, M>
+            + Allocator<F, M, K>
+            + Reallocator<F, M, K, DimMaximum<M, K>, K>
+            + Allocator<usize, K>,
+    {
+        let x = target.params();
+        // a dynamic tangent dimension is only known from the Jacobian
+        let k = K::try_to_usize()
+            .unwrap_or_else(|| target.jacobian().map_or(0, |jacobian| jacobian.ncols()));
+        let target = TangentSpace::new(target, x, K::from_usize(k));
+        let subproblem = |jacobian, residuals: &mut _, factor: &mut IncrementalQR<F>| {
+            Ok(factor.factorize(jacobian, residuals, self.pivoting))
+        };
+        let mut lm = match LM::start(self, target) {
+            Err((target, report)) => return (target.into_inner(), report),
+            Ok(lm) => lm,
+        };
+        loop {
+            let result = lm.iterate_with(subproblem);
+            lm.reparametrize(|target, step| target.recenter(step));
+            if let Err(reason) = result {
+                let (target, mut report) = lm.into_report(reason);
+                self.analyze_solution(&target, &mut report);
+                return (target.into_inner(), report);
+            }
+        }
+    }
+
+    /// Try to solve a least squares problem with a block-structured Jacobian.
+    ///
+    /// This works like [`minimize`](#method.minimize) but the linear systems
+    /// in every iteration are solved block by block, see
+    /// [`BlockJacobian`](struct.BlockJacobian.html) for the required layout.
+    /// This is much faster than the dense QR decomposition if there are
+    /// many blocks.
+    ///
+    /// The minimization terminates with
+    /// [`WrongDimensions`](enum.TerminationReason.html#variant.WrongDimensions)
+    /// if the number of parameters or residuals does not match the block
+    /// structure.
+    pub fn minimize_blocks<N, M, O>(
+        &self,
+        target: O,
+        blocks: &BlockJacobian,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let n = target.params().nrows();
+        if blocks.ncols() != n {
+            let report = MinimizationReport {
+                termination: TerminationReason::WrongDimensions("block structure"),
+                ..MinimizationReport::unevaluated(self, n, target.jacobian_is_numerical())
+            };
+            return (target, report);
+        }
+        self.minimize_with(target, &mut None, |jacobian, residuals, _| {
+            // the rows of the Jacobian are checked against the residuals
+            if jacobian.nrows() != blocks.nrows() {
+                return Err(TerminationReason::WrongDimensions("block structure"));
+            }
+            Ok(BlockLeastSquaresDiagonalProblem::new(
+                blocks, jacobian, residuals,
+            ))
+        })
+    }
+
+    /// Try to solve a global fit of several datasets with shared parameters.
+    ///
+    /// This works like [`minimize_blocks`](#method.minimize_blocks) with the
+    /// [block structure](struct.GlobalFitProblem.html#method.block_structure)
+    /// of the global fit, the shared parameters are coupled through a Schur
+    /// complement. If the residuals cannot be computed at the initial guess,
+    /// the minimization fails like with [`minimize`](#method.minimize).
+    pub fn minimize_global_fit<N, M, P>(
+        &self,
+        target: GlobalFitProblem<F, M, N, P>,
+    ) -> (GlobalFitProblem<F, M, N, P>, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: Dim,
+        P: LeastSquaresProblem<F, M, N>,
+    {
+        match target.block_structure() {
+            Some(blocks) => self.minimize_blocks(target, &blocks),
+            None => self.minimize(target),
+        }
+    }
+
+    /// Minimize a problem on random minibatches of its residuals.
+    ///
+    /// Every iteration uses the residuals of `batch_size` distinct indices,
+    /// drawn anew after every iteration with a generator seeded by `seed`, so
+    /// the result is reproducible. See
+    /// [`StochasticProblem`](trait.StochasticProblem.html). The trust-region
+    /// state carries over from one batch to the next and the convergence tests
+    /// use the objective function of the current batch. The iterations are
+    /// cheap but the steps only follow the full objective function on average:
+    /// on noisy data the minimization ends near the full-batch solution,
+    /// usually with [`LostPatience`](enum.TerminationReason.html#variant.LostPatience)
+    /// because a new batch keeps moving the minimizer. With `batch_size` at least the number of
+    /// residuals this is the usual minimization, with one more evaluation per
+    /// iteration.
+    ///
+    /// The report refers to the last batch, its
+    /// [`number_of_residuals`](struct.MinimizationReport.html#structfield.number_of_residuals)
+    /// is the batch size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{batch\_size} \leq 0$`.
+    pub fn minimize_stochastic<N, P>(
+        &self,
+        target: P,
+        batch_size: usize,
+        seed: u64,
+    ) -> (P, MinimizationReport<F>)
+    where
+        N: Dim,
+        P: StochasticProblem<F, N>,
+        Dynamic: DimMin<N> + DimMax<N>,
+        DefaultAllocator: Allocator<F, N>
+            + Allocator<F, Dynamic, N>
+            + Reallocator<F, Dynamic, N, DimMaximum<Dynamic, N>, N>
+            + Allocator<usize, N>,
+    {
+        assert!(batch_size > 0, "batch_size must be > 0");
+        let target = Minibatch::new(target, batch_size, seed);
+        let subproblem = |jacobian, residuals: &mut _, factor: &mut IncrementalQR<F>| {
+            Ok(factor.factorize(jacobian, residuals, self.pivoting))
+        };
+        let mut lm = match LM::start(self, target) {
+            Err((target, report)) => return (target.into_inner(), report),
+            Ok(lm) => lm,
+        };
+        loop {
+            let result = lm
+                .iterate_with(subproblem)
+                .and_then(|()| lm.resample(Minibatch::next_batch));
+            if let Err(reason) = result {
+                let (target, mut report) = lm.into_report(reason);
+                self.analyze_solution(&target, &mut report);
+                return (target.into_inner(), report);
+            }
+        }
+    }
+
+    /// Minimize a problem whose Jacobian is only available as an operator.
+    ///
+    /// The problem provides the products `$\mathbf{J}\vec{v}$` and
+    /// `$\mathbf{J}^\top\vec{u}$`, see [`MatrixFreeProblem`](trait.MatrixFreeProblem.html).
+    /// The linear systems of the trust-region subproblem are solved with the
+    /// conjugate gradient method on the normal equations, every iteration of
+    /// it applies both products once. This is the only option if `$n$` is so
+    /// large that the Jacobian cannot be stored, but the steps are only as
+    /// accurate as the iteration converges and the condition number of
+    /// `$\mathbf{J}$` enters squared. Unless
+    /// [`JacobianOperator::column_norms`](trait.JacobianOperator.html#method.column_norms)
+    /// is provided, every iteration also applies the operator to the `$n$`
+    /// unit vectors.
+    ///
+    /// The options which need the Jacobian matrix, like
+    /// [`with_autoscale`](#method.with_autoscale),
+    /// [`with_weighted_ftol`](#method.with_weighted_ftol) and the statistics
+    /// at the solution, have no effect.
+    pub fn minimize_matrix_free<N, P>(&self, target: P) -> (P, MinimizationReport<F>)
+    where
+        N: Dim,
+        P: MatrixFreeProblem<F, N>,
+        Dynamic: DimMin<N> + DimMax<N>,
+        DefaultAllocator: Allocator<F, N>
+            + Allocator<F, Dynamic, N>
+            + Reallocator<F, Dynamic, N, DimMaximum<Dynamic, N>, N>
+            + Allocator<usize, N>,
+    {
+        let mut lm = match LM::start(self, MatrixFree::new(target)) {
+            Err((target, report)) => return (target.into_inner(), report),
+            Ok(lm) => lm,
+        };
+        loop {
+            let result = lm.iterate_linearized(|lm, residuals| {
+                let n = lm.x.nrows();
+                let lls = timed(&mut lm.evaluation_time, || lm.target.linearize(residuals))
+                    .ok_or(TerminationReason::User("jacobian"))?;
+                if lls.column_norms().nrows() != n {
+                    return Err(TerminationReason::WrongDimensions("jacobian"));
+                }
+                Ok(lls)
+            });
+            if let Err(reason) = result {
+                let (target, report) = lm.into_report(reason);
+                return (target.into_inner(), report);
+            }
+        }
+    }
+
+    /// Minimize a problem subject to the equality constraints `$\vec{c}(\vec{x}) = 0$`.
+    ///
+    /// The constraints are given as a problem of their own whose residuals
+    /// are `$\vec{c}(\vec{x})$`, they can be nonlinear. This is an augmented
+    /// Lagrangian method: the residuals
+    /// `$\sqrt{\mu}\,(\vec{c}(\vec{x}) + \vec{\lambda}/\mu)$` with the
+    /// penalty `$\mu$` and the multipliers `$\vec{\lambda}$` are appended to
+    /// the residuals of `target` and the augmented problem is minimized with
+    /// [`minimize`](#method.minimize). After every minimization the
+    /// multipliers are updated to `$\vec{\lambda} + \mu\vec{c}(\vec{x})$` and
+    /// the penalty grows tenfold if the violation of the constraints did not
+    /// shrink by a factor of four. Unlike with
+    /// [`with_linear_penalty`](trait.LeastSquaresProblem.html#method.with_linear_penalty)
+    /// the constraints are fulfilled exactly in the limit without an infinite
+    /// penalty. Every minimization starts at the solution of the last one.
+    ///
+    /// The iteration stops once `$\max_i |c_i(\vec{x})| \leq \mathtt{tol}$`,
+    /// if a minimization fails or after 20 minimizations. Check
+    /// [`constraint_violation`](struct.ConstrainedReport.html#structfield.constraint_violation)
+    /// of the report.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{tol} < 0$`.
+    pub fn minimize_constrained<N, M, K, O, C>(
+        &self,
+        target: O,
+        mut constraints: C,
+        tol: F,
+    ) -> (O, ConstrainedReport<F>)
+    where
+        N: Dim,
+        M: Dim,
+        K: Dim,
+        O: LeastSquaresProblem<F, M, N>,
+        C: LeastSquaresProblem<F, K, N, ParameterStorage = Owned<F, N>>,
+        Dynamic: DimMin<N> + DimMax<N>,
+        DefaultAllocator: Allocator<F, N>
+            + Allocator<F, Dynamic, N>
+            + Reallocator<F, Dynamic, N, DimMaximum<Dynamic, N>, N>
+            + Allocator<usize, N>,
+    {
+        const MAX_OUTER_ITERATIONS: usize = 20;
+        assert!(tol >= F::zero(), "tol must be >= 0");
+        constraints.set_params(&target.params().clone_owned());
+        let k = constraints.residuals().map_or(0, |c| c.nrows());
+        let mut multipliers = dvector(alloc::vec![F::zero(); k]);
+        let mut penalty: F = convert(10.);
+        let mut target = Augmented::new(target, constraints, penalty, multipliers.clone());
+        let mut previous_violation = <F as Float>::infinity();
+        let mut outer_iterations = 0;
+        loop {
+            let (augmented, report) = self.minimize(target);
+            target = augmented;
+            outer_iterations += 1;
+            let constraints = target.constraint_values();
+            let violation = match &constraints {
+                Some(c) if c.iter().all(|c| c.is_finite()) => c.amax(),
+                _ => <F as Float>::nan(),
+            };
+            if report.termination.was_failure()
+                || !violation.is_finite()
+                || violation <= tol
+                || outer_iterations == MAX_OUTER_ITERATIONS
+            {
+                let report = ConstrainedReport {
+                    report,
+                    multipliers,
+                    penalty,
+                    constraint_violation: violation,
+                    outer_iterations,
+                };
+                return (target.into_inner(), report);
+            }
+            for (lambda, c) in multipliers.iter_mut().zip(constraints.unwrap().iter()) {
+                *lambda += penalty * *c;
+            }
+            if violation > previous_violation * convert(0.25) {
+                penalty *= convert(10.);
+            }
+            previous_violation = violation;
+            target.update(penalty, &multipliers);
+        }
+    }
+
+    fn minimize_in<N, M, O>(
+        &self,
+        target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        self.minimize_with(target, workspace, |jacobian, residuals, factor| {
+            Ok(factor.factorize(jacobian, residuals, self.pivoting))
+        })
+    }
+
+    fn minimize_with<N, M, O, S>(
+        &self,
+        target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        S: Linearization<F, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        let (target, mut report) = self.minimize_scaled(target, workspace, subproblem);
+        self.analyze_solution(&target, &mut report);
+        (target, report)
+    }
+
+    fn minimize_scaled<N, M, O, S>(
+        &self,
+        target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        S: Linearization<F, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        if !self.normalize_initial_objective {
+            return self.minimize_autoscaled(target, workspace, subproblem);
+        }
+        let norm = match target.residuals() {
+            Some(residuals) => enorm(&residuals),
+            // let the minimization report the failure
+            None => return self.minimize_autoscaled(target, workspace, subproblem),
+        };
+        if !norm.is_finite() || norm.is_zero() {
+            return self.minimize_autoscaled(target, workspace, subproblem);
+        }
+        // f(x) = ||r||^2 / 2 becomes one
+        let scale = Float::sqrt(convert::<f64, F>(2.)) / norm;
+        let (target, mut report) =
+            self.minimize_autoscaled(Scaled::new(target, scale), workspace, subproblem);
+        let unscale = F::one() / (scale * scale);
+        report.objective_function *= unscale;
+        report.initial_objective *= unscale;
+        (target.into_inner(), report)
+    }
+
+    fn minimize_autoscaled<N, M, O, S>(
+        &self,
+        mut target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        S: Linearization<F, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        if !self.autoscale {
+            return self.minimize_unscaled(target, workspace, subproblem);
+        }
+        let jacobian = match target.jacobian() {
+            // let the minimization report the failure
+            None => return self.minimize_unscaled(target, workspace, subproblem),
+            Some(jacobian) => jacobian,
+        };
+        let mut scale = target.params();
+        let mut x = scale.clone();
+        for (s, column) in scale.iter_mut().zip(jacobian.column_iter()) {
+            let norm = enorm(&column);
+            *s = if norm.is_zero() || !norm.is_finite() {
+                F::one()
+            } else {
+                F::one() / norm
+            };
+        }
+        x.component_div_assign(&scale);
+        target.set_params(&x);
+        let jac_factor = scale.clone();
+        let target = target.with_param_transform(
+            move |x| {
+                let mut x = x.clone();
+                x.component_mul_assign(&scale);
+                x
+            },
+            move |_| jac_factor.clone(),
+        );
+        let (target, report) = self.minimize_unscaled(target, workspace, subproblem);
+        (target.into_inner(), report)
+    }
+
+    fn minimize_unscaled<N, M, O, S>(
+        &self,
+        target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        S: Linearization<F, N>,
+        DefaultAllocator:
             Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
     {
-        let (mut lm, mut residuals) = match LM::new(self, target) {
+        let mut lm = match LM::start_in(self, target, workspace) {
             Err(report) => return report,
-            Ok(res) => res,
+            Ok(lm) => lm,
+        };
+        loop {
+            if let Err(reason) = lm.iterate_with(&subproblem) {
+                return lm.into_report_in(reason, workspace);
+            }
+        }
+    }
+
+    /// Prepare the minimization of the given least squares problem without running it.
+    ///
+    /// Use the returned state to run the minimization step by step with
+    /// [`LM::step`](struct.LM.html#method.step). If the minimization already
+    /// terminates at the initial guess, the problem and the report are returned
+    /// instead.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn solver<N, M, O>(
+        &self,
+        target: O,
+    ) -> Result<LM<'_, F, N, M, O>, (O, MinimizationReport<F>)>
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        LM::start(self, target)
+    }
+
+    /// Like [`minimize`](#method.minimize), but use `jacobian` in the first iteration.
+    ///
+    /// `jacobian` approximates the Jacobian at the initial parameters, see
+    /// [`LM::with_initial_jacobian`](struct.LM.html#method.with_initial_jacobian).
+    /// The problem is minimized like with [`solver`](#method.solver), so
+    /// [`with_autoscale`](#method.with_autoscale) has no effect.
+    pub fn minimize_with_jacobian<N, M, O>(
+        &self,
+        target: O,
+        jacobian: Matrix<F, M, N, O::JacobianStorage>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        match self.solver(target) {
+            Ok(lm) => lm.with_initial_jacobian(jacobian).finish(),
+            Err(report) => report,
+        }
+    }
+
+    /// Like [`solver`](#method.solver), but continue from a checkpoint.
+    ///
+    /// The parameters of the problem are set to the ones of the checkpoint, see
+    /// [`LmCheckpoint`](struct.LmCheckpoint.html). With the same configuration
+    /// as for the original minimization, it continues exactly where it stopped.
+    ///
+    /// The configuration may also differ, for example to polish the result of
+    /// a coarse fit with tighter tolerances. The tolerances, the limits and the
+    /// strategies of `self` apply from the checkpoint on, the state of the
+    /// tests which `self` does not enable is dropped. A checkpoint of a
+    /// terminated minimization keeps its termination reason, clear
+    /// [`termination`](struct.LmCheckpoint.html#structfield.termination) to
+    /// continue it with the new configuration.
+    ///
+    /// This fails with
+    /// [`WrongDimensions`](enum.TerminationReason.html#variant.WrongDimensions)
+    /// if the number of parameters does not match and with
+    /// [`User`](enum.TerminationReason.html#variant.User) if the residuals
+    /// cannot be computed.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    pub fn solver_from_checkpoint<N, M, O>(
+        &self,
+        target: O,
+        checkpoint: &LmCheckpoint<F, N>,
+    ) -> Result<LM<'_, F, N, M, O>, (O, MinimizationReport<F>)>
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        LM::resume(self, target, checkpoint)
+    }
+
+    /// Continue a minimization from a checkpoint until it terminates.
+    ///
+    /// See [`solver_from_checkpoint`](#method.solver_from_checkpoint).
+    pub fn minimize_from_checkpoint<N, M, O>(
+        &self,
+        target: O,
+        checkpoint: &LmCheckpoint<F, N>,
+    ) -> (O, MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        match self.solver_from_checkpoint(target, checkpoint) {
+            Ok(lm) => lm.finish(),
+            Err(report) => report,
+        }
+    }
+
+    /// Compute the optional statistics from the Jacobian at the solution.
+    ///
+    /// If the Jacobian or the residuals at the solution do not have the
+    /// dimensions of the minimization, no statistics are computed and the
+    /// report terminates with `WrongDimensions`.
+    fn analyze_solution<N, M, O>(&self, target: &O, report: &mut MinimizationReport<F>)
+    where
+        N: Dim,
+        M: DimMin<N> + DimMax<N>,
+        O: LeastSquaresProblem<F, M, N>,
+        DefaultAllocator:
+            Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+    {
+        if let Some(limit) = self.param_magnitude_limit {
+            for (i, x) in target.params().iter().enumerate() {
+                if let Some(side) = bound_side(*x, limit) {
+                    report.active_bounds.push((i, side));
+                }
+            }
+        }
+        if !self.covariance && !self.qr_retention {
+            return;
+        }
+        let jacobian = match target.jacobian() {
+            Some(jacobian) => jacobian,
+            None => return,
+        };
+        let (m, n) = jacobian.shape();
+        if m != report.number_of_residuals || n != report.number_of_parameters {
+            report.wrong_dimensions_after_fit("jacobian");
+            return;
+        }
+        let residuals = match self.qr_retention.then(|| target.residuals()).flatten() {
+            Some(residuals) if residuals.nrows() != m => {
+                report.wrong_dimensions_after_fit("residuals");
+                return;
+            }
+            residuals => residuals,
+        };
+        let variance = report.residual_variance().filter(|v| v.is_finite());
+        if let (true, Some(variance)) = (self.covariance, variance) {
+            let jacobian = DMatrix::from_iterator(m, n, jacobian.iter().copied());
+            report.covariance = jacobian
+                .tr_mul(&jacobian)
+                .cholesky()
+                .map(|cholesky| cholesky.inverse() * variance);
+        }
+        if self.qr_retention {
+            let data = m.saturating_sub(target.number_of_penalty_residuals());
+            // the permuted rows of the Jacobian as columns
+            let mut rows = DMatrix::zeros(n, data);
+            for i in 0..data {
+                for j in 0..n {
+                    rows[(j, i)] = jacobian[(i, j)];
+                }
+            }
+            let qr = PivotedQR::new(jacobian);
+            let (r, permutation) = qr.r_factor();
+            if r.nrows() == n {
+                let rows = rows.select_rows(permutation.iter());
+                report.leverages = r.tr_solve_upper_triangular(&rows).map(|z| {
+                    DVector::from_iterator(data, z.column_iter().map(|z| z.norm_squared()))
+                });
+            }
+            if let Some(mut residuals) = residuals {
+                let k = r.nrows();
+                let lls = qr.into_least_squares_diagonal_problem_in_place(&mut residuals);
+                report.qr_qt_residuals = Some(DVector::from_iterator(
+                    k,
+                    lls.qt_b().iter().copied().take(k),
+                ));
+            }
+            report.qr_r_factor = Some(r);
+            report.qr_permutation = Some(permutation);
+        }
+    }
+}
+
+/// The Jacobian type of a problem.
+type Jacobian<F, M, N, O> = Matrix<F, M, N, <O as LeastSquaresProblem<F, M, N>>::JacobianStorage>;
+
+/// The detection of a constant Jacobian, see `with_detect_constant_jacobian`.
+#[derive(Clone)]
+enum JacobianDetection<X, J> {
+    /// No Jacobian was computed yet
+    Pending,
+    /// The first Jacobian and the parameters it was computed at
+    First(X, J),
+    /// The first two Jacobians agreed, it is reused
+    Constant,
+    /// Not enabled, or it ended with differing Jacobians or a rejected step
+    Off,
+}
+
+/// Residuals and Jacobian scaled by the weights `$\mathbf{W}$` of the weighted `ftol` test.
+struct WeightedResiduals<F: RealField> {
+    weights: DVector<F>,
+    /// `$\mathbf{W}\vec{r}$` at the current parameters
+    residuals: DVector<F>,
+    /// `$\mathbf{W}\mathbf{J}$` at the current parameters
+    jacobian: DMatrix<F>,
+}
+
+impl<X, J> JacobianDetection<X, J> {
+    fn new<F>(config: &LevenbergMarquardt<F>) -> Self {
+        if config.detect_constant_jacobian && !config.constant_jacobian {
+            Self::Pending
+        } else {
+            Self::Off
+        }
+    }
+}
+
+impl<F: RealField + Float + Copy> WeightedResiduals<F> {
+    fn weigh<M: Dim, RS: Storage<F, M>>(&self, residuals: &Vector<F, M, RS>) -> DVector<F> {
+        DVector::from_iterator(
+            residuals.nrows(),
+            residuals
+                .iter()
+                .zip(self.weights.iter())
+                .map(|(r, w)| *r * *w),
+        )
+    }
+
+    /// Compute the weighted residuals at `$\vec{x} - \vec{p}$` and the
+    /// actual and predicted relative reduction of their norm.
+    fn reduction<M, N, RS>(
+        &self,
+        new_residuals: &Vector<F, M, RS>,
+        step: &OVector<F, N>,
+    ) -> (DVector<F>, F, F)
+    where
+        M: Dim,
+        N: Dim,
+        RS: Storage<F, M>,
+        DefaultAllocator: Allocator<F, N>,
+    {
+        let norm = enorm(&self.residuals);
+        let new_residuals = self.weigh(new_residuals);
+        let new_norm = enorm(&new_residuals);
+        let actual_reduction = if new_norm * convert(0.1) < norm {
+            F::one() - Float::powi(new_norm / norm, 2)
+        } else {
+            -F::one()
         };
-        let n = lm.x.nrows();
+        let step = DVector::from_iterator(step.nrows(), step.iter().copied());
+        let linearized = &self.residuals - &self.jacobian * step;
+        let predicted_reduction = F::one() - Float::powi(enorm(&linearized) / norm, 2);
+        (new_residuals, actual_reduction, predicted_reduction)
+    }
+}
+
+/// An iterator over the iterations of a minimization.
+///
+/// Created with [`LM::iterations`](struct.LM.html#method.iterations).
+pub struct Iterations<'l, 'a, F, N, M, O>
+where
+    F: RealField + Copy,
+    N: Dim,
+    M: DimMin<N> + DimMax<N>,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N>,
+{
+    lm: &'l mut LM<'a, F, N, M, O>,
+}
+
+impl<'l, 'a, F, N, M, O> Iterator for Iterations<'l, 'a, F, N, M, O>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: DimMin<N> + DimMax<N>,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator:
+        Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+{
+    type Item = LmCheckpoint<F, N>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.lm.termination.is_some() {
+            return None;
+        }
+        let iteration = self.lm.report.terminated_at_iteration;
+        let _ = self.lm.step();
+        // no item if it terminated before starting another iteration
+        (self.lm.report.terminated_at_iteration > iteration).then(|| self.lm.checkpoint())
+    }
+}
+
+/// A snapshot of the complete state of a running minimization.
+///
+/// Take it between iterations with [`LM::checkpoint`](struct.LM.html#method.checkpoint)
+/// and continue with
+/// [`LevenbergMarquardt::minimize_from_checkpoint`](struct.LevenbergMarquardt.html#method.minimize_from_checkpoint)
+/// or [`solver_from_checkpoint`](struct.LevenbergMarquardt.html#method.solver_from_checkpoint).
+/// If the configuration and the problem are the same, the resumed minimization
+/// performs exactly the same iterations as the uninterrupted one.
+///
+/// All fields are public so that the checkpoint can be stored in any format.
+/// The residuals are not part of it, they are recomputed at `x` when resuming.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LmCheckpoint<F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The current parameters `$\vec{x}$`.
+    pub x: OVector<F, N>,
+    /// The diagonal of the scaling matrix `$\mathbf{D}$`.
+    pub diag: OVector<F, N>,
+    /// The trust-region radius `$\Delta$`.
+    pub delta: F,
+    /// The current Levenberg-Marquardt parameter `$\lambda$`.
+    pub lambda: F,
+    /// The number of inner iterations of the search for `$\lambda$` for the
+    /// last trust-region step, see
+    /// [`LMState::lambda_iterations`](struct.LMState.html#structfield.lambda_iterations).
+    pub lambda_iterations: usize,
+    /// `$\|\mathbf{D}\vec{x}\|$`
+    pub xnorm: F,
+    /// The scaled gradient norm of the last iteration.
+    pub gnorm: F,
+    /// `$\|\vec{r}(\vec{x})\|$`
+    pub residuals_norm: F,
+    /// Set if no trust-region step was tried yet.
+    pub first_trust_region_iteration: bool,
+    /// Set if the diagonal was not initialized yet.
+    pub first_update: bool,
+    /// The number of consecutive rejected trust-region steps.
+    pub rejected_steps: usize,
+    /// The number of consecutive trial steps with non-finite residuals.
+    pub non_finite_steps: usize,
+    /// The number of consecutive trial steps which did not decrease the
+    /// objective function for the divergence test.
+    pub unproductive_steps: usize,
+    /// The row weights of the weighted `ftol` test, once initialized.
+    pub weights: Option<DVector<F>>,
+    /// The typical magnitudes for the `xtol` test.
+    pub typical_magnitudes: Option<OVector<F, N>>,
+    /// The objective function of the last iterations for the stall test.
+    pub objective_history: Vec<F>,
+    /// Set if the diagonal was reset by the stall test and the objective
+    /// function did not decrease enough over a window since.
+    pub stall_reset: bool,
+    /// The number of evaluations at the last accepted step, where the budget
+    /// of `with_patience_reset` starts.
+    pub patience_start: usize,
+    /// The scaled gradient norms of the last iterations for the gradient
+    /// plateau test.
+    pub gradient_history: Vec<F>,
+    /// The residual norms of the last accepted points for the non-monotone
+    /// acceptance.
+    pub recent_residuals_norms: Vec<F>,
+    /// The parameters of the last two iterations for the cycle test.
+    pub previous_params: Vec<OVector<F, N>>,
+    /// The relative changes of the parameters in the last accepted step.
+    pub relative_step: Option<OVector<F, N>>,
+    /// The number of consecutive iterations which returned to the point
+    /// before the last one.
+    pub cycles: usize,
+    /// Set if the minimization already terminated.
+    pub termination: Option<TerminationReason>,
+    /// See [`MinimizationReport::number_of_evaluations`](struct.MinimizationReport.html#structfield.number_of_evaluations).
+    pub number_of_evaluations: usize,
+    /// See [`MinimizationReport::objective_function`](struct.MinimizationReport.html#structfield.objective_function).
+    pub objective_function: F,
+    /// See [`MinimizationReport::initial_objective`](struct.MinimizationReport.html#structfield.initial_objective).
+    pub initial_objective: F,
+    /// See [`MinimizationReport::final_step_norm`](struct.MinimizationReport.html#structfield.final_step_norm).
+    pub final_step_norm: F,
+    /// See [`MinimizationReport::terminated_at_iteration`](struct.MinimizationReport.html#structfield.terminated_at_iteration).
+    pub iteration: usize,
+    /// See [`MinimizationReport::trust_region_reductions`](struct.MinimizationReport.html#structfield.trust_region_reductions).
+    pub trust_region_reductions: usize,
+    /// See [`MinimizationReport::incremental_factorizations`](struct.MinimizationReport.html#structfield.incremental_factorizations).
+    pub incremental_factorizations: usize,
+    /// See [`MinimizationReport::gradient_norm`](struct.MinimizationReport.html#structfield.gradient_norm).
+    pub gradient_norm: Option<F>,
+    /// See [`MinimizationReport::lambda`](struct.MinimizationReport.html#structfield.lambda).
+    pub report_lambda: Option<F>,
+    /// See [`MinimizationReport::certificate`](struct.MinimizationReport.html#structfield.certificate).
+    pub certificate: ConvergenceCertificate<F>,
+}
+
+/// Whether a convergence test with the given tolerance is active.
+///
+/// A tolerance of zero disables the test, except with MINPACK compatibility.
+fn test_enabled<F: RealField>(tol: F) -> bool {
+    tol > F::zero() || cfg!(feature = "minpack-compat")
+}
+
+/// The side of the parameter magnitude limit `x` is at, if any.
+///
+/// See [`MinimizationReport::active_bounds`](struct.MinimizationReport.html#structfield.active_bounds).
+fn bound_side<F: RealField + Float>(x: F, limit: F) -> Option<BoundSide> {
+    let at_bound = limit * (F::one() - Float::sqrt(F::default_epsilon()));
+    if x >= at_bound {
+        Some(BoundSide::Upper)
+    } else if x <= -at_bound {
+        Some(BoundSide::Lower)
+    } else {
+        None
+    }
+}
+
+/// The state which `LM::propose_step` restores after solving the subproblem.
+struct ProposalState<F, N, J>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    report: MinimizationReport<F>,
+    diag: OVector<F, N>,
+    delta: F,
+    lambda: F,
+    lambda_iterations: usize,
+    xnorm: F,
+    gnorm: F,
+    first_update: bool,
+    first_trust_region_iteration: bool,
+    gradient_history: VecDeque<F>,
+    gradient_norms: Option<(F, F)>,
+    constant_jacobian: Option<J>,
+    jacobian_detection: JacobianDetection<OVector<F, N>, J>,
+    reused_jacobians: usize,
+    incremental_qr: IncrementalQR<F>,
+    initial_jacobian: Option<J>,
+}
+
+/// The buffers of a finished minimization for the next problem of a batch.
+struct Workspace<F, N, S>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    tmp: Vector<F, N, S>,
+    diag: OVector<F, N>,
+    objective_history: VecDeque<F>,
+    gradient_history: VecDeque<F>,
+    recent_residuals_norms: VecDeque<F>,
+    previous_params: VecDeque<OVector<F, N>>,
+}
+
+/// Struct which holds the state of the LM algorithm and which implements its individual steps.
+///
+/// Create it with [`LevenbergMarquardt::solver`](struct.LevenbergMarquardt.html#method.solver).
+pub struct LM<'a, F, N, M, O>
+where
+    F: RealField + Copy,
+    N: Dim,
+    M: DimMin<N> + DimMax<N>,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N>,
+{
+    config: &'a LevenbergMarquardt<F>,
+    /// Current parameters `$\vec{x}$`
+    x: Vector<F, N, O::ParameterStorage>,
+    tmp: Vector<F, N, O::ParameterStorage>,
+    /// The implementation of `LeastSquaresProblem`
+    target: O,
+    /// Statistics and termination reasons, used for return value
+    report: MinimizationReport<F>,
+    /// Residuals at `$\vec{x}$`, consumed while an iteration is in progress
+    residuals: Option<Vector<F, M, O::ResidualStorage>>,
+    /// Set once the minimization terminated
+    termination: Option<TerminationReason>,
+    /// The delta from the trust-region algorithm
+    delta: F,
+    lambda: F,
+    /// Number of `$\lambda$` values the subproblem solver tried for the last step
+    lambda_iterations: usize,
+    /// `$\|\mathbf{D}\vec{x}\|`
+    xnorm: F,
+    gnorm: F,
+    residuals_norm: F,
+    /// Relative reductions of the last trust-region step, for the observer
+    predicted_reduction: F,
+    actual_reduction: F,
+    /// The diagonal of `$\mathbf{D}$`
+    diag: OVector<F, N>,
+    /// Flag to check if it is the first trust region iteration
+    first_trust_region_iteration: bool,
+    /// Flag to check if it is the first diagonal update
+    first_update: bool,
+    /// Set if the diagonal was seeded with `with_initial_diag`
+    seeded_diag: bool,
+    /// The weights `$\mathbf{W}$` of `with_trust_region_weights`
+    trust_region_weights: Option<OVector<F, N>>,
+    /// Number of consecutive rejected trust-region steps
+    rejected_steps: usize,
+    /// Number of consecutive trial steps with non-finite residuals
+    non_finite_steps: usize,
+    /// Number of consecutive trial steps without decrease for the divergence test
+    unproductive_steps: usize,
+    /// Set for the weighted `ftol` test after the first Jacobian
+    weighted: Option<WeightedResiduals<F>>,
+    /// Per-parameter scales for the `xtol` test
+    typical_magnitudes: Option<OVector<F, N>>,
+    /// Objective function of the last iterations for the stall test
+    objective_history: VecDeque<F>,
+    /// Set after `diag_reset_on_stall` reset the diagonal until a window without stall
+    stall_reset: bool,
+    /// Number of evaluations at the last accepted step for `patience_reset`
+    patience_start: usize,
+    /// Scaled gradient norms of the last iterations for the plateau test
+    gradient_history: VecDeque<F>,
+    /// Residual norms of the last accepted points for `with_nonmonotone`
+    recent_residuals_norms: VecDeque<F>,
+    /// Parameters of the last two iterations for the cycle test
+    previous_params: VecDeque<OVector<F, N>>,
+    /// The relative changes of the parameters in the last accepted step
+    relative_step: Option<OVector<F, N>>,
+    /// Number of consecutive iterations which returned to the point before the last one
+    cycles: usize,
+    /// Called after every iteration
+    observer: Option<&'a mut dyn Observer<F, N>>,
+    /// Solves the trust-region subproblems instead of the built-in solver
+    trust_region_solver: Option<&'a mut dyn TrustRegionSubproblem<F, N>>,
+    /// The norm of the residuals for the `ftol` test
+    residual_norm: Option<&'a dyn Norm<F>>,
+    /// The residuals at `$\vec{x}$` in the norm `residual_norm`
+    custom_residuals_norm: F,
+    /// The norm of the steps for the `xtol` test
+    step_norm: Option<&'a dyn Norm<F>>,
+    /// The residual groups for the report
+    residual_groups: Option<&'a [Range<usize>]>,
+    /// The Jacobian if it is constant, after the first evaluation
+    constant_jacobian: Option<Matrix<F, M, N, O::JacobianStorage>>,
+    /// State of the detection of a constant Jacobian
+    jacobian_detection: JacobianDetection<OVector<F, N>, Jacobian<F, M, N, O>>,
+    /// Number of iterations which reused a detected constant Jacobian
+    reused_jacobians: usize,
+    /// The last QR factorization for row updates
+    incremental_qr: IncrementalQR<F>,
+    /// A Jacobian at the initial parameters supplied for the first iteration
+    initial_jacobian: Option<Matrix<F, M, N, O::JacobianStorage>>,
+    /// The parameters of a proposed step and the predicted objective function
+    pending_step: Option<(OVector<F, N>, F)>,
+    /// The Jacobian at `$\vec{x}$` evaluated for a proposed step
+    proposed_jacobian: Option<Matrix<F, M, N, O::JacobianStorage>>,
+    /// The norms `$\|\mathbf{J}^\top\vec{r}\|$` of the first and the last
+    /// iteration, only computed for an observer
+    gradient_norms: Option<(F, F)>,
+    max_fev: usize,
+    /// Time spent in residual and Jacobian evaluations, for `evaluation_time_budget`
+    evaluation_time: Duration,
+    /// The iteration when the solver was created, for `max_iterations`
+    first_iteration: usize,
+    /// The generator for the perturbations of `saddle_escape`
+    rng: SplitMix64,
+    m: usize,
+}
+
+impl<'a, F, N, M, O> LM<'a, F, N, M, O>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: DimMin<N> + DimMax<N>,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator:
+        Allocator<F, N> + Reallocator<F, M, N, DimMaximum<M, N>, N> + Allocator<usize, N>,
+{
+    /// Perform one iteration of the algorithm.
+    ///
+    /// The Jacobian is computed once and trust-region steps are tried until
+    /// one is accepted. Returns the reason once the minimization terminated,
+    /// for this and all further calls.
+    pub fn step(&mut self) -> Result<(), TerminationReason> {
+        let pivoting = self.config.pivoting;
+        self.iterate_with(|jacobian, residuals, factor| {
+            Ok(factor.factorize(jacobian, residuals, pivoting))
+        })
+    }
+
+    /// Compute the next step without applying it.
+    ///
+    /// This solves the trust-region subproblem at the current parameters like
+    /// the next iteration of [`step`](#method.step) and returns the parameters
+    /// of its first trial step together with the objective function the model
+    /// predicts for them. Only the Jacobian is evaluated, the state of the
+    /// minimization does not change and the observer is not called. The
+    /// proposal stays pending until it is applied with
+    /// [`commit_step`](#method.commit_step) or dropped with
+    /// [`discard_step`](#method.discard_step), further calls return the same
+    /// proposal. The Jacobian is kept for the next iteration, so proposing
+    /// does not cost an additional evaluation.
+    ///
+    /// Returns `None` if the minimization terminated, see
+    /// [`termination`](#method.termination), or if the next iteration
+    /// terminates before it tries a step. [`step`](#method.step) returns the
+    /// reason then.
+    pub fn propose_step(&mut self) -> Option<(OVector<F, N>, F)> {
+        if self.pending_step.is_none() && self.termination.is_none() {
+            let pivoting = self.config.pivoting;
+            let state = self.proposal_state();
+            let proposal = self.propose_unchecked(|jacobian, residuals, factor| {
+                Ok(factor.factorize(jacobian, residuals, pivoting))
+            });
+            self.restore_proposal_state(state);
+            self.pending_step = proposal.ok();
+        }
+        self.pending_step.clone()
+    }
+
+    /// Apply the step proposed by [`propose_step`](#method.propose_step).
+    ///
+    /// This finishes the iteration like [`step`](#method.step): the residuals
+    /// at the proposed parameters are evaluated, the step is accepted or
+    /// rejected, the trust region is updated and the observer is called. If
+    /// the step is rejected, shorter steps are tried until one is accepted.
+    /// Without a pending proposal this is the same as [`step`](#method.step).
+    pub fn commit_step(&mut self) -> Result<(), TerminationReason> {
+        self.step()
+    }
+
+    /// Drop the step proposed by [`propose_step`](#method.propose_step).
+    ///
+    /// The proposal never changed the state, so this only forgets it. The
+    /// next proposal is the same unless the configuration of the solver
+    /// changed. Returns `false` if no step was pending.
+    pub fn discard_step(&mut self) -> bool {
+        self.pending_step.take().is_some()
+    }
+
+    /// Iterate over the remaining iterations.
+    ///
+    /// Every item is a snapshot taken after one iteration, like with
+    /// [`checkpoint`](#method.checkpoint). The item of the last iteration has
+    /// the [`termination`](struct.LmCheckpoint.html#structfield.termination)
+    /// set, afterwards the iterator is exhausted and the reason is available
+    /// from [`termination`](#method.termination). This also holds for failures:
+    /// if an iteration fails, for example because the Jacobian is not finite,
+    /// its snapshot holds the last valid parameters.
+    ///
+    /// Call [`finish`](#method.finish) afterwards to get the report.
+    pub fn iterations(&mut self) -> Iterations<'_, 'a, F, N, M, O> {
+        Iterations { lm: self }
+    }
+
+    /// Run the remaining iterations and return the problem and the report.
+    pub fn finish(mut self) -> (O, MinimizationReport<F>) {
         loop {
-            // Build linear least squaress problem used for the trust-region subproblem
-            let mut lls = {
-                let jacobian = match lm.jacobian() {
-                    Err(reason) => return lm.into_report(reason),
-                    Ok(jacobian) => jacobian,
-                };
-                if jacobian.ncols() != n || jacobian.nrows() != lm.m {
-                    return lm.into_report(TerminationReason::WrongDimensions("jacobian"));
-                }
+            if let Err(reason) = self.step() {
+                let config = self.config;
+                let (target, mut report) = self.into_report(reason);
+                config.analyze_solution(&target, &mut report);
+                return (target, report);
+            }
+        }
+    }
+}
 
-                let qr = PivotedQR::new(jacobian);
-                qr.into_least_squares_diagonal_problem(residuals)
-            };
+impl<'a, F, N, M, O> LM<'a, F, N, M, O>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: DimMin<N> + DimMax<N>,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N>,
+{
+    /// The current parameters `$\vec{x}$`.
+    pub fn params(&self) -> &Vector<F, N, O::ParameterStorage> {
+        &self.x
+    }
 
-            // Update the diagonal, initialize "delta" in first call
-            if let Err(reason) = lm.update_diag(&mut lls) {
-                return lm.into_report(reason);
-            };
+    /// Share the best parameters so far with other threads.
+    ///
+    /// The returned [`SharedBest`](struct.SharedBest.html) starts at the
+    /// current parameters, attach it with
+    /// [`with_observer`](#method.with_observer) to keep it up to date.
+    /// Requires the feature `std`.
+    #[cfg(feature = "std")]
+    pub fn shared_best(&self) -> crate::SharedBest<F, N> {
+        crate::SharedBest::new(self.x.clone_owned(), self.report.objective_function)
+    }
+
+    /// The reason why the minimization terminated, `None` while it is running.
+    pub fn termination(&self) -> Option<TerminationReason> {
+        self.termination
+    }
+
+    /// The trust-region radius `$\Delta$`.
+    ///
+    /// The first iteration initializes it to
+    /// `$\mathtt{stepbound}\cdot\|\mathbf{D}\vec{x}\|$`, see
+    /// [`with_stepbound`](struct.LevenbergMarquardt.html#method.with_stepbound).
+    /// Zero before the first iteration.
+    pub fn delta(&self) -> F {
+        self.delta
+    }
+
+    /// The current Levenberg-Marquardt parameter `$\lambda$`.
+    pub fn lambda(&self) -> F {
+        self.lambda
+    }
+
+    /// The scaled parameter norm `$\|\mathbf{D}\vec{x}\|$`.
+    ///
+    /// Zero before the first iteration.
+    pub fn xnorm(&self) -> F {
+        self.xnorm
+    }
+
+    /// The diagonal of the scaling matrix `$\mathbf{D}$`.
+    ///
+    /// With [`with_scale_diag`](struct.LevenbergMarquardt.html#method.with_scale_diag)
+    /// the first iteration initializes it to the column norms of the Jacobian.
+    pub fn diag(&self) -> &OVector<F, N> {
+        &self.diag
+    }
+
+    /// Compute `$\mathbf{J}\vec{v}$` with the Jacobian at the current parameters.
+    ///
+    /// This uses the constant Jacobian if
+    /// [`with_constant_jacobian`](struct.LevenbergMarquardt.html#method.with_constant_jacobian)
+    /// is enabled and it was computed already, or else computes it with the
+    /// problem. Returns `None` if the Jacobian could not be computed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `v` does not have one entry per parameter.
+    pub fn jacobian_vector_product(&self, v: &OVector<F, N>) -> Option<OVector<F, M>>
+    where
+        DefaultAllocator: Allocator<F, M>,
+    {
+        self.with_current_jacobian(|jacobian| jacobian * v)
+    }
+
+    /// Compute `$\mathbf{J}^\top\vec{u}$` with the Jacobian at the current parameters.
+    ///
+    /// See [`jacobian_vector_product`](#method.jacobian_vector_product).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `u` does not have one entry per residual.
+    pub fn jacobian_transpose_vector_product(&self, u: &OVector<F, M>) -> Option<OVector<F, N>>
+    where
+        DefaultAllocator: Allocator<F, M>,
+    {
+        self.with_current_jacobian(|jacobian| jacobian.tr_mul(u))
+    }
+
+    fn with_current_jacobian<R>(
+        &self,
+        f: impl FnOnce(&Matrix<F, M, N, O::JacobianStorage>) -> R,
+    ) -> Option<R> {
+        match &self.constant_jacobian {
+            Some(jacobian) => Some(f(jacobian)),
+            None => Some(f(&self.target.jacobian()?)),
+        }
+    }
+
+    /// Take a snapshot of the state to resume the minimization later.
+    ///
+    /// See [`LmCheckpoint`](struct.LmCheckpoint.html).
+    pub fn checkpoint(&self) -> LmCheckpoint<F, N> {
+        LmCheckpoint {
+            x: self.x.clone_owned(),
+            diag: self.diag.clone(),
+            delta: self.delta,
+            lambda: self.lambda,
+            lambda_iterations: self.lambda_iterations,
+            xnorm: self.xnorm,
+            gnorm: self.gnorm,
+            residuals_norm: self.residuals_norm,
+            first_trust_region_iteration: self.first_trust_region_iteration,
+            first_update: self.first_update,
+            rejected_steps: self.rejected_steps,
+            non_finite_steps: self.non_finite_steps,
+            unproductive_steps: self.unproductive_steps,
+            weights: self
+                .weighted
+                .as_ref()
+                .map(|weighted| weighted.weights.clone()),
+            typical_magnitudes: self.typical_magnitudes.clone(),
+            objective_history: self.objective_history.iter().copied().collect(),
+            stall_reset: self.stall_reset,
+            patience_start: self.patience_start,
+            gradient_history: self.gradient_history.iter().copied().collect(),
+            recent_residuals_norms: self.recent_residuals_norms.iter().copied().collect(),
+            previous_params: self.previous_params.iter().cloned().collect(),
+            relative_step: self.relative_step.clone(),
+            cycles: self.cycles,
+            termination: self.termination,
+            number_of_evaluations: self.report.number_of_evaluations,
+            objective_function: self.report.objective_function,
+            initial_objective: self.report.initial_objective,
+            final_step_norm: self.report.final_step_norm,
+            iteration: self.report.terminated_at_iteration,
+            trust_region_reductions: self.report.trust_region_reductions,
+            incremental_factorizations: self.report.incremental_factorizations,
+            gradient_norm: self.report.gradient_norm,
+            report_lambda: self.report.lambda,
+            certificate: self.report.certificate,
+        }
+    }
+
+    /// Change the residuals of the target, for example to another minibatch.
+    ///
+    /// The residuals and their norms are evaluated at `$\vec{x}$` again.
+    pub(crate) fn resample(
+        &mut self,
+        change: impl FnOnce(&mut O),
+    ) -> Result<(), TerminationReason> {
+        change(&mut self.target);
+        let mut residuals = self
+            .residuals
+            .take()
+            .expect("residuals are available between iterations");
+        self.target.set_params(&self.x);
+        if let Some(reason) = self.evaluations_exhausted() {
+            return Err(reason);
+        }
+        self.report.number_of_evaluations += 1;
+        if !timed(&mut self.evaluation_time, || {
+            self.target.residuals_into(&mut residuals)
+        }) {
+            return Err(TerminationReason::User("residuals"));
+        }
+        if residuals.nrows() != self.m {
+            return Err(TerminationReason::WrongDimensions("residuals"));
+        }
+        let norm = enorm(&residuals);
+        if !norm.is_finite() {
+            return Err(TerminationReason::Numerical("residuals"));
+        }
+        if let Some(weighted) = &mut self.weighted {
+            weighted.residuals = weighted.weigh(&residuals);
+        }
+        if let Some(norm) = self.residual_norm {
+            self.custom_residuals_norm = norm.norm(residuals.as_slice());
+        }
+        self.residuals_norm = norm;
+        self.report.objective_function = norm * norm * convert(0.5);
+        self.residuals = Some(residuals);
+        Ok(())
+    }
+
+    /// Change the parametrization of the problem around the current parameters.
+    ///
+    /// The closure gets the problem and the current parameters. Afterwards the
+    /// new current parameters are read from the problem.
+    pub(crate) fn reparametrize(
+        &mut self,
+        reparametrize: impl FnOnce(&mut O, &Vector<F, N, O::ParameterStorage>),
+    ) where
+        F: Float,
+    {
+        reparametrize(&mut self.target, &self.x);
+        self.x = self.target.params();
+        self.xnorm = self.scaled_xnorm();
+    }
+
+    /// Judge the step size relative to typical magnitudes of the parameters.
+    ///
+    /// By default, the `xtol` test compares the trust-region radius against
+    /// `$\mathtt{xtol}\cdot\|\mathbf{D}\vec{x}\|$`, a single scaled norm.
+    /// With typical magnitudes `$\vec{t}$` it instead requires
+    /// ```math
+    ///   |p_i| \leq \mathtt{xtol}\cdot t_i \quad\text{for all }i=1,\ldots,n
+    /// ```
+    /// for the last step `$\vec{p}$`. So a parameter of small magnitude which still
+    /// changes significantly prevents the convergence.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an entry of `typical_magnitudes` is not positive or not finite.
+    #[must_use]
+    pub fn with_typical_magnitudes(self, typical_magnitudes: OVector<F, N>) -> Self {
+        assert!(
+            typical_magnitudes
+                .iter()
+                .all(|t| t.is_positive() && t.is_finite()),
+            "typical magnitudes must be > 0"
+        );
+        Self {
+            typical_magnitudes: Some(typical_magnitudes),
+            ..self
+        }
+    }
+
+    /// Start with the diagonal `diag` of the scaling matrix `$\mathbf{D}$`.
+    ///
+    /// By default the diagonal is initialized from the column norms of the
+    /// first Jacobian. With a seed, for example
+    /// [`diag`](struct.LmCheckpoint.html#structfield.diag) from the previous
+    /// run of a refinement on slowly changing data, the first update already
+    /// applies the [rule](struct.LevenbergMarquardt.html#method.with_diag_update)
+    /// of the later iterations, so with the default
+    /// [`DiagUpdate::MinpackMonotone`](enum.DiagUpdate.html#variant.MinpackMonotone)
+    /// the entries still grow with the column norms. The initial trust-region
+    /// radius is computed from the seeded diagonal. This has no effect unless
+    /// [`with_scale_diag`](struct.LevenbergMarquardt.html#method.with_scale_diag)
+    /// is enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `diag` is not the number of parameters or an
+    /// entry is not positive or not finite.
+    #[must_use]
+    pub fn with_initial_diag(self, diag: OVector<F, N>) -> Self {
+        assert_eq!(
+            diag.nrows(),
+            self.diag.nrows(),
+            "diag must have one entry per parameter"
+        );
+        assert!(
+            diag.iter().all(|d| *d > F::zero() && d.is_finite()),
+            "diag entries must be > 0"
+        );
+        Self {
+            diag,
+            seeded_diag: true,
+            ..self
+        }
+    }
+
+    /// Weigh the trust region per parameter with `weights` `$\mathbf{W}$`.
+    ///
+    /// The steps are constrained by
+    /// `$\|\mathbf{D}\mathbf{W}^{-1}\vec{p}\| \leq \Delta$` instead of
+    /// `$\|\mathbf{D}\vec{p}\| \leq \Delta$`, so a weight larger than one
+    /// widens the trust region along the axis of its parameter. The scaling
+    /// `$\mathbf{D}$` itself, and with it the convergence tests, is not affected.
+    /// Use this if some parameters are safe to move much further than the
+    /// column norms of the Jacobian suggest, for example offsets of an
+    /// otherwise nonlinear model.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `weights` is not the number of parameters or an
+    /// entry is not positive or not finite.
+    #[must_use]
+    pub fn with_trust_region_weights(self, weights: OVector<F, N>) -> Self {
+        assert_eq!(
+            weights.nrows(),
+            self.diag.nrows(),
+            "weights must have one entry per parameter"
+        );
+        assert!(
+            weights.iter().all(|w| *w > F::zero() && w.is_finite()),
+            "weights must be > 0"
+        );
+        Self {
+            trust_region_weights: Some(weights),
+            ..self
+        }
+    }
+
+    /// Use `jacobian` in the first iteration instead of computing it.
+    ///
+    /// This saves one Jacobian evaluation if a good approximation at the
+    /// initial parameters is known, for example from the previous frame of a
+    /// tracking problem. If the first trust-region step with it is rejected,
+    /// the first iteration ends, the residuals at `$\vec{x}$` are evaluated
+    /// again and the next iteration computes the Jacobian with the problem.
+    #[must_use]
+    pub fn with_initial_jacobian(self, jacobian: Matrix<F, M, N, O::JacobianStorage>) -> Self {
+        Self {
+            initial_jacobian: Some(jacobian),
+            ..self
+        }
+    }
+
+    /// Call `observer` after every iteration.
+    ///
+    /// See [`Observer`](trait.Observer.html).
+    #[must_use]
+    pub fn with_observer(self, observer: &'a mut dyn Observer<F, N>) -> Self {
+        Self {
+            observer: Some(observer),
+            ..self
+        }
+    }
+
+    /// Solve the trust-region subproblems with `solver`.
+    ///
+    /// By default the Levenberg-Marquardt step of
+    /// [`Levenberg`](struct.Levenberg.html) is used. The steps of other
+    /// solvers are accepted or rejected with the prediction they return, the
+    /// options for `$\lambda$` like `with_damping_schedule` and the Newton
+    /// correction of `with_hessian_correction` only apply to `Levenberg`.
+    /// See [`TrustRegionSubproblem`](trait.TrustRegionSubproblem.html).
+    #[must_use]
+    pub fn with_trust_region_solver(self, solver: &'a mut dyn TrustRegionSubproblem<F, N>) -> Self {
+        Self {
+            trust_region_solver: Some(solver),
+            ..self
+        }
+    }
+
+    /// Measure the residuals with `norm` in the `ftol` test.
+    ///
+    /// The actual relative reduction of the test becomes
+    /// ```math
+    ///   1 - \left(\frac{N(\vec{r}(\vec{x} - \vec{p}))}{N(\vec{r}(\vec{x}))}\right)^2
+    /// ```
+    /// for the norm `$N$`, by default it is Euclidean. The predicted reduction
+    /// comes from the linear model, it stays Euclidean like everything which
+    /// belongs to the trust-region step, see [`Norm`](trait.Norm.html). The
+    /// [weighted `ftol` test](struct.LevenbergMarquardt.html#method.with_weighted_ftol)
+    /// takes precedence over this.
+    #[must_use]
+    pub fn with_residual_norm(self, norm: &'a dyn Norm<F>) -> Self {
+        let custom_residuals_norm = self
+            .residuals
+            .as_ref()
+            .map_or(F::zero(), |residuals| norm.norm(residuals.as_slice()));
+        Self {
+            residual_norm: Some(norm),
+            custom_residuals_norm,
+            ..self
+        }
+    }
+
+    /// Measure the steps with `norm` in the `xtol` test.
+    ///
+    /// By default the test compares the trust-region radius, which bounds
+    /// `$\|\mathbf{D}\vec{p}\|$`, against `$\mathtt{xtol}\cdot\|\mathbf{D}\vec{x}\|$`.
+    /// With the norm `$N$` it requires
+    /// ```math
+    ///   N(\mathbf{D}\vec{p}) \leq \mathtt{xtol}\cdot N(\mathbf{D}\vec{x})
+    /// ```
+    /// for the last step `$\vec{p}$` instead. The trust region itself stays a
+    /// Euclidean ball, see [`Norm`](trait.Norm.html). This has no effect with
+    /// [typical magnitudes](#method.with_typical_magnitudes) or the
+    /// [componentwise test](struct.LevenbergMarquardt.html#method.with_xtol_componentwise),
+    /// which replace the test.
+    #[must_use]
+    pub fn with_step_norm(self, norm: &'a dyn Norm<F>) -> Self {
+        Self {
+            step_norm: Some(norm),
+            ..self
+        }
+    }
 
-            residuals = loop {
-                let param =
-                    determine_lambda_and_parameter_update(&mut lls, &lm.diag, lm.delta, lm.lambda);
-                let tr_iteration = lm.trust_region_iteration(&mut lls, param);
-                match tr_iteration {
-                    // successful paramter update, break and recompute Jacobian
-                    Ok(Some(residuals)) => break residuals,
-                    // terminate (either success or failure)
-                    Err(reason) => return lm.into_report(reason),
-                    // need another iteration
-                    Ok(None) => (),
-                }
-            };
+    /// Report the sum of squares of the residuals in every group of `groups`.
+    ///
+    /// Each range selects the residuals of one data source, for example one
+    /// sensor. The ranges may overlap and do not need to cover every residual.
+    /// See [`MinimizationReport::group_objectives`](struct.MinimizationReport.html#method.group_objectives).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range ends after the last residual.
+    #[must_use]
+    pub fn with_residual_groups(self, groups: &'a [Range<usize>]) -> Self {
+        assert!(
+            groups
+                .iter()
+                .all(|group| group.end <= self.report.number_of_residuals),
+            "residual groups must be within the residuals"
+        );
+        Self {
+            residual_groups: Some(groups),
+            ..self
         }
     }
-}
 
-/// Struct which holds the state of the LM algorithm and which implements its individual steps.
-struct LM<'a, F, N, M, O>
-where
-    F: RealField + Copy,
-    N: Dim,
-    M: DimMin<N> + DimMax<N>,
-    O: LeastSquaresProblem<F, M, N>,
-    DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N>,
-{
-    config: &'a LevenbergMarquardt<F>,
-    /// Current parameters `$\vec{x}$`
-    x: Vector<F, N, O::ParameterStorage>,
-    tmp: Vector<F, N, O::ParameterStorage>,
-    /// The implementation of `LeastSquaresProblem`
-    target: O,
-    /// Statistics and termination reasons, used for return value
-    report: MinimizationReport<F>,
-    /// The delta from the trust-region algorithm
-    delta: F,
-    lambda: F,
-    /// `$\|\mathbf{D}\vec{x}\|`
-    xnorm: F,
-    gnorm: F,
-    residuals_norm: F,
-    /// The diagonal of `$\mathbf{D}$`
-    diag: OVector<F, N>,
-    /// Flag to check if it is the first trust region iteration
-    first_trust_region_iteration: bool,
-    /// Flag to check if it is the first diagonal update
-    first_update: bool,
-    max_fev: usize,
-    m: usize,
-}
+    /// Compute the objective function `$f$` at the given parameters.
+    ///
+    /// This counts as an evaluation, see
+    /// [`number_of_evaluations`](struct.MinimizationReport.html#structfield.number_of_evaluations).
+    /// The state of the minimization is not changed, the parameters of the
+    /// problem are reset to `$\vec{x}$` afterwards.
+    ///
+    /// Returns `None` without an evaluation if the budget of
+    /// [`with_max_evaluations`](struct.LevenbergMarquardt.html#method.with_max_evaluations)
+    /// or [`with_evaluation_time_budget`](struct.LevenbergMarquardt.html#method.with_evaluation_time_budget)
+    /// is used up. Also returns `None` if the residuals could not be computed
+    /// or their number changed.
+    pub fn evaluate_at(&mut self, x: &OVector<F, N>) -> Option<F> {
+        if self.evaluations_exhausted().is_some() {
+            return None;
+        }
+        self.tmp.copy_from(x);
+        self.target.set_params(&self.tmp);
+        self.report.number_of_evaluations += 1;
+        let residuals = timed(&mut self.evaluation_time, || self.target.residuals());
+        let objective_function = residuals
+            .filter(|residuals| residuals.nrows() == self.m)
+            .map(|residuals| {
+                let norm = enorm(&residuals);
+                norm * norm * convert(0.5)
+            });
+        self.target.set_params(&self.x);
+        objective_function
+    }
 
-impl<'a, F, N, M, O> LM<'a, F, N, M, O>
-where
-    F: RealField + Float + Copy,
-    N: Dim,
-    M: DimMin<N> + DimMax<N>,
-    O: LeastSquaresProblem<F, M, N>,
-    DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N>,
-{
-    #[allow(clippy::type_complexity)]
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
     fn new(
         config: &'a LevenbergMarquardt<F>,
         target: O,
     ) -> Result<(Self, Vector<F, M, O::ResidualStorage>), (O, MinimizationReport<F>)> {
+        Self::new_in(config, target, &mut None)
+    }
+
+    /// Like `new`, but take the buffers from `workspace` if they fit.
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    fn new_in(
+        config: &'a LevenbergMarquardt<F>,
+        target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+    ) -> Result<(Self, Vector<F, M, O::ResidualStorage>), (O, MinimizationReport<F>)> {
+        let x = target.params();
         let mut report = MinimizationReport {
-            termination: TerminationReason::ResidualsZero,
             number_of_evaluations: 1,
-            objective_function: <F as Float>::nan(),
+            ..MinimizationReport::unevaluated(config, x.nrows(), target.jacobian_is_numerical())
         };
 
         // Evaluate at start point
-        let x = target.params();
-        let (residuals, residuals_norm) = if let Some(residuals) = target.residuals() {
+        let mut evaluation_time = Duration::ZERO;
+        let residuals = timed(&mut evaluation_time, || target.residuals());
+        let (residuals, residuals_norm) = if let Some(residuals) = residuals {
             let norm = enorm(&residuals);
             report.objective_function = norm * norm * convert(0.5);
+            report.initial_objective = report.objective_function;
+            report.number_of_residuals = residuals.nrows();
             (residuals, norm)
         } else {
             return Err((
@@ -371,159 +3841,1238 @@ where
             ));
         };
 
-        // Initialize diagonal
-        let n = x.shape_generic().0;
-        let diag = OVector::<F, N>::from_element_generic(n, Dim::from_usize(1), F::one());
-        // Check n > 0
-        if diag.nrows() == 0 {
-            return Err((
-                target,
-                MinimizationReport {
-                    termination: TerminationReason::NoParameters,
-                    ..report
-                },
-            ));
+        // Check n > 0
+        let n = x.shape_generic().0;
+        if n.value() == 0 {
+            return Err((
+                target,
+                MinimizationReport {
+                    termination: TerminationReason::NoParameters,
+                    ..report
+                },
+            ));
+        }
+
+        let m = residuals.nrows();
+        if m == 0 {
+            return Err((
+                target,
+                MinimizationReport {
+                    termination: TerminationReason::NoResiduals,
+                    ..report
+                },
+            ));
+        }
+
+        if !residuals_norm.is_finite() && !cfg!(feature = "minpack-compat") {
+            return Err((
+                target,
+                MinimizationReport {
+                    termination: TerminationReason::InvalidInitialPoint,
+                    ..report
+                },
+            ));
+        }
+
+        if residuals_norm <= Float::min_positive_value() && !cfg!(feature = "minpack-compat") {
+            // Already zero, nothing to do
+            return Err((target, report));
+        }
+
+        let Workspace {
+            mut tmp,
+            mut diag,
+            mut objective_history,
+            mut gradient_history,
+            mut recent_residuals_norms,
+            mut previous_params,
+        } = match workspace.take() {
+            Some(workspace) if workspace.diag.nrows() == n.value() => workspace,
+            _ => Workspace {
+                tmp: x.clone(),
+                diag: OVector::<F, N>::from_element_generic(n, Dim::from_usize(1), F::one()),
+                objective_history: VecDeque::new(),
+                gradient_history: VecDeque::new(),
+                recent_residuals_norms: VecDeque::new(),
+                previous_params: VecDeque::new(),
+            },
+        };
+        // Initialize diagonal
+        tmp.copy_from(&x);
+        diag.fill(F::one());
+        objective_history.clear();
+        gradient_history.clear();
+        recent_residuals_norms.clear();
+        previous_params.clear();
+
+        if config.stall_window.is_some() {
+            objective_history.push_back(report.objective_function);
+        }
+        if config.nonmonotone.is_some() {
+            recent_residuals_norms.push_back(residuals_norm);
+        }
+        if config.cycle_detection.is_some() {
+            previous_params.push_back(x.clone_owned());
+        }
+
+        Ok((
+            Self {
+                config,
+                target,
+                report,
+                residuals: None,
+                termination: None,
+                tmp,
+                x,
+                diag,
+                seeded_diag: false,
+                trust_region_weights: None,
+                delta: F::zero(),
+                lambda: F::zero(),
+                lambda_iterations: 0,
+                xnorm: F::zero(),
+                gnorm: F::zero(),
+                residuals_norm,
+                predicted_reduction: F::zero(),
+                actual_reduction: F::zero(),
+                first_trust_region_iteration: true,
+                first_update: true,
+                rejected_steps: 0,
+                non_finite_steps: 0,
+                unproductive_steps: 0,
+                weighted: None,
+                typical_magnitudes: None,
+                objective_history,
+                stall_reset: false,
+                patience_start: 0,
+                gradient_history,
+                recent_residuals_norms,
+                previous_params,
+                relative_step: None,
+                cycles: 0,
+                observer: None,
+                trust_region_solver: None,
+                residual_norm: None,
+                custom_residuals_norm: F::zero(),
+                step_norm: None,
+                residual_groups: None,
+                constant_jacobian: None,
+                jacobian_detection: JacobianDetection::new(config),
+                reused_jacobians: 0,
+                incremental_qr: IncrementalQR::new(),
+                initial_jacobian: None,
+                pending_step: None,
+                proposed_jacobian: None,
+                gradient_norms: None,
+                max_fev: config.patience * (n.value() + 1),
+                evaluation_time,
+                first_iteration: 0,
+                rng: SplitMix64::new(config.saddle_escape.map_or(0, |(seed, _)| seed)),
+                m,
+            },
+            residuals,
+        ))
+    }
+
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    fn start(
+        config: &'a LevenbergMarquardt<F>,
+        target: O,
+    ) -> Result<Self, (O, MinimizationReport<F>)> {
+        let (mut lm, residuals) = Self::new(config, target)?;
+        lm.residuals = Some(residuals);
+        Ok(lm)
+    }
+
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    fn start_in(
+        config: &'a LevenbergMarquardt<F>,
+        target: O,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+    ) -> Result<Self, (O, MinimizationReport<F>)> {
+        let (mut lm, residuals) = Self::new_in(config, target, workspace)?;
+        lm.residuals = Some(residuals);
+        Ok(lm)
+    }
+
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    fn resume(
+        config: &'a LevenbergMarquardt<F>,
+        mut target: O,
+        checkpoint: &LmCheckpoint<F, N>,
+    ) -> Result<Self, (O, MinimizationReport<F>)> {
+        let mut x = target.params();
+        let mut report = MinimizationReport {
+            termination: TerminationReason::User("residuals"),
+            number_of_evaluations: checkpoint.number_of_evaluations,
+            objective_function: checkpoint.objective_function,
+            initial_objective: checkpoint.initial_objective,
+            final_step_norm: checkpoint.final_step_norm,
+            terminated_at_iteration: checkpoint.iteration,
+            trust_region_reductions: checkpoint.trust_region_reductions,
+            incremental_factorizations: checkpoint.incremental_factorizations,
+            gradient_norm: checkpoint.gradient_norm,
+            lambda: checkpoint.report_lambda,
+            certificate: checkpoint.certificate,
+            ..MinimizationReport::unevaluated(config, x.nrows(), target.jacobian_is_numerical())
+        };
+        if x.nrows() != checkpoint.x.nrows() {
+            return Err((
+                target,
+                MinimizationReport {
+                    termination: TerminationReason::WrongDimensions("checkpoint"),
+                    ..report
+                },
+            ));
+        }
+        x.copy_from(&checkpoint.x);
+        target.set_params(&x);
+        let mut evaluation_time = Duration::ZERO;
+        let residuals = match timed(&mut evaluation_time, || target.residuals()) {
+            Some(residuals) => residuals,
+            None => return Err((target, report)),
+        };
+        report.number_of_residuals = residuals.nrows();
+        let weights = checkpoint.weights.as_ref().filter(|_| config.weighted_ftol);
+        let weighted = weights.map(|weights| {
+            let mut weighted = WeightedResiduals {
+                weights: weights.clone(),
+                residuals: DVector::zeros(0),
+                jacobian: DMatrix::zeros(0, 0),
+            };
+            weighted.residuals = weighted.weigh(&residuals);
+            weighted
+        });
+        // the state of the stall and cycle tests of the original configuration
+        let history = &checkpoint.objective_history;
+        let kept = config
+            .stall_window
+            .map_or(0, |(window, _)| Ord::min(window, history.len()));
+        let objective_history = history[history.len() - kept..].iter().copied().collect();
+        let history = &checkpoint.gradient_history;
+        let kept = config
+            .gradient_plateau
+            .map_or(0, |(window, _)| Ord::min(window, history.len()));
+        let gradient_history = history[history.len() - kept..].iter().copied().collect();
+        let recent = &checkpoint.recent_residuals_norms;
+        let recent_residuals_norms = match config.nonmonotone {
+            Some(window) if !recent.is_empty() => recent
+                [recent.len() - Ord::min(window, recent.len())..]
+                .iter()
+                .copied()
+                .collect(),
+            Some(_) => [checkpoint.residuals_norm].into_iter().collect(),
+            None => VecDeque::new(),
+        };
+        let previous_params = if config.cycle_detection.is_some() {
+            checkpoint.previous_params.iter().cloned().collect()
+        } else {
+            VecDeque::new()
+        };
+        let n = x.nrows();
+        Ok(Self {
+            config,
+            target,
+            report,
+            m: residuals.nrows(),
+            residuals: Some(residuals),
+            // the iteration limit, the time budget and the parameter target only
+            // apply to one call
+            termination: checkpoint.termination.filter(|reason| {
+                !matches!(
+                    reason,
+                    TerminationReason::MaxIterations
+                        | TerminationReason::EvaluationTimeBudget
+                        | TerminationReason::ParameterTargetReached
+                )
+            }),
+            tmp: x.clone(),
+            x,
+            diag: checkpoint.diag.clone(),
+            seeded_diag: false,
+            trust_region_weights: None,
+            delta: checkpoint.delta,
+            lambda: checkpoint.lambda,
+            lambda_iterations: checkpoint.lambda_iterations,
+            xnorm: checkpoint.xnorm,
+            gnorm: checkpoint.gnorm,
+            residuals_norm: checkpoint.residuals_norm,
+            predicted_reduction: F::zero(),
+            actual_reduction: F::zero(),
+            first_trust_region_iteration: checkpoint.first_trust_region_iteration,
+            first_update: checkpoint.first_update,
+            rejected_steps: checkpoint.rejected_steps,
+            non_finite_steps: checkpoint.non_finite_steps,
+            unproductive_steps: checkpoint.unproductive_steps,
+            weighted,
+            typical_magnitudes: checkpoint.typical_magnitudes.clone(),
+            objective_history,
+            stall_reset: checkpoint.stall_reset,
+            patience_start: checkpoint.patience_start,
+            gradient_history,
+            recent_residuals_norms,
+            previous_params,
+            relative_step: checkpoint.relative_step.clone(),
+            cycles: checkpoint.cycles,
+            observer: None,
+            trust_region_solver: None,
+            residual_norm: None,
+            custom_residuals_norm: F::zero(),
+            step_norm: None,
+            residual_groups: None,
+            constant_jacobian: None,
+            jacobian_detection: JacobianDetection::new(config),
+            reused_jacobians: 0,
+            incremental_qr: IncrementalQR::new(),
+            initial_jacobian: None,
+            pending_step: None,
+            proposed_jacobian: None,
+            gradient_norms: None,
+            max_fev: config.patience * (n + 1),
+            evaluation_time,
+            first_iteration: checkpoint.iteration,
+            rng: SplitMix64::new(config.saddle_escape.map_or(0, |(seed, _)| seed)),
+        })
+    }
+
+    /// Perform one iteration of the algorithm.
+    ///
+    /// This computes the Jacobian once and tries trust-region steps until one
+    /// is accepted. Returns the termination reason once the minimization
+    /// terminated, also for all further calls.
+    fn iterate_with<S>(
+        &mut self,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> Result<(), TerminationReason>
+    where
+        S: Linearization<F, N>,
+    {
+        self.iterate_linearized(|lm, residuals| lm.linearize(residuals, subproblem))
+    }
+
+    /// Like `iterate_with`, but `linearize` computes the subproblem from the
+    /// residuals without a Jacobian.
+    fn iterate_linearized<S>(
+        &mut self,
+        linearize: impl FnOnce(
+            &mut Self,
+            &mut Vector<F, M, O::ResidualStorage>,
+        ) -> Result<S, TerminationReason>,
+    ) -> Result<(), TerminationReason>
+    where
+        S: Linearization<F, N>,
+    {
+        self.pending_step = None;
+        if let Some(reason) = self.termination {
+            return Err(reason);
+        }
+        let iteration = self.report.terminated_at_iteration;
+        let result = self.iterate_unchecked(linearize);
+        // the iteration used the Jacobian of a proposal or moved away from it
+        self.proposed_jacobian = None;
+        if let Err(reason) = result {
+            self.termination = Some(reason);
+        }
+        let estimated_progress = self.estimated_progress();
+        if let Some(observer) = &mut self.observer {
+            if self.report.terminated_at_iteration > iteration {
+                observer.on_iteration(&LMState {
+                    iteration: self.report.terminated_at_iteration,
+                    number_of_evaluations: self.report.number_of_evaluations,
+                    objective_function: self.report.objective_function,
+                    params: self.x.as_slice(),
+                    diag: &self.diag,
+                    delta: self.delta,
+                    lambda: self.lambda,
+                    lambda_iterations: self.lambda_iterations,
+                    gradient_norm: self.gnorm,
+                    predicted_reduction: self.predicted_reduction,
+                    actual_reduction: self.actual_reduction,
+                    estimated_progress,
+                    terminated: result.is_err(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Build the subproblem from the Jacobian.
+    fn linearize<S>(
+        &mut self,
+        residuals: &mut Vector<F, M, O::ResidualStorage>,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> Result<S, TerminationReason> {
+        let jacobian = self.jacobian(residuals)?;
+        if jacobian.ncols() != self.x.nrows() || jacobian.nrows() != self.m {
+            return Err(TerminationReason::WrongDimensions("jacobian"));
+        }
+        if self.config.weighted_ftol {
+            self.weigh_jacobian(&jacobian, residuals);
+        }
+        let lls = subproblem(jacobian, residuals, &mut self.incremental_qr)?;
+        if self.incremental_qr.updated {
+            self.report.incremental_factorizations += 1;
+        }
+        Ok(lls)
+    }
+
+    fn iterate_unchecked<S>(
+        &mut self,
+        linearize: impl FnOnce(
+            &mut Self,
+            &mut Vector<F, M, O::ResidualStorage>,
+        ) -> Result<S, TerminationReason>,
+    ) -> Result<(), TerminationReason>
+    where
+        S: Linearization<F, N>,
+    {
+        let n = self.x.nrows();
+        self.check_limits()?;
+        self.report.terminated_at_iteration += 1;
+        let target_before = self.config.parameter_target.map(|(index, ..)| {
+            assert!(index < n, "parameter target index out of range");
+            self.x[index]
+        });
+        let mut residuals = self
+            .residuals
+            .take()
+            .expect("residuals are available between iterations");
+        let supplied_jacobian = self.initial_jacobian.is_some();
+        // Build linear least squaress problem used for the trust-region subproblem
+        let mut lls = linearize(self, &mut residuals)?;
+
+        // Update the diagonal, initialize "delta" in first call
+        match self.update_diag(&mut lls) {
+            Err(
+                reason @ (TerminationReason::Orthogonal | TerminationReason::NoGradientInformation),
+            ) if self.config.saddle_escape.is_some() => {
+                return self.escape_saddle(residuals, reason);
+            }
+            result => result?,
+        }
+
+        let weighted_diag = self.weighted_diag();
+        let lambda_floor = self.lambda_floor(&lls, weighted_diag.as_ref());
+        loop {
+            let (param, prediction) =
+                self.solve_subproblem(&mut lls, weighted_diag.as_ref(), lambda_floor);
+            let rejected_step = match self.config.line_search {
+                LineSearch::Backtracking { .. } => Some(param.step.clone()),
+                LineSearch::None => None,
+            };
+            // successful paramter update, recompute Jacobian in the next iteration
+            if self.trust_region_iteration(&mut lls, param, prediction, &mut residuals)? {
+                self.rejected_steps = 0;
+                self.residuals = Some(residuals);
+                return self.check_progress(target_before);
+            }
+            self.rejected_steps += 1;
+            if let (Some(step), LineSearch::Backtracking { c, rho }) =
+                (rejected_step, self.config.line_search)
+            {
+                if self.line_search(&mut lls, &step, c, rho, &mut residuals)? {
+                    self.rejected_steps = 0;
+                    self.residuals = Some(residuals);
+                    return self.check_progress(target_before);
+                }
+            }
+            if supplied_jacobian {
+                // the supplied Jacobian is a poor model, compute it in the next iteration
+                return self.reevaluate_residuals(residuals);
+            }
+            if let JacobianDetection::Constant = self.jacobian_detection {
+                // the detected constant Jacobian is outdated, do not reuse it anymore
+                self.jacobian_detection = JacobianDetection::Off;
+                self.constant_jacobian = None;
+                return self.reevaluate_residuals(residuals);
+            }
+            if self.config.gradient_fallback
+                && self.rejected_steps >= Ord::max(self.config.patience / 2, 1)
+            {
+                self.rejected_steps = 0;
+                if self.gradient_step(&mut lls, &mut residuals)? {
+                    self.residuals = Some(residuals);
+                    return self.check_progress(target_before);
+                }
+            }
+        }
+    }
+
+    /// Terminate with `max_evaluations`, `evaluation_time_budget` or
+    /// `max_iterations` before the next iteration.
+    fn check_limits(&self) -> Result<(), TerminationReason> {
+        if let Some(reason) = self.evaluations_exhausted() {
+            return Err(reason);
+        }
+        if self.config.max_iterations.is_some_and(|max_iterations| {
+            self.report.terminated_at_iteration - self.first_iteration >= max_iterations
+        }) {
+            return Err(TerminationReason::MaxIterations);
+        }
+        Ok(())
+    }
+
+    /// The lower bound for `$\lambda$` of `with_damping_schedule` and
+    /// `with_conditioning_guard` in the current iteration.
+    fn lambda_floor(
+        &self,
+        lls: &impl Linearization<F, N>,
+        weighted_diag: Option<&OVector<F, N>>,
+    ) -> Option<F> {
+        let scheduled_floor = self
+            .config
+            .damping_schedule
+            .filter(|(iterations, _)| self.report.terminated_at_iteration <= *iterations)
+            .map(|(_, floor)| floor);
+        let conditioning_floor = self.config.conditioning_guard.and_then(|threshold| {
+            let diag = weighted_diag.unwrap_or(&self.diag);
+            let (max, min) = lls.singular_value_estimates(diag)?;
+            let threshold = threshold * threshold;
+            let floor = (max * max - threshold * min * min) / (threshold - F::one());
+            floor.is_positive().then_some(floor)
+        });
+        match (scheduled_floor, conditioning_floor) {
+            (Some(scheduled), Some(conditioning)) => Some(Float::max(scheduled, conditioning)),
+            (floor, None) | (None, floor) => floor,
+        }
+    }
+
+    /// Solve the trust-region subproblem for the current `delta`.
+    ///
+    /// Also returns the predicted reduction of a custom subproblem solver.
+    fn solve_subproblem(
+        &mut self,
+        lls: &mut impl Linearization<F, N>,
+        weighted_diag: Option<&OVector<F, N>>,
+        lambda_floor: Option<F>,
+    ) -> (LMParameter<F, N>, Option<F>) {
+        let diag = weighted_diag.unwrap_or(&self.diag);
+        let (mut param, prediction) = match self.trust_region_solver.as_mut() {
+            Some(solver) => {
+                let mut model = TrustRegionModel::new(
+                    lls,
+                    self.residuals_norm,
+                    self.lambda,
+                    self.config.max_lambda_iterations,
+                    self.config.min_norm,
+                );
+                solver
+                    .solve(&mut model, diag, self.delta)
+                    .into_parameter(diag)
+            }
+            None => (
+                lls.solve_trust_region(
+                    diag,
+                    self.delta,
+                    self.lambda,
+                    self.config.max_lambda_iterations,
+                    self.config.min_norm,
+                ),
+                None,
+            ),
+        };
+        if let Some(floor) =
+            lambda_floor.filter(|floor| prediction.is_none() && param.lambda < *floor)
+        {
+            // a larger lambda gives a shorter step, it is still in the trust region
+            if let Some(damped) = lls.solve_damped(diag, floor) {
+                param = LMParameter {
+                    lambda_iterations: param.lambda_iterations,
+                    ..damped
+                };
+            }
+        }
+        (param, prediction)
+    }
+
+    /// The first trial step of the next iteration, see `propose_step`.
+    ///
+    /// This changes the state like the iteration does until it evaluates the
+    /// trial step, the caller restores it with `restore_proposal_state`. An
+    /// evaluated Jacobian is kept for the iteration.
+    fn propose_unchecked<S>(
+        &mut self,
+        subproblem: impl Fn(
+            Matrix<F, M, N, O::JacobianStorage>,
+            &mut Vector<F, M, O::ResidualStorage>,
+            &mut IncrementalQR<F>,
+        ) -> Result<S, TerminationReason>,
+    ) -> Result<(OVector<F, N>, F), TerminationReason>
+    where
+        S: Linearization<F, N>,
+    {
+        self.check_limits()?;
+        self.report.terminated_at_iteration += 1;
+        let mut residuals = self
+            .residuals
+            .take()
+            .expect("residuals are available between iterations");
+        // the factorization overwrites the residuals
+        let values = residuals.as_slice().to_vec();
+        let evaluated = self.constant_jacobian.is_none() && self.initial_jacobian.is_none();
+        let result = self.jacobian(&residuals).and_then(|jacobian| {
+            if jacobian.ncols() != self.x.nrows() || jacobian.nrows() != self.m {
+                return Err(TerminationReason::WrongDimensions("jacobian"));
+            }
+            if evaluated {
+                self.proposed_jacobian = Some(jacobian.clone());
+            }
+            let mut lls = subproblem(jacobian, &mut residuals, &mut self.incremental_qr)?;
+            self.update_diag(&mut lls)?;
+            let weighted_diag = self.weighted_diag();
+            let lambda_floor = self.lambda_floor(&lls, weighted_diag.as_ref());
+            let (param, prediction) =
+                self.solve_subproblem(&mut lls, weighted_diag.as_ref(), lambda_floor);
+            let (_, _, predicted_reduction, _) = self.trial_step(&mut lls, param, prediction)?;
+            let predicted = self.report.objective_function * (F::one() - predicted_reduction);
+            Ok((self.tmp.clone_owned(), predicted))
+        });
+        residuals.as_mut_slice().copy_from_slice(&values);
+        self.residuals = Some(residuals);
+        result
+    }
+
+    /// The part of the state which `propose_unchecked` changes.
+    fn proposal_state(&self) -> ProposalState<F, N, Jacobian<F, M, N, O>> {
+        ProposalState {
+            report: self.report.clone(),
+            diag: self.diag.clone(),
+            delta: self.delta,
+            lambda: self.lambda,
+            lambda_iterations: self.lambda_iterations,
+            xnorm: self.xnorm,
+            gnorm: self.gnorm,
+            first_update: self.first_update,
+            first_trust_region_iteration: self.first_trust_region_iteration,
+            gradient_history: self.gradient_history.clone(),
+            gradient_norms: self.gradient_norms,
+            constant_jacobian: self.constant_jacobian.clone(),
+            jacobian_detection: self.jacobian_detection.clone(),
+            reused_jacobians: self.reused_jacobians,
+            incremental_qr: self.incremental_qr.clone(),
+            initial_jacobian: self.initial_jacobian.clone(),
+        }
+    }
+
+    fn restore_proposal_state(&mut self, state: ProposalState<F, N, Jacobian<F, M, N, O>>) {
+        self.report = state.report;
+        self.diag = state.diag;
+        self.delta = state.delta;
+        self.lambda = state.lambda;
+        self.lambda_iterations = state.lambda_iterations;
+        self.xnorm = state.xnorm;
+        self.gnorm = state.gnorm;
+        self.first_update = state.first_update;
+        self.first_trust_region_iteration = state.first_trust_region_iteration;
+        self.gradient_history = state.gradient_history;
+        self.gradient_norms = state.gradient_norms;
+        self.constant_jacobian = state.constant_jacobian;
+        self.jacobian_detection = state.jacobian_detection;
+        self.reused_jacobians = state.reused_jacobians;
+        self.incremental_qr = state.incremental_qr;
+        self.initial_jacobian = state.initial_jacobian;
+    }
+
+    /// Evaluate the residuals at `$\vec{x}$` again to end an iteration early.
+    fn reevaluate_residuals(
+        &mut self,
+        mut residuals: Vector<F, M, O::ResidualStorage>,
+    ) -> Result<(), TerminationReason> {
+        self.target.set_params(&self.x);
+        if let Some(reason) = self.evaluations_exhausted() {
+            return Err(reason);
+        }
+        self.report.number_of_evaluations += 1;
+        if !timed(&mut self.evaluation_time, || {
+            self.target.residuals_into(&mut residuals)
+        }) {
+            return Err(TerminationReason::User("residuals"));
+        }
+        if residuals.nrows() != self.m {
+            return Err(TerminationReason::WrongDimensions("residuals"));
+        }
+        self.residuals = Some(residuals);
+        Ok(())
+    }
+
+    /// Move away from a stationary point with random perturbations.
+    ///
+    /// Returns `reason` if no perturbation decreased the objective function.
+    fn escape_saddle(
+        &mut self,
+        mut residuals: Vector<F, M, O::ResidualStorage>,
+        reason: TerminationReason,
+    ) -> Result<(), TerminationReason> {
+        const MAX_ATTEMPTS: usize = 10;
+
+        let magnitude = match self.config.saddle_escape {
+            Some((_, magnitude)) => magnitude,
+            None => return Err(reason),
+        };
+        for _ in 0..MAX_ATTEMPTS {
+            if self.patience_exhausted() || self.evaluations_exhausted().is_some() {
+                break;
+            }
+            self.tmp.copy_from(&self.x);
+            for x in self.tmp.iter_mut() {
+                *x += magnitude * self.rng.next_symmetric::<F>();
+            }
+            self.target.set_params(&self.tmp);
+            self.report.number_of_evaluations += 1;
+            if !timed(&mut self.evaluation_time, || {
+                self.target.residuals_into(&mut residuals)
+            }) {
+                continue;
+            }
+            if residuals.nrows() != self.m {
+                self.target.set_params(&self.x);
+                return Err(TerminationReason::WrongDimensions("residuals"));
+            }
+            let norm = enorm(&residuals);
+            let objective_function = norm * norm * convert(0.5);
+            if objective_function < self.report.objective_function {
+                core::mem::swap(&mut self.x, &mut self.tmp);
+                self.record_accepted_step();
+                self.tmp -= &self.x;
+                self.tmp.component_mul_assign(&self.diag);
+                self.report.final_step_norm = enorm(&self.tmp);
+                self.xnorm = self.scaled_xnorm();
+                if let Some(weighted) = &mut self.weighted {
+                    weighted.residuals = weighted.weigh(&residuals);
+                }
+                if let Some(norm) = self.residual_norm {
+                    self.custom_residuals_norm = norm.norm(residuals.as_slice());
+                }
+                self.residuals_norm = norm;
+                self.report.objective_function = objective_function;
+                self.residuals = Some(residuals);
+                return Ok(());
+            }
+        }
+        self.target.set_params(&self.x);
+        Err(reason)
+    }
+
+    /// Record the accepted point for the non-monotone acceptance and apply the
+    /// stall, cycle and parameter target tests.
+    fn check_progress(&mut self, target_before: Option<F>) -> Result<(), TerminationReason> {
+        if let Some(window) = self.config.nonmonotone {
+            self.recent_residuals_norms.push_back(self.residuals_norm);
+            if self.recent_residuals_norms.len() > window {
+                self.recent_residuals_norms.pop_front();
+            }
+        }
+        self.check_stall()?;
+        self.check_cycle()?;
+        match (self.config.parameter_target, target_before) {
+            (Some((index, value, direction)), Some(before))
+                if direction.crossed(before, self.x[index], value) =>
+            {
+                Err(TerminationReason::ParameterTargetReached)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the scaled gradient of the new Jacobian and apply the plateau test.
+    fn check_gradient_plateau(&mut self) -> Result<(), TerminationReason> {
+        let (window, threshold) = match self.config.gradient_plateau {
+            Some(gradient_plateau) => gradient_plateau,
+            None => return Ok(()),
+        };
+        self.gradient_history.push_back(self.gnorm);
+        if self.gradient_history.len() <= window {
+            return Ok(());
+        }
+        let previous = self.gradient_history.pop_front().unwrap();
+        if Float::abs(previous - self.gnorm) < threshold * previous {
+            return Err(TerminationReason::GradientPlateau);
+        }
+        Ok(())
+    }
+
+    /// Record the parameters of the finished iteration and apply the cycle test.
+    fn check_cycle(&mut self) -> Result<(), TerminationReason> {
+        let (repetitions, tol) = match self.config.cycle_detection {
+            Some(cycle_detection) => cycle_detection,
+            None => return Ok(()),
+        };
+        self.previous_params.push_back(self.x.clone_owned());
+        if self.previous_params.len() <= 2 {
+            return Ok(());
+        }
+        let before_last = self.previous_params.pop_front().unwrap();
+        self.tmp.copy_from(&self.x);
+        self.tmp -= &before_last;
+        self.tmp.component_mul_assign(&self.diag);
+        if enorm(&self.tmp) <= tol * self.xnorm {
+            self.cycles += 1;
+            if self.cycles >= repetitions {
+                return Err(TerminationReason::Cycling);
+            }
+        } else {
+            self.cycles = 0;
         }
+        Ok(())
+    }
 
-        let m = residuals.nrows();
-        if m == 0 {
-            return Err((
-                target,
-                MinimizationReport {
-                    termination: TerminationReason::NoResiduals,
-                    ..report
-                },
-            ));
+    /// Record the objective function of the finished iteration and apply the stall test.
+    fn check_stall(&mut self) -> Result<(), TerminationReason> {
+        let (window, threshold) = match self.config.stall_window {
+            Some(stall_window) => stall_window,
+            None => return Ok(()),
+        };
+        let objective_function = self.report.objective_function;
+        self.objective_history.push_back(objective_function);
+        if self.objective_history.len() <= window {
+            return Ok(());
+        }
+        let previous = self.objective_history.pop_front().unwrap();
+        if (previous - objective_function) >= threshold * previous {
+            self.stall_reset = false;
+            return Ok(());
         }
+        if !self.config.diag_reset_on_stall || self.stall_reset {
+            return Err(TerminationReason::NoImprovement);
+        }
+        // initialize diag and delta from the next Jacobian and start a new window
+        self.stall_reset = true;
+        self.first_update = true;
+        self.seeded_diag = false;
+        self.objective_history.clear();
+        self.objective_history.push_back(objective_function);
+        Ok(())
+    }
 
-        if !residuals_norm.is_finite() && !cfg!(feature = "minpack-compat") {
-            return Err((
-                target,
-                MinimizationReport {
-                    termination: TerminationReason::Numerical("residuals norm"),
-                    ..report
-                },
-            ));
+    /// Try a scaled steepest-descent step with a backtracking line search.
+    ///
+    /// Returns `true` if the step was accepted.
+    fn gradient_step(
+        &mut self,
+        lls: &mut impl Linearization<F, N>,
+        residuals: &mut Vector<F, M, O::ResidualStorage>,
+    ) -> Result<bool, TerminationReason> {
+        const C1: f64 = 1.0e-4;
+
+        // x - p is the steepest-descent direction of f for p = D^-2 J^T r
+        let gradient = lls.a_t_b();
+        let mut p = gradient.clone();
+        for (p, d) in p.iter_mut().zip(self.diag.iter()) {
+            *p /= *d * *d;
+        }
+        let slope = gradient.dot(&p);
+        let j_p_norm = lls.a_x_norm(&p);
+        if !slope.is_positive() || !slope.is_finite() || j_p_norm.is_zero() {
+            return Ok(false);
         }
+        // minimizer of the linear model along the direction
+        let mut alpha = slope / (j_p_norm * j_p_norm);
+        alpha *= self.step_fraction_within_limit(&(&p * alpha));
+        self.backtrack(&p, alpha, slope, convert(C1), convert(0.5), residuals)
+    }
 
-        if residuals_norm <= Float::min_positive_value() && !cfg!(feature = "minpack-compat") {
-            // Already zero, nothing to do
-            return Err((target, report));
+    /// Backtrack along the rejected trust-region step `$\vec{p}$`.
+    ///
+    /// Returns `true` if a step was accepted.
+    fn line_search(
+        &mut self,
+        lls: &mut impl Linearization<F, N>,
+        step: &OVector<F, N>,
+        c: F,
+        rho: F,
+        residuals: &mut Vector<F, M, O::ResidualStorage>,
+    ) -> Result<bool, TerminationReason> {
+        let slope = lls.a_t_b().dot(step);
+        if !slope.is_positive() || !slope.is_finite() {
+            return Ok(false);
         }
+        let alpha = rho * self.step_fraction_within_limit(step);
+        self.backtrack(step, alpha, slope, c, rho, residuals)
+    }
 
-        Ok((
-            Self {
-                config,
-                target,
-                report,
-                tmp: x.clone(),
-                x,
-                diag,
-                delta: F::zero(),
-                lambda: F::zero(),
-                xnorm: F::zero(),
-                gnorm: F::zero(),
-                residuals_norm,
-                first_trust_region_iteration: true,
-                first_update: true,
-                max_fev: config.patience * (n.value() + 1),
-                m,
-            },
-            residuals,
-        ))
+    /// Try the steps `$\vec{x} - \alpha\vec{p}$`, multiplying `$\alpha$` by
+    /// `rho` until the Armijo condition with the constant `c` and the slope
+    /// `$\vec{p}^\top\mathbf{J}^\top\vec{r}$` holds.
+    ///
+    /// Returns `true` if a step was accepted.
+    fn backtrack(
+        &mut self,
+        p: &OVector<F, N>,
+        mut alpha: F,
+        slope: F,
+        c: F,
+        rho: F,
+        residuals: &mut Vector<F, M, O::ResidualStorage>,
+    ) -> Result<bool, TerminationReason> {
+        const MAX_BACKTRACKING: usize = 30;
+
+        let objective_function = self.report.objective_function;
+        let p_norm = enorm(&p.component_mul(&self.diag));
+        for _ in 0..MAX_BACKTRACKING {
+            // the step became too small to matter
+            if alpha * p_norm <= self.config.xtol * self.xnorm {
+                break;
+            }
+            if self.patience_exhausted() {
+                self.target.set_params(&self.x);
+                return Err(TerminationReason::LostPatience);
+            }
+            if let Some(reason) = self.evaluations_exhausted() {
+                self.target.set_params(&self.x);
+                return Err(reason);
+            }
+            self.tmp.copy_from(&self.x);
+            self.tmp.axpy(-alpha, p, F::one());
+            self.target.set_params(&self.tmp);
+            self.report.number_of_evaluations += 1;
+            if !timed(&mut self.evaluation_time, || {
+                self.target.residuals_into(residuals)
+            }) {
+                self.target.set_params(&self.x);
+                return Err(TerminationReason::User("residuals"));
+            }
+            if residuals.nrows() != self.m {
+                self.target.set_params(&self.x);
+                return Err(TerminationReason::WrongDimensions("residuals"));
+            }
+            let norm = enorm(residuals);
+            let new_objective_function = norm * norm * convert(0.5);
+            if new_objective_function <= objective_function - alpha * c * slope {
+                core::mem::swap(&mut self.x, &mut self.tmp);
+                self.record_accepted_step();
+                let dp_norm = alpha * p_norm;
+                self.xnorm = self.scaled_xnorm();
+                if !self.xnorm.is_finite() {
+                    return Err(TerminationReason::Numerical("new x"));
+                }
+                self.delta = Float::max(self.delta, dp_norm);
+                if let Some(weighted) = &mut self.weighted {
+                    weighted.residuals = weighted.weigh(residuals);
+                }
+                if let Some(norm) = self.residual_norm {
+                    self.custom_residuals_norm = norm.norm(residuals.as_slice());
+                }
+                self.residuals_norm = norm;
+                self.report.objective_function = new_objective_function;
+                self.report.final_step_norm = dp_norm;
+                return Ok(true);
+            }
+            alpha *= rho;
+        }
+        self.target.set_params(&self.x);
+        Ok(false)
     }
 
     fn into_report(self, termination: TerminationReason) -> (O, MinimizationReport<F>) {
+        self.into_report_in(termination, &mut None)
+    }
+
+    /// Like `into_report`, but keep the buffers in `workspace` for the next problem.
+    fn into_report_in(
+        self,
+        termination: TerminationReason,
+        workspace: &mut Option<Workspace<F, N, O::ParameterStorage>>,
+    ) -> (O, MinimizationReport<F>) {
+        let group_sums_of_squares = self.residual_groups.and_then(|groups| {
+            let sums = |residuals: &Vector<F, M, O::ResidualStorage>| {
+                (residuals.nrows() == self.report.number_of_residuals).then(|| {
+                    groups
+                        .iter()
+                        .map(|group| {
+                            let norm = enorm(&residuals.rows_range(group.clone()));
+                            norm * norm
+                        })
+                        .collect()
+                })
+            };
+            match &self.residuals {
+                Some(residuals) => sums(residuals),
+                // the residuals are consumed if the iteration terminated early
+                None => sums(&self.target.residuals()?),
+            }
+        });
+        let relative_step = self
+            .relative_step
+            .filter(|_| self.config.qr_retention)
+            .map(|step| crate::constrained::dvector(step.iter().copied().collect()));
+        *workspace = Some(Workspace {
+            tmp: self.tmp,
+            diag: self.diag,
+            objective_history: self.objective_history,
+            gradient_history: self.gradient_history,
+            recent_residuals_norms: self.recent_residuals_norms,
+            previous_params: self.previous_params,
+        });
         (
             self.target,
             MinimizationReport {
                 termination,
+                group_sums_of_squares,
+                relative_step,
                 ..self.report
             },
         )
     }
 
-    fn jacobian(&self) -> Result<Matrix<F, M, N, O::JacobianStorage>, TerminationReason> {
-        match self.target.jacobian() {
-            Some(jacobian) => Ok(jacobian),
-            None => Err(TerminationReason::User("jacobian")),
+    fn jacobian(
+        &mut self,
+        residuals: &Vector<F, M, O::ResidualStorage>,
+    ) -> Result<Matrix<F, M, N, O::JacobianStorage>, TerminationReason> {
+        if let Some(jacobian) = &self.constant_jacobian {
+            if !self.config.constant_jacobian {
+                self.reused_jacobians += 1;
+            }
+            self.incremental_qr.reset();
+            return Ok(jacobian.clone());
+        }
+        let jacobian = match self.initial_jacobian.take() {
+            Some(jacobian) => {
+                self.incremental_qr.reset();
+                jacobian
+            }
+            None => match self.proposed_jacobian.take().or_else(|| {
+                timed(&mut self.evaluation_time, || {
+                    self.target.jacobian_with_residuals(residuals)
+                })
+            }) {
+                Some(jacobian) => {
+                    self.incremental_qr.changed_rows =
+                        self.target.changed_jacobian_rows().map(<[usize]>::to_vec);
+                    jacobian
+                }
+                None => return Err(TerminationReason::User("jacobian")),
+            },
+        };
+        if self.config.constant_jacobian {
+            self.constant_jacobian = Some(jacobian.clone());
         }
+        self.detect_constant_jacobian(&jacobian);
+        Ok(jacobian)
     }
 
-    fn update_diag(
-        &mut self,
-        lls: &mut LinearLeastSquaresDiagonalProblem<F, M, N>,
-    ) -> Result<(), TerminationReason>
-    where
-        DefaultAllocator: Allocator<usize, N>,
-    {
+    /// Compare the Jacobian with the one at the first point.
+    fn detect_constant_jacobian(&mut self, jacobian: &Matrix<F, M, N, O::JacobianStorage>) {
+        let detection = core::mem::replace(&mut self.jacobian_detection, JacobianDetection::Off);
+        self.jacobian_detection = match detection {
+            JacobianDetection::First(x, first) if x != self.x => {
+                let (difference, norm) = jacobian.iter().zip(first.iter()).fold(
+                    (F::zero(), F::zero()),
+                    |(difference, norm), (j, first)| {
+                        (
+                            difference + Float::powi(*j - *first, 2),
+                            norm + *first * *first,
+                        )
+                    },
+                );
+                if difference <= F::default_epsilon() * norm {
+                    self.constant_jacobian = Some(jacobian.clone());
+                    JacobianDetection::Constant
+                } else {
+                    JacobianDetection::Off
+                }
+            }
+            JacobianDetection::Pending | JacobianDetection::First(..) => {
+                JacobianDetection::First(self.x.clone_owned(), jacobian.clone())
+            }
+            detection => detection,
+        };
+    }
+
+    /// Set `relative_step` and `patience_start` after an accepted step from `tmp` to `x`.
+    fn record_accepted_step(&mut self) {
+        self.patience_start = self.report.number_of_evaluations;
+        self.relative_step = Some(self.x.zip_map(&self.tmp, |x, before| {
+            let change = Float::abs(x - before);
+            if change.is_zero() {
+                F::zero()
+            } else {
+                change / Float::abs(x)
+            }
+        }));
+    }
+
+    fn update_diag(&mut self, lls: &mut impl Linearization<F, N>) -> Result<(), TerminationReason> {
+        if !cfg!(feature = "minpack-compat") && lls.column_norms().iter().all(|n| n.is_zero()) {
+            return Err(TerminationReason::NoGradientInformation);
+        }
         // Compute norm of scaled gradient and detect degeneracy
-        self.gnorm = match lls.max_a_t_b_scaled(self.residuals_norm) {
+        let reference_norm = match self.config.gtol_reference {
+            GtolReference::ResidualNorm => self.residuals_norm,
+            GtolReference::Fixed(norm) => norm,
+        };
+        self.gnorm = match lls.max_a_t_b_scaled(reference_norm) {
             Some(max_at_b) => max_at_b,
             None if !cfg!(feature = "minpack-compat") => {
                 return Err(TerminationReason::Numerical("jacobian"))
             }
             None => F::zero(),
         };
-        if self.gnorm <= self.config.gtol {
+        if let Some(limit) = self.config.param_magnitude_limit {
+            if self.x.iter().any(|x| bound_side(*x, limit).is_some()) {
+                self.gnorm = self.projected_gnorm(lls, limit, reference_norm);
+            }
+        }
+        self.report.gradient_norm = Some(self.gnorm);
+        let certificate = &mut self.report.certificate;
+        certificate.gradient_norm = Some(self.gnorm);
+        certificate.gradient_passed =
+            test_enabled(self.config.gtol) && self.gnorm <= self.config.gtol;
+        if self.observer.is_some() {
+            let norm = enorm(&lls.a_t_b());
+            let initial = self.gradient_norms.map_or(norm, |(initial, _)| initial);
+            self.gradient_norms = Some((initial, norm));
+        }
+        if self.report.certificate.gradient_passed {
             return Err(TerminationReason::Orthogonal);
         }
+        self.check_gradient_plateau()?;
 
         if self.first_update {
             // Initialize diag and xnorm
-            self.xnorm = if self.config.scale_diag {
-                for (d, col_norm) in self.diag.iter_mut().zip(lls.column_norms.iter()) {
+            if self.config.scale_diag && self.seeded_diag {
+                self.apply_diag_update(lls);
+            } else if self.config.scale_diag {
+                for (d, col_norm) in self.diag.iter_mut().zip(lls.column_norms().iter()) {
                     *d = if col_norm.is_zero() {
                         F::one()
                     } else {
                         *col_norm
                     };
                 }
-                self.tmp.cmpy(F::one(), &self.diag, &self.x, F::zero());
-                enorm(&self.tmp)
-            } else {
-                enorm(&self.x)
-            };
+            }
+            self.xnorm = self.scaled_xnorm();
             if !self.xnorm.is_finite() && !cfg!(feature = "minpack-compat") {
                 return Err(TerminationReason::Numerical("subproblem x"));
             }
             // Initialize delta
-            self.delta = if self.xnorm.is_zero() {
-                self.config.stepbound
+            self.delta = if let Some(initial_delta) = self.config.initial_delta {
+                initial_delta
             } else {
-                self.config.stepbound * self.xnorm
+                let delta = Float::max(
+                    self.config.stepbound * self.xnorm,
+                    self.config.stepbound_abs,
+                );
+                if delta.is_zero() {
+                    self.config.stepbound
+                } else {
+                    delta
+                }
             };
             self.first_update = false;
         } else if self.config.scale_diag {
-            // Update diag
-            for (d, norm) in self.diag.iter_mut().zip(lls.column_norms.iter()) {
-                *d = Float::max(*norm, *d);
-            }
+            self.apply_diag_update(lls);
         }
         Ok(())
     }
 
+    /// The scaled gradient norm of `update_diag` without the components of
+    /// parameters at the magnitude limit whose descent direction points out of
+    /// the limit.
+    fn projected_gnorm(&self, lls: &impl Linearization<F, N>, limit: F, reference_norm: F) -> F {
+        let gradient = lls.a_t_b();
+        let mut max = F::zero();
+        for ((x, g), scale) in self
+            .x
+            .iter()
+            .zip(gradient.iter())
+            .zip(lls.column_norms().iter())
+        {
+            // the descent direction is -g
+            let blocked = match bound_side(*x, limit) {
+                Some(BoundSide::Upper) => g.is_negative(),
+                Some(BoundSide::Lower) => g.is_positive(),
+                None => false,
+            };
+            if blocked || scale.is_zero() {
+                continue;
+            }
+            max = Float::max(max, Float::abs(*g / reference_norm / *scale));
+        }
+        max
+    }
+
+    /// The diagonal `$\mathbf{D}\mathbf{W}^{-1}$` of the trust-region constraint,
+    /// `None` if there are no trust-region weights.
+    fn weighted_diag(&self) -> Option<OVector<F, N>> {
+        let weights = self.trust_region_weights.as_ref()?;
+        Some(self.diag.component_div(weights))
+    }
+
+    /// Update diag with the rule of `with_diag_update`.
+    fn apply_diag_update(&mut self, lls: &impl Linearization<F, N>) {
+        match self.config.diag_update {
+            DiagUpdate::MinpackMonotone => {
+                for (d, norm) in self.diag.iter_mut().zip(lls.column_norms().iter()) {
+                    *d = Float::max(*norm, *d);
+                }
+            }
+            DiagUpdate::Recompute => {
+                for (d, norm) in self.diag.iter_mut().zip(lls.column_norms().iter()) {
+                    *d = if norm.is_zero() { F::one() } else { *norm };
+                }
+            }
+            DiagUpdate::Fixed => (),
+        }
+    }
+
+    /// Shorten the step to the limits, compute its predicted reduction and
+    /// put the trial parameters `$\vec{x} - \vec{p}$` into `tmp`.
+    ///
+    /// Returns the step, its scaled norm, the predicted reduction and the
+    /// directional derivative.
     #[allow(clippy::type_complexity)]
-    fn trust_region_iteration(
+    fn trial_step(
         &mut self,
-        lls: &mut LinearLeastSquaresDiagonalProblem<F, M, N>,
-        param: LMParameter<F, N>,
-    ) -> Result<Option<Vector<F, M, O::ResidualStorage>>, TerminationReason>
-    where
-        DefaultAllocator: Allocator<usize, N>,
-    {
-        const P1: f64 = 0.1;
-        const P0001: f64 = 1.0e-4;
-
+        lls: &mut impl Linearization<F, N>,
+        mut param: LMParameter<F, N>,
+        prediction: Option<F>,
+    ) -> Result<(LMParameter<F, N>, F, F, F), TerminationReason> {
         self.lambda = param.lambda;
+        self.lambda_iterations = param.lambda_iterations;
+        self.report.lambda = Some(self.lambda);
         let pnorm = param.dp_norm;
         if !pnorm.is_finite() && !cfg!(feature = "minpack-compat") {
             return Err(TerminationReason::Numerical("subproblem ||Dp||"));
         }
+        let newton_model = if self.config.hessian_correction && prediction.is_none() {
+            self.newton_correction(lls, &mut param)
+        } else {
+            None
+        };
+        let pnorm = param.dp_norm;
+        let fraction = self.step_fraction_within_limit(&param.step);
 
         let predicted_reduction;
         let dir_der;
-        {
+        if let Some((gp, php)) = newton_model {
+            // the quadratic model of the true Hessian along the shortened step
+            let scale = self.residuals_norm * self.residuals_norm;
+            let two: F = convert(2.);
+            predicted_reduction = fraction * (two * gp - fraction * php) / scale;
+            dir_der = -fraction * gp / scale;
+        } else if let Some(predicted) = prediction {
+            // the quadratic model 2at - bt^2 with the prediction 2a - b of the whole step
+            let temp1 = Float::powi(lls.a_x_norm(&param.step) / self.residuals_norm, 2);
+            if !temp1.is_finite() || !predicted.is_finite() {
+                return Err(TerminationReason::Numerical("trust-region reduction"));
+            }
+            let slope = (predicted + temp1) * convert(0.5);
+            if fraction < F::one() {
+                let two: F = convert(2.);
+                predicted_reduction = fraction * (two * slope - fraction * temp1);
+            } else {
+                predicted_reduction = predicted;
+            }
+            dir_der = -fraction * slope;
+        } else {
             let temp1 = Float::powi(lls.a_x_norm(&param.step) / self.residuals_norm, 2);
             if !temp1.is_finite() && !cfg!(feature = "minpack-compat") {
                 return Err(TerminationReason::Numerical("trust-region reduction"));
@@ -532,9 +5081,22 @@ where
             if !temp2.is_finite() && !cfg!(feature = "minpack-compat") {
                 return Err(TerminationReason::Numerical("trust-region reduction"));
             }
-            predicted_reduction = temp1 + temp2 / convert(0.5);
-            dir_der = -(temp1 + temp2);
+            if fraction < F::one() {
+                // the linear model along the shortened step
+                let two: F = convert(2.);
+                predicted_reduction = fraction * ((two - fraction) * temp1 + two * temp2);
+                dir_der = -fraction * (temp1 + temp2);
+            } else {
+                predicted_reduction = temp1 + temp2 / convert(0.5);
+                dir_der = -(temp1 + temp2);
+            }
         }
+        let pnorm = if fraction < F::one() {
+            param.step *= fraction;
+            pnorm * fraction
+        } else {
+            pnorm
+        };
 
         if self.first_trust_region_iteration && pnorm < self.delta {
             self.delta = pnorm;
@@ -544,21 +5106,68 @@ where
         // Compute new parameters: x - p
         self.tmp.copy_from(&self.x);
         self.tmp.axpy(-F::one(), &param.step, F::one());
+        Ok((param, pnorm, predicted_reduction, dir_der))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn trust_region_iteration(
+        &mut self,
+        lls: &mut impl Linearization<F, N>,
+        param: LMParameter<F, N>,
+        prediction: Option<F>,
+        residuals: &mut Vector<F, M, O::ResidualStorage>,
+    ) -> Result<bool, TerminationReason> {
+        const P1: f64 = 0.1;
+
+        let (param, pnorm, predicted_reduction, dir_der) =
+            self.trial_step(lls, param, prediction)?;
 
         // Evaluate
+        if let Some(reason) = self.evaluations_exhausted() {
+            self.target.set_params(&self.x);
+            return Err(reason);
+        }
         self.target.set_params(&self.tmp);
         self.report.number_of_evaluations += 1;
         let new_objective_function;
-        let (residuals, new_residuals_norm) = if let Some(residuals) = self.target.residuals() {
+        let new_residuals_norm = if timed(&mut self.evaluation_time, || {
+            self.target.residuals_into(residuals)
+        }) {
             if residuals.nrows() != self.m {
+                self.target.set_params(&self.x);
                 return Err(TerminationReason::WrongDimensions("residuals"));
             }
-            let norm = enorm(&residuals);
+            let norm = enorm(residuals);
             new_objective_function = norm * norm * convert(0.5);
-            (residuals, norm)
+            norm
         } else {
             return Err(TerminationReason::User("residuals"));
         };
+        if new_residuals_norm.is_finite() {
+            self.non_finite_steps = 0;
+        } else if !cfg!(feature = "minpack-compat") {
+            self.non_finite_steps += 1;
+            if let Some(recovery_attempts) = self.config.recovery_attempts {
+                if self.non_finite_steps > recovery_attempts {
+                    self.target.set_params(&self.x);
+                    return Err(TerminationReason::Numerical("residuals"));
+                }
+            }
+        }
+        if let Some((window, growth)) = self.config.divergence_detection {
+            if new_objective_function < self.report.objective_function {
+                self.unproductive_steps = 0;
+            } else {
+                self.unproductive_steps += 1;
+            }
+            if self.unproductive_steps >= window
+                || (new_objective_function.is_finite()
+                    && new_objective_function > growth * self.report.initial_objective)
+            {
+                self.target.set_params(&self.x);
+                return Err(TerminationReason::Diverged);
+            }
+        }
 
         // Compute predicted and actual reduction
         let actual_reduction = if new_residuals_norm * convert(P1) < self.residuals_norm {
@@ -572,8 +5181,38 @@ where
         } else {
             actual_reduction / predicted_reduction
         };
+        // the gain ratio for the acceptance and the trust-region update
+        let trust_ratio = match self
+            .recent_residuals_norms
+            .iter()
+            .copied()
+            .reduce(Float::max)
+        {
+            Some(reference) if !predicted_reduction.is_zero() => {
+                // the reduction from the worst recent point, relative to the current one
+                let reduction = Float::powi(reference / self.residuals_norm, 2)
+                    - Float::powi(new_residuals_norm / self.residuals_norm, 2);
+                Float::max(ratio, reduction / predicted_reduction)
+            }
+            _ => ratio,
+        };
+        self.predicted_reduction = predicted_reduction;
+        self.actual_reduction = actual_reduction;
+        let weighted_reduction = self
+            .weighted
+            .as_ref()
+            .map(|weighted| weighted.reduction(residuals, &param.step));
+        let custom_reduction = self.residual_norm.map(|norm| {
+            let new_norm = norm.norm(residuals.as_slice());
+            let reduction = if new_norm * convert(P1) < self.custom_residuals_norm {
+                F::one() - Float::powi(new_norm / self.custom_residuals_norm, 2)
+            } else {
+                -F::one()
+            };
+            (new_norm, reduction)
+        });
         let half: F = convert(0.5);
-        if ratio <= convert(0.25) {
+        if trust_ratio <= convert(0.25) {
             let mut temp = if !actual_reduction.is_negative() {
                 half
             } else {
@@ -584,37 +5223,96 @@ where
             };
             self.delta = temp * Float::min(self.delta, pnorm * convert(10.));
             self.lambda /= temp;
-        } else if self.lambda.is_zero() || ratio >= convert(0.75) {
+            self.report.trust_region_reductions += 1;
+        } else if self.lambda.is_zero() || trust_ratio >= convert(0.75) {
             self.delta = pnorm / convert(0.5);
             self.lambda *= half;
         }
 
-        let update_considered_good = ratio >= convert(P0001);
+        let update_considered_good = trust_ratio >= self.config.acceptance_ratio;
+        // the trust region shrank to the rounding errors of x although the
+        // model still sees a clear descent direction
+        if !update_considered_good
+            && !cfg!(feature = "minpack-compat")
+            && self.lambda > F::zero()
+            && self.delta <= Float::max(self.config.xtol, epsmch()) * self.xnorm
+            && self.gnorm > Float::sqrt(Float::sqrt(epsmch::<F>()))
+        {
+            self.target.set_params(&self.x);
+            return Err(TerminationReason::TrustRegionCollapsed);
+        }
         if update_considered_good {
             // update x, residuals and their norms
             core::mem::swap(&mut self.x, &mut self.tmp);
-            self.xnorm = if self.config.scale_diag {
-                self.tmp.cmpy(F::one(), &self.diag, &self.x, F::zero());
-                enorm(&self.tmp)
-            } else {
-                enorm(&self.x)
-            };
+            self.record_accepted_step();
+            self.xnorm = self.scaled_xnorm();
             if !self.xnorm.is_finite() && !cfg!(feature = "minpack-compat") {
                 return Err(TerminationReason::Numerical("new x"));
             }
             self.residuals_norm = new_residuals_norm;
             self.report.objective_function = new_objective_function;
+            self.report.final_step_norm = pnorm;
+            if let (Some(weighted), Some((new_residuals, ..))) =
+                (&mut self.weighted, &weighted_reduction)
+            {
+                weighted.residuals.copy_from(new_residuals);
+            }
+            if let Some((new_norm, _)) = custom_reduction {
+                self.custom_residuals_norm = new_norm;
+            }
         }
 
+        // the ftol tests use the weighted reductions if enabled
+        let (actual_reduction, predicted_reduction, ratio) = match weighted_reduction {
+            Some((_, actual_reduction, predicted_reduction)) => {
+                let ratio = if predicted_reduction.is_zero() {
+                    F::zero()
+                } else {
+                    actual_reduction / predicted_reduction
+                };
+                (actual_reduction, predicted_reduction, ratio)
+            }
+            None => match custom_reduction {
+                Some((_, actual_reduction)) => (actual_reduction, predicted_reduction, ratio),
+                None => (actual_reduction, predicted_reduction, ratio),
+            },
+        };
+
         // convergence tests
         if !cfg!(feature = "minpack-compat") && self.residuals_norm <= F::min_positive_value() {
             self.reset_params_if(!update_considered_good);
             return Err(TerminationReason::ResidualsZero);
         }
-        let ftol_check = Float::abs(actual_reduction) <= self.config.ftol
+        let ftol_check = test_enabled(self.config.ftol)
+            && Float::abs(actual_reduction) <= self.config.ftol
             && predicted_reduction <= self.config.ftol
             && ratio * convert(0.5) <= F::one();
-        let xtol_check = self.delta <= self.config.xtol * self.xnorm;
+        let xtol_check = match self.config.xtol_componentwise {
+            Some(xtol) => param
+                .step
+                .iter()
+                .zip(self.x.iter())
+                .all(|(p, x)| Float::abs(*p) <= xtol * (Float::abs(*x) + F::default_epsilon())),
+            None => {
+                test_enabled(self.config.xtol)
+                    && match &self.typical_magnitudes {
+                        Some(typical_magnitudes) => param
+                            .step
+                            .iter()
+                            .zip(typical_magnitudes.iter())
+                            .all(|(p, t)| Float::abs(*p) <= self.config.xtol * *t),
+                        None => match self.step_norm {
+                            Some(norm) => self.step_norm_converged(norm, &param.step),
+                            None => self.delta <= self.config.xtol * self.xnorm,
+                        },
+                    }
+            }
+        };
+        let certificate = &mut self.report.certificate;
+        certificate.objective_change = Some(Float::abs(actual_reduction));
+        certificate.objective_passed = ftol_check;
+        certificate.step_norm = Some(self.delta / self.xnorm);
+        certificate.step_passed = xtol_check;
         if ftol_check || xtol_check {
             self.reset_params_if(!update_considered_good);
             return Err(TerminationReason::Converged {
@@ -624,7 +5322,7 @@ where
         }
 
         // termination tests
-        if self.report.number_of_evaluations >= self.max_fev {
+        if self.patience_exhausted() {
             self.reset_params_if(!update_considered_good);
             return Err(TerminationReason::LostPatience);
         }
@@ -647,12 +5345,209 @@ where
             return Err(TerminationReason::NoImprovementPossible("gtol"));
         }
 
-        if update_considered_good {
-            Ok(Some(residuals))
+        // Need another iteration if the parameters did not change
+        Ok(update_considered_good)
+    }
+
+    /// The `xtol` test `$N(\mathbf{D}\vec{p}) \leq \mathtt{xtol}\cdot N(\mathbf{D}\vec{x})$`
+    /// with the step norm `$N$`.
+    fn step_norm_converged(&mut self, norm: &dyn Norm<F>, step: &OVector<F, N>) -> bool {
+        self.tmp.cmpy(F::one(), &self.diag, step, F::zero());
+        let step_norm = norm.norm(self.tmp.as_slice());
+        self.tmp.cmpy(F::one(), &self.diag, &self.x, F::zero());
+        step_norm <= self.config.xtol * norm.norm(self.tmp.as_slice())
+    }
+
+    /// Update the weighted Jacobian, initialize the weights in the first call.
+    fn weigh_jacobian<JS, RS>(
+        &mut self,
+        jacobian: &Matrix<F, M, N, JS>,
+        residuals: &Vector<F, M, RS>,
+    ) where
+        JS: Storage<F, M, N>,
+        RS: Storage<F, M>,
+    {
+        let (m, n) = jacobian.shape();
+        let weighted = self.weighted.get_or_insert_with(|| {
+            let weights = DVector::from_iterator(
+                m,
+                jacobian.row_iter().map(|row| {
+                    let norm = enorm(&DVector::from_iterator(n, row.iter().copied()));
+                    if norm.is_zero() {
+                        F::one()
+                    } else {
+                        F::one() / norm
+                    }
+                }),
+            );
+            let mut weighted = WeightedResiduals {
+                residuals: DVector::zeros(m),
+                jacobian: DMatrix::zeros(m, n),
+                weights,
+            };
+            weighted.residuals = weighted.weigh(residuals);
+            weighted
+        });
+        let weights = &weighted.weights;
+        weighted.jacobian = DMatrix::from_fn(m, n, |i, j| weights[i] * jacobian[(i, j)]);
+    }
+
+    /// Refine the step with conjugate gradient iterations on the true Hessian.
+    ///
+    /// Returns `$\vec{g}^\top\vec{p}$` and `$\vec{p}^\top\nabla^2 f\,\vec{p}$` for the
+    /// gradient `$\vec{g}$` and the refined step, or `None` to keep the
+    /// Levenberg-Marquardt step with the Gauss-Newton model.
+    fn newton_correction(
+        &mut self,
+        lls: &mut impl Linearization<F, N>,
+        param: &mut LMParameter<F, N>,
+    ) -> Option<(F, F)> {
+        const MAX_ITERATIONS: usize = 10;
+        // the problem is still at a rejected step
+        self.target.set_params(&self.x);
+        let lambda = param.lambda;
+        let weighted_diag = self.weighted_diag();
+        let diag = weighted_diag.as_ref().unwrap_or(&self.diag);
+        let target = &self.target;
+        let apply = |v: &OVector<F, N>| -> Option<OVector<F, N>> {
+            let mut hv = target.hessian_vector_product(v)?;
+            for ((hv, v), d) in hv.iter_mut().zip(v.iter()).zip(diag.iter()) {
+                *hv += lambda * *d * *d * *v;
+            }
+            hv.iter().all(|hv| hv.is_finite()).then_some(hv)
+        };
+        let dp_norm = |p: &OVector<F, N>| enorm(&p.component_mul(diag));
+
+        let gradient = lls.a_t_b();
+        let mut p = param.step.clone();
+        let mut residual = &gradient - apply(&p)?;
+        let mut direction = residual.clone();
+        let mut residual_norm2 = residual.norm_squared();
+        let tolerance = gradient.norm_squared() * convert(1e-12);
+        let max_norm = self.delta * convert(1.1);
+        for _ in 0..Ord::min(MAX_ITERATIONS, p.nrows()) {
+            if residual_norm2 <= tolerance {
+                break;
+            }
+            let a_direction = apply(&direction)?;
+            let curvature = direction.dot(&a_direction);
+            if !curvature.is_positive() {
+                break;
+            }
+            let alpha = residual_norm2 / curvature;
+            let next = &p + &direction * alpha;
+            if dp_norm(&next) > Float::max(max_norm, param.dp_norm) {
+                break;
+            }
+            p = next;
+            residual.axpy(-alpha, &a_direction, F::one());
+            let next_norm2 = residual.norm_squared();
+            direction = &residual + &direction * (next_norm2 / residual_norm2);
+            residual_norm2 = next_norm2;
+        }
+
+        let hp = target.hessian_vector_product(&p)?;
+        let (gp, php) = (gradient.dot(&p), p.dot(&hp));
+        let two: F = convert(2.);
+        if !(two * gp - php).is_positive() || !php.is_finite() {
+            return None;
+        }
+        param.dp_norm = dp_norm(&p);
+        param.step = p;
+        Some((gp, php))
+    }
+
+    /// Largest fraction `$t \in (0, 1]$` of `step` for which `$\vec{x} - t\vec{p}$`
+    /// respects the parameter magnitude limit.
+    fn step_fraction_within_limit(&self, step: &OVector<F, N>) -> F {
+        let limit = match self.config.param_magnitude_limit {
+            Some(limit) => limit,
+            None => return F::one(),
+        };
+        let mut fraction = F::one();
+        for (x, p) in self.x.iter().zip(step.iter()) {
+            if Float::abs(*x) > limit || p.is_zero() {
+                continue;
+            }
+            let boundary = if p.is_negative() { limit } else { -limit };
+            fraction = Float::min(fraction, (*x - boundary) / *p);
+        }
+        fraction
+    }
+
+    /// The number of residual and Jacobian evaluations so far.
+    fn evaluations_used(&self) -> usize {
+        let iterations = self.report.terminated_at_iteration;
+        let jacobians = if self.config.constant_jacobian {
+            Ord::min(iterations, 1)
         } else {
-            // Need another iteration, did not change the parameters
-            Ok(None)
+            iterations - self.reused_jacobians
+        };
+        self.report.number_of_evaluations + jacobians
+    }
+
+    /// Whether the budget of `patience` is used up.
+    fn patience_exhausted(&self) -> bool {
+        let evaluations = if self.config.patience_reset {
+            self.report
+                .number_of_evaluations
+                .saturating_sub(self.patience_start)
+        } else {
+            self.report.number_of_evaluations
+        };
+        evaluations >= self.max_fev
+    }
+
+    /// Check if the budget for residual and Jacobian evaluations is used up.
+    ///
+    /// Returns the reason to terminate with.
+    fn evaluations_exhausted(&self) -> Option<TerminationReason> {
+        let evaluations = self.evaluations_used();
+        if self
+            .config
+            .max_evaluations
+            .is_some_and(|max_evaluations| evaluations >= max_evaluations)
+        {
+            return Some(TerminationReason::MaxEvaluations);
         }
+        self.config
+            .evaluation_time_budget
+            .filter(|budget| self.evaluation_time >= *budget)
+            .map(|_| TerminationReason::EvaluationTimeBudget)
+    }
+
+    /// See [`LMState::estimated_progress`](struct.LMState.html#structfield.estimated_progress).
+    fn estimated_progress(&self) -> Option<F> {
+        let gtol = self.config.gtol;
+        let gradient = match self.gradient_norms {
+            Some((initial, norm)) if gtol > F::zero() && gtol < F::one() => {
+                // zero at the first iteration and one once the gradient shrank by gtol
+                let progress = Float::ln(norm / initial) / Float::ln(gtol);
+                Some(progress).filter(|progress| !progress.is_nan())
+            }
+            _ => None,
+        };
+        let budget = self.config.max_evaluations.map(|max_evaluations| {
+            convert::<_, F>(self.evaluations_used() as f64) / convert(max_evaluations as f64)
+        });
+        let iterations = self.config.max_iterations.map(|max_iterations| {
+            let iterations = self.report.terminated_at_iteration - self.first_iteration;
+            convert::<_, F>(iterations as f64) / convert(max_iterations as f64)
+        });
+        let progress = [gradient, budget, iterations]
+            .into_iter()
+            .flatten()
+            .reduce(Float::max)?;
+        Some(Float::min(Float::max(progress, F::zero()), F::one()))
+    }
+
+    /// The norm `$\|\mathbf{D}\vec{x}\|$` of the current parameters.
+    ///
+    /// Without `scale_diag` the diagonal is the identity, but it is still
+    /// applied so that all trust-region quantities use the same metric.
+    fn scaled_xnorm(&mut self) -> F {
+        self.tmp.cmpy(F::one(), &self.diag, &self.x, F::zero());
+        enorm(&self.tmp)
     }
 
     #[inline]
@@ -0,0 +1,122 @@
+use alloc::vec::Vec;
+use approx::assert_relative_eq;
+
+use nalgebra::{storage::Owned, DMatrix, DVector, Dynamic};
+
+use super::{LevenbergMarquardt, TerminationReason};
+use crate::{BlockJacobian, LeastSquaresProblem};
+
+/// Fit `$y = a_i \exp(-b t)$` to several curves with individual
+/// amplitudes `$a_i$` and a shared decay rate `$b$`.
+#[derive(Clone)]
+struct Decay {
+    t: Vec<f64>,
+    y: Vec<Vec<f64>>,
+    p: DVector<f64>,
+}
+
+impl Decay {
+    fn new() -> Self {
+        let t: Vec<f64> = (0..6).map(|k| k as f64 * 0.5).collect();
+        let y = [1.5, 3., 0.7]
+            .iter()
+            .map(|&a| t.iter().map(|&t| a * f64::exp(-0.8 * t)).collect())
+            .collect();
+        Self {
+            t,
+            y,
+            p: DVector::from_vec(alloc::vec![1., 1., 1., 0.1]),
+        }
+    }
+
+    fn blocks(&self) -> BlockJacobian {
+        self.y
+            .iter()
+            .fold(BlockJacobian::new(1), |b, y| b.with_block(y.len(), 1))
+    }
+}
+
+impl LeastSquaresProblem<f64, Dynamic, Dynamic> for Decay {
+    type ParameterStorage = Owned<f64, Dynamic>;
+    type ResidualStorage = Owned<f64, Dynamic>;
+    type JacobianStorage = Owned<f64, Dynamic, Dynamic>;
+
+    fn set_params(&mut self, p: &DVector<f64>) {
+        self.p.copy_from(p);
+    }
+
+    fn params(&self) -> DVector<f64> {
+        self.p.clone()
+    }
+
+    fn residuals(&self) -> Option<DVector<f64>> {
+        let b = self.p[self.y.len()];
+        Some(DVector::from_iterator(
+            self.y.len() * self.t.len(),
+            self.y.iter().enumerate().flat_map(|(i, y)| {
+                let a = self.p[i];
+                self.t
+                    .iter()
+                    .zip(y.iter())
+                    .map(move |(&t, &y)| a * f64::exp(-b * t) - y)
+            }),
+        ))
+    }
+
+    fn jacobian(&self) -> Option<DMatrix<f64>> {
+        let n = self.y.len();
+        let b = self.p[n];
+        let mut jacobian = DMatrix::zeros(n * self.t.len(), n + 1);
+        for i in 0..n {
+            for (k, &t) in self.t.iter().enumerate() {
+                let row = i * self.t.len() + k;
+                jacobian[(row, i)] = f64::exp(-b * t);
+                jacobian[(row, n)] = -t * self.p[i] * f64::exp(-b * t);
+            }
+        }
+        Some(jacobian)
+    }
+}
+
+#[test]
+fn blocks_match_dense() {
+    let problem = Decay::new();
+    let blocks = problem.blocks();
+    let (dense, dense_report) = LevenbergMarquardt::new().minimize(problem.clone());
+    let (block, block_report) = LevenbergMarquardt::new().minimize_blocks(problem, &blocks);
+    assert!(dense_report.termination.was_successful());
+    assert!(block_report.termination.was_successful());
+    assert_relative_eq!(
+        block.p,
+        DVector::from_vec(alloc::vec![1.5, 3., 0.7, 0.8]),
+        epsilon = 1e-8
+    );
+    assert_relative_eq!(block.p, dense.p, epsilon = 1e-8);
+}
+
+#[test]
+fn blocks_wrong_parameter_count() {
+    let problem = Decay::new();
+    let blocks = BlockJacobian::new(2).with_block(18, 1);
+    let (_, report) = LevenbergMarquardt::new().minimize_blocks(problem, &blocks);
+    assert_eq!(
+        report.termination,
+        TerminationReason::WrongDimensions("block structure")
+    );
+    assert_eq!(report.number_of_evaluations, 0);
+}
+
+#[test]
+fn blocks_wrong_residual_count() {
+    let problem = Decay::new();
+    let blocks = BlockJacobian::new(1)
+        .with_block(6, 1)
+        .with_block(6, 1)
+        .with_block(5, 1);
+    let (_, report) = LevenbergMarquardt::new().minimize_blocks(problem, &blocks);
+    assert_eq!(
+        report.termination,
+        TerminationReason::WrongDimensions("block structure")
+    );
+    assert_eq!(report.number_of_residuals, 18);
+}
@@ -2,12 +2,16 @@
 //!
 //! There is also a Python implementation in `test_examples.py`
 //! which was used to get the output from MINPACK.
+use ::core::cell::Cell;
 use approx::assert_relative_eq;
 use nalgebra::*;
 use nalgebra::{allocator::Allocator, storage::Owned};
 
 use crate::utils::differentiate_numerically;
-use crate::{LeastSquaresProblem, LevenbergMarquardt, TerminationReason};
+use crate::{
+    curve_fit, BoundSide, ConfigOverride, CrossDirection, CurveFit, GtolReference, InfinityNorm,
+    LeastSquaresProblem, LevenbergMarquardt, LineSearch, LmCheckpoint, TerminationReason,
+};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "minpack-compat")] {
@@ -633,4 +637,4049 @@ impl LeastSquaresProblem<f64, U1, U2> for Beale {
     }
 }
 
+#[test]
+fn test_final_step_norm_xtol() {
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let xtol = 1e-6;
+    let (problem, report) = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_xtol(xtol)
+        .with_gtol(0.)
+        .with_scale_diag(false)
+        .minimize(problem);
+    assert_eq!(
+        report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+    assert!(report.final_step_norm > 0.);
+    assert!(report.final_step_norm <= xtol * problem.params.norm());
+}
+
+/// Linear problem `$\mathbf{A}\vec{x} - \vec{b}$` with badly scaled columns.
+#[derive(Clone)]
+struct BadlyScaled {
+    params: OVector<f64, U2>,
+}
+
+impl BadlyScaled {
+    fn matrix() -> OMatrix<f64, U3, U2> {
+        Matrix3x2::new(1e-4, 1e4, 2e-4, -1e4, 1e-4, 3e4)
+    }
+}
+
+impl LeastSquaresProblem<f64, U3, U2> for BadlyScaled {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U2>;
+
+    fn set_params(&mut self, params: &OVector<f64, U2>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> OVector<f64, U2> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<OVector<f64, U3>> {
+        Some(Self::matrix() * self.params - Vector3::new(1., 2., 3.))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<f64, U3, U2>> {
+        Some(Self::matrix())
+    }
+}
+
+#[test]
+fn test_autoscale() {
+    let problem = BadlyScaled {
+        params: Vector2::new(1., 1.),
+    };
+    let lm = LevenbergMarquardt::new().with_scale_diag(false);
+    let (plain, plain_report) = lm.minimize(problem.clone());
+    let (scaled, scaled_report) = lm.with_autoscale(true).minimize(problem);
+    assert!(plain_report.termination.was_successful());
+    assert!(scaled_report.termination.was_successful());
+    assert!(scaled_report.number_of_evaluations < plain_report.number_of_evaluations);
+    assert_relative_eq!(scaled.params, plain.params, max_relative = 1e-10);
+    assert_relative_eq!(
+        scaled_report.objective_function,
+        0.2903225806451612,
+        epsilon = 1e-12
+    );
+}
+
+#[test]
+fn test_scale_diag_first_step() {
+    let x0 = Vector2::new(1., 1.);
+    let objective_function = |x: &Vector2<f64>| {
+        let problem = BadlyScaled { params: *x };
+        problem.residuals().unwrap().norm_squared() * 0.5
+    };
+    let f0 = objective_function(&x0);
+    for scale_diag in [true, false] {
+        let config = LevenbergMarquardt::new().with_scale_diag(scale_diag);
+        let mut lm = config.solver(BadlyScaled { params: x0 }).ok().unwrap();
+        assert_eq!(lm.step(), Ok(()));
+        let x1 = *lm.params();
+        assert!(objective_function(&x1) < 1e-3 * f0);
+        // the first step stays within the initial trust region, up to the
+        // relative tolerance of the lambda iteration
+        let checkpoint = lm.checkpoint();
+        let step = (x1 - x0).component_mul(&checkpoint.diag).norm();
+        assert!(step <= 1.1 * 100. * x0.component_mul(&checkpoint.diag).norm());
+        if !scale_diag {
+            assert_eq!(checkpoint.diag, Vector2::new(1., 1.));
+        }
+    }
+}
+
+#[test]
+fn test_trust_region_state() {
+    let x0 = Vector2::new(1., 1.);
+    let config = LevenbergMarquardt::new().with_stepbound(0.01);
+    let mut lm = config.solver(Line { params: x0 }).ok().unwrap();
+    assert_eq!((lm.delta(), lm.xnorm()), (0., 0.));
+    assert_eq!(lm.step(), Ok(()));
+
+    // the diagonal holds the column norms of the Jacobian
+    let jacobian = Line { params: x0 }.jacobian().unwrap();
+    let diag = *lm.diag();
+    assert_relative_eq!(diag.x, jacobian.column(0).norm());
+    assert_relative_eq!(diag.y, jacobian.column(1).norm());
+    let x1 = *lm.params();
+    assert_relative_eq!(lm.xnorm(), x1.component_mul(&diag).norm());
+
+    // the first step ends at the initial radius, up to the tolerance of the
+    // lambda iteration, and the problem is linear, so the radius doubles
+    let step = (x1 - x0).component_mul(&diag).norm();
+    let initial_delta = 0.01 * x0.component_mul(&diag).norm();
+    assert!((step - initial_delta).abs() <= 0.1 * initial_delta);
+    assert_relative_eq!(lm.delta(), 2. * step, epsilon = 1e-12);
+    assert!(lm.lambda() > 0.);
+}
+
+#[test]
+fn test_lambda_iterations() {
+    // the Gauss-Newton step of the linear problem leaves the small trust region
+    let config = LevenbergMarquardt::new().with_stepbound(0.01);
+    let problem = || Line {
+        params: Vector2::new(1., 1.),
+    };
+    let mut trajectory = crate::Trajectory::new();
+    let (_, report) = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_observer(&mut trajectory)
+        .finish();
+    assert!(report.termination.was_successful());
+    let (_, counts) = trajectory.lambda_iterations_series();
+    assert!(counts[0] > 0);
+    // the bound of MINPACK
+    assert!(counts.iter().all(|&count| count <= 10));
+
+    // the checkpoints record the same counts, zero for Gauss-Newton steps
+    let mut lm = config.solver(problem()).ok().unwrap();
+    let history: alloc::vec::Vec<_> = lm.iterations().collect();
+    assert_eq!(history.len(), counts.len());
+    for (count, checkpoint) in counts.iter().zip(&history) {
+        assert_eq!(*count, checkpoint.lambda_iterations);
+        assert_eq!(*count == 0, checkpoint.lambda == 0.);
+    }
+
+    let config = config.with_max_lambda_iterations(2);
+    let mut lm = config.solver(problem()).ok().unwrap();
+    assert!(lm
+        .iterations()
+        .all(|checkpoint| checkpoint.lambda_iterations <= 2));
+}
+
+#[test]
+fn test_propose_step() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let mut automatic = config.solver(problem()).ok().unwrap();
+    let mut observer = CountingObserver {
+        calls: 0,
+        iterations: [0; 32],
+        terminated: false,
+    };
+    let mut supervised = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_observer(&mut observer);
+    let termination = loop {
+        let before = supervised.checkpoint();
+        let proposal = supervised.propose_step();
+        // proposing is a dry run
+        assert_eq!(supervised.checkpoint(), before);
+        assert_eq!(supervised.propose_step(), proposal);
+        let (x, predicted) = proposal.unwrap();
+        assert!(predicted < before.objective_function);
+
+        // discarding keeps the state, the same step is proposed again
+        assert!(supervised.discard_step());
+        assert!(!supervised.discard_step());
+        assert_eq!(supervised.checkpoint(), before);
+        assert_eq!(supervised.termination(), None);
+        assert_eq!(supervised.propose_step(), proposal);
+
+        let result = supervised.commit_step();
+        assert_eq!(result, automatic.step());
+        let after = supervised.checkpoint();
+        assert_eq!(after, automatic.checkpoint());
+        if after.trust_region_reductions == before.trust_region_reductions {
+            // the trial step was accepted without shrinking the trust region
+            assert_eq!(after.x, x);
+        }
+        assert!(!supervised.discard_step());
+        if let Err(reason) = result {
+            break reason;
+        }
+    };
+    // the last proposal was discarded once before it terminated the minimization
+    assert!(termination.was_successful());
+    assert_eq!(supervised.propose_step(), None);
+    let (_, report) = supervised.finish();
+    let (_, expected) = automatic.finish();
+    assert!(report.termination.was_successful());
+    assert_eq!(report.termination, expected.termination);
+    assert_eq!(
+        report.terminated_at_iteration,
+        expected.terminated_at_iteration
+    );
+    assert_eq!(report.number_of_evaluations, expected.number_of_evaluations);
+    assert_eq!(report.objective_function, expected.objective_function);
+    // only the committed steps are observed
+    assert_eq!(observer.calls, report.terminated_at_iteration);
+    assert!(observer.terminated);
+}
+
+#[test]
+fn test_evaluate_at() {
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let (_, expected) = config.minimize(problem.clone());
+
+    let mut lm = config.solver(problem).ok().unwrap();
+    let termination = loop {
+        if let Err(reason) = lm.step() {
+            break reason;
+        }
+    };
+    assert_eq!(termination, expected.termination);
+    assert_eq!(lm.step(), Err(termination));
+
+    let x = *lm.params();
+    let objective_function = lm.evaluate_at(&x).unwrap();
+    assert_eq!(objective_function, expected.objective_function);
+    // probing does not move the solution
+    assert!(lm.evaluate_at(&(x * 2.)).unwrap() > objective_function);
+    assert_eq!(*lm.params(), x);
+
+    let (problem, report) = lm.finish();
+    assert_eq!(problem.params, x);
+    assert_eq!(report.termination, expected.termination);
+    assert_eq!(
+        report.number_of_evaluations,
+        expected.number_of_evaluations + 2
+    );
+    assert_eq!(report.objective_function, expected.objective_function);
+
+    // the evaluation budget applies to probes as well
+    let config = config.with_max_evaluations(2);
+    let mut lm = config.solver(problem).ok().unwrap();
+    assert!(lm.evaluate_at(&(x * 2.)).is_some());
+    assert_eq!(lm.evaluate_at(&(x * 2.)), None);
+    assert_eq!(lm.finish().1.number_of_evaluations, 2);
+
+    // residuals of a different length are not compared
+    let config = LevenbergMarquardt::new();
+    let mut lm = config.solver(Truncated::new(1, usize::MAX)).ok().unwrap();
+    let x = *lm.params();
+    assert!(lm.evaluate_at(&x).is_none());
+}
+
+#[test]
+fn test_terminated_at_iteration() {
+    // linear problem, the first step is exact and the second iteration converges
+    let mut problem = LinearFullRank::new(OVector::<f64, U5>::zeros(), 10);
+    problem.set_params(&OVector::<f64, U5>::from_element(1.));
+    let (_, report) = LevenbergMarquardt::new()
+        .with_tol(TOL)
+        .minimize(problem.clone());
+    assert_eq!(report.terminated_at_iteration, 2);
+
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let mut lm = config.solver(problem).ok().unwrap();
+    let mut completed = 0;
+    while lm.step().is_ok() {
+        completed += 1;
+    }
+    let (_, report) = lm.finish();
+    assert_eq!(report.terminated_at_iteration, completed + 1);
+}
+
+#[test]
+fn test_checkpoint() {
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let configs = [
+        LevenbergMarquardt::new().with_tol(TOL),
+        LevenbergMarquardt::new()
+            .with_tol(TOL)
+            .with_weighted_ftol(true)
+            .with_stall_window(2, 1e-3),
+    ];
+    for config in configs {
+        let (expected_problem, expected) = config.minimize(problem.clone());
+        for k in 0..4 {
+            let mut lm = config.solver(problem.clone()).ok().unwrap();
+            for _ in 0..k {
+                lm.step().unwrap();
+            }
+            let checkpoint = lm.checkpoint();
+            drop(lm);
+
+            // resume with a fresh problem
+            let (resumed, report) = config.minimize_from_checkpoint(
+                HelicalValley {
+                    params: OVector::<f64, U3>::zeros(),
+                },
+                &checkpoint,
+            );
+            assert_eq!(resumed.params, expected_problem.params);
+            assert_eq!(report.termination, expected.termination);
+            assert_eq!(report.objective_function, expected.objective_function);
+            assert_eq!(report.final_step_norm, expected.final_step_norm);
+            assert_eq!(report.lambda, expected.lambda);
+            assert_eq!(report.gradient_norm, expected.gradient_norm);
+            assert_eq!(
+                report.terminated_at_iteration,
+                expected.terminated_at_iteration
+            );
+            assert_eq!(
+                report.trust_region_reductions,
+                expected.trust_region_reductions
+            );
+            assert_eq!(report.number_of_evaluations, expected.number_of_evaluations);
+        }
+    }
+}
+
+#[test]
+fn test_resume_with_other_config() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let coarse = LevenbergMarquardt::new()
+        .with_tol(1e-1)
+        .with_weighted_ftol(true)
+        .with_stall_window(2, 1e-3);
+    let fine = LevenbergMarquardt::new().with_tol(TOL);
+    let mut lm = coarse.solver(problem()).ok().unwrap();
+    while lm.step().is_ok() {}
+    let mut checkpoint = lm.checkpoint();
+    let (rough, rough_report) = lm.finish();
+    assert!(rough_report.termination.was_successful());
+    assert!((rough.params - Vector3::new(1., 0., 0.)).norm() > 1e-6);
+
+    // a terminated checkpoint stays terminated
+    let (_, report) = fine.minimize_from_checkpoint(problem(), &checkpoint);
+    assert_eq!(report.termination, rough_report.termination);
+    assert_eq!(
+        report.number_of_evaluations,
+        rough_report.number_of_evaluations
+    );
+
+    checkpoint.termination = None;
+    let (polished, report) = fine.minimize_from_checkpoint(problem(), &checkpoint);
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration > rough_report.terminated_at_iteration);
+    assert!(report.objective_function < rough_report.objective_function);
+    assert_relative_eq!(polished.params, Vector3::new(1., 0., 0.), epsilon = 1e-12);
+}
+
+/// Records the iterations in a fixed buffer, without allocations.
+struct CountingObserver {
+    calls: usize,
+    iterations: [usize; 32],
+    terminated: bool,
+}
+
+impl crate::Observer<f64, U3> for CountingObserver {
+    fn on_iteration(&mut self, state: &crate::LMState<'_, f64, U3>) {
+        assert!(!self.terminated, "no calls after the termination");
+        self.iterations[self.calls] = state.iteration;
+        self.calls += 1;
+        self.terminated = state.terminated;
+        assert_eq!(state.params.len(), 3);
+        assert!(state.objective_function.is_finite());
+    }
+}
+
+#[test]
+fn test_observer() {
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let mut observer = CountingObserver {
+        calls: 0,
+        iterations: [0; 32],
+        terminated: false,
+    };
+    let lm = config
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_observer(&mut observer);
+    let (_, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert!(observer.terminated);
+    assert_eq!(observer.calls, report.terminated_at_iteration);
+    for (i, iteration) in observer.iterations[..observer.calls].iter().enumerate() {
+        assert_eq!(*iteration, i + 1);
+    }
+}
+
+#[test]
+fn test_trajectory() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let mut trajectory = crate::Trajectory::new();
+    let (_, report) = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_observer(&mut trajectory)
+        .finish();
+    assert!(report.termination.was_successful());
+    assert_eq!(trajectory.len(), report.terminated_at_iteration);
+
+    let mut lm = config.solver(problem()).ok().unwrap();
+    let history: alloc::vec::Vec<_> = lm.iterations().collect();
+    let (iterations, objective) = trajectory.objective_series();
+    assert_eq!(
+        iterations,
+        (1..=history.len()).collect::<alloc::vec::Vec<_>>()
+    );
+    for (value, checkpoint) in objective.iter().zip(&history) {
+        assert_eq!(*value, checkpoint.objective_function);
+    }
+    let (lambda_iterations, lambda) = trajectory.lambda_series();
+    let (gradient_iterations, gradient_norm) = trajectory.gradient_norm_series();
+    assert_eq!(lambda_iterations, iterations);
+    assert_eq!(gradient_iterations, iterations);
+    assert_eq!(lambda.len(), history.len());
+    for (value, checkpoint) in gradient_norm.iter().zip(&history) {
+        assert_eq!(Some(*value), checkpoint.gradient_norm);
+    }
+    assert_eq!(gradient_norm.last().copied(), report.gradient_norm);
+}
+
+/// Checks the reductions of the accepted steps.
+struct ReductionObserver {
+    objective_function: f64,
+    accepted: usize,
+}
+
+impl crate::Observer<f64, U3> for ReductionObserver {
+    fn on_iteration(&mut self, state: &crate::LMState<'_, f64, U3>) {
+        if state.objective_function < self.objective_function {
+            self.accepted += 1;
+            assert!(state.actual_reduction > 0.);
+            assert!(state.predicted_reduction > 0.);
+            assert!(state.gain_ratio() >= 1e-4);
+            let actual = 1. - state.objective_function / self.objective_function;
+            assert_relative_eq!(state.actual_reduction, actual, epsilon = 1e-12);
+        } else {
+            assert!(state.terminated);
+        }
+        self.objective_function = state.objective_function;
+    }
+}
+
+#[test]
+fn test_observer_reductions() {
+    let problem = HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let mut observer = ReductionObserver {
+        objective_function: 0.5 * problem.residuals().unwrap().norm_squared(),
+        accepted: 0,
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let lm = config
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_observer(&mut observer);
+    let (_, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert!(observer.accepted + 1 >= report.terminated_at_iteration);
+}
+
+/// [`HelicalValley`] whose Jacobian becomes `NaN` after a few evaluations.
+struct Diverging {
+    valley: HelicalValley,
+    valid_jacobians: usize,
+    jacobians: Cell<usize>,
+}
+
+impl LeastSquaresProblem<f64, U3, U3> for Diverging {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U3>;
+
+    fn set_params(&mut self, params: &Vector3<f64>) {
+        self.valley.set_params(params);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.valley.params()
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        self.valley.residuals()
+    }
+
+    fn jacobian(&self) -> Option<Matrix3<f64>> {
+        self.jacobians.set(self.jacobians.get() + 1);
+        if self.jacobians.get() > self.valid_jacobians {
+            Some(Matrix3::from_element(f64::NAN))
+        } else {
+            self.valley.jacobian()
+        }
+    }
+}
+
+#[test]
+fn test_iterations_until_failure() {
+    // MINPACK does not check the Jacobian for NaN
+    if cfg!(feature = "minpack-compat") {
+        return;
+    }
+    let problem = Diverging {
+        valley: HelicalValley {
+            params: Vector3::new(-1., 0., 0.),
+        },
+        valid_jacobians: 3,
+        jacobians: Cell::new(0),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let mut lm = config.solver(problem).ok().unwrap();
+    let states: alloc::vec::Vec<_> = lm.iterations().collect();
+    assert_eq!(states.len(), 4);
+    for (i, state) in states.iter().enumerate() {
+        assert_eq!(state.iteration, i + 1);
+        assert!(state.objective_function.is_finite());
+    }
+    assert!(states[..3].iter().all(|state| state.termination.is_none()));
+    assert!(states[2].objective_function < states[0].objective_function);
+
+    // the failed iteration keeps the last valid parameters
+    let failure = TerminationReason::Numerical("jacobian");
+    assert_eq!(states[3].termination, Some(failure));
+    assert_eq!(states[3].x, states[2].x);
+    assert_eq!(lm.termination(), Some(failure));
+    assert_eq!(lm.iterations().count(), 0);
+    let (problem, report) = lm.finish();
+    assert_eq!(report.termination, failure);
+    assert_eq!(problem.params(), states[2].x);
+}
+
+#[test]
+fn test_config_variants() {
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let base = LevenbergMarquardt::new()
+        .with_tol(TOL)
+        .with_stepbound(10.)
+        .with_diag_update(crate::DiagUpdate::Recompute);
+    let variant = base.with_xtol(1e-4);
+    let copy = base;
+    assert_eq!(copy, base);
+    assert_ne!(variant, base);
+
+    let (first, report) = base.minimize(problem.clone());
+    let (second, copy_report) = copy.minimize(problem.clone());
+    assert!(report.termination.was_successful());
+    assert_eq!(first.params, second.params);
+    assert_eq!(report.termination, copy_report.termination);
+    assert_eq!(
+        report.number_of_evaluations,
+        copy_report.number_of_evaluations
+    );
+    assert_eq!(report.objective_function, copy_report.objective_function);
+
+    let (_, variant_report) = variant.minimize(problem);
+    assert!(variant_report.number_of_evaluations < report.number_of_evaluations);
+    let cloned = variant_report.clone();
+    assert_eq!(cloned.termination, variant_report.termination);
+}
+
+#[test]
+fn test_minimize_batch() {
+    let problems: alloc::vec::Vec<_> = (0..8)
+        .map(|i| {
+            let mut problem = Beale {
+                params: OVector::<f64, U2>::zeros(),
+            };
+            problem.set_params(&Vector2::new(1. + 0.1 * i as f64, 1. - 0.05 * i as f64));
+            problem
+        })
+        .collect();
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let results = config.minimize_batch(problems.clone());
+    assert_eq!(results.len(), problems.len());
+    for (problem, (batch_problem, batch_report)) in problems.into_iter().zip(results) {
+        let (problem, report) = config.minimize(problem);
+        assert_eq!(batch_problem.params, problem.params);
+        assert_eq!(batch_report.termination, report.termination);
+        assert_eq!(
+            batch_report.number_of_evaluations,
+            report.number_of_evaluations
+        );
+        assert_eq!(batch_report.objective_function, report.objective_function);
+    }
+}
+
+#[test]
+fn test_minimize_batch_reuses_buffers() {
+    // the buffers of the previous problem must not leak into the next one,
+    // also if the number of parameters changes
+    let problems: alloc::vec::Vec<_> = [3, 3, 5, 2, 2]
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| {
+            let mut params = DVector::from_element(n, 1.);
+            params[n - 1] = 0.1 + 0.05 * i as f64;
+            ManySettled { params }
+        })
+        .collect();
+    let config = LevenbergMarquardt::new()
+        .with_stall_window(3, 1e-12)
+        .with_gradient_plateau(3, 1e-12)
+        .with_nonmonotone(2)
+        .with_cycle_detection(2, 1e-12);
+    let copies = problems
+        .iter()
+        .map(|problem| ManySettled {
+            params: problem.params.clone(),
+        })
+        .collect::<alloc::vec::Vec<_>>();
+    let results = config.minimize_batch(copies);
+    for (problem, (batch_problem, batch_report)) in problems.into_iter().zip(results) {
+        let (problem, report) = config.minimize(problem);
+        assert_eq!(batch_problem.params, problem.params);
+        assert_eq!(batch_report.termination, report.termination);
+        assert_eq!(
+            batch_report.number_of_evaluations,
+            report.number_of_evaluations
+        );
+        assert_eq!(batch_report.objective_function, report.objective_function);
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_minimize_batch_parallel() {
+    let problems: alloc::vec::Vec<_> = (0..64)
+        .map(|i| {
+            let mut problem = Beale {
+                params: OVector::<f64, U2>::zeros(),
+            };
+            problem.set_params(&Vector2::new(1. + 0.01 * i as f64, 1. - 0.005 * i as f64));
+            problem
+        })
+        .collect();
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let results = config.minimize_batch_parallel(problems.clone());
+    assert_eq!(results.len(), problems.len());
+    for (problem, (batch_problem, batch_report)) in problems.into_iter().zip(results) {
+        let (problem, report) = config.minimize(problem);
+        assert_eq!(batch_problem.params, problem.params);
+        assert_eq!(batch_report.termination, report.termination);
+        assert_eq!(batch_report.objective_function, report.objective_function);
+    }
+}
+
+/// Exponential decay which writes the residuals into the buffer of the optimizer.
+struct InPlaceResiduals {
+    params: OVector<f64, U2>,
+    t: DVector<f64>,
+    y: DVector<f64>,
+    allocations: Cell<usize>,
+    buffer: Cell<Option<*const f64>>,
+}
+
+impl InPlaceResiduals {
+    fn write(&self, out: &mut DVector<f64>) {
+        let [a, b] = [self.params[0], self.params[1]];
+        for ((r, t), y) in out.iter_mut().zip(self.t.iter()).zip(self.y.iter()) {
+            *r = a * f64::exp(-b * t) - y;
+        }
+    }
+}
+
+impl LeastSquaresProblem<f64, Dynamic, U2> for InPlaceResiduals {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, Dynamic>;
+    type JacobianStorage = Owned<f64, Dynamic, U2>;
+
+    fn set_params(&mut self, params: &OVector<f64, U2>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> OVector<f64, U2> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<DVector<f64>> {
+        self.allocations.set(self.allocations.get() + 1);
+        let mut out = DVector::zeros(self.t.nrows());
+        self.write(&mut out);
+        Some(out)
+    }
+
+    fn residuals_into(&self, out: &mut DVector<f64>) -> bool {
+        match self.buffer.get() {
+            None => self.buffer.set(Some(out.as_ptr())),
+            Some(buffer) => assert_eq!(buffer, out.as_ptr()),
+        }
+        self.write(out);
+        true
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U2>> {
+        let [a, b] = [self.params[0], self.params[1]];
+        let mut jacobian = OMatrix::<f64, Dynamic, U2>::zeros(self.t.nrows());
+        for (i, t) in self.t.iter().enumerate() {
+            jacobian[(i, 0)] = f64::exp(-b * t);
+            jacobian[(i, 1)] = -a * t * f64::exp(-b * t);
+        }
+        Some(jacobian)
+    }
+}
+
+#[test]
+fn test_residuals_into() {
+    let t = DVector::from_fn(20, |i, _| i as f64 * 0.25);
+    let y = t.map(|t| 2. * f64::exp(-0.7 * t));
+    let problem = InPlaceResiduals {
+        params: Vector2::new(1., 1.),
+        t,
+        y,
+        allocations: Cell::new(0),
+        buffer: Cell::new(None),
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+    assert!(report.termination.was_successful());
+    assert!(report.number_of_evaluations > 2);
+    assert_relative_eq!(problem.params, Vector2::new(2., 0.7), epsilon = 1e-10);
+    // only the initial evaluation allocates
+    assert_eq!(problem.allocations.get(), 1);
+    assert!(problem.buffer.get().is_some());
+}
+
+/// Rosenbrock's valley `$\vec{r} = (k(y - x^2), 1 - x)$` with a steepness `$k$`.
+#[derive(Clone)]
+struct Valley {
+    params: Vector2<f64>,
+    k: f64,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for Valley {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Vector2::new(self.k * (y - x * x), 1. - x))
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        let x = self.params.x;
+        Some(Matrix2::new(-2. * self.k * x, self.k, -1., 0.))
+    }
+}
+
+#[test]
+fn test_diag_reset_on_stall() {
+    let problem = || Valley {
+        params: Vector2::new(-5., 20.),
+        k: 100.,
+    };
+    // the first iterations along the curved valley reduce too little
+    let lm = LevenbergMarquardt::new().with_stall_window(3, 0.1);
+    let (_, plain) = lm.minimize(problem());
+    assert_eq!(plain.termination, TerminationReason::NoImprovement);
+    assert!(plain.objective_function > 1.);
+
+    let (fit, reset) = lm.with_diag_reset_on_stall(true).minimize(problem());
+    assert!(reset.termination.was_successful());
+    assert!(reset.terminated_at_iteration > plain.terminated_at_iteration);
+    assert_relative_eq!(fit.params, Vector2::new(1., 1.), epsilon = 1e-8);
+
+    // without a stall window nothing changes
+    let (_, free) = LevenbergMarquardt::new().minimize(problem());
+    let (_, unused) = LevenbergMarquardt::new()
+        .with_diag_reset_on_stall(true)
+        .minimize(problem());
+    assert_eq!(free, unused);
+}
+
+#[test]
+fn test_gradient_fallback() {
+    let problem = Valley {
+        params: Vector2::new(-5., 20.),
+        k: 1000.,
+    };
+    // with very little patience pure LM gets stuck at the valley wall
+    let lm = LevenbergMarquardt::new().with_patience(2);
+    let (_, pure) = lm.minimize(problem.clone());
+    let (_, fallback) = lm.with_gradient_fallback(true).minimize(problem.clone());
+    assert_eq!(pure.termination, TerminationReason::LostPatience);
+    assert_eq!(fallback.termination, TerminationReason::LostPatience);
+    assert!(fallback.number_of_evaluations <= pure.number_of_evaluations);
+    assert!(fallback.objective_function < 1e-1 * pure.objective_function);
+
+    // a flat valley is solved with and without the fallback
+    let problem = Valley {
+        params: Vector2::new(-5., 20.),
+        k: 10.,
+    };
+    let lm = LevenbergMarquardt::new().with_patience(10);
+    let (pure, _) = lm.minimize(problem.clone());
+    let (fallback, report) = lm.with_gradient_fallback(true).minimize(problem);
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(fallback.params, Vector2::new(1., 1.), epsilon = 1e-10);
+    assert_relative_eq!(fallback.params, pure.params, epsilon = 1e-10);
+}
+
+/// Counts the residual and Jacobian evaluations of an expensive problem.
+struct Expensive {
+    valley: Valley,
+    calls: Cell<usize>,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for Expensive {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.valley.set_params(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.valley.params()
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        self.calls.set(self.calls.get() + 1);
+        self.valley.residuals()
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        self.calls.set(self.calls.get() + 1);
+        self.valley.jacobian()
+    }
+}
+
+#[test]
+fn test_max_evaluations() {
+    let expensive = || Expensive {
+        valley: Valley {
+            params: Vector2::new(-1.2, 1.),
+            k: 100.,
+        },
+        calls: Cell::new(0),
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(expensive());
+    assert!(report.termination.was_successful());
+    let unlimited = problem.calls.get();
+    assert!(unlimited > 20);
+
+    for max_evaluations in [1, 2, 5, 20] {
+        let (problem, report) = LevenbergMarquardt::new()
+            .with_max_evaluations(max_evaluations)
+            .minimize(expensive());
+        assert_eq!(report.termination, TerminationReason::MaxEvaluations);
+        assert_eq!(problem.calls.get(), max_evaluations);
+        assert_eq!(
+            report.number_of_evaluations + report.terminated_at_iteration,
+            max_evaluations
+        );
+    }
+
+    let (problem, report) = LevenbergMarquardt::new()
+        .with_max_evaluations(unlimited)
+        .minimize(expensive());
+    assert!(report.termination.was_successful());
+    assert_eq!(problem.calls.get(), unlimited);
+}
+
+/// Sleeps in every residual evaluation.
+#[cfg(feature = "std")]
+struct Sleepy {
+    valley: Valley,
+    sleep: std::time::Duration,
+    calls: Cell<usize>,
+}
+
+#[cfg(feature = "std")]
+impl LeastSquaresProblem<f64, U2, U2> for Sleepy {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.valley.set_params(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.valley.params()
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        self.calls.set(self.calls.get() + 1);
+        std::thread::sleep(self.sleep);
+        self.valley.residuals()
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        self.valley.jacobian()
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_evaluation_time_budget() {
+    use std::time::Duration;
+
+    let sleepy = |sleep| Sleepy {
+        valley: Valley {
+            params: Vector2::new(-1.2, 1.),
+            k: 100.,
+        },
+        sleep,
+        calls: Cell::new(0),
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(sleepy(Duration::ZERO));
+    assert!(report.termination.was_successful());
+    assert!(problem.calls.get() > 10);
+
+    // every evaluation takes at least 5ms, so the budget lasts for 4 of them
+    let (problem, report) = LevenbergMarquardt::new()
+        .with_evaluation_time_budget(Duration::from_millis(20))
+        .minimize(sleepy(Duration::from_millis(5)));
+    assert_eq!(report.termination, TerminationReason::EvaluationTimeBudget);
+    assert!(!report.termination.was_failure());
+    assert!((1..=4).contains(&problem.calls.get()));
+    assert_eq!(report.number_of_evaluations, problem.calls.get());
+
+    // fast evaluations do not reach a generous budget
+    let (_, report) = LevenbergMarquardt::new()
+        .with_evaluation_time_budget(Duration::from_secs(10))
+        .minimize(sleepy(Duration::ZERO));
+    assert!(report.termination.was_successful());
+}
+
+#[test]
+fn test_report_display() {
+    let problem = Valley {
+        params: Vector2::new(-1.2, 1.),
+        k: 10.,
+    };
+    let (_, report) = LevenbergMarquardt::new().minimize(problem);
+    let summary = alloc::format!("{}", report);
+    assert!(summary.contains(&alloc::format!("{:?}", report.termination)));
+    assert!(summary.contains(&alloc::format!("{}", report.objective_function)));
+    assert!(summary.contains("gradient norm:"));
+    assert!(summary.contains("lambda:"));
+    assert!(!summary.contains("numerical"));
+    // as many residuals as parameters and no retained factor
+    assert!(!summary.contains("reduced chi^2:"));
+    assert!(!summary.contains("condition number:"));
+
+    let (_, report) = LevenbergMarquardt::new()
+        .with_qr_retention(true)
+        .minimize(LinearFullRank::new(Vector5::from_element(1.), 10));
+    let summary = alloc::format!("{}", report);
+    let variance = report.residual_variance().unwrap();
+    assert!(summary.contains(&alloc::format!("reduced chi^2:      {}", variance)));
+    let diagonal = report.qr_r_factor.as_ref().unwrap().diagonal().abs();
+    let condition = diagonal.max() / diagonal.min();
+    assert!(summary.contains(&alloc::format!("condition number:   {}", condition)));
+
+    // nothing but the initial evaluation
+    let problem = Valley {
+        params: Vector2::new(-1.2, 1.),
+        k: 10.,
+    };
+    let (_, report) = LevenbergMarquardt::new()
+        .with_max_evaluations(1)
+        .minimize(problem);
+    let summary = alloc::format!("{}", report);
+    assert!(summary.contains("MaxEvaluations"));
+    assert!(!summary.contains("gradient norm:"));
+    assert!(!summary.contains("lambda:"));
+    assert_eq!(summary.lines().count(), 5);
+}
+
+#[test]
+fn test_convergence_certificate() {
+    let problem = || LinearFullRank::new(Vector5::from_element(1.), 10);
+    let configs = [
+        LevenbergMarquardt::new(),
+        LevenbergMarquardt::new().with_gtol(1e-3),
+        LevenbergMarquardt::new().with_ftol(0.).with_xtol(1e-3),
+        LevenbergMarquardt::new().with_xtol(0.),
+    ];
+    for config in configs {
+        let (_, report) = config.minimize(problem());
+        let certificate = report.certificate;
+        assert!(report.termination.was_successful());
+        assert_eq!(certificate.gradient_norm, report.gradient_norm);
+        assert_eq!(
+            (certificate.ftol, certificate.xtol),
+            (config.ftol, config.xtol)
+        );
+        let gradient_norm = certificate.gradient_norm.unwrap();
+        assert_eq!(
+            certificate.gradient_passed,
+            certificate.gtol > 0. && gradient_norm <= certificate.gtol
+        );
+        let step_norm = certificate.step_norm.unwrap();
+        assert_eq!(
+            certificate.step_passed,
+            certificate.xtol > 0. && step_norm <= certificate.xtol
+        );
+        let objective_change = certificate.objective_change.unwrap();
+        if certificate.objective_passed {
+            assert!(objective_change <= certificate.ftol);
+        }
+        match report.termination {
+            TerminationReason::Orthogonal => assert!(certificate.gradient_passed),
+            TerminationReason::Converged { ftol, xtol } => {
+                assert_eq!(certificate.objective_passed, ftol);
+                assert_eq!(certificate.step_passed, xtol);
+            }
+            reason => panic!("unexpected termination {:?}", reason),
+        }
+        assert!(certificate.passed());
+    }
+
+    // nothing was tested before the budget ran out
+    let (_, report) = LevenbergMarquardt::new()
+        .with_max_evaluations(1)
+        .minimize(problem());
+    assert_eq!(report.certificate.gradient_norm, None);
+    assert_eq!(report.certificate.step_norm, None);
+    assert!(!report.certificate.passed());
+}
+
+/// Two blocks of residuals of very different magnitude.
+///
+/// The first block `$10^6(a t_i - y_i)$` is linear with a remaining residual,
+/// the second block `$e^{b t_i} - z_i$` can be fitted exactly.
+#[derive(Clone)]
+struct TwoBlocks {
+    params: Vector2<f64>,
+}
+
+impl TwoBlocks {
+    const T: [f64; 5] = [0.25, 0.5, 0.75, 1., 1.25];
+    const Y: [f64; 5] = [0.49, 1.02, 1.48, 2.01, 2.5];
+}
+
+impl LeastSquaresProblem<f64, U10, U2> for TwoBlocks {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U10>;
+    type JacobianStorage = Owned<f64, U10, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<OVector<f64, U10>> {
+        let [a, b] = [self.params.x, self.params.y];
+        Some(OVector::<f64, U10>::from_fn(|i, _| {
+            if i < 5 {
+                1e6 * (a * Self::T[i] - Self::Y[i])
+            } else {
+                let t = Self::T[i - 5];
+                f64::exp(b * t) - f64::exp(-1.5 * t)
+            }
+        }))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<f64, U10, U2>> {
+        let b = self.params.y;
+        Some(OMatrix::<f64, U10, U2>::from_fn(|i, j| match (i < 5, j) {
+            (true, 0) => 1e6 * Self::T[i],
+            (false, 1) => {
+                let t = Self::T[i - 5];
+                t * f64::exp(b * t)
+            }
+            _ => 0.,
+        }))
+    }
+}
+
+#[test]
+fn test_weighted_ftol() {
+    let problem = TwoBlocks {
+        params: Vector2::new(1., 1.),
+    };
+    let lm = LevenbergMarquardt::new()
+        .with_ftol(1e-8)
+        .with_xtol(1e-15)
+        .with_gtol(0.);
+    // the relative reduction is dominated by the remaining large residuals
+    let (plain, report) = lm.minimize(problem.clone());
+    assert!(matches!(
+        report.termination,
+        TerminationReason::Converged { ftol: true, .. }
+    ));
+    assert!((plain.params.y + 1.5).abs() > 0.5);
+
+    let (weighted, report) = lm.with_weighted_ftol(true).minimize(problem);
+    assert!(matches!(
+        report.termination,
+        TerminationReason::Converged { ftol: true, .. }
+    ));
+    assert_relative_eq!(weighted.params.x, plain.params.x, epsilon = 1e-12);
+    assert_relative_eq!(weighted.params.y, -1.5, epsilon = 1e-3);
+}
+
+/// Fit a line `$y = a + b t$`.
+struct Line {
+    params: Vector2<f64>,
+}
+
+impl Line {
+    const T: [f64; 6] = [0., 1., 2., 3., 4., 5.];
+    const Y: [f64; 6] = [1.1, 2.9, 5.2, 6.8, 9.1, 11.];
+}
+
+impl LeastSquaresProblem<f64, U6, U2> for Line {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U6>;
+    type JacobianStorage = Owned<f64, U6, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector6<f64>> {
+        let [a, b] = [self.params.x, self.params.y];
+        Some(Vector6::from_fn(|i, _| a + b * Self::T[i] - Self::Y[i]))
+    }
+
+    fn jacobian(&self) -> Option<Matrix6x2<f64>> {
+        Some(Matrix6x2::from_fn(
+            |i, j| if j == 0 { 1. } else { Self::T[i] },
+        ))
+    }
+}
+
+#[test]
+fn test_prediction_variance() {
+    let problem = Line {
+        params: Vector2::new(0., 0.),
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+    assert!(report.covariance.is_none());
+    assert_eq!(report.prediction_variance(&Vector2::new(1., 2.)), None);
+
+    let (problem, report) = LevenbergMarquardt::new()
+        .with_covariance(true)
+        .minimize(problem);
+    assert!(report.termination.was_successful());
+
+    // closed form for the variance of a line fit at t
+    let n = Line::T.len() as f64;
+    let mean = Line::T.iter().sum::<f64>() / n;
+    let sxx: f64 = Line::T.iter().map(|t| (t - mean).powi(2)).sum();
+    let s2 = problem.residuals().unwrap().norm_squared() / (n - 2.);
+    for t in [-1., 0., 2.5, 7.] {
+        let expected = s2 * (1. / n + (t - mean).powi(2) / sxx);
+        let variance = report.prediction_variance(&Vector2::new(1., t)).unwrap();
+        assert_relative_eq!(variance, expected, epsilon = 1e-12);
+    }
+    let covariance = report.covariance.unwrap();
+    assert_relative_eq!(covariance[(1, 1)], s2 / sxx, epsilon = 1e-12);
+}
+
+#[test]
+fn test_covariance_with_variance() {
+    let (_, report) = LevenbergMarquardt::new()
+        .with_covariance(true)
+        .minimize(Line {
+            params: Vector2::zeros(),
+        });
+    assert!(report.termination.was_successful());
+    let covariance = report.covariance.as_ref().unwrap();
+    let estimated = report.residual_variance().unwrap();
+    let known = report.covariance_with_variance(0.04).unwrap();
+    assert_relative_eq!(known, covariance * (0.04 / estimated), epsilon = 1e-14);
+    assert_relative_eq!(
+        report.covariance_with_variance(estimated).unwrap(),
+        covariance,
+        epsilon = 1e-14
+    );
+
+    let (_, report) = LevenbergMarquardt::new().minimize(Line {
+        params: Vector2::zeros(),
+    });
+    assert_eq!(report.covariance_with_variance(0.04), None);
+}
+
+#[test]
+fn test_stepbound_abs() {
+    let x0 = Vector2::new(1e-10, 1e-10);
+    let objective_function = |x: &Vector2<f64>| {
+        let problem = Line { params: *x };
+        problem.residuals().unwrap().norm_squared() * 0.5
+    };
+    let first_step = |config: super::LevenbergMarquardt<f64>| {
+        let mut lm = config.solver(Line { params: x0 }).ok().unwrap();
+        // the full step to the solution of the linear problem may converge
+        assert!(matches!(
+            lm.step(),
+            Ok(()) | Err(TerminationReason::Converged { .. })
+        ));
+        (*lm.params(), lm.checkpoint())
+    };
+
+    // the relative bound only allows a step of the order of 1e-8
+    let (x1, checkpoint) = first_step(LevenbergMarquardt::new());
+    let step = (x1 - x0).component_mul(&checkpoint.diag).norm();
+    assert!(step <= 1.1 * 100. * x0.component_mul(&checkpoint.diag).norm());
+    assert!(step < 1e-7);
+    assert!(objective_function(&x1) > 0.99 * objective_function(&x0));
+
+    // the absolute floor allows the Gauss-Newton step to the solution
+    let (x1, _) = first_step(LevenbergMarquardt::new().with_stepbound_abs(100.));
+    assert_relative_eq!(x1, Vector2::new(1.0, 2.0), epsilon = 0.2);
+    assert!(objective_function(&x1) < 0.1);
+}
+
+#[test]
+fn test_jacobian_products() {
+    let v = Vector2::new(0.5, -2.);
+    let u = Vector6::new(1., -1., 2., 0.5, 0., 3.);
+    for constant_jacobian in [false, true] {
+        let config = LevenbergMarquardt::new().with_constant_jacobian(constant_jacobian);
+        let mut lm = config
+            .solver(Line {
+                params: Vector2::new(0.3, 1.7),
+            })
+            .ok()
+            .unwrap();
+        assert_eq!(lm.step(), Ok(()));
+        let jacobian = Matrix6x2::from_fn(|i, j| if j == 0 { 1. } else { Line::T[i] });
+        assert_eq!(lm.jacobian_vector_product(&v).unwrap(), jacobian * v);
+        assert_eq!(
+            lm.jacobian_transpose_vector_product(&u).unwrap(),
+            jacobian.transpose() * u
+        );
+    }
+}
+
+#[test]
+fn test_residual_variance() {
+    let (_, report) = LevenbergMarquardt::new().minimize(Line {
+        params: Vector2::zeros(),
+    });
+    assert!(report.termination.was_successful());
+    assert_eq!(report.number_of_residuals, 6);
+    assert_eq!(report.number_of_parameters, 2);
+    // sum of squared residuals of the regression line is 281/2625
+    assert_relative_eq!(
+        report.residual_variance().unwrap(),
+        281. / 10500.,
+        epsilon = 1e-12
+    );
+    // the objective function has the factor 1/2, the sum of squares not
+    assert_eq!(report.sum_of_squares(), 2. * report.objective_function);
+    assert_relative_eq!(report.sum_of_squares(), 281. / 2625., epsilon = 1e-12);
+
+    let (_, report) = LevenbergMarquardt::new().minimize(Underdetermined {
+        params: Vector3::zeros(),
+    });
+    assert_eq!(report.residual_variance(), None);
+}
+
+#[test]
+fn test_information_criteria() {
+    // a line with a small periodic deviation
+    let data: alloc::vec::Vec<(f64, f64)> = (0..20)
+        .map(|i| 0.25 * i as f64)
+        .map(|t| (t, 1. + 2. * t + 0.01 * f64::cos(7. * t)))
+        .collect();
+    let line = |x: &Vector2<f64>, t: f64| x[0] + x[1] * t;
+    let cubic = |x: &Vector4<f64>, t: f64| x[0] + t * (x[1] + t * (x[2] + t * x[3]));
+    let config = LevenbergMarquardt::new();
+    let (_, simple, _) = curve_fit(Vector2::zeros(), &data, line, &config);
+    let (_, complex, _) = curve_fit(Vector4::zeros(), &data, cubic, &config);
+    assert!(simple.termination.was_successful());
+    assert!(complex.termination.was_successful());
+    // the cubic fits a little better, but not enough to justify two parameters
+    assert!(complex.objective_function < simple.objective_function);
+    assert!(simple.aic() < complex.aic());
+    assert!(simple.bic(20) < complex.bic(20));
+
+    let sum_of_squares = 2. * simple.objective_function;
+    assert_relative_eq!(
+        simple.aic(),
+        20. * f64::ln(sum_of_squares / 20.) + 4.,
+        epsilon = 1e-12
+    );
+    assert_relative_eq!(
+        simple.bic(20),
+        20. * f64::ln(sum_of_squares / 20.) + 2. * f64::ln(20.),
+        epsilon = 1e-12
+    );
+}
+
+#[test]
+fn test_qr_retention() {
+    let problem = Line {
+        params: Vector2::new(0., 0.),
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+    assert!(report.qr_r_factor.is_none());
+    assert!(report.qr_permutation.is_none());
+    assert_eq!(report.effective_dof(), None);
+
+    let (problem, report) = LevenbergMarquardt::new()
+        .with_qr_retention(true)
+        .minimize(problem);
+    assert!(report.termination.was_successful());
+    let r = report.qr_r_factor.clone().unwrap();
+    let permutation = report.qr_permutation.clone().unwrap();
+    assert_eq!(r.shape(), (2, 2));
+    // the second column has the larger norm and is moved to the front
+    assert_eq!(permutation, [1, 0]);
+    assert_eq!(r[(1, 0)], 0.);
+
+    let jacobian = problem.jacobian().unwrap();
+    let jtj = jacobian.tr_mul(&jacobian);
+    let rtr = r.tr_mul(&r);
+    for i in 0..2 {
+        for j in 0..2 {
+            assert_relative_eq!(
+                jtj[(permutation[i], permutation[j])],
+                rtr[(i, j)],
+                epsilon = 1e-12
+            );
+        }
+    }
+
+    // the diagonal of the hat matrix, its trace is the number of parameters
+    let hat = jacobian * jtj.try_inverse().unwrap() * jacobian.transpose();
+    let leverages = report.leverages.as_ref().unwrap();
+    assert_relative_eq!(
+        leverages.as_slice(),
+        hat.diagonal().as_slice(),
+        epsilon = 1e-12
+    );
+    assert_relative_eq!(report.effective_dof().unwrap(), 4., epsilon = 1e-12);
+}
+
+/// Parameters of very different magnitude, `$x_1 = 1000$` and `$x_2 = 10^{-3}$`.
+struct MixedScale {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for MixedScale {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        let [x1, x2] = [self.params.x, self.params.y];
+        Some(Vector2::new(x1 - 1000., x2 * x2 - 1e-6))
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        Some(Matrix2::new(1., 0., 0., 2. * self.params.y))
+    }
+}
+
+#[test]
+fn test_typical_magnitudes() {
+    let config = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_gtol(0.)
+        .with_xtol(1e-8);
+    let problem = || MixedScale {
+        params: Vector2::new(900., 0.1),
+    };
+
+    // the global norm is dominated by the first parameter
+    let (global, report) = config.minimize(problem());
+    assert_eq!(
+        report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+    assert!((global.params.y - 1e-3).abs() > 1e-5 * 1e-3);
+    let global_iterations = report.terminated_at_iteration;
+
+    let lm = config.solver(problem()).ok().unwrap();
+    let (typical, report) = lm
+        .with_typical_magnitudes(Vector2::new(1000., 1e-3))
+        .finish();
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration > global_iterations);
+    assert_relative_eq!(typical.params.y, 1e-3, max_relative = 1e-8);
+    assert_relative_eq!(typical.params.x, 1000., epsilon = 1e-12);
+}
+
+#[test]
+fn test_xtol_componentwise() {
+    let config = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_gtol(0.)
+        .with_xtol(1e-8);
+    let problem = || MixedScale {
+        params: Vector2::new(900., 0.1),
+    };
+
+    // the small parameter still changes when the global test passes
+    let (global, report) = config.minimize(problem());
+    assert_eq!(
+        report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+    assert!((global.params.y - 1e-3).abs() > 1e-5 * 1e-3);
+    let global_iterations = report.terminated_at_iteration;
+
+    let (componentwise, report) = config.with_xtol_componentwise(1e-8).minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration > global_iterations);
+    assert_relative_eq!(componentwise.params.y, 1e-3, max_relative = 1e-8);
+    assert_relative_eq!(componentwise.params.x, 1000., epsilon = 1e-12);
+
+    // a loose threshold stops once the small parameter settled
+    let (_, report) = config.with_xtol_componentwise(1e-2).minimize(problem());
+    assert_eq!(
+        report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+}
+
+/// Residual `$\arctan(x - 4.3)$` which is only finite for `$|x| \leq 10$`.
+///
+/// The flat tails make the first Gauss-Newton step from `$x = 0$` overshoot.
+struct Saturating {
+    params: Vector1<f64>,
+    largest: Cell<f64>,
+}
+
+impl LeastSquaresProblem<f64, U1, U1> for Saturating {
+    type ParameterStorage = Owned<f64, U1>;
+    type ResidualStorage = Owned<f64, U1>;
+    type JacobianStorage = Owned<f64, U1, U1>;
+
+    fn set_params(&mut self, params: &Vector1<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector1<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector1<f64>> {
+        let x = self.params.x;
+        self.largest.set(self.largest.get().max(x.abs()));
+        if x.abs() > 10. {
+            return Some(Vector1::new(f64::NAN));
+        }
+        Some(Vector1::new((x - 4.3).atan()))
+    }
+
+    fn jacobian(&self) -> Option<Vector1<f64>> {
+        let x = self.params.x;
+        Some(Vector1::new(1. / (1. + (x - 4.3).powi(2))))
+    }
+}
+
+#[test]
+fn test_param_magnitude_limit() {
+    let problem = || Saturating {
+        params: Vector1::new(0.),
+        largest: Cell::new(0.),
+    };
+    // the first step leaves the domain of the model
+    let (plain, _) = LevenbergMarquardt::new().minimize(problem());
+    assert!(plain.largest.get() > 10.);
+
+    let (limited, report) = LevenbergMarquardt::new()
+        .with_param_magnitude_limit(10.)
+        .minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!(limited.largest.get() <= 10.);
+    assert_relative_eq!(limited.params.x, 4.3, epsilon = 1e-10);
+}
+
+/// Residuals `$\vec{x} - \vec{t}$` for the target `$\vec{t}$`.
+struct Pulled {
+    params: Vector3<f64>,
+    target: Vector3<f64>,
+}
+
+impl LeastSquaresProblem<f64, U3, U3> for Pulled {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U3>;
+
+    fn set_params(&mut self, params: &Vector3<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        Some(self.params - self.target)
+    }
+
+    fn jacobian(&self) -> Option<Matrix3<f64>> {
+        Some(Matrix3::identity())
+    }
+}
+
+#[test]
+fn test_active_bounds() {
+    let problem = |target| Pulled {
+        params: Vector3::zeros(),
+        target,
+    };
+    let (_, report) = LevenbergMarquardt::new().minimize(problem(Vector3::new(1., -8., 2.)));
+    assert!(report.active_bounds.is_empty());
+
+    let config = LevenbergMarquardt::new().with_param_magnitude_limit(3.);
+    // the steps are shortened in their direction, so only the parameter
+    // pulled the furthest ends up at the limit
+    let (limited, report) = config.minimize(problem(Vector3::new(1., -8., 2.)));
+    assert!(report.termination.was_successful());
+    assert_eq!(limited.params.y, -3.);
+    assert!(limited.params.x.abs() < 3. && limited.params.z.abs() < 3.);
+    assert_eq!(report.active_bounds, [(1, BoundSide::Lower)]);
+
+    let (_, report) = config.minimize(problem(Vector3::new(2., 1., 5.)));
+    assert_eq!(report.active_bounds, [(2, BoundSide::Upper)]);
+}
+
+#[test]
+fn test_projected_gradient_at_bounds() {
+    let problem = |target| Pulled {
+        params: Vector3::zeros(),
+        target,
+    };
+    let config = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_xtol(0.)
+        .with_gtol(1e-8)
+        .with_param_magnitude_limit(3.);
+    for (target, side) in [(5., BoundSide::Upper), (-5., BoundSide::Lower)] {
+        // the unconstrained optimum is outside of the limit, the gradient
+        // stays away from zero at the boundary
+        let (limited, report) = config.minimize(problem(Vector3::new(0., target, 0.)));
+        assert_eq!(report.termination, TerminationReason::Orthogonal);
+        assert_eq!(limited.params, Vector3::new(0., target.signum() * 3., 0.));
+        assert_eq!(report.active_bounds, [(1, side)]);
+        assert_eq!(report.gradient_norm, Some(0.));
+        assert_relative_eq!(report.objective_function, 2.);
+    }
+    // a gradient pointing into the limit is not projected away
+    let (limited, report) = config.minimize(Pulled {
+        params: Vector3::new(0., 3., 0.),
+        target: Vector3::new(0., 1., 0.),
+    });
+    assert!(report.termination.was_successful());
+    assert!(report.active_bounds.is_empty());
+    assert_relative_eq!(limited.params.y, 1.);
+}
+
+/// Residual `$x - 3$` which is `NaN` for the first trial steps.
+struct Flaky {
+    params: Vector1<f64>,
+    evaluations: Cell<usize>,
+    non_finite: usize,
+}
+
+impl LeastSquaresProblem<f64, U1, U1> for Flaky {
+    type ParameterStorage = Owned<f64, U1>;
+    type ResidualStorage = Owned<f64, U1>;
+    type JacobianStorage = Owned<f64, U1, U1>;
+
+    fn set_params(&mut self, params: &Vector1<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector1<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector1<f64>> {
+        let evaluations = self.evaluations.get();
+        self.evaluations.set(evaluations + 1);
+        if (1..=self.non_finite).contains(&evaluations) {
+            return Some(Vector1::new(f64::NAN));
+        }
+        Some(Vector1::new(self.params.x - 3.))
+    }
+
+    fn jacobian(&self) -> Option<Matrix1<f64>> {
+        Some(Matrix1::new(1.))
+    }
+}
+
+#[test]
+fn test_recovery_attempts() {
+    // the first three trial steps have to be shrunk
+    let problem = || Flaky {
+        params: Vector1::new(0.),
+        evaluations: Cell::new(0),
+        non_finite: 3,
+    };
+    let (recovered, report) = LevenbergMarquardt::new()
+        .with_recovery_attempts(3)
+        .minimize(problem());
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(recovered.params.x, 3., epsilon = 1e-12);
+    assert!(report.trust_region_reductions >= 3);
+
+    // MINPACK does not check the residuals for NaN
+    if cfg!(feature = "minpack-compat") {
+        return;
+    }
+    let (failed, report) = LevenbergMarquardt::new()
+        .with_recovery_attempts(2)
+        .minimize(problem());
+    assert_eq!(
+        report.termination,
+        TerminationReason::Numerical("residuals")
+    );
+    assert_eq!(report.number_of_evaluations, 4);
+    assert_eq!(failed.params.x, 0.);
+}
+
+#[test]
+fn test_try_minimize() {
+    let problem = |x| Saturating {
+        params: Vector1::new(x),
+        largest: Cell::new(0.),
+    };
+    let (solved, report) = LevenbergMarquardt::new().try_minimize(problem(0.)).unwrap();
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(solved.params.x, 4.3, epsilon = 1e-10);
+
+    // the residuals are NaN at the initial guess, MINPACK does not check this
+    if !cfg!(feature = "minpack-compat") {
+        let error = LevenbergMarquardt::new()
+            .try_minimize(problem(11.))
+            .err()
+            .unwrap();
+        assert!(error.report.termination.was_failure());
+        assert_eq!(
+            error.report.termination,
+            TerminationReason::InvalidInitialPoint
+        );
+        assert_eq!(error.report.number_of_evaluations, 1);
+        assert!(error.report.objective_function.is_nan());
+    }
+
+    // running out of evaluations is not a failure
+    let (_, report) = LevenbergMarquardt::new()
+        .with_max_evaluations(1)
+        .try_minimize(problem(0.))
+        .unwrap();
+    assert_eq!(report.termination, TerminationReason::MaxEvaluations);
+}
+
+/// Residuals `$\vec{x} - (1, 2)^\top$` with deterministic noise which
+/// changes with every evaluation, so the objective function wiggles in a band.
+struct Noisy {
+    params: Vector2<f64>,
+    calls: Cell<usize>,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for Noisy {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        let k = self.calls.get() as f64;
+        self.calls.set(self.calls.get() + 1);
+        let noise = Vector2::new(f64::sin(7. * k), f64::cos(3. * k)) * 1e-3;
+        Some(self.params - Vector2::new(1., 2.) + noise)
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        Some(Matrix2::identity())
+    }
+}
+
+#[test]
+fn test_stall_window() {
+    let problem = || Noisy {
+        params: Vector2::new(-3., 5.),
+        calls: Cell::new(0),
+    };
+    // every step reduces the objective function by more than ftol, the
+    // minimization only stops once the noise rejects even the tiniest steps
+    let (_, plain) = LevenbergMarquardt::new().minimize(problem());
+    if cfg!(feature = "minpack-compat") {
+        assert_eq!(
+            plain.termination,
+            TerminationReason::Converged {
+                ftol: false,
+                xtol: true
+            }
+        );
+    } else {
+        assert_eq!(plain.termination, TerminationReason::TrustRegionCollapsed);
+    }
+
+    let (_, report) = LevenbergMarquardt::new()
+        .with_stall_window(3, 0.5)
+        .minimize(problem());
+    assert_eq!(report.termination, TerminationReason::NoImprovement);
+    assert!(report.terminated_at_iteration < plain.terminated_at_iteration);
+    assert!(report.objective_function < 1e-5);
+}
+
+/// Residuals `$(x_1^4, x_2^4)^\top$` with a degenerate minimum at zero.
+///
+/// The Gauss-Newton steps shrink both parameters by `$\frac{3}{4}$`, so the
+/// scaled gradient stays constant while the objective function keeps decreasing.
+struct Quartic {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for Quartic {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        Some(self.params.map(|x| x.powi(4)))
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        Some(Matrix2::from_diagonal(&self.params.map(|x| 4. * x.powi(3))))
+    }
+}
+
+#[test]
+fn test_gradient_plateau() {
+    let problem = || Quartic {
+        params: Vector2::new(1., 2.),
+    };
+    let (_, plain) = LevenbergMarquardt::new().minimize(problem());
+    assert_ne!(plain.termination, TerminationReason::GradientPlateau);
+
+    let (_, report) = LevenbergMarquardt::new()
+        .with_gradient_plateau(3, 1e-3)
+        .minimize(problem());
+    assert_eq!(report.termination, TerminationReason::GradientPlateau);
+    assert!(!report.termination.was_failure());
+    assert!(report.terminated_at_iteration < plain.terminated_at_iteration);
+    // the objective function was still decreasing
+    assert!(report.objective_function < report.initial_objective);
+    assert!(plain.objective_function < report.objective_function);
+}
+
+/// A residual which halves with every evaluation and a Jacobian which sends
+/// the Gauss-Newton step from `$x$` to `$3 - x$`, so the accepted parameters
+/// alternate between 1 and 2.
+struct Alternating {
+    params: Vector1<f64>,
+    residual: Cell<f64>,
+}
+
+impl LeastSquaresProblem<f64, U1, U1> for Alternating {
+    type ParameterStorage = Owned<f64, U1>;
+    type ResidualStorage = Owned<f64, U1>;
+    type JacobianStorage = Owned<f64, U1, U1>;
+
+    fn set_params(&mut self, params: &Vector1<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector1<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector1<f64>> {
+        let residual = self.residual.get() * 0.5;
+        self.residual.set(residual);
+        Some(Vector1::new(residual))
+    }
+
+    fn jacobian(&self) -> Option<Matrix1<f64>> {
+        // the step -r / J lands on 3 - x
+        let x = self.params.x;
+        Some(Matrix1::new(-self.residual.get() / (3. - 2. * x)))
+    }
+}
+
+#[test]
+fn test_cycle_detection() {
+    let problem = || Alternating {
+        params: Vector1::new(1.),
+        residual: Cell::new(2.),
+    };
+    let (_, plain) = LevenbergMarquardt::new().minimize(problem());
+    assert_eq!(plain.termination, TerminationReason::LostPatience);
+
+    let config = LevenbergMarquardt::new().with_cycle_detection(3, 1e-10);
+    let mut lm = config.solver(problem()).ok().unwrap();
+    let points: alloc::vec::Vec<f64> = lm.iterations().map(|checkpoint| checkpoint.x.x).collect();
+    assert_eq!(lm.termination(), Some(TerminationReason::Cycling));
+    assert_eq!(points.len(), 4);
+    for (i, x) in points.iter().enumerate() {
+        assert_relative_eq!(*x, if i % 2 == 0 { 2. } else { 1. }, epsilon = 1e-12);
+    }
+    let (_, report) = lm.finish();
+    assert_eq!(report.termination, TerminationReason::Cycling);
+    assert!(report.number_of_evaluations < plain.number_of_evaluations);
+}
+
+#[test]
+fn test_trust_region_reductions() {
+    let problem = || Valley {
+        params: Vector2::new(-3., 10.),
+        k: 10.,
+    };
+    // the first steps overshoot into the walls of the valley
+    let (_, report) = LevenbergMarquardt::new().minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!(report.trust_region_reductions > 0);
+    assert!(report.trust_region_reductions < report.number_of_evaluations);
+
+    let (_, small) = LevenbergMarquardt::new()
+        .with_stepbound(0.01)
+        .minimize(problem());
+    assert!(small.termination.was_successful());
+    assert!(small.trust_region_reductions < report.trust_region_reductions);
+}
+
+/// Linear residuals `$(x_1 - 1, x_2 - 2, x_1 + x_2 + x_3 - 10)$`.
+///
+/// If `frozen` is set, `$x_3$` is held fixed: updates of it are ignored and
+/// its column of the Jacobian is zero, so the optimizer never tries to move it.
+struct Frozen {
+    params: Vector3<f64>,
+    frozen: bool,
+}
+
+impl LeastSquaresProblem<f64, U3, U3> for Frozen {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U3>;
+
+    fn set_params(&mut self, x: &Vector3<f64>) {
+        let z = if self.frozen { self.params.z } else { x.z };
+        self.params = Vector3::new(x.x, x.y, z);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        let x = &self.params;
+        Some(Vector3::new(x.x - 1., x.y - 2., x.x + x.y + x.z - 10.))
+    }
+
+    fn jacobian(&self) -> Option<Matrix3<f64>> {
+        let dz = if self.frozen { 0. } else { 1. };
+        Some(Matrix3::new(
+            1., 0., 0., //
+            0., 1., 0., //
+            1., 1., dz,
+        ))
+    }
+}
+
+#[test]
+fn test_gradient_components() {
+    let problem = Frozen {
+        params: Vector3::zeros(),
+        frozen: true,
+    };
+    let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+    assert!(report.termination.was_successful());
+    assert!(report.gradient_components().is_none());
+    assert_relative_eq!(
+        problem.params,
+        Vector3::new(10. / 3., 13. / 3., 0.),
+        epsilon = 1e-8
+    );
+
+    // inspect the full problem at the solution with the frozen parameter
+    let mut problem = problem;
+    problem.frozen = false;
+    let (problem, report) = LevenbergMarquardt::new()
+        .with_qr_retention(true)
+        .with_max_evaluations(1)
+        .minimize(problem);
+    assert_eq!(report.termination, TerminationReason::MaxEvaluations);
+    let gradient = report.gradient_components().unwrap();
+    let jacobian = problem.jacobian().unwrap();
+    let residuals = problem.residuals().unwrap();
+    assert_relative_eq!(
+        gradient.as_slice(),
+        jacobian.tr_mul(&residuals).as_slice(),
+        epsilon = 1e-12
+    );
+    // the free parameters are satisfied, the frozen one still pulls
+    assert!(gradient[0].abs() < 1e-8 && gradient[1].abs() < 1e-8);
+    assert_relative_eq!(gradient[2], -7. / 3., epsilon = 1e-8);
+}
+
+/// Linear residuals `$\mathbf{A}\vec{x} - \vec{b}$` with two equations for three unknowns.
+struct Underdetermined {
+    params: Vector3<f64>,
+}
+
+impl Underdetermined {
+    fn a() -> Matrix2x3<f64> {
+        Matrix2x3::new(
+            1., 2., 3., //
+            4., 5., 6.,
+        )
+    }
+
+    fn b() -> Vector2<f64> {
+        Vector2::new(2., 5.)
+    }
+}
+
+impl LeastSquaresProblem<f64, U2, U3> for Underdetermined {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U3>;
+
+    fn set_params(&mut self, x: &Vector3<f64>) {
+        self.params.copy_from(x);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        Some(Self::a() * self.params - Self::b())
+    }
+
+    fn jacobian(&self) -> Option<Matrix2x3<f64>> {
+        Some(Self::a())
+    }
+}
+
+#[test]
+fn test_min_norm() {
+    let a = Underdetermined::a();
+    let pseudo_inverse = a.transpose() * (a * a.transpose()).try_inverse().unwrap();
+    let lm = LevenbergMarquardt::new().with_scale_diag(false);
+
+    for x0 in [Vector3::zeros(), Vector3::new(1., -2., 0.5)] {
+        let (problem, report) = lm
+            .with_min_norm(true)
+            .minimize(Underdetermined { params: x0 });
+        assert!(report.termination.was_successful());
+        let nearest = x0 + pseudo_inverse * (Underdetermined::b() - a * x0);
+        assert_relative_eq!(problem.params, nearest, epsilon = 1e-12);
+    }
+
+    // the basic solution from MINPACK is another one
+    let (problem, report) = lm.minimize(Underdetermined {
+        params: Vector3::zeros(),
+    });
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(a * problem.params, Underdetermined::b(), epsilon = 1e-12);
+    assert!((problem.params - pseudo_inverse * Underdetermined::b()).norm() > 0.1);
+
+    // already a solution, but not the one with minimal norm
+    let x0 = Vector3::new(1., -1., 1.);
+    let (problem, report) = lm
+        .with_min_norm(true)
+        .minimize(Underdetermined { params: x0 });
+    assert!(report.termination.was_successful());
+    assert_eq!(report.number_of_evaluations, 1);
+    assert_eq!(problem.params, x0);
+}
+
+/// Residuals `$(x, x^2 - 1, y, 2(y^2 - 1))$` which do not vanish at the minimum.
+///
+/// The second-order terms of the Hessian are comparable to `$\mathbf{J}^\top\mathbf{J}$`
+/// at the solution, so Gauss-Newton only converges linearly.
+struct LargeResidual {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U4, U2> for LargeResidual {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U4>;
+    type JacobianStorage = Owned<f64, U4, U2>;
+
+    fn set_params(&mut self, x: &Vector2<f64>) {
+        self.params.copy_from(x);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector4<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Vector4::new(x, x * x - 1., y, 2. * (y * y - 1.)))
+    }
+
+    fn jacobian(&self) -> Option<Matrix4x2<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Matrix4x2::new(
+            1.,
+            0., //
+            2. * x,
+            0., //
+            0.,
+            1., //
+            0.,
+            4. * y,
+        ))
+    }
+
+    fn hessian_vector_product(&self, v: &Vector2<f64>) -> Option<Vector2<f64>> {
+        let jacobian = self.jacobian()?;
+        let r = self.residuals()?;
+        // second derivatives of r_2 and r_4
+        let second_order = Vector2::new(2. * r[1] * v.x, 8. * r[3] * v.y);
+        Some(jacobian.tr_mul(&(jacobian * v)) + second_order)
+    }
+}
+
+#[test]
+fn test_hessian_correction() {
+    let solution = Vector2::new(0.5f64.sqrt(), (7f64 / 8.).sqrt());
+    let x0 = Vector2::new(2., 1.6);
+    let lm = LevenbergMarquardt::new().with_tol(1e-15);
+    let (gauss_newton, report) = lm.minimize(LargeResidual { params: x0 });
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(gauss_newton.params, solution, epsilon = 1e-7);
+
+    let (newton, newton_report) = lm
+        .with_hessian_correction(true)
+        .minimize(LargeResidual { params: x0 });
+    assert!(newton_report.termination.was_successful());
+    assert_relative_eq!(newton.params, solution, epsilon = 1e-7);
+    // Gauss-Newton only gains a constant factor per iteration
+    assert!(
+        2 * newton_report.terminated_at_iteration <= report.terminated_at_iteration,
+        "{} vs. {} iterations",
+        newton_report.terminated_at_iteration,
+        report.terminated_at_iteration
+    );
+
+    // without a Hessian the option has no effect
+    let (_, line_report) = lm.with_hessian_correction(true).minimize(Line {
+        params: Vector2::zeros(),
+    });
+    let (_, expected) = lm.minimize(Line {
+        params: Vector2::zeros(),
+    });
+    assert_eq!(line_report.objective_function, expected.objective_function);
+    assert_eq!(
+        line_report.number_of_evaluations,
+        expected.number_of_evaluations
+    );
+}
+
+#[test]
+fn test_disable_individual_tests() {
+    let problem = Bard {
+        params: Vector3::new(1., 1., 1.),
+    };
+    let lm = LevenbergMarquardt::new().with_ftol(0.).with_gtol(1e-10);
+    let (_, report) = lm.with_xtol(1e-3).minimize(problem.clone());
+    assert_eq!(
+        report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+
+    let (_, disabled) = lm.with_xtol(0.).minimize(problem);
+    assert_eq!(disabled.termination, TerminationReason::Orthogonal);
+    assert!(disabled.gradient_norm.unwrap() <= 1e-10);
+    assert!(disabled.terminated_at_iteration > report.terminated_at_iteration);
+}
+
+/// The [`Line`] fit counting the Jacobian evaluations.
+struct CountingLine {
+    line: Line,
+    jacobians: Cell<usize>,
+}
+
+impl LeastSquaresProblem<f64, U6, U2> for CountingLine {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U6>;
+    type JacobianStorage = Owned<f64, U6, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.line.set_params(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.line.params()
+    }
+
+    fn residuals(&self) -> Option<Vector6<f64>> {
+        self.line.residuals()
+    }
+
+    fn jacobian(&self) -> Option<Matrix6x2<f64>> {
+        self.jacobians.set(self.jacobians.get() + 1);
+        self.line.jacobian()
+    }
+}
+
+#[test]
+fn test_constant_jacobian() {
+    let problem = || CountingLine {
+        line: Line {
+            params: Vector2::new(5., -3.),
+        },
+        jacobians: Cell::new(0),
+    };
+    let (expected, expected_report) = LevenbergMarquardt::new().minimize(problem());
+    assert!(expected_report.termination.was_successful());
+    assert!(expected.jacobians.get() > 1);
+
+    let (constant, report) = LevenbergMarquardt::new()
+        .with_constant_jacobian(true)
+        .minimize(problem());
+    assert!(report.termination.was_successful());
+    assert_eq!(constant.jacobians.get(), 1);
+    assert_relative_eq!(constant.line.params, expected.line.params, epsilon = 1e-12);
+    assert!(report.terminated_at_iteration <= expected_report.terminated_at_iteration);
+
+    // the first step already lands on the solution
+    let (single, _) = LevenbergMarquardt::new()
+        .with_constant_jacobian(true)
+        .with_max_evaluations(3)
+        .minimize(problem());
+    assert_eq!(single.jacobians.get(), 1);
+    assert_relative_eq!(single.line.params, expected.line.params, epsilon = 1e-12);
+}
+
+/// Fit `$y = 1 + 2t$` with residuals which are three times steeper close to zero.
+///
+/// The Jacobian is the same at all points where every residual is larger
+/// than one in magnitude.
+struct Kinked {
+    params: Vector2<f64>,
+    jacobians: Cell<usize>,
+}
+
+impl Kinked {
+    fn deviations(&self) -> Vector6<f64> {
+        let [a, b] = [self.params.x, self.params.y];
+        Vector6::from_fn(|i, _| a + b * i as f64 - (1. + 2. * i as f64))
+    }
+}
+
+impl LeastSquaresProblem<f64, U6, U2> for Kinked {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U6>;
+    type JacobianStorage = Owned<f64, U6, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector6<f64>> {
+        Some(self.deviations().map(|u| {
+            if u.abs() <= 1. {
+                3. * u
+            } else {
+                u + 2. * u.signum()
+            }
+        }))
+    }
+
+    fn jacobian(&self) -> Option<Matrix6x2<f64>> {
+        self.jacobians.set(self.jacobians.get() + 1);
+        let slopes = self
+            .deviations()
+            .map(|u| if u.abs() <= 1. { 3. } else { 1. });
+        Some(Matrix6x2::from_fn(|i, j| {
+            if j == 0 {
+                slopes[i]
+            } else {
+                slopes[i] * i as f64
+            }
+        }))
+    }
+}
+
+#[test]
+fn test_detect_constant_jacobian() {
+    let config = LevenbergMarquardt::new().with_detect_constant_jacobian(true);
+    let line = || CountingLine {
+        line: Line {
+            params: Vector2::new(5., -3.),
+        },
+        jacobians: Cell::new(0),
+    };
+    // a small trust region takes several steps to the solution
+    let (expected, _) = LevenbergMarquardt::new()
+        .with_stepbound(0.01)
+        .minimize(line());
+    assert!(expected.jacobians.get() > 2);
+    let (detected, report) = config.with_stepbound(0.01).minimize(line());
+    assert!(report.termination.was_successful());
+    // one Jacobian at the initial guess and one to compare with
+    assert_eq!(detected.jacobians.get(), 2);
+    assert!(report.terminated_at_iteration > 2);
+    assert_relative_eq!(detected.line.params, expected.line.params, epsilon = 1e-12);
+
+    // a nonlinear problem takes the same path as without the detection
+    let (expected, expected_report) = LevenbergMarquardt::new().minimize(HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    });
+    let (nonlinear, report) = config.minimize(HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    });
+    assert_eq!(report.termination, expected_report.termination);
+    assert_eq!(
+        report.number_of_evaluations,
+        expected_report.number_of_evaluations
+    );
+    assert_eq!(nonlinear.params, expected.params);
+
+    // the first Jacobians agree, the reused one leads to a rejected step
+    // once the residuals get small
+    let kinked = || Kinked {
+        params: Vector2::new(11., 2.),
+        jacobians: Cell::new(0),
+    };
+    let (expected, _) = LevenbergMarquardt::new()
+        .with_stepbound(0.01)
+        .minimize(kinked());
+    let (fallback, report) = config.with_stepbound(0.01).minimize(kinked());
+    assert!(report.termination.was_successful());
+    assert!(report.trust_region_reductions > 0);
+    assert!(fallback.jacobians.get() > 2);
+    assert!(fallback.jacobians.get() < report.terminated_at_iteration);
+    assert_relative_eq!(fallback.params, expected.params, epsilon = 1e-12);
+    assert_relative_eq!(fallback.params, Vector2::new(1., 2.), epsilon = 1e-12);
+}
+
+#[test]
+fn test_initial_jacobian() {
+    let x0 = Vector2::new(5., -3.);
+    let problem = || CountingLine {
+        line: Line { params: x0 },
+        jacobians: Cell::new(0),
+    };
+    let jacobian = Line { params: x0 }.jacobian().unwrap();
+    let (expected, expected_report) = LevenbergMarquardt::new().minimize(problem());
+    assert!(expected_report.termination.was_successful());
+
+    let config = LevenbergMarquardt::new();
+    let mut lm = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_initial_jacobian(jacobian);
+    assert_eq!(lm.step(), Ok(()));
+    assert_ne!(*lm.params(), x0);
+    let (supplied, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert_eq!(supplied.jacobians.get(), report.terminated_at_iteration - 1);
+    assert_relative_eq!(supplied.line.params, expected.line.params, epsilon = 1e-12);
+
+    // a Jacobian pointing uphill is replaced after the rejected first step
+    let mut lm = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_initial_jacobian(-jacobian);
+    assert_eq!(lm.step(), Ok(()));
+    assert_eq!(*lm.params(), x0);
+    assert_eq!(lm.target.jacobians.get(), 0);
+    let (fallback, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert_eq!(fallback.jacobians.get(), report.terminated_at_iteration - 1);
+    assert_relative_eq!(fallback.line.params, expected.line.params, epsilon = 1e-12);
+
+    let (minimized, report) = config.minimize_with_jacobian(problem(), jacobian);
+    assert!(report.termination.was_successful());
+    assert_eq!(
+        minimized.jacobians.get(),
+        report.terminated_at_iteration - 1
+    );
+}
+
+/// Linear residuals with one component of enormous magnitude far from the solution.
+#[derive(Clone)]
+struct HugeResidual {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U3, U2> for HugeResidual {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Vector3::new(1e200 * (x - 1.), y - 1., y - 3.))
+    }
+
+    fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+        Some(Matrix3x2::new(1e200, 0., 0., 1., 0., 1.))
+    }
+}
+
+#[test]
+fn test_huge_residuals() {
+    let problem = HugeResidual {
+        params: Vector2::new(0.5, 0.),
+    };
+    for lm in [
+        LevenbergMarquardt::new(),
+        LevenbergMarquardt::new().with_scale_diag(false),
+        LevenbergMarquardt::new().with_gradient_fallback(true),
+    ] {
+        let (problem, report) = lm.minimize(problem.clone());
+        assert!(report.termination.was_successful());
+        assert!(report.objective_function.is_finite());
+        assert_relative_eq!(report.objective_function, 1., epsilon = 1e-12);
+        assert_relative_eq!(problem.params, Vector2::new(1., 2.), epsilon = 1e-12);
+    }
+}
+
+#[derive(Default)]
+struct ProgressObserver {
+    progress: alloc::vec::Vec<f64>,
+}
+
+impl crate::Observer<f64, U3> for ProgressObserver {
+    fn on_iteration(&mut self, state: &crate::LMState<'_, f64, U3>) {
+        self.progress.push(state.estimated_progress.unwrap());
+    }
+}
+
+#[test]
+fn test_estimated_progress() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    for config in [
+        LevenbergMarquardt::new().with_gtol(1e-14),
+        LevenbergMarquardt::new()
+            .with_gtol(0.)
+            .with_max_evaluations(30),
+        LevenbergMarquardt::new()
+            .with_gtol(0.)
+            .with_max_iterations(30),
+    ] {
+        let mut observer = ProgressObserver::default();
+        let lm = config
+            .solver(problem())
+            .ok()
+            .unwrap()
+            .with_observer(&mut observer);
+        let (_, report) = lm.finish();
+        assert!(report.termination.was_successful());
+        let progress = &observer.progress;
+        assert!(progress.iter().all(|p| (0. ..=1.).contains(p)));
+        let increases = progress.windows(2).filter(|p| p[1] > p[0]).count();
+        assert!(2 * increases >= progress.len());
+        assert!(progress.last().unwrap() > &progress[0]);
+    }
+}
+
+/// A model which does not depend on its parameters.
+#[derive(Clone)]
+struct Flat {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U3, U2> for Flat {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        Some(Vector3::new(1., -2., 0.5))
+    }
+
+    fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+        Some(Matrix3x2::zeros())
+    }
+}
+
+#[test]
+fn test_zero_jacobian() {
+    if cfg!(feature = "minpack-compat") {
+        // MINPACK reports the zero gradient as orthogonality
+        return;
+    }
+    let problem = Flat {
+        params: Vector2::new(0.5, -1.),
+    };
+    for lm in [
+        LevenbergMarquardt::new(),
+        LevenbergMarquardt::new().with_gtol(0.),
+        LevenbergMarquardt::new().with_gradient_fallback(true),
+    ] {
+        let (problem, report) = lm.minimize(problem.clone());
+        assert_eq!(report.termination, TerminationReason::NoGradientInformation);
+        assert!(!report.termination.was_successful());
+        assert_eq!(report.number_of_evaluations, 1);
+        assert_eq!(problem.params, Vector2::new(0.5, -1.));
+    }
+}
+
+/// Fit `$y = a e^{-kt}$` to `$m$` samples on `$[0, 4]$`.
+struct Sampled {
+    t: DVector<f64>,
+    y: DVector<f64>,
+    params: Vector2<f64>,
+}
+
+impl Sampled {
+    fn new(m: usize) -> Self {
+        let t = DVector::from_fn(m, |i, _| 4. * i as f64 / (m - 1) as f64);
+        let y = t.map(|t| 3. * f64::exp(-0.8 * t) + 0.05 * f64::sin(11. * t));
+        Self {
+            t,
+            y,
+            params: Vector2::new(1., 0.),
+        }
+    }
+}
+
+impl LeastSquaresProblem<f64, Dynamic, U2> for Sampled {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, Dynamic>;
+    type JacobianStorage = Owned<f64, Dynamic, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<DVector<f64>> {
+        let [a, k] = [self.params.x, self.params.y];
+        Some(self.t.map(|t| a * f64::exp(-k * t)) - &self.y)
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U2>> {
+        let [a, k] = [self.params.x, self.params.y];
+        Some(OMatrix::<f64, Dynamic, U2>::from_fn(
+            self.t.len(),
+            |i, j| {
+                let t = self.t[i];
+                if j == 0 {
+                    f64::exp(-k * t)
+                } else {
+                    -a * t * f64::exp(-k * t)
+                }
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_tolerances_independent_of_sample_size() {
+    // all tolerances are relative, so they do not depend on m
+    let lm = LevenbergMarquardt::new().with_ftol(1e-6);
+    let (small, small_report) = lm.minimize(Sampled::new(100));
+    let (large, large_report) = lm.minimize(Sampled::new(1000));
+    assert!(small_report.termination.was_successful());
+    assert_eq!(small_report.termination, large_report.termination);
+    assert_eq!(
+        small_report.terminated_at_iteration,
+        large_report.terminated_at_iteration
+    );
+    assert_relative_eq!(small.params, large.params, epsilon = 1e-2);
+}
+
+/// A line with a plateau, the Gauss-Newton step from one lands on it.
+struct Plateau {
+    params: Vector1<f64>,
+    jacobians: Cell<usize>,
+}
+
+impl LeastSquaresProblem<f64, U1, U1> for Plateau {
+    type ParameterStorage = Owned<f64, U1>;
+    type ResidualStorage = Owned<f64, U1>;
+    type JacobianStorage = Owned<f64, U1, U1>;
+
+    fn set_params(&mut self, params: &Vector1<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector1<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector1<f64>> {
+        let x = self.params.x;
+        Some(Vector1::new(if x < 0.5 { 0.99999 } else { x }))
+    }
+
+    fn jacobian(&self) -> Option<Vector1<f64>> {
+        self.jacobians.set(self.jacobians.get() + 1);
+        Some(Vector1::new(1.))
+    }
+}
+
+#[test]
+fn test_acceptance_ratio() {
+    let problem = || Plateau {
+        params: Vector1::new(1.),
+        jacobians: Cell::new(0),
+    };
+    // the step to the plateau has a gain ratio of 2e-5
+    let config = LevenbergMarquardt::new();
+    let mut default = config.solver(problem()).ok().unwrap();
+    assert_eq!(default.step(), Ok(()));
+    assert!(default.params().x >= 0.5);
+    assert!(default.checkpoint().number_of_evaluations > 2);
+
+    let config = config.with_acceptance_ratio(1e-5);
+    let mut loosened = config.solver(problem()).ok().unwrap();
+    assert_eq!(loosened.step(), Ok(()));
+    assert_eq!(loosened.params().x, 0.);
+    assert_eq!(loosened.checkpoint().number_of_evaluations, 2);
+}
+
+#[test]
+fn test_initial_objective() {
+    let problem = HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let initial_objective = 0.5 * problem.residuals().unwrap().norm_squared();
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let mut lm = config.solver(problem).ok().unwrap();
+    assert_eq!(lm.step(), Ok(()));
+    let checkpoint = lm.checkpoint();
+    assert_eq!(checkpoint.initial_objective, initial_objective);
+
+    let (problem, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert_eq!(report.initial_objective, initial_objective);
+    assert_eq!(
+        report.improvement_factor(),
+        initial_objective / report.objective_function
+    );
+    assert!(report.improvement_factor() > 1e10);
+
+    let (_, resumed) = config.minimize_from_checkpoint(problem, &checkpoint);
+    assert_eq!(resumed.initial_objective, initial_objective);
+}
+
+/// Count the iterations which moved to a new point.
+struct AcceptedObserver {
+    objective_function: f64,
+    accepted: usize,
+}
+
+impl crate::Observer<f64, U1> for AcceptedObserver {
+    fn on_iteration(&mut self, state: &crate::LMState<'_, f64, U1>) {
+        if state.objective_function < self.objective_function {
+            self.accepted += 1;
+        }
+        self.objective_function = state.objective_function;
+    }
+}
+
+#[test]
+fn test_no_jacobian_for_rejected_steps() {
+    let problem = Plateau {
+        params: Vector1::new(1.),
+        jacobians: Cell::new(0),
+    };
+    let mut observer = AcceptedObserver {
+        objective_function: 0.5,
+        accepted: 0,
+    };
+    let config = LevenbergMarquardt::new();
+    let lm = config
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_observer(&mut observer);
+    let (problem, report) = lm.finish();
+    // most trial steps land on the plateau and are rejected
+    let trial_steps = report.number_of_evaluations - 1;
+    assert!(trial_steps >= observer.accepted + 10);
+    // only the initial guess and the accepted points need a Jacobian
+    let jacobians = problem.jacobians.get();
+    assert_eq!(jacobians, report.terminated_at_iteration);
+    assert!(jacobians <= observer.accepted + 1);
+}
+
+/// The [`Line`] fit in single precision.
+struct LineF32 {
+    params: Vector2<f32>,
+}
+
+impl LeastSquaresProblem<f32, U6, U2> for LineF32 {
+    type ParameterStorage = Owned<f32, U2>;
+    type ResidualStorage = Owned<f32, U6>;
+    type JacobianStorage = Owned<f32, U6, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f32>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f32> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector6<f32>> {
+        let line = Line {
+            params: self.params.cast(),
+        };
+        Some(line.residuals()?.cast())
+    }
+
+    fn jacobian(&self) -> Option<Matrix6x2<f32>> {
+        let line = Line {
+            params: self.params.cast(),
+        };
+        Some(line.jacobian()?.cast())
+    }
+}
+
+#[test]
+fn test_machine_precision_tolerances() {
+    let double = super::LevenbergMarquardt::<f64>::new().with_machine_precision_tolerances();
+    let single = super::LevenbergMarquardt::<f32>::new().with_machine_precision_tolerances();
+    assert_eq!(double.ftol, 30. * f64::EPSILON);
+    assert_eq!(single.ftol, 30. * f32::EPSILON);
+    for (ftol, xtol, gtol) in [
+        (double.ftol, double.xtol, double.gtol),
+        (single.ftol as f64, single.xtol as f64, single.gtol as f64),
+    ] {
+        assert_eq!(ftol, xtol);
+        assert_eq!(ftol, gtol);
+    }
+
+    let (line, report) = single.minimize(LineF32 {
+        params: Vector2::new(5., -3.),
+    });
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration < 10);
+    let (expected, _) = double.minimize(Line {
+        params: Vector2::new(5., -3.),
+    });
+    assert_relative_eq!(line.params, expected.params.cast(), epsilon = 1e-5);
+}
+
+/// `$r(x) = x^2$`, but only every `period`-th trial point is not rejected.
+struct Stalling {
+    params: Vector1<f64>,
+    evaluations: Cell<usize>,
+    period: usize,
+}
+
+impl LeastSquaresProblem<f64, U1, U1> for Stalling {
+    type ParameterStorage = Owned<f64, U1>;
+    type ResidualStorage = Owned<f64, U1>;
+    type JacobianStorage = Owned<f64, U1, U1>;
+
+    fn set_params(&mut self, params: &Vector1<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector1<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector1<f64>> {
+        let trial = self.evaluations.get();
+        self.evaluations.set(trial + 1);
+        if trial > 0 && !trial.is_multiple_of(self.period) {
+            return Some(Vector1::new(1e3));
+        }
+        Some(self.params.map(|x| x * x))
+    }
+
+    fn jacobian(&self) -> Option<Vector1<f64>> {
+        Some(self.params.map(|x| 2. * x))
+    }
+}
+
+#[test]
+fn test_patience_is_cumulative() {
+    let patience = 6;
+    let problem = Stalling {
+        params: Vector1::new(1.),
+        evaluations: Cell::new(0),
+        period: patience,
+    };
+    // the rejected steps shrink the trust region, keep xtol out of the way
+    let config = LevenbergMarquardt::new()
+        .with_patience(patience)
+        .with_xtol(0.);
+    let mut lm = config.solver(problem).ok().unwrap();
+    // `patience - 1` consecutive rejected steps are survived, the count of
+    // rejected steps starts over after the accepted step
+    assert_eq!(lm.step(), Ok(()));
+    assert_eq!(lm.checkpoint().number_of_evaluations, patience + 1);
+    assert_eq!(lm.checkpoint().rejected_steps, 0);
+    assert!(lm.params().x < 1.);
+    // but the budget of `patience * (n + 1)` evaluations is shared by all steps
+    assert_eq!(lm.step(), Err(TerminationReason::LostPatience));
+    let (problem, report) = lm.finish();
+    assert_eq!(report.termination, TerminationReason::LostPatience);
+    assert_eq!(report.number_of_evaluations, 2 * patience);
+    assert_eq!(problem.evaluations.get(), 2 * patience);
+}
+
+#[test]
+fn test_patience_reset() {
+    let patience = 6;
+    let problem = |period| Stalling {
+        params: Vector1::new(1.),
+        evaluations: Cell::new(0),
+        period,
+    };
+    // minpack-compat does not disable zero tolerances, use tiny ones instead
+    let config = LevenbergMarquardt::new()
+        .with_patience(patience)
+        .with_ftol(1e-30)
+        .with_xtol(1e-30)
+        .with_patience_reset(true);
+    let mut lm = config.solver(problem(patience)).ok().unwrap();
+    // `patience - 1` rejected steps, an accepted one and the same again
+    assert_eq!(lm.step(), Ok(()));
+    let x = lm.params().x;
+    assert!(x < 1.);
+    assert_eq!(lm.step(), Ok(()));
+    let checkpoint = lm.checkpoint();
+    assert_eq!(checkpoint.number_of_evaluations, 2 * patience + 1);
+    assert_eq!(checkpoint.patience_start, 2 * patience + 1);
+    assert!(lm.params().x < x);
+
+    // a run of rejected steps longer than the budget still loses patience
+    let (problem, report) = config.minimize(problem(10 * patience));
+    assert_eq!(report.termination, TerminationReason::LostPatience);
+    assert_eq!(report.number_of_evaluations, 2 * patience);
+    assert_eq!(problem.params.x, 1.);
+}
+
+/// Residuals `$s_i(x - t_i)$` with scales over 42 orders of magnitude.
+struct Spread {
+    params: Vector1<f64>,
+}
+
+impl Spread {
+    const SCALES: [f64; 3] = [1e-22, 1., 1e20];
+}
+
+impl LeastSquaresProblem<f64, U3, U1> for Spread {
+    type ParameterStorage = Owned<f64, U1>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U1>;
+
+    fn set_params(&mut self, params: &Vector1<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector1<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        let x = self.params.x;
+        Some(Vector3::from_fn(|i, _| Self::SCALES[i] * (x - i as f64)))
+    }
+
+    fn jacobian(&self) -> Option<Matrix3x1<f64>> {
+        Some(Matrix3x1::from_column_slice(&Self::SCALES))
+    }
+}
+
+#[test]
+fn test_objective_matches_minpack_enorm() {
+    use crate::utils::minpack_enorm;
+    let objective = |problem: &Spread| {
+        let norm = minpack_enorm(problem.residuals().unwrap().as_slice());
+        0.5 * norm * norm
+    };
+    let problem = Spread {
+        params: Vector1::new(-3.),
+    };
+    let initial_objective = objective(&problem);
+    let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(problem.params.x, 2., epsilon = 1e-12);
+    if cfg!(feature = "minpack-compat") {
+        // the thresholds of MINPACK are used, the sums agree bit for bit
+        assert_eq!(report.initial_objective, initial_objective);
+        assert_eq!(report.objective_function, objective(&problem));
+    } else {
+        let tolerance = 8. * f64::EPSILON;
+        assert_relative_eq!(
+            report.initial_objective,
+            initial_objective,
+            max_relative = tolerance,
+            epsilon = 0.
+        );
+        assert_relative_eq!(
+            report.objective_function,
+            objective(&problem),
+            max_relative = tolerance,
+            epsilon = 0.
+        );
+    }
+}
+
+/// Residuals `$(x_1 - 1, x_2^2 - c)$`, insensitive to `$x_2$` close to zero.
+struct Sluggish {
+    params: Vector2<f64>,
+    c: f64,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for Sluggish {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Vector2::new(x - 1., y * y - self.c))
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        Some(Matrix2::new(1., 0., 0., 2. * self.params.y))
+    }
+}
+
+#[test]
+fn test_initial_diag() {
+    let problem = |c| Sluggish {
+        params: Vector2::new(1., 1e-6),
+        c,
+    };
+    let config = LevenbergMarquardt::new();
+    let mut first_round = config.solver(problem(0.04)).ok().unwrap();
+    while first_round.step().is_ok() {}
+    let diag = first_round.checkpoint().diag;
+    assert!(first_round.finish().1.termination.was_successful());
+
+    // the column norm of x_2 vanishes at the start, the trust region allows
+    // a huge first step which is rejected again and again
+    let mut cold = config.solver(problem(0.05)).ok().unwrap();
+    assert_eq!(cold.step(), Ok(()));
+    let mut warm = config
+        .solver(problem(0.05))
+        .ok()
+        .unwrap()
+        .with_initial_diag(diag);
+    assert_eq!(warm.step(), Ok(()));
+    let (cold, warm) = (cold.checkpoint(), warm.checkpoint());
+    assert!(warm.trust_region_reductions < cold.trust_region_reductions);
+    assert!(warm.number_of_evaluations < cold.number_of_evaluations);
+    // the seed is updated with the monotone rule
+    assert!(warm.diag.iter().zip(diag.iter()).all(|(d, seed)| d >= seed));
+}
+
+#[test]
+#[should_panic(expected = "diag entries must be > 0")]
+fn test_initial_diag_rejects_zero() {
+    let problem = Sluggish {
+        params: Vector2::new(1., 1e-8),
+        c: 0.04,
+    };
+    let config = LevenbergMarquardt::new();
+    let _ = config
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_initial_diag(Vector2::new(1., 0.));
+}
+
+#[test]
+fn test_trust_region_weights() {
+    // a decay on top of a large offset
+    let data: alloc::vec::Vec<(f64, f64)> = (0..20)
+        .map(|i| 0.5 * i as f64)
+        .map(|t| (t, 50. + 2. * f64::exp(-0.5 * t)))
+        .collect();
+    let problem = || {
+        CurveFit::new(Vector3::new(0., 1., 1.), &data, |x: &Vector3<f64>, t| {
+            x[0] + x[1] * f64::exp(-x[2] * t)
+        })
+    };
+    let config = LevenbergMarquardt::new().with_stepbound(0.1);
+    // the decay takes over the offset, its rate goes to zero
+    let (uniform, uniform_report) = config.solver(problem()).ok().unwrap().finish();
+    assert!(uniform.params().z < 1e-3);
+    let (weighted, weighted_report) = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_trust_region_weights(Vector3::new(1e3, 1., 1.))
+        .finish();
+    // the offset reaches its value in a few long steps
+    assert!(weighted_report.termination.was_successful());
+    assert!(weighted_report.terminated_at_iteration < 10);
+    assert!(uniform_report.terminated_at_iteration > 100);
+    assert_relative_eq!(
+        weighted.params(),
+        Vector3::new(50., 2., 0.5),
+        epsilon = 1e-8
+    );
+}
+
+#[test]
+fn test_damping_schedule() {
+    // the global minimum of the Kowalik-Osborne function
+    let optimum = 1.5375280e-4;
+    let initial = Vector4::new(0.25, 0.39, 0.415, 0.39);
+    for scale in [10., 100.] {
+        let problem = || KowalikOsborne {
+            params: initial * scale,
+        };
+        let config = LevenbergMarquardt::new().with_tol(TOL);
+        // the first Gauss-Newton steps end up in another basin
+        let (_, report) = config.minimize(problem());
+        assert!(report.objective_function > 2. * optimum || !report.termination.was_successful());
+
+        let (_, report) = config.with_damping_schedule(5, 1.).minimize(problem());
+        assert!(report.termination.was_successful());
+        assert_relative_eq!(report.objective_function, optimum, max_relative = 1e-6);
+    }
+}
+
+/// Residuals `$(u - 2, \tanh v + \frac{1}{2})$` with `$u = x + y$` and `$v = x - y$`.
+///
+/// Where `$|v|$` is large the second residual is flat, the columns of the
+/// Jacobian are nearly parallel there.
+struct Sigmoid {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for Sigmoid {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Vector2::new(x + y - 2., f64::tanh(x - y) + 0.5))
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        let slope = 1. - f64::tanh(x - y).powi(2);
+        Some(Matrix2::new(1., 1., slope, -slope))
+    }
+}
+
+#[test]
+fn test_conditioning_guard() {
+    // the condition number of the Jacobian at the initial guess is about 100
+    let problem = || Sigmoid {
+        params: Vector2::new(2.5, -0.5),
+    };
+    let v = f64::atanh(-0.5);
+    let solution = Vector2::new(1. + 0.5 * v, 1. - 0.5 * v);
+    // the first step jumps far out onto the flat part of tanh
+    let (plain, _) = LevenbergMarquardt::new().minimize(problem());
+    assert!(plain.params.x < -50.);
+    assert_relative_eq!(plain.residuals().unwrap().y, -0.5, epsilon = 1e-12);
+
+    let (guarded, report) = LevenbergMarquardt::new()
+        .with_conditioning_guard(10.)
+        .minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!(report.objective_function < 1e-20);
+    assert_relative_eq!(guarded.params, solution, epsilon = 1e-10);
+}
+
+#[test]
+fn test_max_iterations() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let (expected, full) = config.minimize(problem());
+    assert!(full.termination.was_successful());
+    assert!(full.terminated_at_iteration < 10);
+
+    let config = config.with_max_iterations(1);
+    let (_, report) = config.minimize(problem());
+    assert_eq!(report.termination, TerminationReason::MaxIterations);
+    assert_eq!(report.terminated_at_iteration, 1);
+    assert!(report.objective_function < report.initial_objective);
+
+    // one step per call, warm-started from the checkpoint of the last call
+    let mut problem = problem();
+    let mut checkpoint: Option<LmCheckpoint<f64, U3>> = None;
+    let mut report = None;
+    for _ in 0..10 {
+        let mut lm = match &checkpoint {
+            Some(checkpoint) => config.solver_from_checkpoint(problem, checkpoint),
+            None => config.solver(problem),
+        }
+        .ok()
+        .unwrap();
+        while lm.step().is_ok() {}
+        checkpoint = Some(lm.checkpoint());
+        let finished = lm.finish();
+        problem = finished.0;
+        report = Some(finished.1);
+    }
+    let report = report.unwrap();
+    assert_eq!(report.termination, full.termination);
+    assert_eq!(report.terminated_at_iteration, full.terminated_at_iteration);
+    assert_eq!(report.number_of_evaluations, full.number_of_evaluations);
+    assert_eq!(problem.params, expected.params);
+}
+
+#[test]
+fn test_refine() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let (expected, full) = config.minimize(problem());
+    assert!(full.termination.was_successful());
+
+    let mut problem = problem();
+    let mut refinements = 0;
+    loop {
+        let before = problem.params;
+        let report = config.refine(&mut problem, 2);
+        refinements += 1;
+        assert!(report.improvement() >= 0.);
+        assert_eq!(report.step_norm, (problem.params - before).norm());
+        if report.termination != TerminationReason::MaxIterations {
+            assert!(report.termination.was_successful());
+            break;
+        }
+        assert!(refinements < 50);
+    }
+    assert!(refinements > 1);
+    assert_relative_eq!(problem.params, expected.params, epsilon = 1e-8);
+}
+
+#[test]
+fn test_parameter_target() {
+    let problem = || Line {
+        params: Vector2::new(1., 1.),
+    };
+    // short steps, the slope increases from 1 to about 2 over several iterations
+    let config = LevenbergMarquardt::new().with_stepbound(0.01);
+    let mut lm = config.solver(problem()).ok().unwrap();
+    let mut slopes = alloc::vec![lm.params().y];
+    while lm.step().is_ok() {
+        slopes.push(lm.params().y);
+    }
+    assert!(slopes.windows(2).all(|w| w[0] <= w[1]));
+    let crossing = slopes.iter().position(|slope| *slope >= 1.5).unwrap();
+    assert!(crossing > 1);
+
+    let (line, report) = config
+        .with_parameter_target(1, 1.5, CrossDirection::Upward)
+        .minimize(problem());
+    assert_eq!(
+        report.termination,
+        TerminationReason::ParameterTargetReached
+    );
+    assert!(!report.termination.was_successful() && !report.termination.was_failure());
+    assert_eq!(report.terminated_at_iteration, crossing);
+    assert_eq!(line.params.y, slopes[crossing]);
+
+    // the slope never decreases through the target
+    let (_, expected) = config.minimize(problem());
+    let (_, report) = config
+        .with_parameter_target(1, 1.5, CrossDirection::Downward)
+        .minimize(problem());
+    assert!(report.termination.was_successful());
+    assert_eq!(report, expected);
+}
+
+/// Residuals `$(x_1^2 - 1, x_2)$`, a saddle at `$x_1 = 0$` between two minima.
+struct DoubleWell {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U2, U2> for DoubleWell {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U2>;
+    type JacobianStorage = Owned<f64, U2, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector2<f64>> {
+        let [x, y] = [self.params.x, self.params.y];
+        Some(Vector2::new(x * x - 1., y))
+    }
+
+    fn jacobian(&self) -> Option<Matrix2<f64>> {
+        Some(Matrix2::new(2. * self.params.x, 0., 0., 1.))
+    }
+}
+
+#[test]
+fn test_saddle_escape() {
+    let problem = || DoubleWell {
+        params: Vector2::new(0., 1.),
+    };
+    let (stuck, report) = LevenbergMarquardt::new().minimize(problem());
+    assert_eq!(report.termination, TerminationReason::Orthogonal);
+    assert_eq!(stuck.params.x, 0.);
+    assert_eq!(report.objective_function, 0.5);
+
+    let config = LevenbergMarquardt::new().with_saddle_escape(7, 1e-3);
+    let (escaped, report) = config.minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!((escaped.params.x.abs() - 1.).abs() < 1e-8);
+    assert!(report.objective_function < 1e-16);
+    // the same seed takes the same path
+    let (again, repeated) = config.minimize(problem());
+    assert_eq!(again.params, escaped.params);
+    assert_eq!(repeated.number_of_evaluations, report.number_of_evaluations);
+}
+
+/// Decay `$a e^{-kt_i} - y_i$` which loses its last sample after a few evaluations.
+struct Truncated {
+    params: Vector2<f64>,
+    evaluations: Cell<usize>,
+    residuals_after: usize,
+    jacobian_after: usize,
+}
+
+impl Truncated {
+    const T: [f64; 8] = [0., 0.5, 1., 1.5, 2., 2.5, 3., 3.5];
+
+    fn new(residuals_after: usize, jacobian_after: usize) -> Self {
+        Self {
+            params: Vector2::new(1., 0.),
+            evaluations: Cell::new(0),
+            residuals_after,
+            jacobian_after,
+        }
+    }
+
+    fn samples(&self, truncate_after: usize) -> usize {
+        if self.evaluations.get() > truncate_after {
+            Self::T.len() - 1
+        } else {
+            Self::T.len()
+        }
+    }
+
+    fn full_objective(&self) -> f64 {
+        let [a, k] = [self.params.x, self.params.y];
+        let r = DVector::from_fn(Self::T.len(), |i, _| {
+            let t = Self::T[i];
+            a * f64::exp(-k * t) - 2. * f64::exp(-0.6 * t)
+        });
+        0.5 * r.norm_squared()
+    }
+}
+
+impl LeastSquaresProblem<f64, Dynamic, U2> for Truncated {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, Dynamic>;
+    type JacobianStorage = Owned<f64, Dynamic, U2>;
+
+    fn set_params(&mut self, params: &Vector2<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<DVector<f64>> {
+        self.evaluations.set(self.evaluations.get() + 1);
+        let [a, k] = [self.params.x, self.params.y];
+        Some(DVector::from_fn(
+            self.samples(self.residuals_after),
+            |i, _| {
+                let t = Self::T[i];
+                a * f64::exp(-k * t) - 2. * f64::exp(-0.6 * t)
+            },
+        ))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U2>> {
+        let [a, k] = [self.params.x, self.params.y];
+        Some(OMatrix::<f64, Dynamic, U2>::from_fn(
+            self.samples(self.jacobian_after),
+            |i, j| {
+                let t = Self::T[i];
+                let e = f64::exp(-k * t);
+                if j == 0 {
+                    e
+                } else {
+                    -a * t * e
+                }
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_residual_count_changes() {
+    let (problem, report) =
+        LevenbergMarquardt::new().minimize(Truncated::new(usize::MAX, usize::MAX));
+    assert!(report.termination.was_successful());
+    assert!(report.number_of_evaluations > 4);
+    assert!((problem.params.y - 0.6).abs() < 1e-8);
+
+    let (problem, report) = LevenbergMarquardt::new().minimize(Truncated::new(3, usize::MAX));
+    assert_eq!(
+        report.termination,
+        TerminationReason::WrongDimensions("residuals")
+    );
+    assert_eq!(report.number_of_evaluations, 4);
+    assert_eq!(report.number_of_residuals, 8);
+    // the parameters are the last accepted ones
+    assert_relative_eq!(
+        problem.full_objective(),
+        report.objective_function,
+        epsilon = 0.,
+        max_relative = 1e-12
+    );
+    assert!(report.objective_function < report.initial_objective);
+
+    let (problem, report) = LevenbergMarquardt::new().minimize(Truncated::new(usize::MAX, 3));
+    assert_eq!(
+        report.termination,
+        TerminationReason::WrongDimensions("jacobian")
+    );
+    assert_relative_eq!(
+        problem.full_objective(),
+        report.objective_function,
+        epsilon = 0.,
+        max_relative = 1e-12
+    );
+}
+
+#[test]
+fn test_dimensions_change_after_fit() {
+    let config = LevenbergMarquardt::new()
+        .with_covariance(true)
+        .with_qr_retention(true);
+    let (_, expected) = config.minimize(Truncated::new(usize::MAX, usize::MAX));
+    assert!(expected.termination.was_successful());
+    assert!(expected.covariance.is_some());
+    assert!(expected.qr_r_factor.is_some());
+    let evaluations = expected.number_of_evaluations;
+
+    // the Jacobian at the solution has a row less
+    let (_, report) = config.minimize(Truncated::new(usize::MAX, evaluations - 1));
+    assert_eq!(
+        report.termination,
+        TerminationReason::WrongDimensions("jacobian")
+    );
+    assert_eq!(report.objective_function, expected.objective_function);
+    assert!(report.covariance.is_none());
+    assert!(report.qr_r_factor.is_none());
+
+    // the residuals at the solution have a row less
+    let (_, report) = config.minimize(Truncated::new(evaluations, usize::MAX));
+    assert_eq!(
+        report.termination,
+        TerminationReason::WrongDimensions("residuals")
+    );
+    assert_eq!(report.objective_function, expected.objective_function);
+    assert!(report.covariance.is_none());
+    assert!(report.qr_r_factor.is_none());
+}
+
+#[test]
+fn test_gtol_reference() {
+    // the residual norm drops from about 7 to the noise level 1e-6
+    let data: alloc::vec::Vec<(f64, f64)> = (0..30)
+        .map(|i| 0.2 * i as f64)
+        .map(|t| (t, 2. * f64::exp(-0.6 * t) + 1e-6 * f64::cos(17. * t)))
+        .collect();
+    let problem = || {
+        CurveFit::new(Vector2::new(8., 2.), &data, |x: &Vector2<f64>, t| {
+            x[0] * f64::exp(-x[1] * t)
+        })
+    };
+    let config = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_xtol(0.)
+        .with_gtol(0.1);
+
+    // the cosines stay large until the residuals reach the noise
+    let (_, cosines) = config.minimize(problem());
+    assert_eq!(cosines.termination, TerminationReason::Orthogonal);
+    assert!(cosines.objective_function < 1e-10);
+
+    let (fixed, report) = config
+        .with_gtol_reference(GtolReference::Fixed(1.))
+        .minimize(problem());
+    assert_eq!(report.termination, TerminationReason::Orthogonal);
+    assert!(report.gradient_norm.unwrap() <= 0.1);
+    assert!(report.number_of_evaluations < cosines.number_of_evaluations);
+    assert!(report.objective_function > 1e-4);
+    assert!((fixed.params() - Vector2::new(2., 0.6)).norm() < 0.1);
+}
+
+#[test]
+fn test_config_override() {
+    let data: alloc::vec::Vec<(f64, f64)> = (0..30)
+        .map(|i| 0.2 * i as f64)
+        .map(|t| (t, 2. * f64::exp(-0.6 * t) + 1e-6 * f64::cos(17. * t)))
+        .collect();
+    let problem = || {
+        CurveFit::new(Vector2::new(8., 2.), &data, |x: &Vector2<f64>, t| {
+            x[0] * f64::exp(-x[1] * t)
+        })
+    };
+    let base = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_xtol(0.)
+        .with_gtol(0.)
+        .with_max_iterations(40)
+        .with_stepbound(0.001);
+    // without gtol the fit runs until the steps are lost in rounding
+    let (_, report) = base.minimize(problem());
+    assert!(matches!(
+        report.termination,
+        TerminationReason::NoImprovementPossible(_)
+    ));
+
+    let config = ConfigOverride {
+        gtol: Some(0.1),
+        ..Default::default()
+    };
+    assert_eq!(base.with_override(&config), base.with_gtol(0.1));
+    let (_, overridden) = base.minimize_with_override(problem(), &config);
+    assert_eq!(overridden.termination, TerminationReason::Orthogonal);
+    assert!(overridden.terminated_at_iteration < 40);
+    assert_eq!(overridden, base.with_gtol(0.1).minimize(problem()).1);
+    // an empty override changes nothing
+    assert_eq!(base.with_override(&ConfigOverride::default()), base);
+}
+
+/// [`HelicalValley`] with the sign of the Jacobian flipped.
+struct FlippedJacobian(HelicalValley);
+
+impl LeastSquaresProblem<f64, U3, U3> for FlippedJacobian {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U3>;
+
+    fn set_params(&mut self, params: &Vector3<f64>) {
+        self.0.set_params(params);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.0.params()
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        self.0.residuals()
+    }
+
+    fn jacobian(&self) -> Option<Matrix3<f64>> {
+        self.0.jacobian().map(|jacobian| -jacobian)
+    }
+}
+
+#[test]
+fn test_divergence_detection() {
+    let start = Vector3::new(-1., 0., 0.);
+    let problem = || FlippedJacobian(HelicalValley { params: start });
+
+    // every step goes uphill until the trust region is tiny
+    let (flipped, report) = LevenbergMarquardt::new().minimize(problem());
+    assert_ne!(report.termination, TerminationReason::Diverged);
+    assert_eq!(flipped.params(), start);
+
+    let (flipped, diverged) = LevenbergMarquardt::new()
+        .with_divergence_detection(8, 1e3)
+        .minimize(problem());
+    assert_eq!(diverged.termination, TerminationReason::Diverged);
+    assert!(diverged.termination.was_failure());
+    assert_eq!(flipped.params(), start);
+    // the initial evaluation and eight trial steps
+    assert_eq!(diverged.number_of_evaluations, 9);
+    assert!(diverged.number_of_evaluations < report.number_of_evaluations);
+
+    // the first trial step already increases the objective function
+    let (flipped, grown) = LevenbergMarquardt::new()
+        .with_divergence_detection(100, 1.5)
+        .minimize(problem());
+    assert_eq!(grown.termination, TerminationReason::Diverged);
+    assert_eq!(grown.number_of_evaluations, 2);
+    assert_eq!(flipped.params(), start);
+
+    // a correct Jacobian is not affected
+    let (_, report) = LevenbergMarquardt::new().minimize(HelicalValley { params: start });
+    let (_, detected) = LevenbergMarquardt::new()
+        .with_divergence_detection(8, 1e3)
+        .minimize(HelicalValley { params: start });
+    assert!(detected.termination.was_successful());
+    assert_eq!(detected, report);
+}
+
+#[test]
+fn test_trust_region_collapse() {
+    // MINPACK stops with the xtol test
+    if cfg!(feature = "minpack-compat") {
+        return;
+    }
+    let start = Vector3::new(-1., 0., 0.);
+    // every step goes uphill, the trust region shrinks to nothing
+    let (flipped, report) =
+        LevenbergMarquardt::new().minimize(FlippedJacobian(HelicalValley { params: start }));
+    assert_eq!(report.termination, TerminationReason::TrustRegionCollapsed);
+    assert!(report.termination.was_failure());
+    assert_eq!(flipped.params(), start);
+    assert_eq!(report.objective_function, report.initial_objective);
+    assert!(report.trust_region_reductions > 10);
+    assert!(report.gradient_norm.unwrap() > 0.1);
+
+    // the same tolerance for a correct Jacobian
+    let (_, report) = LevenbergMarquardt::new()
+        .with_xtol(1e-8)
+        .minimize(HelicalValley { params: start });
+    assert!(report.termination.was_successful());
+}
+
+#[test]
+fn test_without_pivoting() {
+    let problem = || HelicalValley {
+        params: Vector3::new(-1., 0., 0.),
+    };
+    let pivoted = LevenbergMarquardt::new().with_tol(TOL);
+    let unpivoted = pivoted.with_pivoting(false);
+    for iterations in 1..4 {
+        let mut lm = pivoted.solver(problem()).ok().unwrap();
+        let mut without = unpivoted.solver(problem()).ok().unwrap();
+        for _ in 0..iterations {
+            assert_eq!(lm.step(), Ok(()));
+            assert_eq!(without.step(), Ok(()));
+        }
+        assert_relative_eq!(*without.params(), *lm.params(), epsilon = 1e-14);
+    }
+
+    let (expected, report) = pivoted.minimize(problem());
+    let (problem, without) = unpivoted.minimize(problem());
+    assert!(without.termination.was_successful());
+    assert_eq!(
+        without.terminated_at_iteration,
+        report.terminated_at_iteration
+    );
+    assert_relative_eq!(problem.params, expected.params, epsilon = 1e-14);
+}
+
+/// Residuals `$x_i - 1$` for all but the last parameter `$y$`, which has the
+/// residual `$y^2 - 10^{-6}$`.
+///
+/// Starting at `$x_i = 1$` only `$y$` moves, roughly halving in every step.
+struct ManySettled {
+    params: DVector<f64>,
+}
+
+impl LeastSquaresProblem<f64, Dynamic, Dynamic> for ManySettled {
+    type ParameterStorage = Owned<f64, Dynamic>;
+    type ResidualStorage = Owned<f64, Dynamic>;
+    type JacobianStorage = Owned<f64, Dynamic, Dynamic>;
+
+    fn set_params(&mut self, params: &DVector<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> DVector<f64> {
+        self.params.clone()
+    }
+
+    fn residuals(&self) -> Option<DVector<f64>> {
+        let n = self.params.nrows();
+        Some(DVector::from_fn(n, |i, _| {
+            let x = self.params[i];
+            if i + 1 < n {
+                x - 1.
+            } else {
+                x * x - 1e-6
+            }
+        }))
+    }
+
+    fn jacobian(&self) -> Option<DMatrix<f64>> {
+        let n = self.params.nrows();
+        let mut jacobian = DMatrix::identity(n, n);
+        jacobian[(n - 1, n - 1)] = 2. * self.params[n - 1];
+        Some(jacobian)
+    }
+}
+
+#[test]
+fn test_step_norm() {
+    let problem = || {
+        let mut params = DVector::from_element(101, 1.);
+        params[100] = 0.1;
+        ManySettled { params }
+    };
+    let config = LevenbergMarquardt::new()
+        .with_ftol(0.)
+        .with_gtol(0.)
+        .with_xtol(1e-3);
+    // the Euclidean norm of the settled parameters hides the one which moves
+    let (euclidean, euclidean_report) = config.minimize(problem());
+    assert_eq!(
+        euclidean_report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+
+    let (infinity, report) = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_step_norm(&InfinityNorm)
+        .finish();
+    assert_eq!(
+        report.termination,
+        TerminationReason::Converged {
+            ftol: false,
+            xtol: true
+        }
+    );
+    assert!(report.terminated_at_iteration > euclidean_report.terminated_at_iteration);
+    let error = |params: &DVector<f64>| (params[100] - 1e-3).abs();
+    assert!(error(&infinity.params) < 0.1 * error(&euclidean.params));
+
+    // the same norm as a closure
+    let max_norm = |v: &[f64]| v.iter().fold(0., |max: f64, v| max.max(v.abs()));
+    let (closure, closure_report) = config
+        .solver(problem())
+        .ok()
+        .unwrap()
+        .with_step_norm(&max_norm)
+        .finish();
+    assert_eq!(
+        closure_report.terminated_at_iteration,
+        report.terminated_at_iteration
+    );
+    assert_eq!(closure.params, infinity.params);
+}
+
+#[test]
+fn test_nonmonotone() {
+    let problem = || Rosenbruck {
+        params: Vector2::new(-1.2, 1.),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let (_, monotone) = config.minimize(problem());
+    assert!(monotone.termination.was_successful());
+    // a window of one is the monotone acceptance
+    let (_, report) = config.with_nonmonotone(1).minimize(problem());
+    assert_eq!(report, monotone);
+
+    // longer steps along the curved valley
+    let (rosenbruck, report) = config.with_nonmonotone(5).minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration < monotone.terminated_at_iteration);
+    assert_relative_eq!(rosenbruck.params, Vector2::new(1., 1.), epsilon = 1e-10);
+}
+
+#[test]
+fn test_line_search() {
+    let problem = || Rosenbruck {
+        params: Vector2::new(-1.2, 1.),
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let (_, plain) = config.minimize(problem());
+    assert!(plain.termination.was_successful());
+    let (_, report) = config
+        .with_line_search(LineSearch::None)
+        .minimize(problem());
+    assert_eq!(report, plain);
+
+    // the rejected steps along the curved valley are shortened instead of
+    // solving the subproblem for the smaller trust region
+    let (rosenbruck, report) = config
+        .with_line_search(LineSearch::Backtracking { c: 1e-4, rho: 0.5 })
+        .minimize(problem());
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration < plain.terminated_at_iteration);
+    assert!(report.number_of_evaluations < plain.number_of_evaluations);
+    assert_relative_eq!(rosenbruck.params, Vector2::new(1., 1.), epsilon = 1e-10);
+}
+
+#[test]
+fn test_normalize_initial_objective() {
+    // a fit with large residuals, the objective function stays far from zero
+    let data: alloc::vec::Vec<(f64, f64)> = (0..30)
+        .map(|i| 0.2 * i as f64)
+        .map(|t| (t, 300. * f64::exp(-0.6 * t) + 5. * f64::cos(17. * t)))
+        .collect();
+    let problem = || {
+        CurveFit::new(Vector2::new(800., 2.), &data, |x: &Vector2<f64>, t| {
+            x[0] * f64::exp(-x[1] * t)
+        })
+    };
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let normalized = config.with_normalize_initial_objective(true);
+    let (_, report) = config.minimize(problem());
+    assert!(report.termination.was_successful());
+    let initial = report.initial_objective;
+    assert!(initial > 1e4);
+
+    // the same iterates up to rounding, the reported objective function is not normalized
+    for iterations in 1..=report.terminated_at_iteration {
+        let (expected, expected_report) =
+            config.with_max_iterations(iterations).minimize(problem());
+        let (fit, fit_report) = normalized
+            .with_max_iterations(iterations)
+            .minimize(problem());
+        assert_relative_eq!(fit.params(), expected.params(), max_relative = 1e-8);
+        assert_relative_eq!(
+            fit_report.objective_function,
+            expected_report.objective_function,
+            max_relative = 1e-6
+        );
+        assert_relative_eq!(fit_report.initial_objective, initial, max_relative = 1e-12);
+    }
+
+    // internally the objective function is divided by its initial value
+    let scaled = crate::weights::Scaled::new(problem(), 1. / f64::sqrt(initial));
+    let (_, scaled_report) = config.minimize(scaled);
+    assert_relative_eq!(scaled_report.initial_objective, 1., epsilon = 1e-12);
+    assert_relative_eq!(
+        scaled_report.objective_function * initial,
+        report.objective_function,
+        max_relative = 1e-10
+    );
+}
+
+#[test]
+fn test_residual_groups() {
+    // the second sensor sees a curvature which the line cannot model
+    let data: alloc::vec::Vec<(f64, f64)> = (0..40)
+        .map(|i| {
+            let t = i as f64 / 10.;
+            let noise = 1e-3 * f64::sin(13. * t);
+            if i < 20 {
+                (t, 1. + 2. * t + noise)
+            } else {
+                (t, 1. + 2. * t + 0.5 * (t - 3.) * (t - 3.) + noise)
+            }
+        })
+        .collect();
+    let problem = CurveFit::new(Vector2::new(0., 0.), &data, |x: &Vector2<f64>, t| {
+        x[0] + x[1] * t
+    });
+    let groups = [0..20, 20..40];
+    let (_, report) = LevenbergMarquardt::new()
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_residual_groups(&groups)
+        .finish();
+    assert!(report.termination.was_successful());
+    let objectives = report.group_objectives().unwrap();
+    assert_eq!(objectives.len(), 2);
+    assert_relative_eq!(
+        objectives[0] + objectives[1],
+        report.objective_function,
+        epsilon = 1e-12
+    );
+    assert!(objectives[1] > 10. * objectives[0]);
+    let sums = report.group_sums_of_squares.as_ref().unwrap();
+    assert_relative_eq!(sums[1], 2. * objectives[1]);
+
+    // without groups nothing is reported
+    let (_, report) = LevenbergMarquardt::new().minimize(CurveFit::new(
+        Vector2::new(0., 0.),
+        &data,
+        |x: &Vector2<f64>, t| x[0] + x[1] * t,
+    ));
+    assert_eq!(report.group_objectives(), None);
+}
+
+/// Four linear residuals and two nonlinear ones, `$x_1 x_2 - 2$` and
+/// `$e^{x_3} - 3$`, so only the last two rows of the Jacobian change.
+struct PartlyLinear {
+    params: Vector3<f64>,
+    report_changes: bool,
+}
+
+impl LeastSquaresProblem<f64, U6, U3> for PartlyLinear {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U6>;
+    type JacobianStorage = Owned<f64, U6, U3>;
+
+    fn set_params(&mut self, params: &Vector3<f64>) {
+        self.params.copy_from(params);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector6<f64>> {
+        let [a, b, c] = [self.params.x, self.params.y, self.params.z];
+        Some(Vector6::new(
+            a - 1.,
+            b - 2.,
+            a + c,
+            b - c - 1.,
+            a * b - 2.,
+            c.exp() - 3.,
+        ))
+    }
+
+    fn jacobian(&self) -> Option<Matrix6x3<f64>> {
+        let [a, b, c] = [self.params.x, self.params.y, self.params.z];
+        #[rustfmt::skip]
+        let jacobian = Matrix6x3::new(
+            1., 0., 0.,
+            0., 1., 0.,
+            1., 0., 1.,
+            0., 1., -1.,
+            b, a, 0.,
+            0., 0., c.exp(),
+        );
+        Some(jacobian)
+    }
+
+    fn changed_jacobian_rows(&self) -> Option<&[usize]> {
+        self.report_changes.then_some(&[4, 5])
+    }
+}
+
+#[test]
+fn test_incremental_factorization() {
+    let problem = |report_changes| PartlyLinear {
+        params: Vector3::new(0.5, 0.5, 0.5),
+        report_changes,
+    };
+    let (full, expected) = LevenbergMarquardt::new().minimize(problem(false));
+    assert!(expected.termination.was_successful());
+    assert_eq!(expected.incremental_factorizations, 0);
+
+    let (incremental, report) = LevenbergMarquardt::new().minimize(problem(true));
+    assert!(report.termination.was_successful());
+    // every Jacobian but the first is an update
+    assert_eq!(
+        report.incremental_factorizations,
+        report.terminated_at_iteration - 1
+    );
+    assert_relative_eq!(incremental.params, full.params, epsilon = 1e-10);
+    assert_relative_eq!(
+        report.objective_function,
+        expected.objective_function,
+        epsilon = 1e-12
+    );
+}
+
+#[test]
+fn test_min_curvature() {
+    let data: alloc::vec::Vec<(f64, f64)> = (0..20)
+        .map(|i| {
+            let t = i as f64 / 4.;
+            (t, 1. + 2. * t + 1e-2 * f64::sin(7. * t))
+        })
+        .collect();
+    let config = LevenbergMarquardt::new().with_qr_retention(true);
+
+    let (_, report) = config.minimize(CurveFit::new(
+        Vector2::new(0., 0.),
+        &data,
+        |x: &Vector2<f64>, t| x[0] + x[1] * t,
+    ));
+    assert!(report.termination.was_successful());
+    let well_posed = report.min_curvature().unwrap();
+    // the exact eigenvalue of J^T J
+    let jacobian = DMatrix::from_fn(data.len(), 2, |i, j| if j == 0 { 1. } else { data[i].0 });
+    let eigenvalues = jacobian.tr_mul(&jacobian).symmetric_eigenvalues();
+    assert_relative_eq!(well_posed, eigenvalues.min(), max_relative = 1e-6);
+    assert!(well_posed > 1.);
+
+    // only the sum of the first two parameters is determined
+    let (_, report) = config.minimize(CurveFit::new(
+        Vector3::new(0., 0., 0.),
+        &data,
+        |x: &Vector3<f64>, t| x[0] + x[1] + x[2] * t,
+    ));
+    assert!(!report.termination.was_failure());
+    let flat = report.min_curvature().unwrap();
+    assert!(flat < 1e-8 * well_posed);
+
+    // nothing is reported without the QR decomposition
+    let (_, report) = LevenbergMarquardt::new().minimize(CurveFit::new(
+        Vector2::new(0., 0.),
+        &data,
+        |x: &Vector2<f64>, t| x[0] + x[1] * t,
+    ));
+    assert_eq!(report.min_curvature(), None);
+}
+
+/// Counts the calls and delegates to the built-in solver.
+struct DelegatingSolver {
+    calls: usize,
+}
+
+impl crate::TrustRegionSubproblem<f64, U3> for DelegatingSolver {
+    fn solve(
+        &mut self,
+        model: &mut crate::TrustRegionModel<'_, f64, U3>,
+        diag: &Vector3<f64>,
+        radius: f64,
+    ) -> crate::TrustRegionStep<f64, U3> {
+        self.calls += 1;
+        crate::Levenberg.solve(model, diag, radius)
+    }
+}
+
+#[test]
+fn test_trust_region_solver() {
+    let mut problem = HelicalValley {
+        params: OVector::<f64, U3>::zeros(),
+    };
+    problem.set_params(&Vector3::new(-1., 0., 0.));
+    let config = LevenbergMarquardt::new().with_tol(TOL);
+    let (expected, expected_report) = config.minimize(problem.clone());
+
+    let mut solver = DelegatingSolver { calls: 0 };
+    let lm = config
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_trust_region_solver(&mut solver);
+    let (delegated, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert_eq!(delegated.params, expected.params);
+    assert_eq!(
+        report.number_of_evaluations,
+        expected_report.number_of_evaluations
+    );
+    assert_eq!(
+        report.objective_function,
+        expected_report.objective_function
+    );
+    assert_eq!(solver.calls, report.number_of_evaluations - 1);
+
+    // the dogleg steps reach the minimum as well
+    let problem = Rosenbruck {
+        params: Vector2::new(-1.2, 1.),
+    };
+    let mut dogleg = crate::Dogleg;
+    let lm = config
+        .solver(problem)
+        .ok()
+        .unwrap()
+        .with_trust_region_solver(&mut dogleg);
+    let (fit, report) = lm.finish();
+    assert!(report.termination.was_successful());
+    assert_relative_eq!(fit.params, Vector2::new(1., 1.), epsilon = 1e-10);
+}
+
+/// The residuals `$(x_1 - 1, x_2 - 2, e^{-x_3})^\top$`, the minimum of the
+/// last one is at infinity.
+struct Drifting {
+    params: Vector3<f64>,
+}
+
+impl LeastSquaresProblem<f64, U3, U3> for Drifting {
+    type ParameterStorage = Owned<f64, U3>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U3>;
+
+    fn set_params(&mut self, x: &Vector3<f64>) {
+        self.params.copy_from(x);
+    }
+
+    fn params(&self) -> Vector3<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        let p = self.params;
+        Some(Vector3::new(p[0] - 1., p[1] - 2., f64::exp(-p[2])))
+    }
+
+    fn jacobian(&self) -> Option<Matrix3<f64>> {
+        let p = self.params;
+        Some(Matrix3::from_diagonal(&Vector3::new(
+            1.,
+            1.,
+            -f64::exp(-p[2]),
+        )))
+    }
+}
+
+#[test]
+fn test_parameter_convergence() {
+    let problem = Drifting {
+        params: Vector3::new(0., 0., 0.),
+    };
+    let config = LevenbergMarquardt::new().with_qr_retention(true);
+    let (fit, report) = config.minimize(problem);
+    assert!(!report.termination.was_failure());
+    assert_relative_eq!(fit.params[0], 1.);
+    assert_relative_eq!(fit.params[1], 2.);
+    let score = report.parameter_convergence().unwrap();
+    assert_eq!(score.len(), 3);
+    // the first two parameters settled in the first step
+    assert!(score[0] < 1e-10);
+    assert!(score[1] < 1e-10);
+    // the last one still moves noticeably
+    assert!(score[2] > 1e-3, "{}", score[2]);
+    let relative_step = report.relative_step.unwrap();
+    assert!(relative_step[2] > 1e-3);
+
+    // nothing is reported without the QR decomposition
+    let (_, report) = LevenbergMarquardt::new().minimize(Drifting {
+        params: Vector3::new(0., 0., 0.),
+    });
+    assert_eq!(report.relative_step, None);
+    assert_eq!(report.parameter_convergence(), None);
+}
+
 include!("test_examples_gen.rs");
@@ -23,10 +23,7 @@ fn nan_or_inf_none_residual() {
     let problem =
         MockProblem::<U2, U3>::new(Vector2::zeros(), vec![Some(Vector3::new(1., 1., INFINITY))]);
     let (mut problem, err) = LM::new(&LevenbergMarquardt::new(), problem).err().unwrap();
-    assert_eq!(
-        err.termination,
-        TerminationReason::Numerical("residuals norm")
-    );
+    assert_eq!(err.termination, TerminationReason::InvalidInitialPoint);
     assert_eq!(err.number_of_evaluations, 1);
     assert_eq!(problem.calls(), [MockCall::Residuals].as_ref());
     assert!(err.objective_function.is_infinite());
@@ -35,10 +32,7 @@ fn nan_or_inf_none_residual() {
     let problem =
         MockProblem::<U2, U3>::new(Vector2::zeros(), vec![Some(Vector3::new(1., 1., NAN))]);
     let (mut problem, err) = LM::new(&LevenbergMarquardt::new(), problem).err().unwrap();
-    assert_eq!(
-        err.termination,
-        TerminationReason::Numerical("residuals norm")
-    );
+    assert_eq!(err.termination, TerminationReason::InvalidInitialPoint);
     assert_eq!(err.number_of_evaluations, 1);
     assert_eq!(problem.calls(), [MockCall::Residuals].as_ref());
     assert!(err.objective_function.is_nan());
@@ -6,7 +6,7 @@ use nalgebra::*;
 
 use super::test_helpers::{MockCall, MockProblem};
 
-use super::{LevenbergMarquardt, TerminationReason, LM};
+use super::{DiagUpdate, LevenbergMarquardt, TerminationReason, LM};
 use crate::qr::PivotedQR;
 
 #[test]
@@ -28,6 +28,31 @@ fn gnorm_and_gtol() {
     assert_eq!(lm.target.calls(), &[MockCall::Residuals]);
 }
 
+#[test]
+fn gradient_norm_is_gtol_ratio() {
+    let problem =
+        MockProblem::<U2, U3>::new(Vector2::zeros(), vec![Some(Vector3::new(1., 2., 0.5))]);
+    let jacobian = Matrix3x2::new(1., 2., 4., -2., 0.5, 0.1);
+    // the first column is closest to the residuals
+    let residuals = Vector3::new(1., 2., 0.5);
+    let column = jacobian.column(0);
+    let expected = column.dot(&residuals).abs() / (column.norm() * residuals.norm());
+    assert!(0.96 < expected && expected < 0.98);
+
+    for (gtol, orthogonal) in [(0.98, true), (0.96, false)] {
+        let config = LevenbergMarquardt::new().with_gtol(gtol);
+        let (mut lm, residuals) = LM::new(&config, problem.clone()).ok().unwrap();
+        assert_eq!(lm.report.gradient_norm, None);
+        let mut lls =
+            PivotedQR::new(jacobian.clone()).into_least_squares_diagonal_problem(residuals);
+        let result = lm.update_diag(&mut lls);
+        assert_eq!(result == Err(TerminationReason::Orthogonal), orthogonal);
+        let gradient_norm = lm.report.gradient_norm.unwrap();
+        assert_relative_eq!(gradient_norm, expected);
+        assert_eq!(gradient_norm <= gtol, orthogonal);
+    }
+}
+
 #[test]
 fn diag_init_and_second_call() {
     let problem = MockProblem::<U2, U3>::new(
@@ -66,6 +91,37 @@ fn diag_init_and_second_call() {
     assert_eq!(lm.delta, delta);
 }
 
+#[test]
+fn diag_update_recompute_can_decrease() {
+    let problem = MockProblem::<U2, U3>::new(
+        Vector2::new(1.5, 10.),
+        vec![Some(Vector3::new(1., 2., 0.5))],
+    );
+    let jacobian = Matrix3x2::new(1., 2., 4., -2., 0.5, 0.1);
+    // column norms of J shrink in the second iteration
+    let smaller_jacobian = jacobian * 0.5;
+    let initial_diag = Vector2::new(4.153311931459037, 2.8301943396169813);
+
+    let second_diag = |diag_update| {
+        let config = LevenbergMarquardt::new().with_diag_update(diag_update);
+        let (mut lm, residuals) = LM::new(&config, problem.clone()).ok().unwrap();
+        let mut lls =
+            PivotedQR::new(jacobian.clone()).into_least_squares_diagonal_problem(residuals);
+        assert!(lm.update_diag(&mut lls).is_ok());
+        assert_relative_eq!(lm.diag, initial_diag);
+        let mut lls =
+            PivotedQR::new(smaller_jacobian.clone()).into_least_squares_diagonal_problem(residuals);
+        assert!(lm.update_diag(&mut lls).is_ok());
+        lm.diag
+    };
+
+    // MINPACK keeps the larger entries
+    assert_relative_eq!(second_diag(DiagUpdate::MinpackMonotone), initial_diag);
+    // recomputing follows the column norms down
+    assert_relative_eq!(second_diag(DiagUpdate::Recompute), initial_diag * 0.5);
+    assert_relative_eq!(second_diag(DiagUpdate::Fixed), initial_diag);
+}
+
 #[test]
 fn nan_inf_xnorm() {
     fn setup(x: Vector2<f64>, jacobian: Matrix3x2<f64>) -> TerminationReason {
@@ -152,3 +208,20 @@ fn no_scale_diag() {
     assert_eq!(lm.xnorm, 123.);
     assert_eq!(lm.delta, delta);
 }
+
+#[test]
+fn initial_delta_overrides_stepbound() {
+    let problem = MockProblem::<U2, U3>::new(
+        Vector2::new(1.5, 10.),
+        vec![Some(Vector3::new(1., 2., 0.5))],
+    );
+    let config = LevenbergMarquardt::new()
+        .with_stepbound(42.)
+        .with_initial_delta(0.25);
+    let jacobian = Matrix3x2::new(1., 2., 4., -2., 0.5, 0.1);
+    let (mut lm, residuals) = LM::new(&config, problem).ok().unwrap();
+    let mut lls = PivotedQR::new(jacobian).into_least_squares_diagonal_problem(residuals);
+    assert!(lm.update_diag(&mut lls).is_ok());
+    assert_relative_eq!(lm.xnorm, 28.979518629542486);
+    assert_eq!(lm.delta, 0.25);
+}
@@ -0,0 +1,284 @@
+//! Least squares problems with parameters on a manifold.
+use nalgebra::{
+    allocator::Allocator, storage::Owned, Const, DefaultAllocator, Dim, OMatrix, OVector, RealField,
+};
+
+use crate::LeastSquaresProblem;
+
+/// A least squares problem whose parameters live on a manifold.
+///
+/// This is minimized with
+/// [`LevenbergMarquardt::minimize_manifold`](struct.LevenbergMarquardt.html#method.minimize_manifold).
+/// The parameters `$\vec{x}$` have `$n$` coordinates but the manifold only has
+/// dimension `$k$`, for example a rotation in 3D stored as a unit quaternion
+/// (`$n = 4$`, `$k = 3$`). The additive update `$\vec{x} + \vec{p}$` does not
+/// stay on the manifold. Instead, the steps `$\vec{\delta}$` are computed in
+/// the tangent space at `$\vec{x}$` and applied with a
+/// [`retract`](#tymethod.retract)ion, like the exponential map.
+///
+/// The Jacobian is the derivative with respect to the tangent coordinates,
+/// ```math
+///   \mathbf{J} = \frac{\partial}{\partial\vec{\delta}}\,
+///     \vec{r}\bigl(\operatorname{retract}(\vec{x}, \vec{\delta})\bigr)\Big|_{\vec{\delta} = 0}
+///   \in \R^{m\times k}.
+/// ```
+/// The tangent space is centered at the current parameters after every
+/// iteration. Because the tangent coordinates of the current parameters are
+/// always zero, the `xtol` test never triggers, rely on `ftol` and `gtol`.
+pub trait ManifoldLeastSquaresProblem<F, M, N, K>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    K: Dim,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, K> + Allocator<F, M> + Allocator<F, M, K>,
+{
+    /// Set the stored parameters `$\vec{x}$`.
+    fn set_params(&mut self, x: &OVector<F, N>);
+
+    /// Get the current parameter vector `$\vec{x}$`.
+    fn params(&self) -> OVector<F, N>;
+
+    /// Move from `x` along the tangent vector `step`.
+    ///
+    /// This must map a zero step to `x` and its derivative with respect to
+    /// `step` at zero must be invertible on the tangent space.
+    fn retract(&self, x: &OVector<F, N>, step: &OVector<F, K>) -> OVector<F, N>;
+
+    /// Compute the residual vector.
+    fn residuals(&self) -> Option<OVector<F, M>>;
+
+    /// Compute the Jacobian of the residual vector with respect to the tangent
+    /// coordinates at the current parameters.
+    fn jacobian(&self) -> Option<OMatrix<F, M, K>>;
+}
+
+/// The problem in tangent coordinates around a base point.
+pub(crate) struct TangentSpace<P, F, N, K>
+where
+    F: RealField,
+    N: Dim,
+    K: Dim,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, K>,
+{
+    problem: P,
+    base: OVector<F, N>,
+    step: OVector<F, K>,
+}
+
+impl<P, F, N, K> TangentSpace<P, F, N, K>
+where
+    F: RealField + Copy,
+    N: Dim,
+    K: Dim,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, K>,
+{
+    pub(crate) fn new(problem: P, base: OVector<F, N>, k: K) -> Self {
+        Self {
+            problem,
+            base,
+            step: OVector::zeros_generic(k, Const::<1>),
+        }
+    }
+
+    /// Make the point with tangent coordinates `step` the new base point.
+    pub(crate) fn recenter<M>(&mut self, step: &OVector<F, K>)
+    where
+        M: Dim,
+        P: ManifoldLeastSquaresProblem<F, M, N, K>,
+        DefaultAllocator: Allocator<F, M> + Allocator<F, M, K>,
+    {
+        self.base = self.problem.retract(&self.base, step);
+        self.step.fill(F::zero());
+        self.problem.set_params(&self.base);
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, N, K, P> LeastSquaresProblem<F, M, K> for TangentSpace<P, F, N, K>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    K: Dim,
+    P: ManifoldLeastSquaresProblem<F, M, N, K>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, K> + Allocator<F, M> + Allocator<F, M, K>,
+{
+    type ResidualStorage = Owned<F, M>;
+    type JacobianStorage = Owned<F, M, K>;
+    type ParameterStorage = Owned<F, K>;
+
+    fn set_params(&mut self, step: &OVector<F, K>) {
+        self.step.copy_from(step);
+        let x = self.problem.retract(&self.base, step);
+        self.problem.set_params(&x);
+    }
+
+    fn params(&self) -> OVector<F, K> {
+        self.step.clone()
+    }
+
+    fn residuals(&self) -> Option<OVector<F, M>> {
+        self.problem.residuals()
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, M, K>> {
+        self.problem.jacobian()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{
+        storage::Owned, Const, Dynamic, Matrix2, OMatrix, OVector, Vector1, Vector2, U1, U2,
+    };
+
+    use super::ManifoldLeastSquaresProblem;
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Fit a rotation `$\mathbf{R}$` with `$\mathbf{R}\vec{p}_i \approx \vec{q}_i$`.
+    ///
+    /// The rotation is stored as a point `$(\cos\theta, \sin\theta)$` on the
+    /// unit circle. The measured points are shrunk, so the best matrix of the
+    /// form `$\bigl(\begin{smallmatrix}c & -s\\ s & c\end{smallmatrix}\bigr)$`
+    /// is not a rotation.
+    struct Rotation {
+        p: Vec<Vector2<f64>>,
+        q: Vec<Vector2<f64>>,
+        params: Vector2<f64>,
+    }
+
+    const ANGLE: f64 = 2.5;
+
+    impl Rotation {
+        fn new() -> Self {
+            let p: Vec<_> = [(1., 0.), (0., 2.), (-1., 1.), (3., -1.)]
+                .iter()
+                .map(|&(x, y)| Vector2::new(x, y))
+                .collect();
+            let q = p
+                .iter()
+                .map(|p| Self::matrix(&Vector2::new(ANGLE.cos(), ANGLE.sin())) * p * 0.8)
+                .collect();
+            Self {
+                p,
+                q,
+                params: Vector2::new(1., 0.),
+            }
+        }
+
+        fn matrix(x: &Vector2<f64>) -> Matrix2<f64> {
+            Matrix2::new(x.x, -x.y, x.y, x.x)
+        }
+
+        fn residuals(&self) -> OVector<f64, Dynamic> {
+            let rotation = Self::matrix(&self.params);
+            let mut residuals = OVector::<f64, Dynamic>::zeros(2 * self.p.len());
+            for (i, (p, q)) in self.p.iter().zip(self.q.iter()).enumerate() {
+                residuals
+                    .fixed_rows_mut::<2>(2 * i)
+                    .copy_from(&(rotation * p - q));
+            }
+            residuals
+        }
+    }
+
+    impl ManifoldLeastSquaresProblem<f64, Dynamic, U2, U1> for Rotation {
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn retract(&self, x: &Vector2<f64>, step: &Vector1<f64>) -> Vector2<f64> {
+            Self::matrix(&Vector2::new(step.x.cos(), step.x.sin())) * x
+        }
+
+        fn residuals(&self) -> Option<OVector<f64, Dynamic>> {
+            Some(Rotation::residuals(self))
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U1>> {
+            // derivative of exp(i delta) at zero is a rotation by 90°
+            let tangent = Self::matrix(&Vector2::new(-self.params.y, self.params.x));
+            let mut jacobian = OMatrix::<f64, Dynamic, U1>::zeros(2 * self.p.len());
+            for (i, p) in self.p.iter().enumerate() {
+                jacobian
+                    .fixed_rows_mut::<2>(2 * i)
+                    .copy_from(&(tangent * p));
+            }
+            Some(jacobian)
+        }
+    }
+
+    /// The same fit with additive updates of `$(\cos\theta, \sin\theta)$`.
+    struct Additive {
+        rotation: Rotation,
+    }
+
+    impl LeastSquaresProblem<f64, Dynamic, U2> for Additive {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.rotation.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.rotation.params
+        }
+
+        fn residuals(&self) -> Option<OVector<f64, Dynamic>> {
+            Some(self.rotation.residuals())
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U2>> {
+            let p = &self.rotation.p;
+            let mut jacobian = OMatrix::<f64, Dynamic, U2>::zeros(2 * p.len());
+            for (i, p) in p.iter().enumerate() {
+                jacobian.fixed_rows_mut::<2>(2 * i).copy_from(&Matrix2::new(
+                    p.x, -p.y, //
+                    p.y, p.x,
+                ));
+            }
+            Some(jacobian)
+        }
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let base = Vector2::new(0.6, 0.8);
+        let mut rotation = Rotation::new();
+        rotation.params = base;
+        let mut problem = super::TangentSpace::new(rotation, base, Const::<1>);
+        let numerical = crate::differentiate_numerically(&mut problem).unwrap();
+        let jacobian = problem.jacobian().unwrap();
+        assert_relative_eq!(jacobian, numerical, epsilon = 1e-8);
+    }
+
+    #[test]
+    fn additive_updates_leave_the_circle() {
+        let (problem, report) = LevenbergMarquardt::new().minimize(Additive {
+            rotation: Rotation::new(),
+        });
+        assert!(report.termination.was_successful());
+        assert_relative_eq!(problem.rotation.params.norm(), 0.8, epsilon = 1e-10);
+    }
+
+    #[test]
+    fn retraction_stays_on_the_circle() {
+        let (problem, report) = LevenbergMarquardt::new().minimize_manifold(Rotation::new());
+        assert!(report.termination.was_successful());
+        assert_relative_eq!(problem.params.norm(), 1., epsilon = 1e-14);
+        assert_relative_eq!(problem.params.x, ANGLE.cos(), epsilon = 1e-5);
+        assert_relative_eq!(problem.params.y, ANGLE.sin(), epsilon = 1e-5);
+    }
+}
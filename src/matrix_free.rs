@@ -0,0 +1,512 @@
+//! Least squares problems whose Jacobian is only available as an operator.
+//!
+//! For problems with very many parameters the Jacobian can be too large to
+//! form, while the products `$\mathbf{J}\vec{v}$` and `$\mathbf{J}^\top\vec{u}$`
+//! are cheap. The trust-region subproblem is then solved with the conjugate
+//! gradient method on the normal equations instead of a QR decomposition.
+use nalgebra::{
+    allocator::Allocator, convert, storage::Owned, DVector, DefaultAllocator, Dim, Dynamic,
+    OMatrix, OVector, RealField,
+};
+use num_traits::Float;
+
+use crate::trust_region::{LMParameter, Linearization};
+use crate::utils::{dwarf, enorm, epsmch};
+use crate::LeastSquaresProblem;
+
+/// The Jacobian `$\mathbf{J}$` at fixed parameters as a linear operator.
+///
+/// See [`MatrixFreeProblem`](trait.MatrixFreeProblem.html).
+pub trait JacobianOperator<F, N>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Compute `$\mathbf{J}\vec{v}$`.
+    fn apply(&self, v: &OVector<F, N>) -> DVector<F>;
+
+    /// Compute `$\mathbf{J}^\top\vec{u}$`.
+    fn apply_transpose(&self, u: &DVector<F>) -> OVector<F, N>;
+
+    /// The column norms `$\|\mathbf{J}\vec{e}_i\|$`.
+    ///
+    /// They are needed once per iteration. By default `None` is returned and
+    /// the norms are computed by applying the operator to every unit vector,
+    /// return them if they are cheaper to compute.
+    fn column_norms(&self) -> Option<OVector<F, N>> {
+        None
+    }
+}
+
+/// A least squares problem which only provides Jacobian-vector products.
+///
+/// This is minimized with
+/// [`LevenbergMarquardt::minimize_matrix_free`](struct.LevenbergMarquardt.html#method.minimize_matrix_free).
+/// The operator returned by [`jacobian_operator`](#tymethod.jacobian_operator)
+/// must not change when the parameters change afterwards, it usually holds a
+/// copy of the parameters or of intermediate results.
+pub trait MatrixFreeProblem<F, N>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The type of the Jacobian operator.
+    type Operator: JacobianOperator<F, N>;
+
+    /// Set the stored parameters `$\vec{x}$`.
+    fn set_params(&mut self, x: &OVector<F, N>);
+
+    /// Get the current parameter vector `$\vec{x}$`.
+    fn params(&self) -> OVector<F, N>;
+
+    /// Compute the residual vector.
+    fn residuals(&self) -> Option<DVector<F>>;
+
+    /// The Jacobian at the current parameters as an operator.
+    fn jacobian_operator(&self) -> Option<Self::Operator>;
+}
+
+/// The least squares problem of a `MatrixFreeProblem`, it has no Jacobian matrix.
+pub(crate) struct MatrixFree<P> {
+    problem: P,
+}
+
+impl<P> MatrixFree<P> {
+    pub(crate) fn new(problem: P) -> Self {
+        Self { problem }
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+
+    /// Build the subproblem at the current parameters.
+    pub(crate) fn linearize<F, N>(
+        &self,
+        residuals: &DVector<F>,
+    ) -> Option<OperatorLinearization<F, N, P::Operator>>
+    where
+        F: RealField + Float,
+        N: Dim,
+        P: MatrixFreeProblem<F, N>,
+        DefaultAllocator: Allocator<F, N>,
+    {
+        OperatorLinearization::new(self.problem.jacobian_operator()?, residuals)
+    }
+}
+
+impl<F, N, P> LeastSquaresProblem<F, Dynamic, N> for MatrixFree<P>
+where
+    F: RealField + Copy,
+    N: Dim,
+    P: MatrixFreeProblem<F, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, Dynamic, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, N>;
+    type ParameterStorage = Owned<F, N>;
+
+    fn set_params(&mut self, x: &OVector<F, N>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> OVector<F, N> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        self.problem.residuals()
+    }
+
+    /// The Jacobian is never formed.
+    fn jacobian(&self) -> Option<OMatrix<F, Dynamic, N>> {
+        None
+    }
+}
+
+/// The trust-region subproblem for a Jacobian operator.
+///
+/// All linear systems
+/// `$(\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D})\vec{p} = \vec{b}$`
+/// are solved with the conjugate gradient method.
+pub(crate) struct OperatorLinearization<F, N, A>
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    operator: A,
+    /// `$\mathbf{J}^\top\vec{r}$`
+    gradient: OVector<F, N>,
+    column_norms: OVector<F, N>,
+}
+
+impl<F, N, A> OperatorLinearization<F, N, A>
+where
+    F: RealField + Float,
+    N: Dim,
+    A: JacobianOperator<F, N>,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Returns `None` if the dimensions of the operator do not fit.
+    pub(crate) fn new(operator: A, residuals: &DVector<F>) -> Option<Self> {
+        let gradient = operator.apply_transpose(residuals);
+        let column_norms = match operator.column_norms() {
+            Some(norms) => norms,
+            None => {
+                let mut unit = gradient.map(|_| F::zero());
+                let mut norms = unit.clone();
+                for i in 0..unit.nrows() {
+                    unit[i] = F::one();
+                    let column = operator.apply(&unit);
+                    if column.nrows() != residuals.nrows() {
+                        return None;
+                    }
+                    norms[i] = enorm(&column);
+                    unit[i] = F::zero();
+                }
+                norms
+            }
+        };
+        (column_norms.nrows() == gradient.nrows()).then_some(Self {
+            operator,
+            gradient,
+            column_norms,
+        })
+    }
+
+    /// Solve `$(\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D})\vec{p} = \vec{b}$`.
+    ///
+    /// Returns `None` if the system is singular along the search directions
+    /// or the iteration does not converge.
+    fn solve(&self, diag: &OVector<F, N>, lambda: F, b: &OVector<F, N>) -> Option<OVector<F, N>> {
+        let n = b.nrows();
+        let b_norm = enorm(b);
+        let mut x = b.map(|_| F::zero());
+        if b_norm.is_zero() {
+            return Some(x);
+        }
+        let tolerance = Float::powf(epsmch::<F>(), convert(0.75)) * b_norm;
+        let mut r = b.clone_owned();
+        let mut p = r.clone();
+        let mut rr = r.norm_squared();
+        for _ in 0..Ord::max(2 * n, 10) {
+            let jp = self.operator.apply(&p);
+            let mut ap = self.operator.apply_transpose(&jp);
+            for ((ap, p), d) in ap.iter_mut().zip(p.iter()).zip(diag.iter()) {
+                *ap += lambda * *d * *d * *p;
+            }
+            let curvature = p.dot(&ap);
+            if !curvature.is_positive() || !curvature.is_finite() {
+                return None;
+            }
+            let alpha = rr / curvature;
+            x.axpy(alpha, &p, F::one());
+            r.axpy(-alpha, &ap, F::one());
+            let rr_new = r.norm_squared();
+            if Float::sqrt(rr_new) <= tolerance {
+                return Some(x);
+            }
+            p.axpy(F::one(), &r, rr_new / rr);
+            rr = rr_new;
+        }
+        None
+    }
+
+    /// The Newton correction of `$\lambda$`, see `determine_lambda_and_parameter_update`.
+    fn newton_correction(
+        &self,
+        diag: &OVector<F, N>,
+        lambda: F,
+        diag_p: &OVector<F, N>,
+        diag_p_norm: F,
+        fp: F,
+        delta: F,
+    ) -> Option<F> {
+        let v = diag_p.zip_map(diag, |dp, d| dp / diag_p_norm * d);
+        // v^T (J^T J + lambda D D)^{-1} v
+        let norm_sq = v.dot(&self.solve(diag, lambda, &v)?);
+        Some((fp / delta) / norm_sq)
+    }
+}
+
+impl<F, N, A> Linearization<F, N> for OperatorLinearization<F, N, A>
+where
+    F: RealField + Float,
+    N: Dim,
+    A: JacobianOperator<F, N>,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn column_norms(&self) -> &OVector<F, N> {
+        &self.column_norms
+    }
+
+    fn max_a_t_b_scaled(&mut self, b_norm: F) -> Option<F> {
+        let mut max = F::zero();
+        for (g, scale) in self.gradient.iter().zip(self.column_norms.iter()) {
+            if scale.is_zero() {
+                continue;
+            }
+            let temp = Float::abs(*g / b_norm / *scale);
+            if temp.is_nan() {
+                return None;
+            }
+            max = Float::max(max, temp);
+        }
+        Some(max)
+    }
+
+    fn a_x_norm(&mut self, x: &OVector<F, N>) -> F {
+        enorm(&self.operator.apply(x))
+    }
+
+    fn a_t_b(&self) -> OVector<F, N> {
+        self.gradient.clone()
+    }
+
+    /// Same safeguarded Newton iteration as
+    /// [`determine_lambda_and_parameter_update`](../trust_region/fn.determine_lambda_and_parameter_update.html),
+    /// but all linear systems are solved with conjugate gradients.
+    fn solve_trust_region(
+        &mut self,
+        diag: &OVector<F, N>,
+        delta: F,
+        initial_lambda: F,
+        max_iterations: usize,
+        _min_norm: bool,
+    ) -> LMParameter<F, N> {
+        const P1: f64 = 0.1;
+
+        // Gauss-Newton step, only available if the iteration converges
+        let mut p = self.gradient.map(|_| F::zero());
+        let mut diag_p_norm = F::zero();
+        let mut fp = <F as Float>::infinity();
+        let mut lambda_lower = F::zero();
+        if let Some(step) = self.solve(diag, F::zero(), &self.gradient) {
+            p = step;
+            let diag_p = p.component_mul(diag);
+            diag_p_norm = enorm(&diag_p);
+            fp = diag_p_norm - delta;
+            if fp <= delta * convert(P1) {
+                return LMParameter {
+                    step: p,
+                    lambda: F::zero(),
+                    dp_norm: diag_p_norm,
+                    lambda_iterations: 0,
+                };
+            }
+            lambda_lower = self
+                .newton_correction(diag, F::zero(), &diag_p, diag_p_norm, fp, delta)
+                .unwrap_or_else(F::zero);
+        }
+
+        // Upper bound is given by ||(J * D^T)^T r|| / delta
+        let gnorm = enorm(&self.gradient.component_div(diag));
+        let mut lambda_upper = gnorm / delta;
+        if lambda_upper.is_zero() {
+            lambda_upper = dwarf::<F>() / Float::min(delta, convert(P1));
+        }
+
+        let mut lambda = Float::min(Float::max(initial_lambda, lambda_lower), lambda_upper);
+        if lambda.is_zero() && !diag_p_norm.is_zero() {
+            lambda = gnorm / diag_p_norm;
+        }
+
+        let mut lambda_iterations = 0;
+        for iteration in 1.. {
+            lambda_iterations = iteration;
+            if lambda.is_zero() {
+                lambda = Float::max(dwarf(), lambda_upper * convert(0.001));
+            }
+            p = match self.solve(diag, lambda, &self.gradient) {
+                Some(step) => step,
+                None => break,
+            };
+            let diag_p = p.component_mul(diag);
+            diag_p_norm = enorm(&diag_p);
+            if iteration == max_iterations {
+                break;
+            }
+            let fp_old = fp;
+            fp = diag_p_norm - delta;
+            if Float::abs(fp) <= delta * convert(P1)
+                || (lambda_lower.is_zero() && fp <= fp_old && fp_old.is_negative())
+            {
+                break;
+            }
+
+            let newton_correction =
+                match self.newton_correction(diag, lambda, &diag_p, diag_p_norm, fp, delta) {
+                    Some(correction) => correction,
+                    None => break,
+                };
+            if fp.is_positive() {
+                lambda_lower = Float::max(lambda_lower, lambda);
+            } else {
+                lambda_upper = Float::min(lambda_upper, lambda);
+            }
+            lambda = Float::max(lambda_lower, lambda + newton_correction);
+        }
+
+        LMParameter {
+            step: p,
+            lambda,
+            dp_norm: diag_p_norm,
+            lambda_iterations,
+        }
+    }
+
+    fn solve_damped(&mut self, diag: &OVector<F, N>, lambda: F) -> Option<LMParameter<F, N>> {
+        let step = self.solve(diag, lambda, &self.gradient)?;
+        let dp_norm = enorm(&step.component_mul(diag));
+        Some(LMParameter {
+            step,
+            lambda,
+            dp_norm,
+            lambda_iterations: 1,
+        })
+    }
+
+    /// The Jacobian is not decomposed, there is no cheap estimate.
+    fn singular_value_estimates(&self, _diag: &OVector<F, N>) -> Option<(F, F)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DMatrix, DVector, Dynamic};
+
+    use super::{JacobianOperator, MatrixFreeProblem, OperatorLinearization};
+    use crate::qr::PivotedQR;
+    use crate::trust_region::Linearization;
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    impl JacobianOperator<f64, Dynamic> for DMatrix<f64> {
+        fn apply(&self, v: &DVector<f64>) -> DVector<f64> {
+            self * v
+        }
+
+        fn apply_transpose(&self, u: &DVector<f64>) -> DVector<f64> {
+            self.tr_mul(u)
+        }
+    }
+
+    #[test]
+    fn test_iterative_step_matches_qr() {
+        let jacobian = DMatrix::from_fn(8, 5, |i, j| {
+            let (i, j) = (i as f64, j as f64);
+            f64::sin(1. + 3. * i + 7. * j) + if i == j { 2. } else { 0. }
+        });
+        let residuals = DVector::from_fn(8, |i, _| f64::cos(2. * i as f64));
+        let diag = DVector::from_column_slice(&[1., 2., 0.5, 1.5, 3.]);
+        for delta in [0.01, 0.1, 1.0, 100.0] {
+            let mut iterative = OperatorLinearization::new(jacobian.clone(), &residuals).unwrap();
+            let mut dense = PivotedQR::new(jacobian.clone())
+                .into_least_squares_diagonal_problem(residuals.clone());
+            let param_iterative = iterative.solve_trust_region(&diag, delta, 0., 10, false);
+            let param_dense = dense.solve_trust_region(&diag, delta, 0., 10, false);
+            assert_relative_eq!(param_iterative.step, param_dense.step, epsilon = 1e-8);
+            assert_relative_eq!(param_iterative.lambda, param_dense.lambda, epsilon = 1e-6);
+            assert_relative_eq!(
+                iterative.max_a_t_b_scaled(2.).unwrap(),
+                dense.max_a_t_b_scaled(2.).unwrap(),
+                epsilon = 1e-12
+            );
+        }
+    }
+
+    /// The residuals `$\mathbf{A}\vec{x} + \frac{1}{10}(x_{i \bmod n}^2)_i - \vec{b}$`.
+    struct Mildly {
+        a: DMatrix<f64>,
+        b: DVector<f64>,
+        params: DVector<f64>,
+    }
+
+    impl Mildly {
+        fn new() -> Self {
+            let a = DMatrix::from_fn(9, 6, |i, j| {
+                f64::cos(1. + 2. * i as f64 + 5. * j as f64) + if i == j { 3. } else { 0. }
+            });
+            Self {
+                a,
+                b: DVector::from_fn(9, |i, _| 1. + i as f64 / 3.),
+                params: DVector::zeros(6),
+            }
+        }
+
+        fn jacobian_matrix(&self) -> DMatrix<f64> {
+            let n = self.params.nrows();
+            let mut jacobian = self.a.clone();
+            for i in 0..jacobian.nrows() {
+                jacobian[(i, i % n)] += 0.2 * self.params[i % n];
+            }
+            jacobian
+        }
+    }
+
+    impl LeastSquaresProblem<f64, Dynamic, Dynamic> for Mildly {
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, Dynamic>;
+        type ParameterStorage = Owned<f64, Dynamic>;
+
+        fn set_params(&mut self, x: &DVector<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> DVector<f64> {
+            self.params.clone()
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            let n = self.params.nrows();
+            let mut residuals = &self.a * &self.params - &self.b;
+            for (i, r) in residuals.iter_mut().enumerate() {
+                *r += 0.1 * self.params[i % n] * self.params[i % n];
+            }
+            Some(residuals)
+        }
+
+        fn jacobian(&self) -> Option<DMatrix<f64>> {
+            Some(self.jacobian_matrix())
+        }
+    }
+
+    impl MatrixFreeProblem<f64, Dynamic> for Mildly {
+        type Operator = DMatrix<f64>;
+
+        fn set_params(&mut self, x: &DVector<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> DVector<f64> {
+            self.params.clone()
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            LeastSquaresProblem::residuals(self)
+        }
+
+        fn jacobian_operator(&self) -> Option<DMatrix<f64>> {
+            Some(self.jacobian_matrix())
+        }
+    }
+
+    #[test]
+    fn test_matrix_free_matches_dense() {
+        let config = LevenbergMarquardt::new();
+        let (dense, dense_report) = config.minimize(Mildly::new());
+        assert!(dense_report.termination.was_successful());
+        let (matrix_free, report) = config.minimize_matrix_free(Mildly::new());
+        assert!(report.termination.was_successful());
+        assert_relative_eq!(matrix_free.params, dense.params, epsilon = 1e-8);
+        assert_relative_eq!(
+            report.objective_function,
+            dense_report.objective_function,
+            max_relative = 1e-8
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Least squares problems evaluated in single precision.
+//!
+//! This only saves memory bandwidth in the evaluation of the problem, it does
+//! not make the minimization more robust than a minimization in single
+//! precision.
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::Allocator, convert, convert_unchecked, storage::Owned, DefaultAllocator, Dim,
+    OMatrix, OVector, RealField,
+};
+
+use crate::LeastSquaresProblem;
+
+/// A problem in a lower precision `F` which is minimized in a higher one.
+///
+/// Typically `F` is `f32` and the minimization runs in `f64`: the wrapped
+/// problem stores its data and computes the residuals and the Jacobian in
+/// single precision, the minimization runs in double precision.
+/// Every evaluation is promoted to `f64` before it enters the solver, so the
+/// QR decomposition and the trust-region subproblem are computed in `f64`.
+/// The parameters are rounded to `f32` when they are set on the wrapped
+/// problem.
+///
+/// Use this if the evaluation of the problem is bound by memory bandwidth,
+/// for example if the residuals come from a large design matrix. Only the
+/// data and the evaluations of the problem are in `f32`, the solver keeps its
+/// working copy of the Jacobian in `f64`.
+///
+/// The achievable accuracy is limited by the rounding errors of the residuals
+/// in `f32`, choose `ftol` and `xtol` at the precision of `f32`, see
+/// [`with_tol`](struct.LevenbergMarquardt.html#method.with_tol).
+pub struct MixedPrecision<P, F> {
+    problem: P,
+    precision: PhantomData<F>,
+}
+
+impl<P, F> MixedPrecision<P, F> {
+    /// Wrap a problem which is evaluated in precision `F`.
+    pub fn new(problem: P) -> Self {
+        Self {
+            problem,
+            precision: PhantomData,
+        }
+    }
+
+    /// The wrapped problem.
+    pub fn inner(&self) -> &P {
+        &self.problem
+    }
+
+    /// Extract the wrapped problem.
+    pub fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+/// Convert between two precisions through `f64`.
+fn promote<F: RealField, G: RealField>(x: F) -> G {
+    convert(convert_unchecked::<F, f64>(x))
+}
+
+impl<F, G, M, N, P> LeastSquaresProblem<G, M, N> for MixedPrecision<P, F>
+where
+    F: RealField + Copy,
+    G: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N, ParameterStorage = Owned<F, N>>,
+    DefaultAllocator: Allocator<F, N> + Allocator<G, N> + Allocator<G, M> + Allocator<G, M, N>,
+{
+    type ResidualStorage = Owned<G, M>;
+    type JacobianStorage = Owned<G, M, N>;
+    type ParameterStorage = Owned<G, N>;
+
+    fn set_params(&mut self, x: &OVector<G, N>) {
+        self.problem.set_params(&x.map(promote));
+    }
+
+    fn params(&self) -> OVector<G, N> {
+        self.problem.params().map(promote)
+    }
+
+    fn residuals(&self) -> Option<OVector<G, M>> {
+        Some(self.problem.residuals()?.map(promote))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<G, M, N>> {
+        Some(self.problem.jacobian()?.map(promote))
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DVector, Dynamic, OMatrix, RealField, Vector6, U6};
+    use num_traits::Float;
+
+    use super::MixedPrecision;
+    use crate::lm::LevenbergMarquardt;
+    use crate::LeastSquaresProblem;
+
+    /// Fit a polynomial of degree five in the monomial basis on `$[0, 1]$`.
+    ///
+    /// The columns of the Jacobian are nearly parallel, its condition number
+    /// is about `$4\cdot 10^3$`.
+    struct Polynomial<F: RealField> {
+        design: OMatrix<F, Dynamic, U6>,
+        y: DVector<F>,
+        params: Vector6<F>,
+    }
+
+    impl<F: RealField + Float> Polynomial<F> {
+        fn new() -> Self {
+            let t = DVector::<f64>::from_fn(60, |i, _| i as f64 / 59.);
+            let design = OMatrix::<f64, Dynamic, U6>::from_fn(60, |i, j| t[i].powi(j as i32));
+            let y = t.map(|t| f64::exp(t) * f64::sin(2. * t));
+            Self {
+                design: design.map(|v| F::from(v).unwrap()),
+                y: y.map(|v| F::from(v).unwrap()),
+                params: Vector6::zeros(),
+            }
+        }
+    }
+
+    impl<F: RealField + Float> LeastSquaresProblem<F, Dynamic, U6> for Polynomial<F> {
+        type ParameterStorage = Owned<F, U6>;
+        type ResidualStorage = Owned<F, Dynamic>;
+        type JacobianStorage = Owned<F, Dynamic, U6>;
+
+        fn set_params(&mut self, x: &Vector6<F>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector6<F> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<DVector<F>> {
+            Some(&self.design * self.params - &self.y)
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<F, Dynamic, U6>> {
+            Some(self.design.clone())
+        }
+    }
+
+    #[test]
+    fn matches_double_precision() {
+        let (exact, report) = LevenbergMarquardt::<f64>::new().minimize(Polynomial::<f64>::new());
+        assert!(report.termination.was_successful());
+        let (mixed, report) = LevenbergMarquardt::<f64>::new()
+            .with_tol(1e-6)
+            .minimize(MixedPrecision::new(Polynomial::<f32>::new()));
+        assert!(report.termination.was_successful());
+        assert!(!report.jacobian_is_numerical);
+
+        // evaluate the single precision solution in double precision
+        let mut evaluated = Polynomial::<f64>::new();
+        evaluated.params = mixed.params();
+        let optimum = exact.residuals().unwrap().norm();
+        assert_relative_eq!(
+            evaluated.residuals().unwrap().norm(),
+            optimum,
+            max_relative = 1e-6
+        );
+        assert_eq!(mixed.inner().params.map(f64::from), mixed.params());
+    }
+}
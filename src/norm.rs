@@ -0,0 +1,44 @@
+//! Custom norms for the convergence tests.
+use nalgebra::RealField;
+use num_traits::Float;
+
+/// A norm for the `ftol` and `xtol` tests.
+///
+/// Attach it with [`LM::with_residual_norm`](struct.LM.html#method.with_residual_norm)
+/// or [`LM::with_step_norm`](struct.LM.html#method.with_step_norm). It is
+/// implemented for closures `Fn(&[F]) -> F`, so any function of the entries
+/// can be used, for example a weighted norm.
+///
+/// Only the convergence tests use it. The trust-region step is computed from
+/// the QR decomposition of the Jacobian, which minimizes the Euclidean norm of
+/// the linearized residuals subject to a bound on the Euclidean norm
+/// `$\|\mathbf{D}\vec{p}\|$`. So the subproblem, the trust-region radius
+/// `$\Delta$`, the gain ratio which decides whether a step is accepted, the
+/// `gtol` test and the reported objective function stay Euclidean.
+pub trait Norm<F> {
+    /// Compute the norm of `v`.
+    fn norm(&self, v: &[F]) -> F;
+}
+
+impl<F, T> Norm<F> for T
+where
+    T: Fn(&[F]) -> F,
+{
+    fn norm(&self, v: &[F]) -> F {
+        self(v)
+    }
+}
+
+/// The maximum norm `$\|\vec{v}\|_\infty = \max_i |v_i|$`.
+///
+/// As the step norm, the `xtol` test passes once no single scaled parameter
+/// moves by more than `xtol` times the largest scaled parameter.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InfinityNorm;
+
+impl<F: RealField + Float> Norm<F> for InfinityNorm {
+    fn norm(&self, v: &[F]) -> F {
+        v.iter()
+            .fold(F::zero(), |max, v| Float::max(max, Float::abs(*v)))
+    }
+}
@@ -0,0 +1,621 @@
+//! Least squares problems with a finite-difference Jacobian.
+use core::cell::RefCell;
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::{Allocator, Reallocator},
+    storage::{Owned, RawStorage},
+    DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Matrix, OMatrix, OVector, RealField, Vector,
+};
+use num_traits::Float;
+
+use crate::lm::{LevenbergMarquardt, MinimizationReport};
+use crate::utils::epsmch;
+use crate::LeastSquaresProblem;
+
+/// A problem whose Jacobian is approximated with forward differences.
+///
+/// Wrap a problem in this if you can only compute the residuals. The
+/// [`jacobian`](trait.LeastSquaresProblem.html#tymethod.jacobian) of the
+/// wrapped problem is never called, it may simply return `None`.
+///
+/// Column `$j$` of the Jacobian is approximated by
+/// ```math
+///   \frac{\vec{r}(\vec{x} + h_j\vec{e}_j) - \vec{r}(\vec{x})}{h_j}
+///   \quad\text{with}\quad h_j = \sqrt{\varepsilon}\,|x_j|,
+/// ```
+/// where `$\varepsilon$` is the machine epsilon. If `$x_j = 0$`, then
+/// `$h_j = \sqrt{\varepsilon}$` is used. This is the same as in MINPACK.
+/// Explicit steps can be set with
+/// [`with_finite_diff_step`](#method.with_finite_diff_step), a relative step
+/// with a lower bound with [`with_relative_step`](#method.with_relative_step).
+/// One Jacobian costs `$n$` residual evaluations during the minimization,
+/// which passes the residuals at `$\vec{x}$` to
+/// [`jacobian_with_residuals`](trait.LeastSquaresProblem.html#method.jacobian_with_residuals).
+/// Calling [`jacobian`](trait.LeastSquaresProblem.html#tymethod.jacobian)
+/// directly costs `$n + 1$` evaluations.
+///
+/// The [`MinimizationReport`](struct.MinimizationReport.html) marks that the
+/// Jacobian was approximated, see
+/// [`jacobian_is_numerical`](struct.MinimizationReport.html#structfield.jacobian_is_numerical).
+pub struct NumericalDifferentiation<P, S = ()> {
+    problem: RefCell<P>,
+    steps: S,
+}
+
+impl<P> NumericalDifferentiation<P> {
+    pub fn new(problem: P) -> Self {
+        Self {
+            problem: RefCell::new(problem),
+            steps: (),
+        }
+    }
+
+    /// Use `steps[j]` as the step `$h_j$` for parameter `$j$`.
+    ///
+    /// Use this if the residuals have structure at a known scale in some
+    /// parameter, so that the automatic step is too large or too small.
+    /// Entries which are zero or not finite fall back to the automatic step for
+    /// that parameter. A negative step gives a backward difference.
+    ///
+    /// The Jacobian computation panics if `steps` does not have one entry per
+    /// parameter.
+    pub fn with_finite_diff_step<F, N>(
+        self,
+        steps: OVector<F, N>,
+    ) -> NumericalDifferentiation<P, OVector<F, N>>
+    where
+        F: RealField,
+        N: Dim,
+        DefaultAllocator: Allocator<F, N>,
+    {
+        NumericalDifferentiation {
+            problem: self.problem,
+            steps,
+        }
+    }
+
+    /// Use the step `$h_j = \max(\mathtt{relative}\cdot|x_j|, \mathtt{absolute})$`.
+    ///
+    /// The automatic step is proportional to `$|x_j|$`. A parameter which
+    /// comes close to zero, but is not exactly zero, then gets a step so small
+    /// that the difference of the residuals is dominated by their rounding
+    /// errors. The lower bound `absolute` keeps the step at the scale on which
+    /// the parameter is meaningful. Without a natural scale choose
+    /// `$\mathtt{relative} = \sqrt{\varepsilon}$` and
+    /// `absolute` as `$\sqrt{\varepsilon}$` times the typical magnitude of the
+    /// parameter. With `relative` zero all steps are `absolute`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `$\mathtt{relative} < 0$` or `$\mathtt{absolute} \leq 0$`.
+    pub fn with_relative_step<F: RealField + Float>(
+        self,
+        relative: F,
+        absolute: F,
+    ) -> NumericalDifferentiation<P, RelativeStep<F>> {
+        assert!(!relative.is_negative(), "relative must be >= 0");
+        assert!(absolute.is_positive(), "absolute must be > 0");
+        NumericalDifferentiation {
+            problem: self.problem,
+            steps: RelativeStep { relative, absolute },
+        }
+    }
+}
+
+impl<P, S> NumericalDifferentiation<P, S> {
+    /// Extract the wrapped problem.
+    pub fn into_inner(self) -> P {
+        self.problem.into_inner()
+    }
+}
+
+/// Explicit finite-difference steps, `()` for none.
+pub trait StepSizes<F> {
+    /// The step for parameter `j` of `n` at `x_j`, `None` for the automatic choice.
+    fn step(&self, j: usize, n: usize, x_j: F) -> Option<F>;
+}
+
+impl<F> StepSizes<F> for () {
+    fn step(&self, _j: usize, _n: usize, _x_j: F) -> Option<F> {
+        None
+    }
+}
+
+/// The steps of [`with_relative_step`](struct.NumericalDifferentiation.html#method.with_relative_step).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RelativeStep<F> {
+    relative: F,
+    absolute: F,
+}
+
+impl<F: RealField + Float> StepSizes<F> for RelativeStep<F> {
+    fn step(&self, _j: usize, _n: usize, x_j: F) -> Option<F> {
+        Some(Float::max(self.relative * Float::abs(x_j), self.absolute))
+    }
+}
+
+impl<F, N> StepSizes<F> for OVector<F, N>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn step(&self, j: usize, n: usize, _x_j: F) -> Option<F> {
+        assert_eq!(self.nrows(), n, "steps must have one entry per parameter");
+        let h = self[j];
+        (h.is_finite() && !h.is_zero()).then_some(h)
+    }
+}
+
+impl<F, M, N, P, S> LeastSquaresProblem<F, M, N> for NumericalDifferentiation<P, S>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+    S: StepSizes<F>,
+    DefaultAllocator: Allocator<F, M, N, Buffer = P::JacobianStorage>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.get_mut().set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.borrow().params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        self.problem.borrow().residuals()
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        self.problem.borrow().residuals_into(out)
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let base = self.problem.borrow().residuals()?;
+        self.jacobian_with_residuals(&base)
+    }
+
+    fn jacobian_with_residuals(
+        &self,
+        base: &Vector<F, M, Self::ResidualStorage>,
+    ) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let mut problem = self.problem.borrow_mut();
+        let mut x = problem.params();
+        let n = x.data.shape().0;
+        let m = base.data.shape().0;
+        let eps = Float::sqrt(epsmch::<F>());
+        let mut jacobian = Matrix::<F, M, N, Self::JacobianStorage>::zeros_generic(m, n);
+        for j in 0..n.value() {
+            let x_j = x[j];
+            let h = self.steps.step(j, n.value(), x_j).unwrap_or_else(|| {
+                let h = eps * Float::abs(x_j);
+                if h.is_zero() {
+                    eps
+                } else {
+                    h
+                }
+            });
+            x[j] = x_j + h;
+            problem.set_params(&x);
+            let perturbed = problem.residuals();
+            x[j] = x_j;
+            let perturbed = match perturbed {
+                Some(perturbed) => perturbed,
+                None => {
+                    problem.set_params(&x);
+                    return None;
+                }
+            };
+            for i in 0..m.value() {
+                jacobian[(i, j)] = (perturbed[i] - base[i]) / h;
+            }
+        }
+        // reset the initial params
+        problem.set_params(&x);
+        Some(jacobian)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        true
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.borrow().number_of_penalty_residuals()
+    }
+}
+
+/// Fit parameters with only a residual function.
+///
+/// This minimizes `$\frac{1}{2}\|\vec{r}(\vec{x})\|^2$` starting at `x0`.
+/// The Jacobian is approximated with forward differences, see
+/// [`NumericalDifferentiation`](struct.NumericalDifferentiation.html) for the
+/// step sizes `$h_j = \sqrt{\varepsilon}\,|x_j|$`. Return `None` from
+/// `residuals` if they cannot be computed at the given parameters.
+///
+/// Returns the final parameters and the report.
+///
+/// # Example
+///
+/// Fit a Gaussian peak `$a\exp\bigl(-(t - \mu)^2 / (2\sigma^2)\bigr)$`:
+///
+/// ```
+/// # use levenberg_marquardt::{least_squares_numeric, LevenbergMarquardt};
+/// # use nalgebra::{DVector, Vector3};
+/// # use approx::assert_relative_eq;
+/// let t: Vec<f64> = (0..40).map(|i| 0.25 * i as f64).collect();
+/// let y: Vec<f64> = t
+///     .iter()
+///     .map(|t| 3. * f64::exp(-(t - 4.).powi(2) / (2. * 1.5f64.powi(2))))
+///     .collect();
+///
+/// let (x, report) = least_squares_numeric(
+///     Vector3::new(1., 3., 1.),
+///     |x: &Vector3<f64>| {
+///         let [a, mu, sigma] = [x[0], x[1], x[2]];
+///         Some(DVector::from_iterator(
+///             t.len(),
+///             t.iter().zip(&y).map(|(t, y)| {
+///                 a * f64::exp(-(t - mu).powi(2) / (2. * sigma * sigma)) - y
+///             }),
+///         ))
+///     },
+///     &LevenbergMarquardt::new(),
+/// );
+/// assert!(report.termination.was_successful());
+/// assert!(report.jacobian_is_numerical);
+/// assert_relative_eq!(x, Vector3::new(3., 4., 1.5), epsilon = 1e-6);
+/// ```
+pub fn least_squares_numeric<F, M, N, R>(
+    x0: OVector<F, N>,
+    residuals: R,
+    config: &LevenbergMarquardt<F>,
+) -> (OVector<F, N>, MinimizationReport<F>)
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: DimMin<N> + DimMax<N>,
+    R: FnMut(&OVector<F, N>) -> Option<OVector<F, M>>,
+    DefaultAllocator: Allocator<F, N>
+        + Allocator<F, M>
+        + Reallocator<F, M, N, DimMaximum<M, N>, N>
+        + Allocator<usize, N>,
+{
+    let problem = NumericalDifferentiation::new(ResidualFn {
+        x: x0,
+        residuals: RefCell::new(residuals),
+        m: PhantomData,
+    });
+    let (problem, report) = config.minimize(problem);
+    (problem.into_inner().x, report)
+}
+
+/// A problem given by a residual closure, without a Jacobian.
+struct ResidualFn<F, M, N, R>
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    x: OVector<F, N>,
+    residuals: RefCell<R>,
+    m: PhantomData<M>,
+}
+
+impl<F, M, N, R> LeastSquaresProblem<F, M, N> for ResidualFn<F, M, N, R>
+where
+    F: RealField + Copy,
+    N: Dim,
+    M: Dim,
+    R: FnMut(&OVector<F, N>) -> Option<OVector<F, M>>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, M> + Allocator<F, M, N>,
+{
+    type ResidualStorage = Owned<F, M>;
+    type JacobianStorage = Owned<F, M, N>;
+    type ParameterStorage = Owned<F, N>;
+
+    fn set_params(&mut self, x: &OVector<F, N>) {
+        self.x.copy_from(x);
+    }
+
+    fn params(&self) -> OVector<F, N> {
+        self.x.clone()
+    }
+
+    fn residuals(&self) -> Option<OVector<F, M>> {
+        (self.residuals.borrow_mut())(&self.x)
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, M, N>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use core::{
+        cell::{Cell, RefCell},
+        marker::PhantomData,
+    };
+    use nalgebra::{storage::Owned, DVector, Matrix2, Matrix3x2, Vector2, Vector3, U2, U3};
+
+    use super::{least_squares_numeric, NumericalDifferentiation};
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Himmelblau's function, see the module documentation.
+    struct Himmelblau {
+        p: Vector2<f64>,
+        analytic: bool,
+    }
+
+    impl LeastSquaresProblem<f64, U2, U2> for Himmelblau {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, p: &Vector2<f64>) {
+            self.p.copy_from(p);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.p
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            let [x, y] = [self.p.x, self.p.y];
+            Some(Vector2::new(x * x + y - 11., x + y * y - 7.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            if !self.analytic {
+                return None;
+            }
+            let [x, y] = [self.p.x, self.p.y];
+            Some(Matrix2::new(2. * x, 1., 1., 2. * y))
+        }
+    }
+
+    #[test]
+    fn matches_analytic_jacobian() {
+        let p = Vector2::new(1.5, -0.5);
+        let analytic = Himmelblau { p, analytic: true };
+        let numerical = NumericalDifferentiation::new(Himmelblau { p, analytic: false });
+        assert_relative_eq!(
+            numerical.jacobian().unwrap(),
+            analytic.jacobian().unwrap(),
+            epsilon = 1e-6
+        );
+        // the parameters are restored
+        assert_eq!(numerical.params(), p);
+    }
+
+    #[test]
+    fn report_marks_numerical_jacobian() {
+        let p = Vector2::new(1., 1.);
+        let (problem, report) =
+            LevenbergMarquardt::new().minimize(NumericalDifferentiation::new(Himmelblau {
+                p,
+                analytic: false,
+            }));
+        assert!(report.termination.was_successful());
+        assert!(report.jacobian_is_numerical);
+        let solution = problem.into_inner().p;
+
+        let (problem, report) =
+            LevenbergMarquardt::new().minimize(Himmelblau { p, analytic: true });
+        assert!(report.termination.was_successful());
+        assert!(!report.jacobian_is_numerical);
+        assert_relative_eq!(problem.p, solution, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn closure_recovers_parameters() {
+        // exponential decay y = a exp(-k t), undefined for negative rates
+        let t = DVector::from_fn(20, |i, _| 0.2 * i as f64);
+        let y = t.map(|t| 2.5 * f64::exp(-0.7 * t));
+        let mut evaluations = 0;
+        let (x, report) = least_squares_numeric(
+            Vector2::new(1., 0.1),
+            |x: &Vector2<f64>| {
+                evaluations += 1;
+                if x.y < 0. {
+                    return None;
+                }
+                Some(t.map(|t| x.x * f64::exp(-x.y * t)) - &y)
+            },
+            &LevenbergMarquardt::new(),
+        );
+        assert!(report.termination.was_successful());
+        assert!(report.jacobian_is_numerical);
+        assert_relative_eq!(x, Vector2::new(2.5, 0.7), epsilon = 1e-8);
+        assert!(evaluations >= report.number_of_evaluations);
+    }
+
+    #[test]
+    fn reuses_base_residuals() {
+        let t = DVector::from_fn(20, |i, _| 0.2 * i as f64);
+        let y = t.map(|t| 2.5 * f64::exp(-0.7 * t));
+        let evaluations = Cell::new(0);
+        let residuals = |x: &Vector2<f64>| {
+            evaluations.set(evaluations.get() + 1);
+            Some(t.map(|t| x.x * f64::exp(-x.y * t)) - &y)
+        };
+        let (_, report) =
+            least_squares_numeric(Vector2::new(1., 0.1), residuals, &LevenbergMarquardt::new());
+        assert!(report.termination.was_successful());
+        // every Jacobian only evaluates the two perturbed points
+        assert_eq!(
+            evaluations.get(),
+            report.number_of_evaluations + 2 * report.terminated_at_iteration
+        );
+
+        evaluations.set(0);
+        let problem = NumericalDifferentiation::new(super::ResidualFn {
+            x: Vector2::new(1., 0.1),
+            residuals: RefCell::new(residuals),
+            m: PhantomData,
+        });
+        problem.jacobian().unwrap();
+        assert_eq!(evaluations.get(), 3);
+    }
+
+    /// `$(\cos x, \cos x + y^2, y)$` at a large `$x$` with curvature at scale one.
+    struct Oscillating {
+        p: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U3, U2> for Oscillating {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U3>;
+        type JacobianStorage = Owned<f64, U3, U2>;
+
+        fn set_params(&mut self, p: &Vector2<f64>) {
+            self.p.copy_from(p);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.p
+        }
+
+        fn residuals(&self) -> Option<Vector3<f64>> {
+            let [x, y] = [self.p.x, self.p.y];
+            Some(Vector3::new(x.cos(), x.cos() + y * y, y))
+        }
+
+        fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+            let [x, y] = [self.p.x, self.p.y];
+            Some(Matrix3x2::new(-x.sin(), 0., -x.sin(), 2. * y, 0., 1.))
+        }
+    }
+
+    #[test]
+    fn explicit_steps_override_heuristic() {
+        let p = Vector2::new(1e6, 0.5);
+        let analytic = Oscillating { p }.jacobian().unwrap();
+        let automatic = NumericalDifferentiation::new(Oscillating { p })
+            .jacobian()
+            .unwrap();
+        // the automatic step for x is about 0.015, too coarse for cos
+        let automatic_error = (automatic.column(0) - analytic.column(0)).amax();
+        assert!(automatic_error > 1e-3);
+
+        for fallback in [0., f64::NAN] {
+            let tuned = NumericalDifferentiation::new(Oscillating { p })
+                .with_finite_diff_step(Vector2::new(1e-7, fallback));
+            let jacobian = tuned.jacobian().unwrap();
+            let tuned_error = (jacobian.column(0) - analytic.column(0)).amax();
+            assert!(tuned_error < 1e-3 * automatic_error);
+            // the other column uses the automatic step
+            assert_eq!(jacobian.column(1), automatic.column(1));
+            assert_eq!(tuned.params(), p);
+        }
+    }
+
+    /// The numerical differentiation of [`Slope`], every Jacobian is compared
+    /// with the exact one.
+    struct Checked<S> {
+        problem: NumericalDifferentiation<Slope, S>,
+        max_error: Cell<f64>,
+    }
+
+    /// Fit `$y = 10^3 - t/2$` with `$10^3 + a t + b$`.
+    ///
+    /// The residuals are computed at the scale of the offset, the slope `$a$`
+    /// changes its sign on the way from an initial guess of one.
+    struct Slope {
+        a: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U3, U2> for Slope {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U3>;
+        type JacobianStorage = Owned<f64, U3, U2>;
+
+        fn set_params(&mut self, a: &Vector2<f64>) {
+            self.a.copy_from(a);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.a
+        }
+
+        fn residuals(&self) -> Option<Vector3<f64>> {
+            let [a, b] = [self.a.x, self.a.y];
+            Some(Vector3::from_fn(|i, _| {
+                let t = i as f64 + 1.;
+                1e3 + a * t + b - (1e3 - 0.5 * t)
+            }))
+        }
+
+        fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+            None
+        }
+    }
+
+    impl<S: super::StepSizes<f64>> LeastSquaresProblem<f64, U3, U2> for Checked<S> {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U3>;
+        type JacobianStorage = Owned<f64, U3, U2>;
+
+        fn set_params(&mut self, a: &Vector2<f64>) {
+            self.problem.set_params(a);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.problem.params()
+        }
+
+        fn residuals(&self) -> Option<Vector3<f64>> {
+            self.problem.residuals()
+        }
+
+        fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+            let jacobian = self.problem.jacobian()?;
+            let exact = Matrix3x2::from_fn(|i, j| if j == 0 { i as f64 + 1. } else { 1. });
+            let error = (jacobian - exact).amax();
+            self.max_error.set(self.max_error.get().max(error));
+            Some(jacobian)
+        }
+    }
+
+    #[test]
+    fn relative_step_near_zero() {
+        let eps = f64::EPSILON.sqrt();
+        for a in [1e-12, 0.] {
+            let p = Vector2::new(a, 1.);
+            let automatic = Checked {
+                problem: NumericalDifferentiation::new(Slope { a: p }),
+                max_error: Cell::new(0.),
+            };
+            let bounded = Checked {
+                problem: NumericalDifferentiation::new(Slope { a: p }).with_relative_step(eps, eps),
+                max_error: Cell::new(0.),
+            };
+            automatic.jacobian().unwrap();
+            bounded.jacobian().unwrap();
+            assert!(bounded.max_error.get() < 1e-4);
+            if a != 0. {
+                // the automatic step is about 1.5e-20, below the rounding of 1e3
+                assert!(automatic.max_error.get() > 1.);
+            }
+        }
+
+        let problem = Checked {
+            problem: NumericalDifferentiation::new(Slope {
+                a: Vector2::new(1., 0.),
+            })
+            .with_relative_step(eps, eps),
+            max_error: Cell::new(0.),
+        };
+        let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+        assert!(report.termination.was_successful());
+        assert!(problem.max_error.get() < 1e-4);
+        assert_relative_eq!(problem.params(), Vector2::new(-0.5, 0.), epsilon = 1e-9);
+    }
+}
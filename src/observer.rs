@@ -0,0 +1,172 @@
+//! Monitoring of the iterations without `std`.
+use alloc::vec::Vec;
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, OVector, RealField};
+
+/// The state of the minimization after an iteration.
+///
+/// Passed to [`Observer::on_iteration`](trait.Observer.html#tymethod.on_iteration).
+#[derive(Debug)]
+pub struct LMState<'s, F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The number of the iteration, starting at one.
+    pub iteration: usize,
+    /// The number of residual evaluations so far.
+    pub number_of_evaluations: usize,
+    /// The objective function `$f(\vec{x})$` at the current parameters.
+    pub objective_function: F,
+    /// The current parameters `$\vec{x}$`.
+    pub params: &'s [F],
+    /// The diagonal of the scaling matrix `$\mathbf{D}$`.
+    pub diag: &'s OVector<F, N>,
+    /// The trust-region radius `$\Delta$`.
+    pub delta: F,
+    /// The Levenberg-Marquardt parameter `$\lambda$` of the last step.
+    pub lambda: F,
+    /// The number of values of `$\lambda$` tried by the search for the last
+    /// step, like the inner iterations of `LMPAR` in `MINPACK`.
+    ///
+    /// This is zero if the Gauss-Newton step was in the trust region and at
+    /// most [`with_max_lambda_iterations`](struct.LevenbergMarquardt.html#method.with_max_lambda_iterations)
+    /// otherwise. A count which is often high signals a hard subproblem, for
+    /// example a very ill-conditioned Jacobian or steps far outside of the
+    /// trust region, then a smaller `stepbound` may help.
+    pub lambda_iterations: usize,
+    /// The ratio which the `gtol` test compared against `gtol` in this
+    /// iteration, see
+    /// [`MinimizationReport::gradient_norm`](struct.MinimizationReport.html#structfield.gradient_norm).
+    pub gradient_norm: F,
+    /// The relative reduction of the objective function predicted by the model
+    /// for the last trust-region step `$\vec{p}$`.
+    pub predicted_reduction: F,
+    /// The actual relative reduction `$1 - f(\vec{x} - \vec{p}) / f(\vec{x})$`
+    /// of the last trust-region step.
+    ///
+    /// This is `-1` if the objective function grew by more than a factor of 100.
+    pub actual_reduction: F,
+    /// A rough estimate in `$[0, 1]$` of how far the minimization has come,
+    /// for example to show a progress bar.
+    ///
+    /// This is a heuristic, it is not guaranteed to increase and the
+    /// minimization may terminate at any value. The reduction of the gradient
+    /// norm `$\|\mathbf{J}^\top\vec{r}\|$` since the first iteration is
+    /// compared against `gtol` on a logarithmic scale, the evaluations so
+    /// far against the budget set with
+    /// [`with_max_evaluations`](struct.LevenbergMarquardt.html#method.with_max_evaluations)
+    /// and the iterations so far against the limit set with
+    /// [`with_max_iterations`](struct.LevenbergMarquardt.html#method.with_max_iterations).
+    /// The largest available estimate is used. `None` if the `gtol` test, the
+    /// evaluation budget and the iteration limit are all disabled.
+    pub estimated_progress: Option<F>,
+    /// Set if the minimization terminated in this iteration.
+    pub terminated: bool,
+}
+
+impl<'s, F: RealField + Copy, N: Dim> LMState<'s, F, N>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The gain ratio of actual and predicted reduction of the last trust-region step.
+    ///
+    /// The step is accepted if the ratio is at least `$10^{-4}$`, see
+    /// [`with_acceptance_ratio`](struct.LevenbergMarquardt.html#method.with_acceptance_ratio).
+    /// The trust region shrinks for a ratio of at most `$\frac{1}{4}$` and
+    /// grows for a ratio of at least `$\frac{3}{4}$`. Zero if no reduction was
+    /// predicted.
+    pub fn gain_ratio(&self) -> F {
+        if self.predicted_reduction.is_zero() {
+            F::zero()
+        } else {
+            self.actual_reduction / self.predicted_reduction
+        }
+    }
+}
+
+/// A hook which is called after every iteration.
+///
+/// Attach it with [`LM::with_observer`](struct.LM.html#method.with_observer).
+/// It only needs `core`, so it can be used to monitor a minimization on
+/// embedded targets, for example by writing to a fixed-capacity buffer.
+pub trait Observer<F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Called once per iteration, after the step was taken or the iteration
+    /// terminated the minimization.
+    fn on_iteration(&mut self, state: &LMState<'_, F, N>);
+}
+
+/// An observer which records the objective function, `$\lambda$`, the inner
+/// iterations of its search and the gradient norm of every iteration.
+///
+/// The series are meant for convergence plots, each one is returned as the
+/// iteration numbers together with the values, ready for a plotting crate.
+/// Unlike [`Observer`](trait.Observer.html) in general this allocates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trajectory<F> {
+    iterations: Vec<usize>,
+    objective_function: Vec<F>,
+    lambda: Vec<F>,
+    lambda_iterations: Vec<usize>,
+    gradient_norm: Vec<F>,
+}
+
+impl<F: RealField + Copy> Trajectory<F> {
+    pub fn new() -> Self {
+        Self {
+            iterations: Vec::new(),
+            objective_function: Vec::new(),
+            lambda: Vec::new(),
+            lambda_iterations: Vec::new(),
+            gradient_norm: Vec::new(),
+        }
+    }
+
+    /// The number of recorded iterations.
+    pub fn len(&self) -> usize {
+        self.iterations.len()
+    }
+
+    /// Whether no iteration was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.iterations.is_empty()
+    }
+
+    /// The objective function `$f(\vec{x})$` after every iteration.
+    pub fn objective_series(&self) -> (Vec<usize>, Vec<F>) {
+        (self.iterations.clone(), self.objective_function.clone())
+    }
+
+    /// The Levenberg-Marquardt parameter `$\lambda$` of every iteration.
+    pub fn lambda_series(&self) -> (Vec<usize>, Vec<F>) {
+        (self.iterations.clone(), self.lambda.clone())
+    }
+
+    /// The inner iterations of the search for `$\lambda$` of every iteration, see
+    /// [`LMState::lambda_iterations`](struct.LMState.html#structfield.lambda_iterations).
+    pub fn lambda_iterations_series(&self) -> (Vec<usize>, Vec<usize>) {
+        (self.iterations.clone(), self.lambda_iterations.clone())
+    }
+
+    /// The scaled gradient norm of every iteration, see
+    /// [`MinimizationReport::gradient_norm`](struct.MinimizationReport.html#structfield.gradient_norm).
+    pub fn gradient_norm_series(&self) -> (Vec<usize>, Vec<F>) {
+        (self.iterations.clone(), self.gradient_norm.clone())
+    }
+}
+
+impl<F, N> Observer<F, N> for Trajectory<F>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn on_iteration(&mut self, state: &LMState<'_, F, N>) {
+        self.iterations.push(state.iteration);
+        self.objective_function.push(state.objective_function);
+        self.lambda.push(state.lambda);
+        self.lambda_iterations.push(state.lambda_iterations);
+        self.gradient_norm.push(state.gradient_norm);
+    }
+}
@@ -0,0 +1,208 @@
+//! Least squares problems with residuals computed on the rayon thread pool.
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, Matrix, OVector, RealField, Vector};
+use rayon::prelude::*;
+
+use crate::LeastSquaresProblem;
+
+/// A least squares problem whose residuals can be computed independently.
+///
+/// This is minimized with
+/// [`LevenbergMarquardt::minimize_parallel`](struct.LevenbergMarquardt.html#method.minimize_parallel).
+/// Use it if computing the residuals is the bottleneck. The components
+/// `$r_i(\vec{x})$` are distributed over the rayon thread pool, the Jacobian
+/// is still computed with
+/// [`LeastSquaresProblem::jacobian`](trait.LeastSquaresProblem.html#tymethod.jacobian).
+///
+/// The minimization needs the residual vector once to allocate it, this first
+/// evaluation calls
+/// [`LeastSquaresProblem::residuals`](trait.LeastSquaresProblem.html#tymethod.residuals).
+/// All further evaluations use [`residuals_parallel`](#method.residuals_parallel).
+pub trait ParallelResiduals<F, M, N>: LeastSquaresProblem<F, M, N> + Sync
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+{
+    /// Compute the residual `$r_i$` at the current parameters.
+    ///
+    /// This is called concurrently for different `i`.
+    fn residual(&self, i: usize) -> Option<F>;
+
+    /// Compute the residual vector into `out` in parallel.
+    ///
+    /// Returns `false` if any residual could not be computed, the contents of
+    /// `out` are unspecified then.
+    fn residuals_parallel(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        out.as_mut_slice()
+            .par_iter_mut()
+            .enumerate()
+            .all(|(i, r)| match self.residual(i) {
+                Some(residual) => {
+                    *r = residual;
+                    true
+                }
+                None => false,
+            })
+    }
+}
+
+/// The problem with the evaluations after the first one in parallel.
+pub(crate) struct ParallelEvaluation<P> {
+    problem: P,
+}
+
+impl<P> ParallelEvaluation<P> {
+    pub(crate) fn new(problem: P) -> Self {
+        Self { problem }
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, M, N> for ParallelEvaluation<P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: ParallelResiduals<F, M, N>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        self.problem.residuals()
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        self.problem.residuals_parallel(out)
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        self.problem.jacobian()
+    }
+
+    fn jacobian_with_residuals(
+        &self,
+        residuals: &Vector<F, M, Self::ResidualStorage>,
+    ) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        self.problem.jacobian_with_residuals(residuals)
+    }
+
+    fn hessian_vector_product(&self, v: &OVector<F, N>) -> Option<OVector<F, N>>
+    where
+        DefaultAllocator: Allocator<F, N>,
+    {
+        self.problem.hessian_vector_product(v)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DVector, Dynamic, OMatrix, Vector2, U2};
+
+    use super::ParallelResiduals;
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Fit `$y = a e^{-kt}$` to many samples.
+    struct Decay {
+        t: DVector<f64>,
+        y: DVector<f64>,
+        params: Vector2<f64>,
+    }
+
+    impl Decay {
+        fn new() -> Self {
+            let t = DVector::from_fn(500, |i, _| 0.01 * i as f64);
+            let y = t.map(|t| 3. * f64::exp(-0.7 * t) + 0.01 * f64::sin(40. * t));
+            Self {
+                t,
+                y,
+                params: Vector2::new(1., 0.),
+            }
+        }
+    }
+
+    impl LeastSquaresProblem<f64, Dynamic, U2> for Decay {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            (0..self.t.len())
+                .map(|i| self.residual(i))
+                .collect::<Option<alloc::vec::Vec<_>>>()
+                .map(DVector::from_vec)
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U2>> {
+            let [a, k] = [self.params.x, self.params.y];
+            Some(OMatrix::<f64, Dynamic, U2>::from_fn(
+                self.t.len(),
+                |i, j| {
+                    let e = f64::exp(-k * self.t[i]);
+                    if j == 0 {
+                        e
+                    } else {
+                        -a * self.t[i] * e
+                    }
+                },
+            ))
+        }
+    }
+
+    impl ParallelResiduals<f64, Dynamic, U2> for Decay {
+        fn residual(&self, i: usize) -> Option<f64> {
+            let [a, k] = [self.params.x, self.params.y];
+            Some(a * f64::exp(-k * self.t[i]) - self.y[i])
+        }
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let mut problem = Decay::new();
+        problem.set_params(&Vector2::new(2., 0.3));
+        let sequential = problem.residuals().unwrap();
+        let mut parallel = DVector::zeros(sequential.nrows());
+        assert!(problem.residuals_parallel(&mut parallel));
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn fit_matches_sequential() {
+        let lm = LevenbergMarquardt::new();
+        let (parallel, report) = lm.minimize_parallel(Decay::new());
+        assert!(report.termination.was_successful());
+        let (sequential, expected) = lm.minimize(Decay::new());
+        assert_eq!(parallel.params, sequential.params);
+        assert_eq!(report.number_of_evaluations, expected.number_of_evaluations);
+        assert_relative_eq!(parallel.params.y, 0.7, epsilon = 1e-2);
+    }
+}
@@ -0,0 +1,206 @@
+//! Soft linear equality constraints.
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::Allocator,
+    storage::{Owned, RawStorage},
+    Const, DefaultAllocator, Dim, DimAdd, DimSum, OMatrix, OVector, RealField, Vector,
+};
+
+use crate::LeastSquaresProblem;
+
+/// A problem with additional residuals penalizing a linear constraint.
+///
+/// For the constraint `$\mathbf{A}\vec{x} = \vec{b}$` with `$k$` rows and a
+/// weight `$w \geq 0$`, the `$k$` residuals
+/// ```math
+///   \sqrt{w}\,(\mathbf{A}\vec{x} - \vec{b})
+/// ```
+/// are appended to the residuals of the wrapped problem, with the Jacobian rows
+/// `$\sqrt{w}\,\mathbf{A}$`. The objective function is then
+/// ```math
+///   \frac{1}{2}\|\vec{r}(\vec{x})\|^2 + \frac{w}{2}\|\mathbf{A}\vec{x} - \vec{b}\|^2.
+/// ```
+/// The constraint is only fulfilled exactly in the limit `$w \to \infty$`,
+/// a finite weight trades it off against the fit of the original residuals.
+/// Very large weights make the problem badly conditioned.
+///
+/// Create it with [`LeastSquaresProblem::with_linear_penalty`](trait.LeastSquaresProblem.html#method.with_linear_penalty).
+pub struct LinearPenalty<P, M, A, B> {
+    problem: P,
+    a: A,
+    b: B,
+    marker: PhantomData<M>,
+}
+
+impl<P, M, A, B> LinearPenalty<P, M, A, B> {
+    pub(crate) fn new(problem: P, a: A, b: B) -> Self {
+        Self {
+            problem,
+            a,
+            b,
+            marker: PhantomData,
+        }
+    }
+
+    /// The wrapped problem.
+    pub fn inner(&self) -> &P {
+        &self.problem
+    }
+
+    /// Extract the wrapped problem.
+    pub fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, K, N, P> LeastSquaresProblem<F, DimSum<M, K>, N>
+    for LinearPenalty<P, M, OMatrix<F, K, N>, OVector<F, K>>
+where
+    F: RealField + Copy,
+    N: Dim,
+    M: Dim + DimAdd<K>,
+    K: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, K, N>
+        + Allocator<F, K>
+        + Allocator<F, DimSum<M, K>>
+        + Allocator<F, DimSum<M, K>, N>,
+{
+    type ResidualStorage = Owned<F, DimSum<M, K>>;
+    type JacobianStorage = Owned<F, DimSum<M, K>, N>;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<OVector<F, DimSum<M, K>>> {
+        let residuals = self.problem.residuals()?;
+        let penalty = &self.a * self.problem.params() - &self.b;
+        let (m, k) = (residuals.data.shape().0, penalty.data.shape().0);
+        let rows = m.value();
+        Some(OVector::from_fn_generic(m.add(k), Const::<1>, |i, _| {
+            if i < rows {
+                residuals[i]
+            } else {
+                penalty[i - rows]
+            }
+        }))
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, DimSum<M, K>, N>> {
+        let jacobian = self.problem.jacobian()?;
+        let (m, n) = jacobian.data.shape();
+        let k = self.a.data.shape().0;
+        let rows = m.value();
+        Some(OMatrix::from_fn_generic(m.add(k), n, |i, j| {
+            if i < rows {
+                jacobian[(i, j)]
+            } else {
+                self.a[(i - rows, j)]
+            }
+        }))
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals() + self.b.nrows()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, Matrix1x2, Matrix2, Vector1, Vector2, U2};
+
+    use super::LinearPenalty;
+    use crate::{differentiate_numerically, LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Direct measurements `$x_1 = 1$` and `$x_2 = 2$`.
+    struct Measurements {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U2, U2> for Measurements {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            Some(self.params - Vector2::new(1., 2.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            Some(Matrix2::identity())
+        }
+    }
+
+    fn sum_constrained(
+        weight: f64,
+    ) -> LinearPenalty<Measurements, U2, Matrix1x2<f64>, Vector1<f64>> {
+        Measurements {
+            params: Vector2::new(0., 0.),
+        }
+        .with_linear_penalty(Matrix1x2::new(1., 1.), Vector1::new(4.), weight)
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let mut problem = sum_constrained(10.);
+        problem.set_params(&Vector2::new(0.5, -1.));
+        let jacobian_numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(
+            problem.jacobian().unwrap(),
+            jacobian_numerical,
+            epsilon = 1e-6
+        );
+    }
+
+    #[test]
+    fn weight_drives_sum_to_target() {
+        let mut previous = f64::INFINITY;
+        for weight in [0., 1., 100., 1e6] {
+            let (problem, report) = LevenbergMarquardt::new().minimize(sum_constrained(weight));
+            assert!(report.termination.was_successful());
+            let violation = (problem.params().sum() - 4.).abs();
+            // minimum of the penalized objective in closed form
+            assert_relative_eq!(violation, 1. / (1. + 2. * weight), epsilon = 1e-10);
+            assert!(violation < previous);
+            previous = violation;
+        }
+        assert!(previous < 1e-6);
+    }
+
+    #[test]
+    fn penalty_raises_effective_dof() {
+        for weight in [0., 1., 100., 1e6] {
+            let (_, report) = LevenbergMarquardt::new()
+                .with_qr_retention(true)
+                .minimize(sum_constrained(weight));
+            assert!(report.termination.was_successful());
+            // the penalty row has no leverage of its own
+            assert_eq!(report.leverages.as_ref().unwrap().len(), 2);
+            // trace of (I + w 11^T)^-1 is 2 - 2w / (1 + 2w)
+            assert_relative_eq!(
+                report.effective_dof().unwrap(),
+                2. * weight / (1. + 2. * weight),
+                epsilon = 1e-12
+            );
+        }
+    }
+}
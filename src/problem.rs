@@ -1,6 +1,9 @@
+use crate::{LinearPenalty, ParamTransform, Weighted};
+use alloc::vec::Vec;
 use nalgebra::{
+    allocator::Allocator,
     storage::{IsContiguous, RawStorageMut, Storage},
-    ComplexField, Dim, Matrix, Vector,
+    ComplexField, DefaultAllocator, Dim, Matrix, OMatrix, OVector, RealField, Vector,
 };
 
 /// A least squares minimization problem.
@@ -17,7 +20,7 @@ where
     /// Storage type used for the residuals. Use `nalgebra::storage::Owned<F, M>`
     /// if you want to use `VectorN` or `MatrixMN`.
     type ResidualStorage: RawStorageMut<F, M> + Storage<F, M> + IsContiguous;
-    type JacobianStorage: RawStorageMut<F, M, N> + Storage<F, M, N> + IsContiguous;
+    type JacobianStorage: RawStorageMut<F, M, N> + Storage<F, M, N> + IsContiguous + Clone;
     type ParameterStorage: RawStorageMut<F, N> + Storage<F, N> + IsContiguous + Clone;
 
     /// Set the stored parameters `$\vec{x}$`.
@@ -29,6 +32,216 @@ where
     /// Compute the residual vector.
     fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>>;
 
+    /// Compute the residual vector into a buffer.
+    ///
+    /// The minimization calls this for all evaluations after the first one,
+    /// with a buffer holding the residuals of a previous evaluation.
+    /// Implement it to avoid allocating a new residual vector every time.
+    /// Returns `false` if the residuals could not be computed.
+    ///
+    /// The default implementation replaces `out` with the result of
+    /// [`residuals`](#tymethod.residuals).
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        match self.residuals() {
+            Some(residuals) => {
+                *out = residuals;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Compute the Jacobian of the residual vector.
+    ///
+    /// The minimization calls this once at the initial parameters and once
+    /// after every accepted step. Rejected steps only change `$\lambda$` and
+    /// reuse the Jacobian.
     fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>>;
+
+    /// Compute the Jacobian with the residuals at the current parameters known.
+    ///
+    /// The minimization calls this instead of [`jacobian`](#tymethod.jacobian)
+    /// with the residuals it already computed at the current parameters.
+    /// Finite differences use them as the base point instead of evaluating the
+    /// residuals again, see
+    /// [`NumericalDifferentiation`](struct.NumericalDifferentiation.html).
+    ///
+    /// The default implementation ignores `residuals` and calls `jacobian`.
+    fn jacobian_with_residuals(
+        &self,
+        residuals: &Vector<F, M, Self::ResidualStorage>,
+    ) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let _ = residuals;
+        self.jacobian()
+    }
+
+    /// Compute the product `$\nabla^2 f(\vec{x})\,\vec{v}$` of the Hessian of the
+    /// objective function with a vector.
+    ///
+    /// The Hessian of `$f = \frac{1}{2}\|\vec{r}\|^2$` is
+    /// ```math
+    ///   \nabla^2 f = \mathbf{J}^\top\mathbf{J} + \sum_{i=1}^m r_i \nabla^2 r_i,
+    /// ```
+    /// the Gauss-Newton approximation only keeps the first term. This is only used
+    /// if enabled with
+    /// [`with_hessian_correction`](struct.LevenbergMarquardt.html#method.with_hessian_correction).
+    /// The default implementation returns `None`.
+    fn hessian_vector_product(&self, v: &OVector<F, N>) -> Option<OVector<F, N>>
+    where
+        DefaultAllocator: Allocator<F, N>,
+    {
+        let _ = v;
+        None
+    }
+
+    /// Whether the Jacobian is only a numerical approximation.
+    ///
+    /// This is recorded in the [`MinimizationReport`](struct.MinimizationReport.html).
+    /// The default is `false`, [`NumericalDifferentiation`](struct.NumericalDifferentiation.html)
+    /// returns `true`.
+    fn jacobian_is_numerical(&self) -> bool {
+        false
+    }
+
+    /// The number of residuals at the end which are penalties rather than data.
+    ///
+    /// These are left out of the
+    /// [`leverages`](struct.MinimizationReport.html#structfield.leverages) and
+    /// the [`effective_dof`](struct.MinimizationReport.html#method.effective_dof)
+    /// in the report. The default is zero,
+    /// [`LinearPenalty`](struct.LinearPenalty.html) adds the rows of its constraint.
+    fn number_of_penalty_residuals(&self) -> usize {
+        0
+    }
+
+    /// The rows of the Jacobian which changed since the previous evaluation.
+    ///
+    /// The minimization calls this right after every
+    /// [`jacobian_with_residuals`](#method.jacobian_with_residuals). If it
+    /// returns the indices of the rows which differ from the previous Jacobian,
+    /// the QR factorization of the previous Jacobian is updated with Givens
+    /// rotations for these rows instead of being computed again. This pays off
+    /// if only a few of many rows change. The returned rows must cover all
+    /// changes, the result is wrong otherwise. The number of updates is
+    /// recorded in
+    /// [`MinimizationReport::incremental_factorizations`](struct.MinimizationReport.html#structfield.incremental_factorizations).
+    ///
+    /// The default implementation returns `None`, every Jacobian is factorized
+    /// from scratch.
+    fn changed_jacobian_rows(&self) -> Option<&[usize]> {
+        None
+    }
+
+    /// Optimize over transformed parameters.
+    ///
+    /// The optimizer works with `$\vec{y}$` and this problem sees
+    /// `$\vec{x} = \texttt{transform}(\vec{y})$`. The closure `jac_factor`
+    /// has to return the diagonal of the Jacobian of `transform`, that is the
+    /// vector of derivatives `$\partial x_i / \partial y_i$`.
+    ///
+    /// See [`ParamTransform`](struct.ParamTransform.html) for details.
+    fn with_param_transform<T, J>(
+        mut self,
+        transform: T,
+        jac_factor: J,
+    ) -> ParamTransform<Self, Vector<F, N, Self::ParameterStorage>, T, J>
+    where
+        Self: Sized,
+        T: Fn(&Vector<F, N, Self::ParameterStorage>) -> Vector<F, N, Self::ParameterStorage>,
+        J: Fn(&Vector<F, N, Self::ParameterStorage>) -> Vector<F, N, Self::ParameterStorage>,
+    {
+        let params = self.params();
+        self.set_params(&transform(&params));
+        ParamTransform::new(self, params, transform, jac_factor)
+    }
+
+    /// Add a soft linear equality constraint `$\mathbf{A}\vec{x} = \vec{b}$`.
+    ///
+    /// The residuals `$\sqrt{w}\,(\mathbf{A}\vec{x} - \vec{b})$` are appended
+    /// to the residuals of this problem. The constraint holds exactly only in
+    /// the limit of an infinite `weight`.
+    ///
+    /// See [`LinearPenalty`](struct.LinearPenalty.html) for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is negative or not finite.
+    fn with_linear_penalty<K>(
+        self,
+        a: OMatrix<F, K, N>,
+        b: OVector<F, K>,
+        weight: F,
+    ) -> LinearPenalty<Self, M, OMatrix<F, K, N>, OVector<F, K>>
+    where
+        Self: Sized,
+        F: RealField,
+        K: Dim,
+        DefaultAllocator: Allocator<F, K, N> + Allocator<F, K>,
+    {
+        assert!(
+            weight >= F::zero() && weight.is_finite(),
+            "weight must be non-negative and finite"
+        );
+        let scale = weight.sqrt();
+        LinearPenalty::new(self, a * scale, b * scale)
+    }
+
+    /// Weigh the residuals individually.
+    ///
+    /// Residual `$r_i$` contributes `$\frac{1}{2}w_i r_i^2$` to the objective
+    /// function. See [`Weighted`](struct.Weighted.html) for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a weight is negative or not finite.
+    fn with_weights(self, weights: OVector<F, M>) -> Weighted<Self, OVector<F, M>>
+    where
+        Self: Sized,
+        F: RealField,
+        DefaultAllocator: Allocator<F, M>,
+    {
+        assert!(
+            weights.iter().all(|w| *w >= F::zero() && w.is_finite()),
+            "weights must be non-negative and finite"
+        );
+        Weighted::new(self, weights.map(|w| w.sqrt()))
+    }
+
+    /// Weigh the residuals by the standard deviations of the measurements.
+    ///
+    /// Residual `$r_i$` is divided by `$\sigma_i$`, this is the same as
+    /// [`with_weights`](#method.with_weights) with the weights `$1/\sigma_i^2$`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a standard deviation is not positive or not finite.
+    fn with_sigmas(self, sigmas: OVector<F, M>) -> Weighted<Self, OVector<F, M>>
+    where
+        Self: Sized,
+        F: RealField,
+        DefaultAllocator: Allocator<F, M>,
+    {
+        assert!(
+            sigmas.iter().all(|s| *s > F::zero() && s.is_finite()),
+            "sigmas must be positive and finite"
+        );
+        self.with_weights(sigmas.map(|s| F::one() / (s * s)))
+    }
+
+    /// Exclude residuals from the minimization.
+    ///
+    /// The residual `$r_i$` and row `$i$` of the Jacobian are set to zero
+    /// unless `mask[i]` is set, so the objective function and the convergence
+    /// tests ignore the masked rows. This is cheaper than building a smaller
+    /// problem, for example for every fold of a cross-validation. The masked
+    /// rows still count for the number of residuals `$m$`.
+    ///
+    /// If the length of `mask` does not match the number of residuals, the
+    /// residuals and the Jacobian cannot be computed.
+    fn with_active_residuals(self, mask: &[bool]) -> Weighted<Self, Vec<bool>>
+    where
+        Self: Sized,
+    {
+        Weighted::new(self, mask.to_vec())
+    }
 }
@@ -0,0 +1,215 @@
+//! Profiles of the objective function along one parameter.
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::{Allocator, Reallocator},
+    convert,
+    storage::Owned,
+    DVector, DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Dynamic, OMatrix, RealField,
+    Vector,
+};
+use num_traits::Float;
+
+use crate::lm::LevenbergMarquardt;
+use crate::LeastSquaresProblem;
+
+/// A problem with the parameter `index` frozen at `value`.
+///
+/// The optimizer only sees the other `$n - 1$` parameters.
+struct Frozen<'p, O, N, F> {
+    problem: &'p mut O,
+    index: usize,
+    value: F,
+    marker: PhantomData<N>,
+}
+
+impl<'p, F, M, N, O> LeastSquaresProblem<F, M, Dynamic> for Frozen<'p, O, N, F>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, M, Dynamic>,
+{
+    type ResidualStorage = O::ResidualStorage;
+    type JacobianStorage = Owned<F, M, Dynamic>;
+    type ParameterStorage = Owned<F, Dynamic>;
+
+    fn set_params(&mut self, y: &DVector<F>) {
+        let mut x = self.problem.params();
+        for (j, x) in x.iter_mut().enumerate() {
+            *x = match j {
+                j if j < self.index => y[j],
+                j if j > self.index => y[j - 1],
+                _ => self.value,
+            };
+        }
+        self.problem.set_params(&x);
+    }
+
+    fn params(&self) -> DVector<F> {
+        let x = self.problem.params();
+        let free = x
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != self.index)
+            .map(|(_, x)| *x)
+            .collect();
+        crate::constrained::dvector(free)
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        self.problem.residuals()
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        self.problem.residuals_into(out)
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, M, Dynamic>> {
+        let jacobian = self.problem.jacobian()?;
+        let (m, n) = jacobian.shape_generic();
+        if n.value() <= self.index {
+            return None;
+        }
+        let index = self.index;
+        Some(OMatrix::from_fn_generic(
+            m,
+            Dynamic::new(n.value() - 1),
+            |i, j| jacobian[(i, if j < index { j } else { j + 1 })],
+        ))
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+/// Compute the profile of the objective function along parameter `index`.
+///
+/// For every value `$v$` in `grid` the parameter `$x_{\mathtt{index}}$` is
+/// frozen at `$v$` and the other parameters are minimized with `config`,
+/// returning the pairs `$(v, \min_{x_j, j \neq \mathtt{index}} f(\vec{x}))$`.
+/// This is the profile likelihood of the parameter for Gaussian errors: with
+/// the minimum `$f^*$` of the full problem, the values with
+/// `$f \leq f^* + \frac{1}{2}\chi^2_{1}(1 - \alpha)$` form a confidence
+/// interval of level `$1 - \alpha$`, for example `$f \leq f^* + \frac{1}{2}$`
+/// for about 68%.
+///
+/// The minimization for a grid value starts at the solution of the previous
+/// value, so a sorted grid follows the valley of the objective function. The
+/// objective function is `NaN` if the minimization failed. The parameters of
+/// `problem` are restored afterwards.
+///
+/// # Panics
+///
+/// Panics if `index` is not below the number of parameters `$n$`.
+pub fn profile<F, M, N, O>(
+    problem: &mut O,
+    index: usize,
+    grid: &[F],
+    config: &LevenbergMarquardt<F>,
+) -> Vec<(F, F)>
+where
+    F: RealField + Float,
+    M: DimMin<Dynamic> + DimMax<Dynamic>,
+    N: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, N>
+        + Allocator<F, Dynamic>
+        + Allocator<usize, Dynamic>
+        + Allocator<F, M, Dynamic>
+        + Reallocator<F, M, Dynamic, DimMaximum<M, Dynamic>, Dynamic>,
+{
+    let params = problem.params();
+    assert!(index < params.nrows(), "profile index out of range");
+    let profile = grid
+        .iter()
+        .map(|&value| {
+            let mut frozen = Frozen {
+                problem: &mut *problem,
+                index,
+                value,
+                marker: PhantomData,
+            };
+            let free = frozen.params();
+            frozen.set_params(&free);
+            let objective = if free.nrows() == 0 {
+                // nothing to minimize, evaluate at the grid value
+                frozen
+                    .residuals()
+                    .map_or(F::nan(), |r| r.norm_squared() * convert(0.5))
+            } else {
+                let (_, report) = config.minimize(frozen);
+                if report.termination.was_failure() {
+                    F::nan()
+                } else {
+                    report.objective_function
+                }
+            };
+            (value, objective)
+        })
+        .collect();
+    problem.set_params(&params);
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, Matrix3x2, Vector2, Vector3, U2, U3};
+
+    use super::profile;
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// The linear residuals `$(x_1 - 1, x_2 - 2, x_1 + x_2 - 4)^\top$`.
+    struct Quadratic {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U3, U2> for Quadratic {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U3>;
+        type JacobianStorage = Owned<f64, U3, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector3<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            Some(Vector3::new(a - 1., b - 2., a + b - 4.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+            Some(Matrix3x2::new(1., 0., 0., 1., 1., 1.))
+        }
+    }
+
+    #[test]
+    fn matches_analytic_parabola() {
+        let mut problem = Quadratic {
+            params: Vector2::new(0., 0.),
+        };
+        let grid: Vec<f64> = (0..9).map(|i| 0.5 * i as f64 - 1.).collect();
+        let curve = profile(&mut problem, 0, &grid, &LevenbergMarquardt::new());
+        assert_eq!(curve.len(), grid.len());
+        // minimizing over b: b = 3 - a / 2 and f = ((a - 1)^2 + 2 (1 - a / 2)^2) / 2
+        for (&v, &(a, f)) in grid.iter().zip(curve.iter()) {
+            assert_eq!(a, v);
+            let expected = ((a - 1.) * (a - 1.) + 2. * (1. - a / 2.) * (1. - a / 2.)) / 2.;
+            assert_relative_eq!(f, expected, epsilon = 1e-10);
+        }
+        // the parameters are restored
+        assert_eq!(problem.params, Vector2::new(0., 0.));
+    }
+}
@@ -5,14 +5,17 @@
 //! solved in the LM algorithm.
 #![allow(clippy::excessive_precision)]
 
+use alloc::{vec, vec::Vec};
 #[cfg(test)]
 use approx::assert_relative_eq;
+use core::cmp::Ordering;
 use core::iter::repeat;
 use nalgebra::{
     allocator::{Allocator, Reallocator},
     convert,
     storage::{IsContiguous, RawStorage, RawStorageMut, Storage},
-    DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Matrix, OMatrix, OVector, Vector,
+    DMatrix, DVector, DefaultAllocator, Dim, DimMax, DimMaximum, DimMin, Matrix, OMatrix, OVector,
+    Vector,
 };
 use num_traits::Float;
 
@@ -57,7 +60,16 @@ where
     DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N> + Allocator<usize, N>,
 {
     /// Create a pivoted QR decomposition of a matrix `$\mathbf{A}\in\R^{m\times n}$`.
-    pub fn new(mut a: Matrix<F, M, N, S>) -> Self {
+    pub fn new(a: Matrix<F, M, N, S>) -> Self {
+        Self::with_pivoting(a, true)
+    }
+
+    /// Create a QR decomposition, with column pivoting only if `pivoting` is set.
+    ///
+    /// Without pivoting `$\mathbf{P}$` is the identity and the partial column
+    /// norms are not updated. The decomposition is then only rank revealing
+    /// if `$\mathbf{A}$` has full column rank.
+    pub fn with_pivoting(mut a: Matrix<F, M, N, S>, pivoting: bool) -> Self {
         // The implementation is based more or less on LAPACK's "xGEQPF"
         let (m, n) = a.data.shape();
         let u1 = Dim::from_usize(1);
@@ -68,7 +80,11 @@ where
         let mut permutation = OVector::<usize, N>::from_iterator_generic(n, u1, 0..n.value());
         for j in 0..m.min(n).value() {
             // pivot
-            let kmax = r_diag.slice_range(j.., ..).imax() + j;
+            let kmax = if pivoting {
+                r_diag.slice_range(j.., ..).imax() + j
+            } else {
+                j
+            };
             if kmax != j {
                 a.swap_columns(j, kmax);
                 permutation.swap_rows(j, kmax);
@@ -100,7 +116,7 @@ where
                 col.axpy(-(dot(&col, &w_j) / w_j[0]), &w_j, F::one());
                 // update partial column norms
                 // see "Lapack Working Note 176"
-                if r_diag[k].is_zero() {
+                if !pivoting || r_diag[k].is_zero() {
                     continue;
                 }
                 let r_diagk = unsafe { r_diag.vget_unchecked_mut(k) };
@@ -124,14 +140,44 @@ where
         }
     }
 
+    /// Extract `$\mathbf{R}$` and the permutation.
+    ///
+    /// Entry `$j$` of the permutation is the column of `$\mathbf{A}$` which
+    /// corresponds to column `$j$` of `$\mathbf{R}$`.
+    pub fn r_factor(&self) -> (DMatrix<F>, Vec<usize>) {
+        let (m, n) = self.qr.shape();
+        let r = DMatrix::from_fn(m.min(n), n, |i, j| match i.cmp(&j) {
+            Ordering::Less => self.qr[(i, j)],
+            Ordering::Equal => self.r_diag[j],
+            Ordering::Greater => F::zero(),
+        });
+        (r, self.permutation.iter().copied().collect())
+    }
+
+    /// Like [`into_least_squares_diagonal_problem_in_place`](#method.into_least_squares_diagonal_problem_in_place)
+    /// but `$\vec{b}$` is consumed.
+    #[cfg(test)]
+    pub fn into_least_squares_diagonal_problem<QS>(
+        self,
+        mut b: Vector<F, M, QS>,
+    ) -> LinearLeastSquaresDiagonalProblem<F, M, N>
+    where
+        QS: RawStorageMut<F, M> + IsContiguous,
+        DefaultAllocator: Reallocator<F, M, N, DimMaximum<M, N>, N>,
+    {
+        self.into_least_squares_diagonal_problem_in_place(&mut b)
+    }
+
     /// Consume the QR-decomposition and transform it into
     /// a parametrized least squares problem.
     ///
+    /// The vector `$\vec{b}$` is used as workspace and overwritten.
+    ///
     /// See [`LinearLeastSquaresDiagonalProblem`](struct.LinearLeastSquaresDiagonalProblem.html)
     /// for details.
-    pub fn into_least_squares_diagonal_problem<QS>(
+    pub fn into_least_squares_diagonal_problem_in_place<QS>(
         mut self,
-        mut b: Vector<F, M, QS>,
+        b: &mut Vector<F, M, QS>,
     ) -> LinearLeastSquaresDiagonalProblem<F, M, N>
     where
         QS: RawStorageMut<F, M> + IsContiguous,
@@ -329,6 +375,21 @@ where
         Some(max)
     }
 
+    /// The first `$n$` entries of `$\mathbf{Q}^\top \vec{b}$`.
+    pub fn qt_b(&self) -> &OVector<F, N> {
+        &self.qt_b
+    }
+
+    /// Compute `$\mathbf{A}^\top\vec{b}$`.
+    pub fn a_t_b(&self) -> OVector<F, N> {
+        let mut out = self.column_norms.clone_owned();
+        for (j, col) in self.upper_r.column_iter().enumerate() {
+            out[self.permutation[j]] =
+                dot(&col.rows_range(..j + 1), &self.qt_b.rows_range(..j + 1));
+        }
+        out
+    }
+
     /// Compute `$\|\mathbf{A}\vec{x}\| = \sqrt{\vec{x}^\top\mathbf{A}^\top\mathbf{A}\vec{x}}$`.
     pub fn a_x_norm(&mut self, x: &OVector<F, N>) -> F {
         self.work.fill(F::zero());
@@ -364,7 +425,7 @@ where
         &mut self,
         diag: &OVector<F, N>,
         mut out: OVector<F, N>,
-    ) -> (OVector<F, N>, CholeskyFactor<F, M, N>) {
+    ) -> (OVector<F, N>, CholeskyFactor<'_, F, M, N>) {
         out.copy_from(&self.qt_b);
         let mut rhs = self.eliminate_diag(diag, out /* will be filled and returnd */);
         core::mem::swap(&mut self.work, &mut rhs);
@@ -372,7 +433,7 @@ where
     }
 
     /// Solve the least squares problem with a zero diagonal.
-    pub fn solve_with_zero_diagonal(&mut self) -> (OVector<F, N>, CholeskyFactor<F, M, N>) {
+    pub fn solve_with_zero_diagonal(&mut self) -> (OVector<F, N>, CholeskyFactor<'_, F, M, N>) {
         let u1 = Dim::from_usize(1);
         let (_m, n) = self.upper_r.data.shape();
         let l = self.upper_r.rows_generic(0, n);
@@ -396,6 +457,41 @@ where
         (x, chol)
     }
 
+    /// Solve the least squares problem with a zero diagonal, choosing the solution
+    /// with the smallest `$\|\mathbf{D}\vec{x}\|$` if `$\mathbf{A}$` is rank deficient.
+    ///
+    /// If `$\mathbf{A}$` has rank `$n$`, this is the unique solution computed by
+    /// [`solve_with_zero_diagonal`](#method.solve_with_zero_diagonal). Otherwise
+    /// that method returns a basic solution, with zeros for the dependent columns.
+    /// Here, the rank-deficient rows `$[\mathbf{R}_{11}\ \mathbf{R}_{12}]$` are
+    /// factorized once more to find the solution orthogonal to their null space.
+    pub fn solve_min_norm(&self, diag: &OVector<F, N>) -> OVector<F, N> {
+        let (_m, n) = self.upper_r.data.shape();
+        let mut x = OVector::<F, N>::zeros_generic(n, Dim::from_usize(1));
+        let rank = self.r_rank();
+        if rank == 0 {
+            return x;
+        }
+        // transposed rows of R for the scaled variables D x
+        let scaled_rt = DMatrix::from_fn(n.value(), rank, |j, i| {
+            if i <= j {
+                self.upper_r[(i, j)] / diag[self.permutation[j]]
+            } else {
+                F::zero()
+            }
+        });
+        let qr = scaled_rt.qr();
+        let mut y = DVector::from_iterator(rank, self.qt_b.iter().copied().take(rank));
+        // R_11 has a non-zero diagonal, so the rows are independent
+        let solved = qr.r().tr_solve_upper_triangular_mut(&mut y);
+        debug_assert!(solved);
+        let z = qr.q() * y;
+        for j in 0..n.value() {
+            x[self.permutation[j]] = z[j] / diag[self.permutation[j]];
+        }
+        x
+    }
+
     /// Compute if the matrix A has rank `$n$`.
     pub fn is_non_singular(&self) -> bool {
         let (_m, n) = self.upper_r.data.shape();
@@ -403,6 +499,28 @@ where
             && !(0..n.value()).any(|j| unsafe { self.upper_r.get_unchecked((j, j)) }.is_zero())
     }
 
+    /// Estimate the largest and the smallest singular value of `$\mathbf{A}\mathbf{D}^{-1}$`.
+    ///
+    /// These are the largest and smallest magnitude on the diagonal of
+    /// `$\mathbf{R}\mathbf{P}^\top\mathbf{D}^{-1}\mathbf{P}$`. With column pivoting
+    /// their ratio is a lower bound for the condition number, which is
+    /// usually within a small factor of it.
+    pub fn singular_value_estimates(&self, diag: &OVector<F, N>) -> (F, F) {
+        let (_m, n) = self.upper_r.data.shape();
+        let rank = self.m.min(n).value();
+        (0..n.value())
+            .map(|j| {
+                if j < rank {
+                    Float::abs(self.upper_r[(j, j)]) / diag[self.permutation[j]]
+                } else {
+                    F::zero()
+                }
+            })
+            .fold((F::zero(), <F as Float>::infinity()), |(max, min), s| {
+                (Float::max(max, s), Float::min(min, s))
+            })
+    }
+
     fn r_rank(&self) -> usize {
         let (_m, n) = self.upper_r.data.shape();
         let max_rank = self.m.min(n).value();
@@ -422,7 +540,7 @@ where
     fn solve_after_elimination(
         &mut self,
         mut x: OVector<F, N>,
-    ) -> (OVector<F, N>, CholeskyFactor<F, M, N>) {
+    ) -> (OVector<F, N>, CholeskyFactor<'_, F, M, N>) {
         let rank = self.rank();
         let rhs = &mut self.work;
         rhs.rows_range_mut(rank..).fill(F::zero());
@@ -525,6 +643,292 @@ where
     }
 }
 
+/// The QR factorization of the last Jacobian, kept to update it row by row.
+///
+/// If the problem reports which rows of the Jacobian changed, see
+/// [`LeastSquaresProblem::changed_jacobian_rows`](trait.LeastSquaresProblem.html#method.changed_jacobian_rows),
+/// the factor `$\mathbf{R}$` of the last Jacobian `$\mathbf{A}$` is updated
+/// with Givens rotations: the new rows are added and the old rows are removed
+/// again, which takes `$O(n^2)$` operations per row instead of `$O(mn^2)$`
+/// for a new factorization. The permutation of the last full factorization
+/// is kept. The first `$n$` entries of `$\mathbf{Q}^\top\vec{b}$` follow from
+/// ```math
+///   \mathbf{R}^\top(\mathbf{Q}^\top\vec{b}) = \mathbf{P}^\top\mathbf{A}^\top\vec{b}.
+/// ```
+/// The Jacobian is factorized from scratch if removing a row would make
+/// `$\mathbf{R}$` singular.
+#[derive(Clone)]
+pub(crate) struct IncrementalQR<F: nalgebra::RealField> {
+    /// The rows which changed in the next Jacobian, `None` if unknown
+    pub(crate) changed_rows: Option<Vec<usize>>,
+    /// Whether the last factorization was an update
+    pub(crate) updated: bool,
+    retained: Option<RetainedFactor<F>>,
+}
+
+/// The last Jacobian `$\mathbf{A}$` with its factor `$\mathbf{R}$` and permutation.
+#[derive(Clone)]
+struct RetainedFactor<F: nalgebra::RealField> {
+    jacobian: DMatrix<F>,
+    /// `$\mathbf{R}$` with a non-negative diagonal
+    r: DMatrix<F>,
+    permutation: Vec<usize>,
+}
+
+impl<F> IncrementalQR<F>
+where
+    F: nalgebra::RealField + Float + Copy,
+{
+    pub(crate) fn new() -> Self {
+        Self {
+            changed_rows: None,
+            updated: false,
+            retained: None,
+        }
+    }
+
+    /// Forget the last factorization, the next one starts from scratch.
+    pub(crate) fn reset(&mut self) {
+        self.changed_rows = None;
+        self.retained = None;
+    }
+
+    /// Factorize `$\mathbf{A}$` and transform it into a parametrized least squares problem.
+    ///
+    /// Like [`PivotedQR::into_least_squares_diagonal_problem_in_place`](struct.PivotedQR.html#method.into_least_squares_diagonal_problem_in_place),
+    /// but the factorization of the last Jacobian is updated if the changed
+    /// rows are known. The factorization is only kept for the next call if
+    /// they are known now.
+    pub(crate) fn factorize<M, N, S, QS>(
+        &mut self,
+        a: Matrix<F, M, N, S>,
+        b: &mut Vector<F, M, QS>,
+        pivoting: bool,
+    ) -> LinearLeastSquaresDiagonalProblem<F, M, N>
+    where
+        M: Dim + DimMin<N> + DimMax<N>,
+        N: Dim,
+        S: RawStorageMut<F, M, N> + Storage<F, M, N> + IsContiguous,
+        QS: RawStorageMut<F, M> + IsContiguous,
+        DefaultAllocator: Allocator<F, N>
+            + Allocator<F, DimMaximum<M, N>, N>
+            + Allocator<usize, N>
+            + Reallocator<F, M, N, DimMaximum<M, N>, N>,
+    {
+        let changed_rows = self.changed_rows.take();
+        let mut retained = self.retained.take();
+        let qt_b = match (&changed_rows, &mut retained) {
+            (Some(rows), Some(retained)) => retained.update(&a, rows, b),
+            _ => None,
+        };
+        self.updated = qt_b.is_some();
+        let jacobian = changed_rows
+            .is_some()
+            .then(|| DMatrix::from_fn(a.nrows(), a.ncols(), |i, j| a[(i, j)]));
+        let lls = match (qt_b, retained) {
+            (Some(qt_b), Some(retained)) => retained.into_least_squares_diagonal_problem(a, qt_b),
+            _ => PivotedQR::with_pivoting(a, pivoting)
+                .into_least_squares_diagonal_problem_in_place(b),
+        };
+        self.retained = jacobian.map(|jacobian| {
+            let n = lls.upper_r.ncols();
+            let mut r = DMatrix::from_fn(n, n, |i, j| {
+                if i <= j {
+                    lls.upper_r[(i, j)]
+                } else {
+                    F::zero()
+                }
+            });
+            for mut row in r.row_iter_mut() {
+                if row
+                    .iter()
+                    .find(|r| !r.is_zero())
+                    .is_some_and(|r| r.is_negative())
+                {
+                    row.neg_mut();
+                }
+            }
+            RetainedFactor {
+                jacobian,
+                r,
+                permutation: lls.permutation.iter().copied().collect(),
+            }
+        });
+        lls
+    }
+}
+
+impl<F> RetainedFactor<F>
+where
+    F: nalgebra::RealField + Float + Copy,
+{
+    /// Replace the changed rows and compute the first `$n$` entries of `$\mathbf{Q}^\top\vec{b}$`.
+    ///
+    /// Returns `None` if the new `$\mathbf{R}$` is singular.
+    fn update<M, N, S, QS>(
+        &mut self,
+        a: &Matrix<F, M, N, S>,
+        rows: &[usize],
+        b: &Vector<F, M, QS>,
+    ) -> Option<OVector<F, N>>
+    where
+        M: Dim,
+        N: Dim,
+        S: Storage<F, M, N>,
+        QS: RawStorage<F, M>,
+        DefaultAllocator: Allocator<F, N>,
+    {
+        let (m, n) = a.shape_generic();
+        if (m.value(), n.value()) != self.jacobian.shape() || m.value() < n.value() {
+            return None;
+        }
+        let mut rows = rows.to_vec();
+        rows.sort_unstable();
+        rows.dedup();
+        let mut row = vec![F::zero(); n.value()];
+        for &i in &rows {
+            if i >= m.value() {
+                return None;
+            }
+            for (x, &p) in row.iter_mut().zip(&self.permutation) {
+                *x = a[(i, p)];
+            }
+            self.add_row(&mut row);
+            for (x, &p) in row.iter_mut().zip(&self.permutation) {
+                *x = self.jacobian[(i, p)];
+            }
+            if !self.remove_row(&mut row) {
+                return None;
+            }
+        }
+        // R^T (Q^T b) = P^T A^T b by forward substitution
+        let mut qt_b = OVector::<F, N>::from_iterator_generic(
+            n,
+            Dim::from_usize(1),
+            self.permutation.iter().map(|&p| {
+                a.column(p)
+                    .iter()
+                    .zip(b.iter())
+                    .fold(F::zero(), |sum, (a, b)| sum + *a * *b)
+            }),
+        );
+        let max_diag = self
+            .r
+            .diagonal()
+            .iter()
+            .fold(F::zero(), |max, r| Float::max(max, *r));
+        for j in 0..n.value() {
+            let r_jj = self.r[(j, j)];
+            if r_jj <= epsmch::<F>() * max_diag {
+                return None;
+            }
+            let sum = (0..j).fold(F::zero(), |sum, i| sum + self.r[(i, j)] * qt_b[i]);
+            qt_b[j] = (qt_b[j] - sum) / r_jj;
+        }
+        qt_b.iter().all(|x| x.is_finite()).then_some(qt_b)
+    }
+
+    /// Add the row `$\vec{v}^\top$` to `$\mathbf{R}^\top\mathbf{R}$`.
+    ///
+    /// `row` is used as workspace and overwritten.
+    fn add_row(&mut self, row: &mut [F]) {
+        for k in 0..row.len() {
+            let (v_k, tail) = row[k..].split_first_mut().unwrap();
+            if v_k.is_zero() {
+                continue;
+            }
+            let r_kk = self.r[(k, k)];
+            let norm = Float::hypot(r_kk, *v_k);
+            let (cos, sin) = (r_kk / norm, *v_k / norm);
+            self.r[(k, k)] = norm;
+            for (j, v_j) in tail.iter_mut().enumerate() {
+                let r_kj = self.r[(k, k + 1 + j)];
+                self.r[(k, k + 1 + j)] = cos * r_kj + sin * *v_j;
+                *v_j = cos * *v_j - sin * r_kj;
+            }
+        }
+    }
+
+    /// Remove the row `$\vec{u}^\top$` from `$\mathbf{R}^\top\mathbf{R}$`,
+    /// like LINPACK's `dchdd`.
+    ///
+    /// `row` is used as workspace and overwritten. Returns `false` if the
+    /// result would be singular, `$\mathbf{R}$` is then invalid.
+    fn remove_row(&mut self, row: &mut [F]) -> bool {
+        let n = row.len();
+        // solve R^T p = u
+        for j in 0..n {
+            let r_jj = self.r[(j, j)];
+            if r_jj.is_zero() {
+                return false;
+            }
+            let sum = (0..j).fold(F::zero(), |sum, i| sum + self.r[(i, j)] * row[i]);
+            row[j] = (row[j] - sum) / r_jj;
+        }
+        let norm_sq = row.iter().fold(F::zero(), |sum, p| sum + *p * *p);
+        let alpha_sq = F::one() - norm_sq;
+        if alpha_sq.is_nan() || alpha_sq <= epsmch() {
+            return false;
+        }
+        // the rotations which eliminate p, stored as (cos, sin)
+        let mut alpha = Float::sqrt(alpha_sq);
+        let mut rotations = vec![(F::one(), F::zero()); n];
+        for i in (0..n).rev() {
+            let scale = alpha + Float::abs(row[i]);
+            let (a, b) = (alpha / scale, row[i] / scale);
+            let norm = Float::hypot(a, b);
+            rotations[i] = (a / norm, b / norm);
+            alpha = scale * norm;
+        }
+        for j in 0..n {
+            let mut xx = F::zero();
+            for i in (0..=j).rev() {
+                let (cos, sin) = rotations[i];
+                let r_ij = self.r[(i, j)];
+                self.r[(i, j)] = cos * r_ij - sin * xx;
+                xx = cos * xx + sin * r_ij;
+            }
+        }
+        true
+    }
+
+    /// Build the least squares problem from the updated factor, reusing the storage of `$\mathbf{A}$`.
+    fn into_least_squares_diagonal_problem<M, N, S>(
+        self,
+        a: Matrix<F, M, N, S>,
+        qt_b: OVector<F, N>,
+    ) -> LinearLeastSquaresDiagonalProblem<F, M, N>
+    where
+        M: Dim + DimMax<N>,
+        N: Dim,
+        S: Storage<F, M, N>,
+        DefaultAllocator: Allocator<F, N>
+            + Allocator<F, DimMaximum<M, N>, N>
+            + Allocator<usize, N>
+            + Reallocator<F, M, N, DimMaximum<M, N>, N>,
+    {
+        let (m, n) = a.shape_generic();
+        let u1 = Dim::from_usize(1);
+        let column_norms =
+            OVector::<F, N>::from_iterator_generic(n, u1, a.column_iter().map(|c| enorm(&c)));
+        let mut upper_r = a.resize_generic(m.max(n), n, F::zero());
+        for j in 0..n.value() {
+            for i in 0..=j {
+                upper_r[(i, j)] = self.r[(i, j)];
+            }
+        }
+        LinearLeastSquaresDiagonalProblem {
+            qt_b,
+            upper_r,
+            l_diag: column_norms.clone(),
+            permutation: OVector::<usize, N>::from_iterator_generic(n, u1, self.permutation),
+            work: column_norms.clone(),
+            column_norms,
+            m,
+        }
+    }
+}
+
 #[test]
 fn test_pivoted_qr() {
     use nalgebra::{Matrix4x3, Vector3};
@@ -555,6 +959,29 @@ fn test_pivoted_qr() {
     assert_relative_eq!(qr.qr, qr_ref, epsilon = 1e-14);
 }
 
+#[test]
+fn test_unpivoted_qr() {
+    use nalgebra::{Matrix3, Matrix4x3, Vector3};
+    #[rustfmt::skip]
+    let a = Matrix4x3::<f64>::new(
+        2.0,  1.,  4.0,
+        0.0, 10., -1.0,
+        0.0,  4.,  0.5,
+        1.0,  0.,   0.,
+    );
+    let qr = PivotedQR::with_pivoting(a, false);
+    assert_eq!(qr.permutation, Vector3::new(0, 1, 2));
+    assert_relative_eq!(
+        qr.column_norms,
+        Vector3::from_iterator(a.column_iter().map(|c| c.norm()))
+    );
+    // R^T R = A^T A without the permutation
+    let (r, permutation) = qr.r_factor();
+    assert_eq!(permutation, [0, 1, 2]);
+    let r = Matrix3::from_iterator(r.iter().copied());
+    assert_relative_eq!(r.transpose() * r, a.transpose() * a, epsilon = 1e-12);
+}
+
 #[test]
 /// Test that for a wide matrix the QR is identical to the case
 /// where the matrix is extended with zero rows.
@@ -609,6 +1036,26 @@ fn test_wide_matrix() {
     assert_relative_eq!(lls1.qt_b, lls2.qt_b);
 }
 
+#[test]
+/// The column norms for the scaling diagonal are the ones computed for the
+/// pivoting, in the original column order.
+fn test_column_norms_are_passed_on() {
+    use crate::trust_region::Linearization;
+    use nalgebra::{Matrix4x3, Vector3, Vector4};
+    #[rustfmt::skip]
+    let a = Matrix4x3::<f64>::new(
+        2.0,  1.,  4.0,
+        0.0, 10., -1.0,
+        0.0,  4.,  0.5,
+        1.0,  0.,   0.,
+    );
+    let expected = Vector3::from_iterator(a.column_iter().map(|c| c.norm()));
+    let qr = PivotedQR::new(a);
+    assert_eq!(qr.permutation, Vector3::new(1, 2, 0));
+    let lls = qr.into_least_squares_diagonal_problem(Vector4::new(1., 2., 3., 4.));
+    assert_relative_eq!(*lls.column_norms(), expected, epsilon = 1e-14);
+}
+
 #[test]
 fn test_pivoted_qr_more_branches() {
     // This test case was crafted to hit all three
@@ -896,3 +1343,77 @@ fn test_a_x_norm() {
     let result = lls.a_x_norm(&Vector3::new(1., 8., 3.));
     assert_relative_eq!(result, Float::sqrt(6710.));
 }
+
+#[test]
+fn test_singular_value_estimates() {
+    use nalgebra::{Matrix4x3, Vector3, Vector4};
+    #[rustfmt::skip]
+    let a = Matrix4x3::<f64>::new(
+        2.0,  1.,  4.0,
+        0.0, 10., -1.0,
+        0.0,  4.,  0.5,
+        1.0,  0.,  1e-3,
+    );
+    let diag = Vector3::new(0.5, 4., 2.);
+    let scaled = Matrix4x3::from_fn(|i, j| a[(i, j)] / diag[j]);
+    let singular_values = scaled.singular_values();
+    let lls = PivotedQR::new(a).into_least_squares_diagonal_problem(Vector4::zeros());
+    let (max, min) = lls.singular_value_estimates(&diag);
+    assert!(max <= singular_values.max() * (1. + 1e-12));
+    assert!(min >= singular_values.min() * (1. - 1e-12));
+    // within a small factor of the condition number
+    let condition = singular_values.max() / singular_values.min();
+    assert!(max / min > 0.1 * condition);
+}
+
+#[test]
+fn test_incremental_qr_matches_refactorization() {
+    use nalgebra::{Matrix5x3, Vector3, Vector5};
+    #[rustfmt::skip]
+    let a = Matrix5x3::<f64>::new(
+        3., 6., 2.,
+        7., 4., 3.,
+        2., 0., 4.,
+        5., 1., 6.,
+        1., 2., 1.,
+    );
+    let mut factor = IncrementalQR::new();
+    factor.changed_rows = Some(Vec::new());
+    factor.factorize(a, &mut Vector5::zeros(), true);
+    assert!(!factor.updated);
+
+    let mut changed = a;
+    changed.set_row(1, &Vector3::new(-1., 4., 8.).transpose());
+    changed.set_row(3, &Vector3::new(5., -2., 0.5).transpose());
+    let b = Vector5::new(1., -2., 3., 0.5, 4.);
+    factor.changed_rows = Some(vec![3, 1]);
+    let mut incremental = factor.factorize(changed, &mut b.clone(), true);
+    assert!(factor.updated);
+    let mut full = PivotedQR::new(changed).into_least_squares_diagonal_problem(b);
+
+    // R^T R = P^T A^T A P, the factor is only unique up to the signs of its rows
+    for x in [Vector3::new(1., 0., 0.), Vector3::new(0.3, -2., 1.)] {
+        assert_relative_eq!(
+            incremental.a_x_norm(&x),
+            (changed * x).norm(),
+            epsilon = 1e-12
+        );
+    }
+    assert_relative_eq!(
+        incremental.a_t_b(),
+        changed.transpose() * b,
+        epsilon = 1e-12
+    );
+    assert_relative_eq!(incremental.a_t_b(), full.a_t_b(), epsilon = 1e-12);
+    let diag = Vector3::new(0.5, 2., 1.);
+    let (x, _) = incremental.solve_with_diagonal(&diag, Vector3::zeros());
+    let (expected, _) = full.solve_with_diagonal(&diag, Vector3::zeros());
+    assert_relative_eq!(x, expected, epsilon = 1e-12);
+    let (x, _) = incremental.solve_with_zero_diagonal();
+    let (expected, _) = full.solve_with_zero_diagonal();
+    assert_relative_eq!(x, expected, epsilon = 1e-12);
+
+    // without the changed rows the Jacobian is factorized from scratch
+    factor.factorize(a, &mut b.clone(), true);
+    assert!(!factor.updated);
+}
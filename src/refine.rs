@@ -0,0 +1,92 @@
+//! Lightweight refinement of a problem in place.
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, Matrix, OVector, RealField, Vector};
+
+use crate::{LeastSquaresProblem, TerminationReason};
+
+/// The result of [`LevenbergMarquardt::refine`](struct.LevenbergMarquardt.html#method.refine).
+///
+/// Only the objective function before and after and the length of the
+/// parameter change are recorded, none of the statistics of the
+/// [`MinimizationReport`](struct.MinimizationReport.html) are computed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RefineReport<F> {
+    /// Why the refinement stopped.
+    ///
+    /// This is [`TerminationReason::MaxIterations`](enum.TerminationReason.html#variant.MaxIterations)
+    /// if the iterations were used up before the minimization terminated.
+    pub termination: TerminationReason,
+    /// The objective function `$f(\vec{x})$` before the refinement.
+    pub initial_objective: F,
+    /// The objective function `$f(\vec{x})$` after the refinement.
+    pub objective_function: F,
+    /// The Euclidean norm of the parameter change `$\|\vec{x}_{\text{after}} - \vec{x}_{\text{before}}\|$`.
+    pub step_norm: F,
+}
+
+impl<F: RealField + Copy> RefineReport<F> {
+    /// The decrease of the objective function, it is never negative.
+    pub fn improvement(&self) -> F {
+        self.initial_objective - self.objective_function
+    }
+}
+
+/// Minimize a problem behind a mutable reference.
+pub(crate) struct Borrowed<'p, O>(pub(crate) &'p mut O);
+
+impl<'p, F, M, N, O> LeastSquaresProblem<F, M, N> for Borrowed<'p, O>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+{
+    type ResidualStorage = O::ResidualStorage;
+    type JacobianStorage = O::JacobianStorage;
+    type ParameterStorage = O::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.0.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.0.params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        self.0.residuals()
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        self.0.residuals_into(out)
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        self.0.jacobian()
+    }
+
+    fn jacobian_with_residuals(
+        &self,
+        residuals: &Vector<F, M, Self::ResidualStorage>,
+    ) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        self.0.jacobian_with_residuals(residuals)
+    }
+
+    fn hessian_vector_product(&self, v: &OVector<F, N>) -> Option<OVector<F, N>>
+    where
+        DefaultAllocator: Allocator<F, N>,
+    {
+        self.0.hessian_vector_product(v)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.0.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.0.number_of_penalty_residuals()
+    }
+
+    fn changed_jacobian_rows(&self) -> Option<&[usize]> {
+        self.0.changed_jacobian_rows()
+    }
+}
@@ -0,0 +1,162 @@
+//! Reading the progress of a minimization from another thread.
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, OVector, RealField};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::observer::{LMState, Observer};
+
+/// The best parameters found so far, readable from other threads.
+///
+/// Create it with [`LM::shared_best`](struct.LM.html#method.shared_best) and
+/// attach it with [`LM::with_observer`](struct.LM.html#method.with_observer).
+/// Every clone reads the same point, send one to another thread, for example
+/// to show the current fit in a user interface while the minimization runs.
+/// [`best_snapshot`](#method.best_snapshot) can be called at any moment, the
+/// parameters and the objective function are updated together under a lock,
+/// so they always belong to the same point.
+///
+/// The objective function of the accepted points never increases, so this is
+/// the current point after every iteration. Requires the feature `std`.
+#[derive(Debug)]
+pub struct SharedBest<F, N>
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    best: Arc<Mutex<(OVector<F, N>, F)>>,
+}
+
+impl<F, N> SharedBest<F, N>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    pub(crate) fn new(params: OVector<F, N>, objective_function: F) -> Self {
+        Self {
+            best: Arc::new(Mutex::new((params, objective_function))),
+        }
+    }
+
+    /// The best parameters so far and their objective function `$f(\vec{x})$`.
+    pub fn best_snapshot(&self) -> (OVector<F, N>, F) {
+        self.best
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl<F, N> Clone for SharedBest<F, N>
+where
+    F: RealField,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            best: Arc::clone(&self.best),
+        }
+    }
+}
+
+impl<F, N> Observer<F, N> for SharedBest<F, N>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn on_iteration(&mut self, state: &LMState<'_, F, N>) {
+        let mut best = self.best.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.objective_function < best.1 {
+            best.0.copy_from_slice(state.params);
+            best.1 = state.objective_function;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use nalgebra::{storage::Owned, Matrix2, Vector2, U2};
+    use std::{thread, time::Duration, vec::Vec};
+
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// The Rosenbrock function, slowed down to be polled during the minimization.
+    struct Rosenbrock {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U2, U2> for Rosenbrock {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            thread::sleep(Duration::from_millis(1));
+            let [x, y] = [self.params.x, self.params.y];
+            Some(Vector2::new(10. * (y - x * x), 1. - x))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            Some(Matrix2::new(-20. * self.params.x, 10., -1., 0.))
+        }
+    }
+
+    #[test]
+    fn polled_during_minimization() {
+        let config = LevenbergMarquardt::new();
+        let lm = config
+            .solver(Rosenbrock {
+                params: Vector2::new(-10., 20.),
+            })
+            .ok()
+            .unwrap();
+        let mut shared = lm.shared_best();
+        let (x0, f0) = shared.best_snapshot();
+        assert_eq!(x0, Vector2::new(-10., 20.));
+
+        let handle = shared.clone();
+        let done = AtomicBool::new(false);
+        let (polled, (problem, report)) = thread::scope(|scope| {
+            let poller = scope.spawn(|| {
+                let mut polled = Vec::new();
+                while !done.load(Ordering::Acquire) {
+                    polled.push(handle.best_snapshot());
+                    thread::sleep(Duration::from_micros(50));
+                }
+                polled
+            });
+            let result = lm.with_observer(&mut shared).finish();
+            done.store(true, Ordering::Release);
+            (poller.join().unwrap(), result)
+        });
+        assert!(report.termination.was_successful());
+
+        let objectives: Vec<f64> = polled.iter().map(|(_, f)| *f).collect();
+        assert!(objectives.windows(2).all(|f| f[1] <= f[0]));
+        // the fit was observed in progress
+        assert!(objectives
+            .iter()
+            .any(|f| *f < f0 && *f > report.objective_function));
+        for (x, f) in &polled {
+            let r = Rosenbrock { params: *x }.residuals().unwrap();
+            // the parameters belong to the objective function
+            assert_relative_eq!(*f, 0.5 * r.norm_squared(), max_relative = 1e-14);
+        }
+        assert_eq!(
+            shared.best_snapshot(),
+            (problem.params, report.objective_function)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Least squares problems with parameters in a borrowed slice.
+use core::marker::PhantomData;
+use nalgebra::{
+    allocator::Allocator, storage::Owned, DVector, DefaultAllocator, Dim, Dynamic, OMatrix,
+    OVector, RealField,
+};
+
+use crate::LeastSquaresProblem;
+
+/// A least squares problem evaluated directly on a parameter slice.
+///
+/// Wrap it together with the slice in a
+/// [`SliceParameters`](struct.SliceParameters.html) to minimize it.
+pub trait SliceLeastSquaresProblem<F, M>
+where
+    F: RealField + Copy,
+    M: Dim,
+    DefaultAllocator: Allocator<F, M> + Allocator<F, M, Dynamic>,
+{
+    /// Compute the residual vector at the parameters `x`.
+    fn residuals(&self, x: &[F]) -> Option<OVector<F, M>>;
+
+    /// Compute the Jacobian of the residual vector at the parameters `x`.
+    fn jacobian(&self, x: &[F]) -> Option<OMatrix<F, M, Dynamic>>;
+}
+
+/// A problem whose parameters are stored in a borrowed `&mut [F]`.
+///
+/// This is useful if the parameters live in a buffer owned by someone else,
+/// for example a C library. Every parameter update is written to the slice and
+/// the residuals and the Jacobian are computed from it, so the slice holds the
+/// solution after the minimization.
+///
+/// ```
+/// # use levenberg_marquardt::{LevenbergMarquardt, SliceLeastSquaresProblem, SliceParameters};
+/// # use nalgebra::{Const, Dynamic, OMatrix, Vector2, U2};
+/// struct Shift;
+///
+/// impl SliceLeastSquaresProblem<f64, U2> for Shift {
+///     fn residuals(&self, x: &[f64]) -> Option<Vector2<f64>> {
+///         Some(Vector2::new(x[0] - 1., x[1] + 2.))
+///     }
+///
+///     fn jacobian(&self, _x: &[f64]) -> Option<OMatrix<f64, U2, Dynamic>> {
+///         Some(OMatrix::identity_generic(Const::<2>, Dynamic::new(2)))
+///     }
+/// }
+///
+/// let mut buffer = [0., 0.];
+/// let (_, report) = LevenbergMarquardt::new().minimize(SliceParameters::new(&mut buffer, Shift));
+/// assert!(report.termination.was_successful());
+/// assert_eq!(buffer, [1., -2.]);
+/// ```
+pub struct SliceParameters<'a, F, M, P> {
+    params: &'a mut [F],
+    problem: P,
+    marker: PhantomData<M>,
+}
+
+impl<'a, F, M, P> SliceParameters<'a, F, M, P> {
+    /// The number of parameters is the length of `params`, its contents are
+    /// the initial parameters.
+    pub fn new(params: &'a mut [F], problem: P) -> Self {
+        Self {
+            params,
+            problem,
+            marker: PhantomData,
+        }
+    }
+
+    /// The wrapped problem.
+    pub fn inner(&self) -> &P {
+        &self.problem
+    }
+
+    /// Extract the wrapped problem, releasing the borrow of the parameters.
+    pub fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<'a, F, M, P> LeastSquaresProblem<F, M, Dynamic> for SliceParameters<'a, F, M, P>
+where
+    F: RealField + Copy,
+    M: Dim,
+    P: SliceLeastSquaresProblem<F, M>,
+    DefaultAllocator: Allocator<F, M> + Allocator<F, M, Dynamic>,
+{
+    type ResidualStorage = Owned<F, M>;
+    type JacobianStorage = Owned<F, M, Dynamic>;
+    type ParameterStorage = Owned<F, Dynamic>;
+
+    fn set_params(&mut self, x: &DVector<F>) {
+        self.params.copy_from_slice(x.as_slice());
+    }
+
+    fn params(&self) -> DVector<F> {
+        DVector::from_column_slice(self.params)
+    }
+
+    fn residuals(&self) -> Option<OVector<F, M>> {
+        self.problem.residuals(self.params)
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, M, Dynamic>> {
+        self.problem.jacobian(self.params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, DMatrix, DVector, Dynamic, OMatrix, Vector3, U3};
+
+    use super::{SliceLeastSquaresProblem, SliceParameters};
+    use crate::{LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Fit `$y = a e^{-kt} + c$` to a few samples.
+    struct Decay {
+        t: DVector<f64>,
+        y: DVector<f64>,
+    }
+
+    impl Decay {
+        fn new() -> Self {
+            let t = DVector::from_fn(15, |i, _| 0.3 * i as f64);
+            let y = t.map(|t| 2. * f64::exp(-1.3 * t) + 0.5 + 0.01 * f64::sin(5. * t));
+            Self { t, y }
+        }
+    }
+
+    impl SliceLeastSquaresProblem<f64, Dynamic> for Decay {
+        fn residuals(&self, x: &[f64]) -> Option<DVector<f64>> {
+            Some(self.t.map(|t| x[0] * f64::exp(-x[1] * t) + x[2]) - &self.y)
+        }
+
+        fn jacobian(&self, x: &[f64]) -> Option<DMatrix<f64>> {
+            let mut jacobian = DMatrix::zeros(self.t.len(), 3);
+            for (i, &t) in self.t.iter().enumerate() {
+                let e = f64::exp(-x[1] * t);
+                jacobian[(i, 0)] = e;
+                jacobian[(i, 1)] = -x[0] * t * e;
+                jacobian[(i, 2)] = 1.;
+            }
+            Some(jacobian)
+        }
+    }
+
+    /// The same fit with the parameters in an `OVector`.
+    struct OwnedDecay {
+        decay: Decay,
+        params: Vector3<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, Dynamic, U3> for OwnedDecay {
+        type ParameterStorage = Owned<f64, U3>;
+        type ResidualStorage = Owned<f64, Dynamic>;
+        type JacobianStorage = Owned<f64, Dynamic, U3>;
+
+        fn set_params(&mut self, x: &Vector3<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector3<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<DVector<f64>> {
+            self.decay.residuals(self.params.as_slice())
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, Dynamic, U3>> {
+            Some(
+                self.decay
+                    .jacobian(self.params.as_slice())?
+                    .fixed_columns::<3>(0)
+                    .into_owned(),
+            )
+        }
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let mut params = vec![1., 1., 0.];
+        let mut problem = SliceParameters::new(&mut params, Decay::new());
+        let numerical = crate::differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(problem.jacobian().unwrap(), numerical, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn matches_owned_parameters() {
+        let mut params = vec![1., 1., 0.];
+        let (_, report) =
+            LevenbergMarquardt::new().minimize(SliceParameters::new(&mut params, Decay::new()));
+        assert!(report.termination.was_successful());
+
+        let (owned, owned_report) = LevenbergMarquardt::new().minimize(OwnedDecay {
+            decay: Decay::new(),
+            params: Vector3::new(1., 1., 0.),
+        });
+        assert!(owned_report.termination.was_successful());
+        assert_eq!(
+            report.number_of_evaluations,
+            owned_report.number_of_evaluations
+        );
+        assert_relative_eq!(params.as_slice(), owned.params.as_slice(), epsilon = 1e-12);
+        assert_relative_eq!(params[1], 1.3, epsilon = 1e-2);
+    }
+}
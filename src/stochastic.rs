@@ -0,0 +1,202 @@
+//! Least squares problems minimized on random minibatches of residuals.
+use alloc::{vec, vec::Vec};
+use nalgebra::{
+    allocator::Allocator, storage::Owned, DVector, DefaultAllocator, Dim, Dynamic, OMatrix,
+    OVector, RealField,
+};
+
+use crate::utils::SplitMix64;
+use crate::LeastSquaresProblem;
+
+/// A least squares problem whose residuals can be evaluated for a subset.
+///
+/// This is minimized with
+/// [`LevenbergMarquardt::minimize_stochastic`](struct.LevenbergMarquardt.html#method.minimize_stochastic).
+/// Every iteration only uses the residuals `$r_i$` for the indices `$i$` of a
+/// random minibatch, like stochastic Gauss-Newton methods. This trades the
+/// exactness of the steps for cheap iterations on huge datasets.
+pub trait StochasticProblem<F, N>
+where
+    F: RealField + Copy,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Set the stored parameters `$\vec{x}$`.
+    fn set_params(&mut self, x: &OVector<F, N>);
+
+    /// Get the current parameter vector `$\vec{x}$`.
+    fn params(&self) -> OVector<F, N>;
+
+    /// The total number `$m$` of residuals.
+    fn number_of_residuals(&self) -> usize;
+
+    /// Compute the residuals `$r_i$` for the indices `$i$` in `batch`, in this order.
+    ///
+    /// The indices are below [`number_of_residuals`](#tymethod.number_of_residuals),
+    /// distinct and sorted.
+    fn residuals(&self, batch: &[usize]) -> Option<DVector<F>>;
+
+    /// Compute the rows of the Jacobian for the indices in `batch`, in this order.
+    fn jacobian(&self, batch: &[usize]) -> Option<OMatrix<F, Dynamic, N>>;
+}
+
+/// The least squares problem of the current minibatch.
+pub(crate) struct Minibatch<P> {
+    problem: P,
+    rng: SplitMix64,
+    /// A permutation of all indices, the batch is drawn from its front
+    indices: Vec<usize>,
+    batch: Vec<usize>,
+}
+
+impl<P> Minibatch<P> {
+    /// Draw the first batch of `batch_size` residuals.
+    pub(crate) fn new<F, N>(problem: P, batch_size: usize, seed: u64) -> Self
+    where
+        F: RealField + Copy,
+        N: Dim,
+        P: StochasticProblem<F, N>,
+        DefaultAllocator: Allocator<F, N>,
+    {
+        let m = problem.number_of_residuals();
+        let mut minibatch = Self {
+            problem,
+            rng: SplitMix64::new(seed),
+            indices: (0..m).collect(),
+            batch: vec![0; Ord::min(batch_size, m)],
+        };
+        minibatch.next_batch();
+        minibatch
+    }
+
+    /// Draw the next batch without replacement.
+    pub(crate) fn next_batch(&mut self) {
+        let (m, k) = (self.indices.len(), self.batch.len());
+        for i in 0..k {
+            let j = i + (self.rng.next_u64() % (m - i) as u64) as usize;
+            self.indices.swap(i, j);
+        }
+        self.batch.copy_from_slice(&self.indices[..k]);
+        self.batch.sort_unstable();
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, N, P> LeastSquaresProblem<F, Dynamic, N> for Minibatch<P>
+where
+    F: RealField + Copy,
+    N: Dim,
+    P: StochasticProblem<F, N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, Dynamic, N>,
+{
+    type ResidualStorage = Owned<F, Dynamic>;
+    type JacobianStorage = Owned<F, Dynamic, N>;
+    type ParameterStorage = Owned<F, N>;
+
+    fn set_params(&mut self, x: &OVector<F, N>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> OVector<F, N> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<DVector<F>> {
+        self.problem.residuals(&self.batch)
+    }
+
+    fn jacobian(&self) -> Option<OMatrix<F, Dynamic, N>> {
+        self.problem.jacobian(&self.batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use approx::assert_relative_eq;
+    use nalgebra::{DVector, Dynamic, OMatrix, Vector2, U2};
+
+    use super::StochasticProblem;
+    use crate::{CurveFit, LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Fit `$y = a + bt$` to many noisy points.
+    struct Line {
+        params: Vector2<f64>,
+        data: Vec<(f64, f64)>,
+    }
+
+    impl StochasticProblem<f64, U2> for Line {
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params = *x;
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn number_of_residuals(&self) -> usize {
+            self.data.len()
+        }
+
+        fn residuals(&self, batch: &[usize]) -> Option<DVector<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            Some(DVector::from_iterator(
+                batch.len(),
+                batch.iter().map(|&i| {
+                    let (t, y) = self.data[i];
+                    a + b * t - y
+                }),
+            ))
+        }
+
+        fn jacobian(&self, batch: &[usize]) -> Option<OMatrix<f64, Dynamic, U2>> {
+            Some(OMatrix::<f64, Dynamic, U2>::from_fn(batch.len(), |i, j| {
+                if j == 0 {
+                    1.
+                } else {
+                    self.data[batch[i]].0
+                }
+            }))
+        }
+    }
+
+    fn data() -> Vec<(f64, f64)> {
+        (0..1000)
+            .map(|i| {
+                let t = i as f64 / 100.;
+                // a deterministic wiggle as noise
+                (t, 1.5 + 0.8 * t + 0.05 * f64::sin(37. * t))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn converges_near_full_batch() {
+        let data = data();
+        let model = |x: &Vector2<f64>, t: f64| x[0] + x[1] * t;
+        let config = LevenbergMarquardt::new();
+        let (full, report) = config.minimize(CurveFit::new(Vector2::zeros(), &data, model));
+        assert!(report.termination.was_successful());
+        let full = full.params();
+
+        let problem = || Line {
+            params: Vector2::zeros(),
+            data: data.clone(),
+        };
+        let (line, report) = config.minimize_stochastic(problem(), 50, 7);
+        assert!(!report.termination.was_failure());
+        assert_eq!(report.number_of_residuals, 50);
+        assert_relative_eq!(line.params, full, epsilon = 0.05);
+
+        // the batches only depend on the seed
+        let (again, _) = config.minimize_stochastic(problem(), 50, 7);
+        assert_eq!(again.params, line.params);
+
+        // a batch with all residuals is the full problem
+        let (line, _) = config.minimize_stochastic(problem(), 1000, 7);
+        assert_relative_eq!(line.params, full, epsilon = 1e-10);
+    }
+}
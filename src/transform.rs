@@ -0,0 +1,191 @@
+//! Reparametrization of least squares problems.
+use nalgebra::{ComplexField, Dim, Matrix, Vector};
+
+use crate::LeastSquaresProblem;
+
+/// A problem whose parameters are mapped through a transformation.
+///
+/// The optimizer works with parameters `$\vec{y}$` and the wrapped problem
+/// sees `$\vec{x} = \vec{g}(\vec{y})$`. The transformation must act
+/// component-wise, so that its Jacobian is the diagonal matrix
+/// ```math
+///   \mathbf{G} = \operatorname{diag}\Bigl(\frac{\partial x_1}{\partial y_1}, \ldots, \frac{\partial x_n}{\partial y_n}\Bigr)
+/// ```
+/// and the Jacobian of the residuals with respect to `$\vec{y}$` is `$\mathbf{J}\mathbf{G}$`.
+///
+/// Use it to keep the optimizer in an unconstrained space, for example to wrap
+/// angles to `$[-\pi, \pi]$` or to map through a link function.
+///
+/// Create it with [`LeastSquaresProblem::with_param_transform`](trait.LeastSquaresProblem.html#method.with_param_transform).
+/// The current parameters of the problem are used as the initial `$\vec{y}$`.
+/// You get the solution in problem space from the wrapped problem with
+/// [`into_inner`](#method.into_inner).
+pub struct ParamTransform<P, X, T, J> {
+    problem: P,
+    params: X,
+    transform: T,
+    jac_factor: J,
+}
+
+impl<P, X, T, J> ParamTransform<P, X, T, J> {
+    pub(crate) fn new(problem: P, params: X, transform: T, jac_factor: J) -> Self {
+        Self {
+            problem,
+            params,
+            transform,
+            jac_factor,
+        }
+    }
+
+    /// The wrapped problem.
+    pub fn inner(&self) -> &P {
+        &self.problem
+    }
+
+    /// Extract the wrapped problem.
+    pub fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, N, P, T, J> LeastSquaresProblem<F, M, N>
+    for ParamTransform<P, Vector<F, N, P::ParameterStorage>, T, J>
+where
+    F: ComplexField + Copy,
+    N: Dim,
+    M: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+    T: Fn(&Vector<F, N, P::ParameterStorage>) -> Vector<F, N, P::ParameterStorage>,
+    J: Fn(&Vector<F, N, P::ParameterStorage>) -> Vector<F, N, P::ParameterStorage>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, y: &Vector<F, N, Self::ParameterStorage>) {
+        self.params = y.clone();
+        self.problem.set_params(&(self.transform)(y));
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.params.clone()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        self.problem.residuals()
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        self.problem.residuals_into(out)
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let mut jacobian = self.problem.jacobian()?;
+        let factor = (self.jac_factor)(&self.params);
+        for (mut column, factor) in jacobian.column_iter_mut().zip(factor.iter()) {
+            column *= *factor;
+        }
+        Some(jacobian)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use core::f64::consts::PI;
+    use nalgebra::{storage::Owned, Vector1, Vector2, U1, U2};
+
+    use crate::{differentiate_numerically, LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Heading measured as a unit vector, the model only accepts
+    /// angles in `$[-\pi, \pi]$`.
+    struct Heading {
+        angle: Vector1<f64>,
+        measured: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U2, U1> for Heading {
+        type ParameterStorage = Owned<f64, U1>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U1>;
+
+        fn set_params(&mut self, x: &Vector1<f64>) {
+            self.angle.copy_from(x);
+        }
+
+        fn params(&self) -> Vector1<f64> {
+            self.angle
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            let angle = self.angle.x;
+            if angle.abs() > PI {
+                return None;
+            }
+            Some(Vector2::new(angle.cos(), angle.sin()) - self.measured)
+        }
+
+        fn jacobian(&self) -> Option<Vector2<f64>> {
+            let angle = self.angle.x;
+            Some(Vector2::new(-angle.sin(), angle.cos()))
+        }
+    }
+
+    fn wrap(y: &Vector1<f64>) -> Vector1<f64> {
+        y.map(|y| (y + PI).rem_euclid(2. * PI) - PI)
+    }
+
+    fn heading() -> Heading {
+        let target: f64 = -3.;
+        Heading {
+            angle: Vector1::new(2.8),
+            measured: Vector2::new(target.cos(), target.sin()),
+        }
+    }
+
+    #[test]
+    fn plain_leaves_domain() {
+        let (_, report) = LevenbergMarquardt::new().minimize(heading());
+        assert!(!report.termination.was_successful());
+    }
+
+    #[test]
+    fn wrapped_angle_converges() {
+        let mut problem = heading().with_param_transform(wrap, |y| y.map(|_| 1.));
+        let jacobian_numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(
+            problem.jacobian().unwrap(),
+            jacobian_numerical,
+            epsilon = 1e-6
+        );
+
+        let (problem, report) = LevenbergMarquardt::new().minimize(problem);
+        assert!(report.termination.was_successful());
+        assert!(report.objective_function < 1e-20);
+        // the optimizer crossed the boundary, the problem did not
+        assert!(problem.params().x > PI);
+        assert_relative_eq!(problem.into_inner().angle.x, -3., epsilon = 1e-10);
+    }
+
+    #[test]
+    fn link_function_scales_jacobian() {
+        let mut problem = heading().with_param_transform(
+            |y: &Vector1<f64>| y.map(|y| 3. * y.tanh()),
+            |y: &Vector1<f64>| y.map(|y| 3. * (1. - y.tanh().powi(2))),
+        );
+        let jacobian_numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(
+            problem.jacobian().unwrap(),
+            jacobian_numerical,
+            epsilon = 1e-6
+        );
+    }
+}
@@ -16,6 +16,116 @@ where
     pub step: OVector<F, N>,
     pub lambda: F,
     pub dp_norm: F,
+    pub lambda_iterations: usize,
+}
+
+/// Linearization of the residuals for which the trust-region subproblem can be solved.
+///
+/// The LM algorithm only needs these operations on the Jacobian `$\mathbf{J}$`
+/// and the residuals `$\vec{r}$` at the current parameters. The default
+/// implementation is [`LinearLeastSquaresDiagonalProblem`](../qr/struct.LinearLeastSquaresDiagonalProblem.html).
+pub trait Linearization<F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The column norms `$\|\mathbf{J}\vec{e}_i\|$`.
+    fn column_norms(&self) -> &OVector<F, N>;
+
+    /// Compute `$\max_i |(\mathbf{J}^\top \vec{r})_i| / \|\mathbf{J}\vec{e}_i\|$`
+    /// with `$\vec{r}$` scaled by `1/b_norm`.
+    ///
+    /// Returns `None` if the computation results in `NaN`.
+    fn max_a_t_b_scaled(&mut self, b_norm: F) -> Option<F>;
+
+    /// Compute `$\|\mathbf{J}\vec{x}\|$`.
+    fn a_x_norm(&mut self, x: &OVector<F, N>) -> F;
+
+    /// Compute the gradient `$\mathbf{J}^\top\vec{r}$`.
+    fn a_t_b(&self) -> OVector<F, N>;
+
+    /// Approximately solve the trust-region subproblem.
+    ///
+    /// See [`determine_lambda_and_parameter_update`](fn.determine_lambda_and_parameter_update.html).
+    fn solve_trust_region(
+        &mut self,
+        diag: &OVector<F, N>,
+        delta: F,
+        initial_lambda: F,
+        max_iterations: usize,
+        min_norm: bool,
+    ) -> LMParameter<F, N>;
+
+    /// Solve `$(\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D})\vec{p} = \mathbf{J}^\top\vec{r}$`
+    /// for a fixed `$\lambda > 0$`.
+    ///
+    /// Returns `None` if the system could not be solved.
+    fn solve_damped(&mut self, diag: &OVector<F, N>, lambda: F) -> Option<LMParameter<F, N>>;
+
+    /// Estimate the largest and the smallest singular value of `$\mathbf{J}\mathbf{D}^{-1}$`.
+    ///
+    /// Returns `None` if no estimate is available.
+    fn singular_value_estimates(&self, diag: &OVector<F, N>) -> Option<(F, F)>;
+}
+
+impl<F, M, N> Linearization<F, N> for LinearLeastSquaresDiagonalProblem<F, M, N>
+where
+    F: RealField + Float,
+    N: Dim,
+    M: Dim + DimMin<N> + DimMax<N>,
+    DefaultAllocator: Allocator<F, N> + Allocator<F, DimMaximum<M, N>, N> + Allocator<usize, N>,
+{
+    fn column_norms(&self) -> &OVector<F, N> {
+        &self.column_norms
+    }
+
+    fn max_a_t_b_scaled(&mut self, b_norm: F) -> Option<F> {
+        LinearLeastSquaresDiagonalProblem::max_a_t_b_scaled(self, b_norm)
+    }
+
+    fn a_x_norm(&mut self, x: &OVector<F, N>) -> F {
+        LinearLeastSquaresDiagonalProblem::a_x_norm(self, x)
+    }
+
+    fn a_t_b(&self) -> OVector<F, N> {
+        LinearLeastSquaresDiagonalProblem::a_t_b(self)
+    }
+
+    fn solve_trust_region(
+        &mut self,
+        diag: &OVector<F, N>,
+        delta: F,
+        initial_lambda: F,
+        max_iterations: usize,
+        min_norm: bool,
+    ) -> LMParameter<F, N> {
+        determine_lambda_and_parameter_update(
+            self,
+            diag,
+            delta,
+            initial_lambda,
+            max_iterations,
+            min_norm,
+        )
+    }
+
+    fn solve_damped(&mut self, diag: &OVector<F, N>, lambda: F) -> Option<LMParameter<F, N>> {
+        debug_assert!(lambda.is_positive());
+        let diag_l = diag * Float::sqrt(lambda);
+        let (step, _) = self.solve_with_diagonal(&diag_l, diag_l.clone());
+        let dp_norm = enorm(&step.component_mul(diag));
+        Some(LMParameter {
+            step,
+            lambda,
+            dp_norm,
+            lambda_iterations: 1,
+        })
+    }
+
+    fn singular_value_estimates(&self, diag: &OVector<F, N>) -> Option<(F, F)> {
+        Some(LinearLeastSquaresDiagonalProblem::singular_value_estimates(
+            self, diag,
+        ))
+    }
 }
 
 /// Approximately solve the LM trust-region subproblem.
@@ -40,6 +150,16 @@ where
 /// The matrix `$\mathbf{F}$` and vector `$\vec{r}$` correspond to `$\mathbf{A}$` and
 /// `$\vec{b}$` of [`LinearLeastSquaresDiagonalProblem`](../qr/struct.LinearLeastSquaresDiagonalProblem.html).
 ///
+/// At most `max_iterations` values of `$\lambda$` are tried. If the iteration
+/// did not converge by then, the step for the last `$\lambda$` is returned.
+/// `MINPACK` uses 10 iterations.
+///
+/// If `$\mathbf{J}$` is rank deficient, for example if `$m < n$`, the system
+/// for `$\lambda = 0$` has many solutions. Like `MINPACK`, a basic solution
+/// is tried by default. With `min_norm`, the solution with the smallest
+/// `$\|\mathbf{D}\vec{p}\|$` is tried instead, which is the limit of the
+/// steps for `$\lambda \to 0$`.
+///
 /// # Reference
 ///
 /// This method resembles `LMPAR` from `MINPACK`. See the following paper
@@ -54,6 +174,8 @@ pub fn determine_lambda_and_parameter_update<F, M, N>(
     diag: &OVector<F, N>,
     delta: F,
     initial_lambda: F,
+    max_iterations: usize,
+    min_norm: bool,
 ) -> LMParameter<F, N>
 where
     F: RealField + Float,
@@ -64,10 +186,15 @@ where
     const P1: f64 = 0.1;
     debug_assert!(delta.is_positive());
     debug_assert!(initial_lambda >= F::zero());
+    debug_assert!(max_iterations > 0);
     debug_assert!(!diag.iter().any(F::is_zero));
 
     let is_non_singular = lls.is_non_singular();
+    let min_norm_step = (min_norm && !is_non_singular).then(|| lls.solve_min_norm(diag));
     let (mut p, mut l) = lls.solve_with_zero_diagonal();
+    if let Some(step) = min_norm_step {
+        p = step;
+    }
     let mut diag_p = p.component_mul(diag);
     let mut diag_p_norm = enorm(&diag_p);
     let mut fp = diag_p_norm - delta;
@@ -77,6 +204,7 @@ where
             step: p,
             lambda: F::zero(),
             dp_norm: diag_p_norm,
+            lambda_iterations: 0,
         };
     }
 
@@ -117,7 +245,9 @@ where
         lambda = gnorm / diag_p_norm;
     }
 
+    let mut lambda_iterations = 0;
     for iteration in 1.. {
+        lambda_iterations = iteration;
         if lambda.is_zero() {
             lambda = Float::max(dwarf(), lambda_upper * convert(0.001));
         }
@@ -127,7 +257,7 @@ where
         p = p_new;
         diag_p = p.component_mul(diag);
         diag_p_norm = enorm(&diag_p);
-        if iteration == 10 {
+        if iteration == max_iterations {
             break;
         }
         let fp_old = fp;
@@ -161,6 +291,269 @@ where
         step: p,
         lambda,
         dp_norm: diag_p_norm,
+        lambda_iterations,
+    }
+}
+
+/// A solver for the trust-region subproblem of an iteration.
+///
+/// Every iteration approximately solves
+/// ```math
+///   \min_{\vec{p}}\|\vec{r} + \mathbf{J}\vec{p}\|^2\text{ subject to }\|\mathbf{D}\vec{p}\|\leq\Delta
+/// ```
+/// for the residuals `$\vec{r}$`, the Jacobian `$\mathbf{J}$`, the diagonal
+/// `$\mathbf{D}$` and the radius `$\Delta$`. The built-in solvers are
+/// [`Levenberg`](struct.Levenberg.html), the default, and
+/// [`Dogleg`](struct.Dogleg.html). Attach a solver with
+/// [`LM::with_trust_region_solver`](struct.LM.html#method.with_trust_region_solver).
+pub trait TrustRegionSubproblem<F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Compute the step `$\vec{p}$` for the new parameters `$\vec{x} + \vec{p}$`.
+    ///
+    /// `model` provides `$\mathbf{J}$` and `$\vec{r}$` only through products,
+    /// so they need not be available as matrices. The step should satisfy
+    /// `$\|\mathbf{D}\vec{p}\|\leq\Delta$` (`radius`), the algorithm only
+    /// checks that the step is finite.
+    fn solve(
+        &mut self,
+        model: &mut TrustRegionModel<'_, F, N>,
+        diag: &OVector<F, N>,
+        radius: F,
+    ) -> TrustRegionStep<F, N>;
+}
+
+/// The linearization of the residuals at the current parameters.
+///
+/// It is passed to a [`TrustRegionSubproblem`](trait.TrustRegionSubproblem.html).
+pub struct TrustRegionModel<'a, F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    linearization: &'a mut dyn Linearization<F, N>,
+    residuals_norm: F,
+    lambda: F,
+    max_lambda_iterations: usize,
+    min_norm: bool,
+}
+
+impl<'a, F, N> TrustRegionModel<'a, F, N>
+where
+    F: RealField + Float,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    pub(crate) fn new(
+        linearization: &'a mut dyn Linearization<F, N>,
+        residuals_norm: F,
+        lambda: F,
+        max_lambda_iterations: usize,
+        min_norm: bool,
+    ) -> Self {
+        Self {
+            linearization,
+            residuals_norm,
+            lambda,
+            max_lambda_iterations,
+            min_norm,
+        }
+    }
+
+    /// The norm `$\|\vec{r}\|$` of the residuals.
+    pub fn residuals_norm(&self) -> F {
+        self.residuals_norm
+    }
+
+    /// The gradient `$\mathbf{J}^\top\vec{r}$` of the objective function.
+    pub fn gradient(&self) -> OVector<F, N> {
+        self.linearization.a_t_b()
+    }
+
+    /// Compute `$\|\mathbf{J}\vec{x}\|$`.
+    pub fn jacobian_product_norm(&mut self, x: &OVector<F, N>) -> F {
+        self.linearization.a_x_norm(x)
+    }
+
+    /// The predicted relative reduction
+    /// `$1 - \|\vec{r} + \mathbf{J}\vec{p}\|^2/\|\vec{r}\|^2$` of the objective
+    /// function for the step `$\vec{p}$`.
+    pub fn predicted_reduction(&mut self, step: &OVector<F, N>) -> F {
+        let jp = self.jacobian_product_norm(step);
+        let gp = self.gradient().dot(step);
+        let two: F = convert(2.);
+        -(two * gp + jp * jp) / (self.residuals_norm * self.residuals_norm)
+    }
+
+    /// Solve `$(\mathbf{J}^\top\mathbf{J} + \lambda\mathbf{D}\mathbf{D})\vec{p} = -\mathbf{J}^\top\vec{r}$`
+    /// for `$\lambda > 0$`.
+    ///
+    /// Returns `None` if the system could not be solved.
+    pub fn solve_damped(&mut self, diag: &OVector<F, N>, lambda: F) -> Option<OVector<F, N>> {
+        let param = self.linearization.solve_damped(diag, lambda)?;
+        Some(-param.step)
+    }
+
+    /// The solution of `$\min_{\vec{p}}\|\vec{r} + \mathbf{J}\vec{p}\|$`, the
+    /// basic one if `$\mathbf{J}$` is rank deficient.
+    pub fn gauss_newton_step(&mut self, diag: &OVector<F, N>) -> OVector<F, N> {
+        let param = self.linearization.solve_trust_region(
+            diag,
+            Float::max_value(),
+            F::zero(),
+            self.max_lambda_iterations,
+            self.min_norm,
+        );
+        -param.step
+    }
+}
+
+/// The result of a [`TrustRegionSubproblem`](trait.TrustRegionSubproblem.html).
+pub struct TrustRegionStep<F: RealField, N: Dim>
+where
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// The step with the sign of the algorithm, `$\vec{x} - \vec{p}$`
+    parameter: LMParameter<F, N>,
+    predicted_reduction: F,
+    /// Set for the steps of `Levenberg`
+    levenberg: bool,
+}
+
+impl<F, N> TrustRegionStep<F, N>
+where
+    F: RealField + Float,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    /// Create the result for the step `$\vec{p}$` and its predicted relative
+    /// reduction `$1 - \|\vec{r} + \mathbf{J}\vec{p}\|^2/\|\vec{r}\|^2$`.
+    ///
+    /// See [`TrustRegionModel::predicted_reduction`](struct.TrustRegionModel.html#method.predicted_reduction).
+    pub fn new(step: OVector<F, N>, predicted_reduction: F) -> Self {
+        Self {
+            parameter: LMParameter {
+                step: -step,
+                lambda: F::zero(),
+                dp_norm: F::nan(),
+                lambda_iterations: 0,
+            },
+            predicted_reduction,
+            levenberg: false,
+        }
+    }
+
+    /// The step `$\vec{p}$`.
+    pub fn step(&self) -> OVector<F, N> {
+        -self.parameter.step.clone()
+    }
+
+    /// The predicted relative reduction of the objective function.
+    pub fn predicted_reduction(&self) -> F {
+        self.predicted_reduction
+    }
+
+    /// The parameter of the algorithm and the predicted reduction, `None`
+    /// for a step of `Levenberg` which is predicted like the default.
+    pub(crate) fn into_parameter(self, diag: &OVector<F, N>) -> (LMParameter<F, N>, Option<F>) {
+        if self.levenberg {
+            return (self.parameter, None);
+        }
+        let mut parameter = self.parameter;
+        parameter.dp_norm = enorm(&parameter.step.component_mul(diag));
+        (parameter, Some(self.predicted_reduction))
+    }
+}
+
+/// The Levenberg-Marquardt step, the default solver.
+///
+/// See [`determine_lambda_and_parameter_update`](fn.determine_lambda_and_parameter_update.html).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Levenberg;
+
+impl<F, N> TrustRegionSubproblem<F, N> for Levenberg
+where
+    F: RealField + Float,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn solve(
+        &mut self,
+        model: &mut TrustRegionModel<'_, F, N>,
+        diag: &OVector<F, N>,
+        radius: F,
+    ) -> TrustRegionStep<F, N> {
+        let parameter = model.linearization.solve_trust_region(
+            diag,
+            radius,
+            model.lambda,
+            model.max_lambda_iterations,
+            model.min_norm,
+        );
+        // J^T r = (J^T J + lambda D D) p for the step x - p
+        let jp = model.linearization.a_x_norm(&parameter.step) / model.residuals_norm;
+        let dp = Float::sqrt(parameter.lambda) * parameter.dp_norm / model.residuals_norm;
+        let two: F = convert(2.);
+        TrustRegionStep {
+            predicted_reduction: jp * jp + two * dp * dp,
+            parameter,
+            levenberg: true,
+        }
+    }
+}
+
+/// Powell's dogleg step.
+///
+/// The step follows the path from `$\vec{0}$` to the Cauchy point, the
+/// minimizer along the scaled steepest descent direction
+/// `$-\mathbf{D}^{-2}\mathbf{J}^\top\vec{r}$`, and on to the Gauss-Newton
+/// step, until it leaves the trust region. This only needs one
+/// factorization per iteration, but the steps are worse than the
+/// Levenberg-Marquardt steps if the trust region is small.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dogleg;
+
+impl<F, N> TrustRegionSubproblem<F, N> for Dogleg
+where
+    F: RealField + Float,
+    N: Dim,
+    DefaultAllocator: Allocator<F, N>,
+{
+    fn solve(
+        &mut self,
+        model: &mut TrustRegionModel<'_, F, N>,
+        diag: &OVector<F, N>,
+        radius: F,
+    ) -> TrustRegionStep<F, N> {
+        let gauss_newton = model.gauss_newton_step(diag);
+        let step = if enorm(&gauss_newton.component_mul(diag)) <= radius {
+            gauss_newton
+        } else {
+            let gradient = model.gradient();
+            let descent = -gradient.component_div(diag).component_div(diag);
+            let scaled_norm = enorm(&descent.component_mul(diag));
+            let curvature = model.jacobian_product_norm(&descent);
+            if scaled_norm.is_zero() || curvature.is_zero() {
+                descent
+            } else {
+                let t = Float::powi(scaled_norm / curvature, 2);
+                if t * scaled_norm >= radius {
+                    descent * (radius / scaled_norm)
+                } else {
+                    // the point on the segment to the Gauss-Newton step with ||Dp|| = radius
+                    let cauchy = descent * t;
+                    let dc = cauchy.component_mul(diag);
+                    let dd = (&gauss_newton - &cauchy).component_mul(diag);
+                    let a = dd.norm_squared();
+                    let b = dc.dot(&dd);
+                    let c = dc.norm_squared() - radius * radius;
+                    let tau = (Float::sqrt(b * b - a * c) - b) / a;
+                    (gauss_newton - &cauchy) * tau + cauchy
+                }
+            }
+        };
+        let predicted_reduction = model.predicted_reduction(&step);
+        TrustRegionStep::new(step, predicted_reduction)
     }
 }
 
@@ -181,7 +574,7 @@ mod tests {
         let qr = PivotedQR::new(j);
         let mut lls = qr.into_least_squares_diagonal_problem(residual);
         let diag = Vector3::new(18.2, 18.2, 3.2);
-        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2);
+        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2, 10, false);
 
         assert_relative_eq!(param.lambda, 34.628643558156341f64);
         let p_r = Vector3::new(0.017591648698939, -0.020395135814051, 0.059285196018896);
@@ -198,7 +591,7 @@ mod tests {
         let qr = PivotedQR::new(j);
         let mut lls = qr.into_least_squares_diagonal_problem(residual);
         let diag = Vector3::new(10.2, 13.2, 1.2);
-        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2f64);
+        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2f64, 10, false);
 
         assert_eq!(param.lambda.classify(), ::core::num::FpCategory::Zero);
         let p_r = Vector3::new(-0.048474221517806, -0.007207732068190, 0.083138659283539);
@@ -215,7 +608,7 @@ mod tests {
         let qr = PivotedQR::new(j);
         let mut lls = qr.into_least_squares_diagonal_problem(residual);
         let diag = Vector3::new(4.2, 8.2, 11.2);
-        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2);
+        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2, 10, false);
 
         assert_relative_eq!(param.lambda, 0.017646940861467262f64, epsilon = 1e-14);
         let p_r = Vector3::new(-0.008462374169585, 0.033658082419054, 0.037230479167632);
@@ -232,10 +625,37 @@ mod tests {
         let qr = PivotedQR::new(j);
         let mut lls = qr.into_least_squares_diagonal_problem(residual);
         let diag = Vector3::new(6.2, 1.2, 0.2);
-        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2);
+        let param = determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0.2, 10, false);
 
         assert_relative_eq!(param.lambda, 0.);
         let p_r = Vector3::new(-0.000277548738904, -0.046232379576219, 0.266724338086713);
         assert_relative_eq!(param.step, p_r, epsilon = 1e-14);
     }
+
+    #[test]
+    fn test_max_iterations() {
+        // nearly rank deficient, the search for lambda needs five iterations
+        let j = Matrix4x3::from_column_slice(&[1., 1., 1., 1., 1., 1., 1., 1., 1e-8, 0., 0., 0.]);
+        let residual = Vector4::new(7., -1., 1., -1.);
+        let diag = Vector3::new(1., 1., 1.);
+        let solve = |max_iterations| {
+            let qr = PivotedQR::new(j);
+            let mut lls = qr.into_least_squares_diagonal_problem(residual);
+            determine_lambda_and_parameter_update(&mut lls, &diag, 0.5, 0., max_iterations, false)
+        };
+
+        let converged = solve(10);
+        assert_relative_eq!(solve(5).lambda, converged.lambda);
+        let mut previous_lambda = 0.;
+        for max_iterations in 1..5 {
+            let param = solve(max_iterations);
+            // the search stopped early with the best lambda so far
+            assert!(param.lambda > previous_lambda);
+            assert!(param.lambda < converged.lambda);
+            previous_lambda = param.lambda;
+            // the step can still be used
+            assert!(param.step.iter().all(|p| p.is_finite()));
+            assert!((j * param.step - residual).norm() < residual.norm());
+        }
+    }
 }
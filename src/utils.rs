@@ -1,9 +1,9 @@
 use crate::LeastSquaresProblem;
-use alloc::{format, string::String};
+use alloc::{format, string::String, vec::Vec};
 use core::cell::RefCell;
 use nalgebra::{
     allocator::Allocator, convert, storage::RawStorage, storage::Storage, Complex, ComplexField,
-    DefaultAllocator, Dim, Matrix, OMatrix, RealField, Vector, U1,
+    DefaultAllocator, Dim, Matrix, OMatrix, OVector, RealField, Vector, U1,
 };
 use num_traits::float::Float;
 
@@ -108,7 +108,6 @@ where
     N: Dim,
     M: Dim,
     O: LeastSquaresProblem<F, M, N>,
-    O::JacobianStorage: Clone,
     DefaultAllocator: Allocator<F, M, N, Buffer = O::JacobianStorage>,
 {
     let params = problem.params();
@@ -135,6 +134,136 @@ where
     Some(jacobian)
 }
 
+/// Compute a numerical approximation of the Jacobian with one-sided differences.
+///
+/// Works like [`differentiate_numerically`](fn.differentiate_numerically.html)
+/// but column `$j$` is approximated with forward differences if `directions[j]`
+/// is positive and with backward differences if it is negative. The residuals
+/// are then only evaluated at `$\vec{x}$` and on the chosen side of `$x_j$`,
+/// up to `$\max\{\ln(1 + |x_j|), 1\}$` away from it. Use this if some
+/// parameters are close to the boundary of the domain of the residuals: choose
+/// backward differences close to an upper bound and forward differences
+/// close to a lower bound.
+///
+/// The one-sided approximation is less precise than the central one.
+///
+/// # Panics
+///
+/// Panics if the length of `directions` differs from the number of parameters
+/// or if an entry is zero or `NaN`.
+pub fn differentiate_numerically_one_sided<F, N, M, O>(
+    problem: &mut O,
+    directions: &[F],
+) -> Option<Matrix<F, M, N, O::JacobianStorage>>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, M, N, Buffer = O::JacobianStorage>,
+{
+    let params = problem.params();
+    let n = params.data.shape().0;
+    assert_eq!(
+        directions.len(),
+        n.value(),
+        "directions must have one entry per parameter"
+    );
+    assert!(
+        directions
+            .iter()
+            .all(|d| d.is_positive() || d.is_negative()),
+        "directions must be positive or negative"
+    );
+    let m = problem.residuals()?.data.shape().0;
+    let params = RefCell::new(params);
+    let problem = RefCell::new(problem);
+    let mut jacobian = Matrix::<F, M, N, O::JacobianStorage>::zeros_generic(m, n);
+    for (j, direction) in directions.iter().enumerate() {
+        let x = params.borrow()[j];
+        for i in 0..m.value() {
+            let f = |x| {
+                params.borrow_mut()[j] = x;
+                let mut problem = problem.borrow_mut();
+                problem.set_params(&params.borrow());
+                problem.residuals().map(|v| v[i])
+            };
+            let derivative = finite_difference::one_sided_derivative(x, direction.is_positive(), f);
+            match derivative {
+                Some(derivative) => jacobian[(i, j)] = derivative,
+                None => {
+                    params.borrow_mut()[j] = x;
+                    problem.borrow_mut().set_params(&params.borrow());
+                    return None;
+                }
+            }
+        }
+        params.borrow_mut()[j] = x;
+    }
+    // reset the initial params
+    problem.borrow_mut().set_params(&params.borrow());
+    Some(jacobian)
+}
+
+/// Estimate the error of a finite-difference Jacobian for every column.
+///
+/// Column `$j$` is approximated with central differences for the steps
+/// `$h = \varepsilon^{1/3}\max\{|x_j|, 1\}$` and `$h/2$`. For smooth
+/// residuals the error of central differences shrinks with `$h^2$`, so by
+/// Richardson extrapolation the error of the approximation with `$h/2$` is
+/// about a third of the difference between the two. The Euclidean norm of
+/// this difference over the column, divided by three, is returned.
+///
+/// Compare the estimates with the column norms of the Jacobian. A large
+/// relative error means that the residuals are noisy on the scale of the
+/// step, so finite differences are unreliable and so are the standard errors
+/// computed from such a Jacobian. Consider an analytic Jacobian, complex steps
+/// ([`differentiate_holomorphic_numerically`](fn.differentiate_holomorphic_numerically.html))
+/// or automatic differentiation instead.
+///
+/// The residuals are evaluated four times per parameter and the parameters
+/// are restored afterwards. Returns `None` if the residuals cannot be computed
+/// or their number changes.
+pub fn finite_difference_error<F, N, M, O>(problem: &mut O) -> Option<OVector<F, N>>
+where
+    F: RealField + Float + Copy,
+    N: Dim,
+    M: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, N>,
+{
+    let mut params = problem.params();
+    let n = params.data.shape().0;
+    let mut errors = OVector::<F, N>::zeros_generic(n, Dim::from_usize(1));
+    let two: F = convert(2.);
+    for j in 0..n.value() {
+        let x = params[j];
+        let h = Float::cbrt(F::default_epsilon()) * Float::max(Float::abs(x), F::one());
+        let mut residuals = Vec::with_capacity(4);
+        for step in [h, -h, h / two, -h / two] {
+            params[j] = x + step;
+            problem.set_params(&params);
+            residuals.push(problem.residuals());
+        }
+        params[j] = x;
+        problem.set_params(&params);
+        let residuals = residuals.into_iter().collect::<Option<Vec<_>>>()?;
+        let [plus, minus, half_plus, half_minus] = [0, 1, 2, 3].map(|k| residuals[k].as_slice());
+        let m = plus.len();
+        if [minus, half_plus, half_minus].iter().any(|r| r.len() != m) {
+            return None;
+        }
+        let mut sum = F::zero();
+        for (((p, q), hp), hq) in plus.iter().zip(minus).zip(half_plus).zip(half_minus) {
+            let coarse = (*p - *q) / (two * h);
+            let fine = (*hp - *hq) / h;
+            sum += Float::powi(coarse - fine, 2);
+        }
+        errors[j] = Float::sqrt(sum) / convert(3.);
+    }
+    Some(errors)
+}
+
 /// Compute a numerical approximation of the Jacobian for _holomorphic_ residuals.
 ///
 /// This method is _much_ more precise than
@@ -242,6 +371,83 @@ where
     Some(jacobian)
 }
 
+/// Compute the gradient `$\mathbf{J}^\top\vec{r}$` of the objective function
+/// `$\frac{1}{2}\|\vec{r}\|^2$` at the current parameters.
+///
+/// This evaluates the residuals and the Jacobian of `problem` once, without
+/// running a minimization. Use it to check a new
+/// [`LeastSquaresProblem`](trait.LeastSquaresProblem.html) before fitting,
+/// for example against the gradient from the Jacobian of
+/// [`differentiate_numerically`](fn.differentiate_numerically.html).
+///
+/// Returns `None` if the residuals or the Jacobian cannot be computed or the
+/// number of rows of the Jacobian is not the number of residuals.
+pub fn objective_gradient<F, N, M, O>(problem: &O) -> Option<OVector<F, N>>
+where
+    F: RealField + Copy,
+    N: Dim,
+    M: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, N>,
+{
+    let residuals = problem.residuals()?;
+    let jacobian = problem.jacobian_with_residuals(&residuals)?;
+    if jacobian.nrows() != residuals.nrows() {
+        return None;
+    }
+    Some(jacobian.tr_mul(&residuals))
+}
+
+/// Compute the Jacobian at the parameters `at` instead of the current ones.
+///
+/// The parameters of `problem` are set to `at` for the evaluation and
+/// restored afterwards, also if the Jacobian cannot be computed. Use it to
+/// analyze a fit at a point which is not the solution, for example at the
+/// mean of a prior.
+///
+/// Returns `None` if the Jacobian cannot be computed at `at`.
+pub fn evaluate_jacobian<F, N, M, O>(
+    problem: &mut O,
+    at: &Vector<F, N, O::ParameterStorage>,
+) -> Option<Matrix<F, M, N, O::JacobianStorage>>
+where
+    F: RealField + Copy,
+    N: Dim,
+    M: Dim,
+    O: LeastSquaresProblem<F, M, N>,
+{
+    let params = problem.params();
+    problem.set_params(at);
+    let jacobian = problem.jacobian();
+    problem.set_params(&params);
+    jacobian
+}
+
+/// The SplitMix64 generator, for perturbations reproducible from a seed.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed number in `$[-1, 1)$`.
+    pub(crate) fn next_symmetric<F: RealField>(&mut self) -> F {
+        // the upper 53 bits give a uniform number in [0, 1)
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        convert(2. * unit - 1.)
+    }
+}
+
 #[inline]
 #[allow(clippy::unreadable_literal)]
 pub(crate) fn epsmch<F: RealField>() -> F {
@@ -403,6 +609,67 @@ fn test_linear_case() {
     assert_relative_eq!(jac_num, jac_trait, epsilon = 1e-12);
 }
 
+#[test]
+fn test_objective_gradient() {
+    use crate::lm::test_examples::LinearFullRank;
+    use approx::assert_relative_eq;
+    use nalgebra::{DMatrix, DVector, OVector, U5};
+    let x = OVector::<f64, U5>::new(1., -2., 0.5, 3., -10.);
+    let problem = LinearFullRank { params: x, m: 7 };
+    // r = A x - 1 with A = [I; 0] - 2/m
+    let a = DMatrix::<f64>::from_fn(7, 5, |i, j| f64::from(u8::from(i == j)) - 2. / 7.);
+    let x = DVector::from_column_slice(x.as_slice());
+    let expected = a.transpose() * (&a * &x - DVector::from_element(7, 1.));
+    let gradient = objective_gradient(&problem).unwrap();
+    assert_relative_eq!(gradient.as_slice(), expected.as_slice(), epsilon = 1e-12);
+
+    // the same as with the numerical Jacobian
+    let mut problem = problem;
+    let jacobian = differentiate_numerically(&mut problem).unwrap();
+    let numerical = jacobian.tr_mul(&problem.residuals().unwrap());
+    assert_relative_eq!(gradient, numerical, epsilon = 1e-10);
+}
+
+#[test]
+fn test_evaluate_jacobian() {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, Matrix2, Vector2, U2};
+    /// `$\vec{r} = (x_1 x_2, \sin x_1)$`
+    struct Product {
+        params: Vector2<f64>,
+    }
+    impl LeastSquaresProblem<f64, U2, U2> for Product {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, params: &Vector2<f64>) {
+            self.params.copy_from(params);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            Some(Vector2::new(a * b, a.sin()))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            Some(Matrix2::new(b, a, a.cos(), 0.))
+        }
+    }
+    let x = Vector2::new(0.5, -2.);
+    let at = Vector2::new(3., 4.);
+    let mut problem = Product { params: x };
+    let jacobian = evaluate_jacobian(&mut problem, &at).unwrap();
+    assert_eq!(problem.params(), x);
+    assert_relative_eq!(jacobian, Product { params: at }.jacobian().unwrap());
+    assert_ne!(jacobian, problem.jacobian().unwrap());
+}
+
 #[test]
 fn test_reset_parameters() {
     use approx::assert_relative_eq;
@@ -443,3 +710,173 @@ fn test_reset_parameters() {
     let jac_trait = problem.jacobian().unwrap();
     assert_relative_eq!(jac_num, jac_trait, epsilon = 1e-12);
 }
+
+#[test]
+fn test_one_sided_near_boundary() {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, Matrix2, OVector, Vector2, U2};
+    /// Only defined for `$x_1 \leq 1$` and `$x_2 \geq 0$`.
+    struct Bounded {
+        params: OVector<f64, U2>,
+    }
+    impl LeastSquaresProblem<f64, U2, U2> for Bounded {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, params: &OVector<f64, U2>) {
+            self.params.copy_from(params);
+        }
+
+        fn params(&self) -> OVector<f64, U2> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<OVector<f64, U2>> {
+            let [x1, x2] = [self.params.x, self.params.y];
+            if x1 > 1. || x2 < 0. {
+                return None;
+            }
+            Some(Vector2::new((1. - x1).sqrt() + x2, x1 * x2.sqrt()))
+        }
+
+        #[rustfmt::skip]
+        fn jacobian(&self) -> Option<OMatrix<f64, U2, U2>> {
+            let [x1, x2] = [self.params.x, self.params.y];
+            Some(Matrix2::new(
+                -0.5 / (1. - x1).sqrt(), 1.,
+                x2.sqrt(), 0.5 * x1 / x2.sqrt(),
+            ))
+        }
+    }
+    let params = Vector2::new(0.9, 0.2);
+    assert!(differentiate_numerically(&mut Bounded { params }).is_none());
+    let mut problem = Bounded { params };
+    assert!(differentiate_numerically_one_sided(&mut problem, &[1., 1.]).is_none());
+    // the parameters are restored after the failure
+    assert_eq!(problem.params, params);
+
+    let jac_num = differentiate_numerically_one_sided(&mut problem, &[-1., 1.]).unwrap();
+    let jac_trait = problem.jacobian().unwrap();
+    assert_relative_eq!(jac_num, jac_trait, epsilon = 1e-9);
+    assert_eq!(problem.params, params);
+}
+
+#[test]
+fn test_finite_difference_error() {
+    use nalgebra::{storage::Owned, OVector, Vector2, U2};
+    /// Smooth residuals with a wiggle of amplitude `noise` in `$x_1$`.
+    struct Wiggly {
+        params: OVector<f64, U2>,
+        noise: f64,
+    }
+    impl LeastSquaresProblem<f64, U2, U2> for Wiggly {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, params: &OVector<f64, U2>) {
+            self.params.copy_from(params);
+        }
+
+        fn params(&self) -> OVector<f64, U2> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<OVector<f64, U2>> {
+            let [x1, x2] = [self.params.x, self.params.y];
+            Some(Vector2::new(
+                x1.exp() - x2 * x2 + self.noise * (1e7 * x1).sin(),
+                (x1 * x2).sin(),
+            ))
+        }
+
+        fn jacobian(&self) -> Option<OMatrix<f64, U2, U2>> {
+            None
+        }
+    }
+    let params = Vector2::new(0.3, -1.2);
+    let mut smooth = Wiggly { params, noise: 0. };
+    let errors = finite_difference_error(&mut smooth).unwrap();
+    assert!(errors.iter().all(|error| *error < 1e-9));
+    assert_eq!(smooth.params, params);
+
+    // noise of 1e-8 in the residuals spoils the first column
+    let mut noisy = Wiggly {
+        params,
+        noise: 1e-8,
+    };
+    let errors = finite_difference_error(&mut noisy).unwrap();
+    assert!(errors.x > 1e-4);
+    assert!(errors.y < 1e-9);
+    assert_eq!(noisy.params, params);
+}
+
+/// `enorm` of MINPACK, transcribed from the Fortran source.
+#[cfg(test)]
+pub(crate) fn minpack_enorm(x: &[f64]) -> f64 {
+    let (rdwarf, rgiant) = (3.834e-20, 1.304e19);
+    let (mut s1, mut s2, mut s3) = (0f64, 0f64, 0f64);
+    let (mut x1max, mut x3max) = (0f64, 0f64);
+    let agiant = rgiant / x.len() as f64;
+    for &xi in x {
+        let xabs = xi.abs();
+        if xabs > rdwarf && xabs < agiant {
+            s2 += xabs * xabs;
+        } else if xabs <= rdwarf {
+            if xabs > x3max {
+                s3 = 1. + s3 * (x3max / xabs).powi(2);
+                x3max = xabs;
+            } else if xabs != 0. {
+                s3 += (xabs / x3max).powi(2);
+            }
+        } else if xabs > x1max {
+            s1 = 1. + s1 * (x1max / xabs).powi(2);
+            x1max = xabs;
+        } else {
+            s1 += (xabs / x1max).powi(2);
+        }
+    }
+    if s1 != 0. {
+        x1max * (s1 + (s2 / x1max) / x1max).sqrt()
+    } else if s2 != 0. {
+        if s2 >= x3max {
+            (s2 * (1. + (x3max / s2) * (x3max * s3))).sqrt()
+        } else {
+            (x3max * ((s2 / x3max) + (x3max * s3))).sqrt()
+        }
+    } else {
+        x3max * s3.sqrt()
+    }
+}
+
+#[test]
+fn test_enorm_matches_minpack() {
+    use nalgebra::DVector;
+    let cases: [&[f64]; 8] = [
+        &[3., -4.],
+        &[1e-300, 1e300, 1., -7.5],
+        &[2e19, 3e-21, 5., 1e-200, -1e200],
+        &[1e-25, -3e-22, 4e-310, 0.],
+        &[1e-25, 1.],
+        &[1e150, 1e160, -1e170, 2.5e-160],
+        &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7],
+        &[0., 0., 0.],
+    ];
+    for case in cases {
+        let norm = enorm(&DVector::from_column_slice(case));
+        let expected = minpack_enorm(case);
+        if cfg!(feature = "minpack-compat") {
+            assert_eq!(norm.to_bits(), expected.to_bits(), "{:?}", case);
+        } else {
+            // only the thresholds of the three sums differ
+            assert!(
+                (norm - expected).abs() <= 4. * f64::EPSILON * expected,
+                "{:?}: {} != {}",
+                case,
+                norm,
+                expected
+            );
+        }
+    }
+}
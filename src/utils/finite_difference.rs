@@ -30,11 +30,39 @@ pub fn derivative<F: Float + RealField + Copy>(x: F, f: impl Fn(F) -> Option<F>)
         quotients.push(quot);
         h /= step_ratio;
     }
-    extrapolate(quotients)
+    extrapolate(quotients, 2, 2)
 }
 
-fn extrapolate<F: RealField + Float>(evaluations: Vec<F>) -> Option<F> {
-    let estimates = richardson_extrapolate(evaluations)?;
+/// Compute the derivative using an adaptive one-sided difference quotient.
+///
+/// Works like [`derivative`] but with first order forward differences, or
+/// backward differences if `forward` is `false`. The function is only evaluated
+/// at `x` and on that side of `x`, at most `max(ln(1 + |x|), 1)` away.
+pub fn one_sided_derivative<F: Float + RealField + Copy>(
+    x: F,
+    forward: bool,
+    f: impl Fn(F) -> Option<F>,
+) -> Option<F> {
+    const STEPS: usize = 15;
+    let step_ratio: F = convert(STEP_RATIO);
+    let mut quotients = Vec::with_capacity(STEPS);
+    let mut h = Float::max(Float::ln(F::one() + Float::abs(x)), F::one());
+    if !forward {
+        h = -h;
+    }
+    let f0 = f(x)?;
+    for _ in 0..STEPS {
+        let quot = (f(x + h)? - f0) / h;
+        quotients.push(quot);
+        h /= step_ratio;
+    }
+    extrapolate(quotients, 1, 1)
+}
+
+/// Extrapolate the quotients, the error of which has the terms
+/// `$h^{\mathtt{order}}, h^{\mathtt{order} + \mathtt{step}}, \ldots$`.
+fn extrapolate<F: RealField + Float>(evaluations: Vec<F>, step: i32, order: i32) -> Option<F> {
+    let estimates = richardson_extrapolate(evaluations, step, order)?;
     let num = estimates.len();
     if num <= 2 {
         return estimates.last().copied();
@@ -136,14 +164,16 @@ fn wynn_extrapolate<F: Float>(estimates: Vec<F>) -> Option<Vec<(F, F)>> {
     Some(derivatives)
 }
 
-fn richardson_extrapolate<F: RealField + Copy>(evaluations: Vec<F>) -> Option<Vec<F>> {
-    const STEP: i32 = 2;
-    const ORDER: i32 = 2;
+fn richardson_extrapolate<F: RealField + Copy>(
+    evaluations: Vec<F>,
+    step: i32,
+    order: i32,
+) -> Option<Vec<F>> {
     let step_ratio: F = convert(STEP_RATIO);
     if evaluations.len() <= 3 {
         return evaluations.last().map(|x| vec![*x]);
     }
-    let entry = |i: i32, j: i32| step_ratio.powi(-i * (STEP * j + ORDER));
+    let entry = |i: i32, j: i32| step_ratio.powi(-i * (step * j + order));
     #[rustfmt::skip]
     let r_matrix = Matrix3::new(
         F::one(),    F::one(),    F::one(),
@@ -247,3 +277,33 @@ fn test_multiscale() {
     let x0 = 0.03;
     assert_relative_eq!(derivative(x0, f).unwrap(), f_prime(x0), epsilon = 5e-11,);
 }
+
+#[test]
+fn test_one_sided() {
+    for forward in [true, false] {
+        assert_relative_eq!(
+            one_sided_derivative(0.123f64, forward, |x| Some(3. * x - 1.)).unwrap(),
+            3.,
+            epsilon = 1e-14
+        );
+        assert_relative_eq!(
+            one_sided_derivative(-1.2, forward, |x| Some(x.exp())).unwrap(),
+            (-1.2).exp(),
+            epsilon = 1e-10
+        );
+        assert_relative_eq!(
+            one_sided_derivative(3., forward, |x| Some(x * x)).unwrap(),
+            6.,
+            epsilon = 1e-10
+        );
+    }
+    // only evaluated on one side
+    let f = |x: f64| if x <= 1. { Some((1. - x).sqrt()) } else { None };
+    assert!(derivative(0.9, f).is_none());
+    assert!(one_sided_derivative(0.9, true, f).is_none());
+    assert_relative_eq!(
+        one_sided_derivative(0.9, false, f).unwrap(),
+        -0.5 / 0.1f64.sqrt(),
+        epsilon = 1e-9
+    );
+}
@@ -0,0 +1,413 @@
+//! Weighted least squares problems.
+use alloc::vec::Vec;
+use nalgebra::{allocator::Allocator, DefaultAllocator, Dim, Matrix, OVector, RealField, Vector};
+
+use crate::LeastSquaresProblem;
+
+/// A problem whose residuals are weighted individually.
+///
+/// For the weights `$w_i \geq 0$` the residuals and the rows of the Jacobian
+/// are scaled by `$\sqrt{w_i}$`, so the objective function is
+/// ```math
+///   \frac{1}{2}\sum_{i=1}^m w_i r_i(\vec{x})^2.
+/// ```
+/// For measurements with independent errors of standard deviation `$\sigma_i$`
+/// the weights `$w_i = 1/\sigma_i^2$` give the maximum likelihood estimate.
+///
+/// Create it with [`LeastSquaresProblem::with_weights`](trait.LeastSquaresProblem.html#method.with_weights)
+/// or [`LeastSquaresProblem::with_sigmas`](trait.LeastSquaresProblem.html#method.with_sigmas).
+/// A mask created with
+/// [`LeastSquaresProblem::with_active_residuals`](trait.LeastSquaresProblem.html#method.with_active_residuals)
+/// acts like the weights 0 and 1.
+/// If the number of weights does not match the number of residuals, the
+/// residuals and the Jacobian cannot be computed.
+pub struct Weighted<P, S> {
+    problem: P,
+    scale: S,
+}
+
+impl<P, S> Weighted<P, S> {
+    pub(crate) fn new(problem: P, scale: S) -> Self {
+        Self { problem, scale }
+    }
+
+    /// The wrapped problem.
+    pub fn inner(&self) -> &P {
+        &self.problem
+    }
+
+    /// Extract the wrapped problem.
+    pub fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, P> Weighted<P, OVector<F, M>>
+where
+    F: RealField + Copy,
+    M: Dim,
+    DefaultAllocator: Allocator<F, M>,
+{
+    /// The residuals `$\sqrt{w_i}\,r_i(\vec{x})$` at the current parameters.
+    ///
+    /// For weights created with
+    /// [`LeastSquaresProblem::with_sigmas`](trait.LeastSquaresProblem.html#method.with_sigmas)
+    /// these are the standardized residuals `$z_i = r_i/\sigma_i$`, in units of
+    /// the standard deviation of the measurement. Call it on the problem
+    /// returned by the minimization to flag outliers at the solution, for
+    /// example with `$|z_i| > 3$`.
+    ///
+    /// Returns `None` if the residuals could not be computed.
+    pub fn standardized_residuals<N>(&self) -> Option<OVector<F, M>>
+    where
+        N: Dim,
+        P: LeastSquaresProblem<F, M, N>,
+    {
+        let residuals = self.problem.residuals()?;
+        if residuals.nrows() != self.scale.nrows() {
+            return None;
+        }
+        Some(residuals.component_mul(&self.scale))
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, M, N> for Weighted<P, OVector<F, M>>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+    DefaultAllocator: Allocator<F, M>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        let mut residuals = self.problem.residuals()?;
+        if residuals.nrows() != self.scale.nrows() {
+            return None;
+        }
+        residuals.component_mul_assign(&self.scale);
+        Some(residuals)
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        if !self.problem.residuals_into(out) || out.nrows() != self.scale.nrows() {
+            return false;
+        }
+        out.component_mul_assign(&self.scale);
+        true
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let mut jacobian = self.problem.jacobian()?;
+        if jacobian.nrows() != self.scale.nrows() {
+            return None;
+        }
+        for mut column in jacobian.column_iter_mut() {
+            column.component_mul_assign(&self.scale);
+        }
+        Some(jacobian)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, M, N> for Weighted<P, Vec<bool>>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        let mut residuals = self.problem.residuals()?;
+        if residuals.nrows() != self.scale.len() {
+            return None;
+        }
+        for (r, active) in residuals.iter_mut().zip(self.scale.iter()) {
+            if !active {
+                *r = F::zero();
+            }
+        }
+        Some(residuals)
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        if !self.problem.residuals_into(out) || out.nrows() != self.scale.len() {
+            return false;
+        }
+        for (r, active) in out.iter_mut().zip(self.scale.iter()) {
+            if !active {
+                *r = F::zero();
+            }
+        }
+        true
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let mut jacobian = self.problem.jacobian()?;
+        if jacobian.nrows() != self.scale.len() {
+            return None;
+        }
+        for (i, active) in self.scale.iter().enumerate() {
+            if !active {
+                jacobian.row_mut(i).fill(F::zero());
+            }
+        }
+        Some(jacobian)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+/// A problem whose residuals are all multiplied by the same factor.
+pub(crate) struct Scaled<P, F> {
+    problem: P,
+    factor: F,
+}
+
+impl<P, F> Scaled<P, F> {
+    pub(crate) fn new(problem: P, factor: F) -> Self {
+        Self { problem, factor }
+    }
+
+    pub(crate) fn into_inner(self) -> P {
+        self.problem
+    }
+}
+
+impl<F, M, N, P> LeastSquaresProblem<F, M, N> for Scaled<P, F>
+where
+    F: RealField + Copy,
+    M: Dim,
+    N: Dim,
+    P: LeastSquaresProblem<F, M, N>,
+{
+    type ResidualStorage = P::ResidualStorage;
+    type JacobianStorage = P::JacobianStorage;
+    type ParameterStorage = P::ParameterStorage;
+
+    fn set_params(&mut self, x: &Vector<F, N, Self::ParameterStorage>) {
+        self.problem.set_params(x);
+    }
+
+    fn params(&self) -> Vector<F, N, Self::ParameterStorage> {
+        self.problem.params()
+    }
+
+    fn residuals(&self) -> Option<Vector<F, M, Self::ResidualStorage>> {
+        let mut residuals = self.problem.residuals()?;
+        residuals *= self.factor;
+        Some(residuals)
+    }
+
+    fn residuals_into(&self, out: &mut Vector<F, M, Self::ResidualStorage>) -> bool {
+        if !self.problem.residuals_into(out) {
+            return false;
+        }
+        *out *= self.factor;
+        true
+    }
+
+    fn jacobian(&self) -> Option<Matrix<F, M, N, Self::JacobianStorage>> {
+        let mut jacobian = self.problem.jacobian()?;
+        jacobian *= self.factor;
+        Some(jacobian)
+    }
+
+    fn jacobian_is_numerical(&self) -> bool {
+        self.problem.jacobian_is_numerical()
+    }
+
+    fn number_of_penalty_residuals(&self) -> usize {
+        self.problem.number_of_penalty_residuals()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use approx::assert_relative_eq;
+    use nalgebra::{storage::Owned, Matrix2, Matrix3x2, Vector2, Vector3, U2, U3};
+
+    use crate::{differentiate_numerically, LeastSquaresProblem, LevenbergMarquardt};
+
+    /// Three measurements of a line `$y = a t + b$`, the last one is an outlier.
+    struct Measurements {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U3, U2> for Measurements {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U3>;
+        type JacobianStorage = Owned<f64, U3, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector3<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            Some(Vector3::new(b - 1., a + b - 3., 2. * a + b - 7.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+            Some(Matrix3x2::new(
+                0., 1., //
+                1., 1., //
+                2., 1.,
+            ))
+        }
+    }
+
+    fn measurements() -> Measurements {
+        Measurements {
+            params: Vector2::zeros(),
+        }
+    }
+
+    #[test]
+    fn jacobian_matches_numerical() {
+        let mut problem = measurements().with_sigmas(Vector3::new(0.5, 1., 20.));
+        problem.set_params(&Vector2::new(0.3, -1.2));
+        let numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(problem.jacobian().unwrap(), numerical, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn sigmas_match_weights() {
+        let sigmas = Vector3::new(0.5, 1., 20.);
+        let lm = LevenbergMarquardt::new();
+        let (by_sigmas, report) = lm.minimize(measurements().with_sigmas(sigmas));
+        assert!(report.termination.was_successful());
+        let (by_weights, weights_report) =
+            lm.minimize(measurements().with_weights(sigmas.map(|s| 1. / (s * s))));
+        assert_eq!(by_sigmas.params(), by_weights.params());
+        assert_eq!(report.objective_function, weights_report.objective_function);
+        assert_eq!(
+            report.number_of_evaluations,
+            weights_report.number_of_evaluations
+        );
+
+        // the outlier hardly matters, the fit goes through the first two points
+        assert_relative_eq!(by_sigmas.params(), Vector2::new(2., 1.), epsilon = 2e-2);
+        let (unweighted, _) = lm.minimize(measurements());
+        assert!((unweighted.params - Vector2::new(2., 1.)).norm() > 0.1);
+    }
+
+    #[test]
+    fn standardized_residuals_at_solution() {
+        let sigmas = Vector3::new(0.5, 1., 20.);
+        let (problem, report) =
+            LevenbergMarquardt::new().minimize(measurements().with_sigmas(sigmas));
+        assert!(report.termination.was_successful());
+        let z = problem.standardized_residuals().unwrap();
+        let expected = problem.inner().residuals().unwrap().component_div(&sigmas);
+        assert_relative_eq!(z, expected, epsilon = 1e-15);
+        assert_relative_eq!(
+            z.norm_squared() / 2.,
+            report.objective_function,
+            epsilon = 1e-12
+        );
+        // in units of its sigma the outlier is not suspicious
+        assert!(z.iter().all(|z| z.abs() < 3.));
+    }
+
+    /// The first two measurements only.
+    struct FirstTwo {
+        params: Vector2<f64>,
+    }
+
+    impl LeastSquaresProblem<f64, U2, U2> for FirstTwo {
+        type ParameterStorage = Owned<f64, U2>;
+        type ResidualStorage = Owned<f64, U2>;
+        type JacobianStorage = Owned<f64, U2, U2>;
+
+        fn set_params(&mut self, x: &Vector2<f64>) {
+            self.params.copy_from(x);
+        }
+
+        fn params(&self) -> Vector2<f64> {
+            self.params
+        }
+
+        fn residuals(&self) -> Option<Vector2<f64>> {
+            let [a, b] = [self.params.x, self.params.y];
+            Some(Vector2::new(b - 1., a + b - 3.))
+        }
+
+        fn jacobian(&self) -> Option<Matrix2<f64>> {
+            Some(Matrix2::new(
+                0., 1., //
+                1., 1.,
+            ))
+        }
+    }
+
+    #[test]
+    fn mask_matches_removed_residual() {
+        let mask = [true, true, false];
+        let mut problem = measurements().with_active_residuals(&mask);
+        problem.set_params(&Vector2::new(0.3, -1.2));
+        let numerical = differentiate_numerically(&mut problem).unwrap();
+        assert_relative_eq!(problem.jacobian().unwrap(), numerical, epsilon = 1e-6);
+        assert_eq!(problem.residuals().unwrap().z, 0.);
+
+        let lm = LevenbergMarquardt::new();
+        let (masked, report) = lm.minimize(measurements().with_active_residuals(&mask));
+        assert!(report.termination.was_successful());
+        let (removed, expected) = lm.minimize(FirstTwo {
+            params: Vector2::zeros(),
+        });
+        assert!(expected.termination.was_successful());
+        assert_relative_eq!(masked.params(), removed.params(), epsilon = 1e-12);
+        assert_relative_eq!(masked.params(), Vector2::new(2., 1.), epsilon = 1e-12);
+
+        let wrong_length = measurements().with_active_residuals(&mask[..2]);
+        assert!(wrong_length.residuals().is_none());
+        assert!(wrong_length.jacobian().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "sigmas must be positive and finite")]
+    fn rejects_zero_sigma() {
+        let _ = measurements().with_sigmas(Vector3::new(1., 0., 1.));
+    }
+}
@@ -0,0 +1,96 @@
+//! The minimization of a problem with fixed dimensions does not allocate.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use levenberg_marquardt::{LeastSquaresProblem, LevenbergMarquardt};
+use nalgebra::{storage::Owned, Matrix3x2, Vector2, Vector3, U2, U3};
+
+/// The system allocator counting the allocations of the current thread.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let result = f();
+    (result, ALLOCATIONS.with(Cell::get) - before)
+}
+
+/// Fit `$y = a e^{bt}$` to three samples.
+struct Exponential {
+    params: Vector2<f64>,
+}
+
+impl LeastSquaresProblem<f64, U3, U2> for Exponential {
+    type ParameterStorage = Owned<f64, U2>;
+    type ResidualStorage = Owned<f64, U3>;
+    type JacobianStorage = Owned<f64, U3, U2>;
+
+    fn set_params(&mut self, x: &Vector2<f64>) {
+        self.params.copy_from(x);
+    }
+
+    fn params(&self) -> Vector2<f64> {
+        self.params
+    }
+
+    fn residuals(&self) -> Option<Vector3<f64>> {
+        let [a, b] = [self.params.x, self.params.y];
+        let t = Vector3::new(0., 1., 2.);
+        Some(t.map(|t| a * f64::exp(b * t)) - Vector3::new(1.1, 2.6, 7.5))
+    }
+
+    fn jacobian(&self) -> Option<Matrix3x2<f64>> {
+        let [a, b] = [self.params.x, self.params.y];
+        let t = Vector3::new(0., 1., 2.);
+        let e = t.map(|t| f64::exp(b * t));
+        Some(Matrix3x2::from_columns(&[e, a * t.component_mul(&e)]))
+    }
+}
+
+#[test]
+fn fixed_dimensions_do_not_allocate() {
+    let lm = LevenbergMarquardt::new();
+    let ((problem, report), count) = allocations(|| {
+        lm.minimize(Exponential {
+            params: Vector2::new(1., 0.5),
+        })
+    });
+    assert!(report.termination.was_successful());
+    assert!(report.terminated_at_iteration > 2);
+    assert!(problem.params.y > 0.);
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn stepping_does_not_allocate() {
+    let lm = LevenbergMarquardt::new().with_hessian_correction(true);
+    let (report, count) = allocations(|| {
+        let mut solver = lm
+            .solver(Exponential {
+                params: Vector2::new(1., 0.5),
+            })
+            .ok()
+            .unwrap();
+        while solver.step().is_ok() {}
+        solver.finish().1
+    });
+    assert!(report.termination.was_successful());
+    assert_eq!(count, 0);
+}